@@ -0,0 +1,289 @@
+use crate::crypto::CryptoService;
+use crate::models::{AuditHead, EncryptedRecord};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+/// What an audit log entry records.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum AuditAction {
+    Unlock,
+    /// The secret was shown on screen.
+    Reveal,
+    /// A field was copied to the clipboard.
+    Copy,
+    /// The secret was handed to another program, e.g. git or a command started by `run`.
+    Use,
+    Add,
+    Edit,
+    /// Moved to the trash or deleted for good.
+    Delete,
+    Restore,
+    Import,
+    Export,
+}
+
+impl AuditAction {
+    pub fn name(self) -> &'static str {
+        match self {
+            AuditAction::Unlock => "unlock",
+            AuditAction::Reveal => "reveal",
+            AuditAction::Copy => "copy",
+            AuditAction::Use => "use",
+            AuditAction::Add => "add",
+            AuditAction::Edit => "edit",
+            AuditAction::Delete => "delete",
+            AuditAction::Restore => "restore",
+            AuditAction::Import => "import",
+            AuditAction::Export => "export",
+        }
+    }
+}
+
+/// One entry of the audit log.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditEntry {
+    /// Position in the log, counting from 0.
+    pub seq: u64,
+    pub at: DateTime<Utc>,
+    pub action: AuditAction,
+    /// Id of the credential concerned; empty for actions on the whole vault.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub credential: String,
+    /// The credential's service at the time, so entries stay readable after it is renamed or purged.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub service: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub detail: String,
+    /// The operating system user that ran the program.
+    pub user: String,
+    /// The part of the program that acted: `tui`, `git-credential`, `run` and so on.
+    pub source: String,
+    /// Hex SHA-256 of the previous stored entry; empty for the first one.
+    pub prev: String,
+}
+
+impl AuditEntry {
+    /// An entry about `service` (or the whole vault, if empty), numbered and chained by [`seal`].
+    pub fn new(action: AuditAction, credential: &str, service: &str, detail: String, source: &str) -> Self {
+        let user = std::env::var("USER").or_else(|_| std::env::var("USERNAME")).unwrap_or_else(|_| "unknown".to_string());
+        AuditEntry {
+            seq: 0,
+            at: Utc::now(),
+            action,
+            credential: credential.to_string(),
+            service: service.to_string(),
+            detail,
+            user,
+            source: source.to_string(),
+            prev: String::new(),
+        }
+    }
+}
+
+/// The audit log as read back, up to the first entry that failed to verify.
+pub struct AuditLog {
+    /// The entries with the hex digest of each stored entry, which the next one's `prev` names.
+    pub entries: Vec<(AuditEntry, String)>,
+    /// Why the log can't be trusted past `entries`, or at all; `None` if it verified.
+    pub problem: Option<String>,
+}
+
+impl AuditLog {
+    /// The log as JSON for handing to auditors: every entry with its digest, and whether the chain verified.
+    pub fn to_json(&self) -> Result<String, Box<dyn std::error::Error>> {
+        #[derive(Serialize)]
+        struct Exported<'a> {
+            #[serde(flatten)]
+            entry: &'a AuditEntry,
+            hash: &'a str,
+        }
+        #[derive(Serialize)]
+        struct Export<'a> {
+            exported_at: DateTime<Utc>,
+            verified: bool,
+            problem: Option<&'a str>,
+            entries: Vec<Exported<'a>>,
+        }
+        let export = Export {
+            exported_at: Utc::now(),
+            verified: self.problem.is_none(),
+            problem: self.problem.as_deref(),
+            entries: self.entries.iter().map(|(entry, hash)| Exported { entry, hash }).collect(),
+        };
+        Ok(serde_json::to_string_pretty(&export)?)
+    }
+}
+
+/// Numbers, chains and encrypts `entries` to follow the log that ends at `head`. Returns the
+/// records to append and the new head.
+///
+/// Each entry names the digest of the one before and is encrypted with its position as associated
+/// data, so an entry can't be changed, dropped or moved without breaking the chain. The head,
+/// under a MAC, counts the entries and names the last one, so cutting entries off the end is
+/// noticed too.
+pub fn seal(key: &[u8], head: Option<&AuditHead>, entries: Vec<AuditEntry>) -> Result<(Vec<EncryptedRecord>, AuditHead), Box<dyn std::error::Error>> {
+    let (mut count, mut last) = head.map_or((0, Vec::new()), |head| (head.count, head.last.clone()));
+    let mut records = Vec::with_capacity(entries.len());
+    for mut entry in entries {
+        entry.seq = count;
+        entry.prev = hex(&last);
+        let (nonce, ciphertext) = CryptoService::encrypt_with_aad(&serde_json::to_vec(&entry)?, key, &entry_aad(count))?;
+        let record = EncryptedRecord { nonce, ciphertext };
+        last = record_digest(&record);
+        count += 1;
+        records.push(record);
+    }
+    let mac = CryptoService::mac(key, &head_data(count, &last));
+    Ok((records, AuditHead { count, last, mac }))
+}
+
+/// Decrypts the log and checks its chain against `head`, and its length against `recorded`, the
+/// count the encrypted vault index holds.
+pub fn verify(key: &[u8], records: &[EncryptedRecord], head: Option<&AuditHead>, recorded: u64) -> AuditLog {
+    let mut log = verify_chain(key, records, head);
+    if log.problem.is_none() && (log.entries.len() as u64) < recorded {
+        log.problem = Some(format!(
+            "The log was rolled back: the vault recorded {} entries but the log has {}",
+            recorded,
+            log.entries.len()
+        ));
+    }
+    log
+}
+
+fn verify_chain(key: &[u8], records: &[EncryptedRecord], head: Option<&AuditHead>) -> AuditLog {
+    let mut log = AuditLog { entries: Vec::with_capacity(records.len()), problem: None };
+    let head = match head {
+        Some(head) => head,
+        None if records.is_empty() => return log,
+        None => {
+            log.problem = Some("The log has entries but no head; it was changed outside the program".to_string());
+            return log;
+        }
+    };
+    if !CryptoService::verify_mac(key, &head_data(head.count, &head.last), &head.mac) {
+        log.problem = Some("The head of the log was changed outside the program".to_string());
+        return log;
+    }
+    let mut last = Vec::new();
+    for (seq, record) in (0u64..).zip(records) {
+        let entry = CryptoService::decrypt_with_aad(&record.ciphertext, &record.nonce, key, &entry_aad(seq))
+            .ok()
+            .and_then(|data| serde_json::from_slice::<AuditEntry>(&data).ok());
+        let entry = match entry {
+            Some(entry) if entry.seq == seq => entry,
+            _ => {
+                log.problem = Some(format!("Entry {} is missing, was changed, or was moved", seq + 1));
+                return log;
+            }
+        };
+        if entry.prev != hex(&last) {
+            log.problem = Some(format!("Entry {} does not follow the one before it; entries were removed or replaced", seq + 1));
+            return log;
+        }
+        last = record_digest(record);
+        log.entries.push((entry, hex(&last)));
+    }
+    let found = records.len() as u64;
+    if found < head.count {
+        log.problem = Some(format!("The log was truncated: it should have {} entries but has {}", head.count, found));
+    } else if found > head.count {
+        log.problem = Some(format!("{} entries were added after the last recorded one", found - head.count));
+    } else if last != head.last {
+        log.problem = Some("The last entry is not the one the head of the log names".to_string());
+    }
+    log
+}
+
+fn entry_aad(seq: u64) -> Vec<u8> {
+    [b"audit entry".as_slice(), &seq.to_le_bytes()].concat()
+}
+
+fn head_data(count: u64, last: &[u8]) -> Vec<u8> {
+    [b"audit head v1".as_slice(), &count.to_le_bytes(), last].concat()
+}
+
+fn record_digest(record: &EncryptedRecord) -> Vec<u8> {
+    CryptoService::digest(&[record.nonce.as_slice(), record.ciphertext.as_slice()].concat())
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entries(count: usize) -> Vec<AuditEntry> {
+        (0..count).map(|i| AuditEntry::new(AuditAction::Copy, "id", &format!("service {}", i), String::new(), "test")).collect()
+    }
+
+    fn problem(key: &[u8], records: &[EncryptedRecord], head: &AuditHead, recorded: u64) -> String {
+        verify(key, records, Some(head), recorded).problem.unwrap_or_default()
+    }
+
+    #[test]
+    fn sealed_entries_verify_in_order() {
+        let key = CryptoService::generate_key();
+        let (mut records, head) = seal(&key, None, entries(2)).unwrap();
+        let (more, head) = seal(&key, Some(&head), entries(1)).unwrap();
+        records.extend(more);
+        let log = verify(&key, &records, Some(&head), 3);
+        assert!(log.problem.is_none());
+        let seqs: Vec<u64> = log.entries.iter().map(|(entry, _)| entry.seq).collect();
+        assert_eq!(seqs, [0, 1, 2]);
+        assert_eq!(log.entries[1].0.prev, log.entries[0].1);
+    }
+
+    #[test]
+    fn changed_removed_and_moved_entries_are_detected() {
+        let key = CryptoService::generate_key();
+        let (records, head) = seal(&key, None, entries(3)).unwrap();
+
+        let mut changed = records.clone();
+        changed[1].ciphertext[0] ^= 1;
+        assert!(problem(&key, &changed, &head, 3).contains("Entry 2"));
+
+        let mut removed = records.clone();
+        removed.remove(1);
+        assert!(problem(&key, &removed, &head, 3).contains("Entry 2"));
+
+        let mut moved = records.clone();
+        moved.swap(0, 1);
+        assert!(problem(&key, &moved, &head, 3).contains("Entry 1"));
+
+        // An entry sealed on its own at the right position still names the wrong predecessor.
+        let (replacement, _) = seal(&key, Some(&AuditHead { count: 1, last: Vec::new(), mac: Vec::new() }), entries(1)).unwrap();
+        let mut replaced = records.clone();
+        replaced[1] = replacement[0].clone();
+        assert!(problem(&key, &replaced, &head, 3).contains("does not follow"));
+    }
+
+    #[test]
+    fn truncation_and_a_changed_head_are_detected() {
+        let key = CryptoService::generate_key();
+        let (records, head) = seal(&key, None, entries(3)).unwrap();
+        assert!(problem(&key, &records[..2], &head, 3).contains("truncated"));
+
+        let (extra, _) = seal(&key, Some(&head), entries(1)).unwrap();
+        let longer: Vec<EncryptedRecord> = records.iter().chain(&extra).cloned().collect();
+        assert!(problem(&key, &longer, &head, 3).contains("added after"));
+
+        let forged = AuditHead { count: 2, ..head.clone() };
+        assert!(problem(&key, &records[..2], &forged, 3).contains("head of the log was changed"));
+        assert!(verify(&key, &records, None, 3).problem.is_some());
+    }
+
+    #[test]
+    fn rolling_back_the_log_and_its_head_together_is_detected() {
+        let key = CryptoService::generate_key();
+        let (old_records, old_head) = seal(&key, None, entries(2)).unwrap();
+        let (_, head) = seal(&key, Some(&old_head), entries(2)).unwrap();
+        // Consistent on its own, but the vault index says the log had more entries.
+        assert!(verify(&key, &old_records, Some(&old_head), 2).problem.is_none());
+        assert!(problem(&key, &old_records, &old_head, head.count).contains("rolled back"));
+        assert!(verify(&key, &[], None, head.count).problem.unwrap().contains("rolled back"));
+    }
+}
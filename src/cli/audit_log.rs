@@ -0,0 +1,47 @@
+use crate::config::Settings;
+
+const USAGE: &str = "Usage: password-manager audit-log [list|verify|export <FILE>]";
+
+/// Runs `password-manager audit-log`: lists the audit log, checks it, or exports it as JSON.
+///
+/// `verify` fails when the log was tampered with, so it can run from cron or CI.
+pub fn run(args: &[String], settings: &Settings) -> Result<(), Box<dyn std::error::Error>> {
+    let operation = args.first().map_or("list", String::as_str);
+    if !["list", "verify", "export"].contains(&operation) {
+        return Err(USAGE.into());
+    }
+    let export_path = match (operation, args.get(1..).unwrap_or_default()) {
+        ("export", [path]) => Some(path),
+        ("export", _) => return Err("Usage: password-manager audit-log export <FILE>".into()),
+        (_, []) => None,
+        _ => return Err(USAGE.into()),
+    };
+    let manager = crate::cli::unlock_manager(settings, "audit-log")?;
+    let log = manager.audit_log()?;
+    if let Some(path) = export_path {
+        std::fs::write(path, log.to_json()?)?;
+        println!("Exported {} audit log entries to {}.", log.entries.len(), path);
+    } else if operation == "list" {
+        for (entry, _) in &log.entries {
+            let mut line = format!(
+                "{:>5}  {}  {:<8} {:<20} {}",
+                entry.seq + 1,
+                entry.at.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M:%S"),
+                entry.action.name(),
+                entry.service,
+                entry.detail
+            );
+            line.truncate(line.trim_end().len());
+            println!("{}  [{} via {}]", line, entry.user, entry.source);
+        }
+    }
+    match log.problem {
+        Some(problem) => Err(format!("The audit log failed verification: {}", problem).into()),
+        None => {
+            if operation == "verify" {
+                println!("The audit log is intact: {} entries.", log.entries.len());
+            }
+            Ok(())
+        }
+    }
+}
@@ -0,0 +1,66 @@
+use crate::config::Settings;
+use crate::keyfile::{self, KeyFiles};
+use std::path::Path;
+
+const USAGE: &str = "Usage: password-manager composite-key [show|generate <FILE>|set [--key-file <FILE>] [--challenge-response-file <FILE>]]";
+
+/// Runs `password-manager composite-key`: shows what unlocks the vault, writes new secret files,
+/// or re-keys the vault to need a different set of files besides the master password.
+pub fn run(args: &[String], settings: &Settings) -> Result<(), Box<dyn std::error::Error>> {
+    match (args.first().map_or("show", String::as_str), args.get(1..).unwrap_or_default()) {
+        ("show", []) => show(settings),
+        ("generate", [path]) => {
+            keyfile::generate(Path::new(path))?;
+            println!("Wrote a new secret to {}.", path);
+            Ok(())
+        }
+        ("generate", _) => Err("Usage: password-manager composite-key generate <FILE>".into()),
+        ("set", options) => set(options, settings),
+        _ => Err(USAGE.into()),
+    }
+}
+
+fn show(settings: &Settings) -> Result<(), Box<dyn std::error::Error>> {
+    let manager = crate::cli::open_manager(settings, "composite-key")?;
+    let (key_file, challenge_response) = manager
+        .kdf_params()?
+        .map_or((false, false), |kdf| (kdf.key_file, kdf.challenge_response));
+    println!("Unlocking needs the master password");
+    if key_file {
+        println!("  and a key file");
+    }
+    if challenge_response {
+        println!("  and a challenge-response file");
+    }
+    Ok(())
+}
+
+/// `composite-key set`: unlocks with the files from the settings and re-keys to need the ones given.
+fn set(options: &[String], settings: &Settings) -> Result<(), Box<dyn std::error::Error>> {
+    let mut target = KeyFiles::default();
+    let mut iter = options.iter();
+    while let Some(option) = iter.next() {
+        let value = iter.next().ok_or_else(|| format!("{} needs a file", option))?;
+        let path = Path::new(value);
+        if !path.is_file() {
+            return Err(format!("{} is not a file", path.display()).into());
+        }
+        match option.as_str() {
+            "--key-file" => target.key_file = Some(path.to_path_buf()),
+            "--challenge-response-file" => target.challenge_response_file = Some(path.to_path_buf()),
+            _ => return Err(USAGE.into()),
+        }
+    }
+    let mut manager = crate::cli::open_manager(settings, "composite-key")?;
+    manager.set_target_key_files(target.clone());
+    crate::cli::unlock(&mut manager)?;
+    match (&target.key_file, &target.challenge_response_file) {
+        (None, None) => println!("Re-keyed the vault: the master password alone unlocks it now."),
+        _ => {
+            println!("Re-keyed the vault: it needs the master password and these files now.");
+            println!("Set key_file and challenge_response_file in the settings, or pass them as options.");
+            println!("Keep copies of the files somewhere safe; without them the vault can't be opened.");
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,221 @@
+use crate::config::Settings;
+use crate::manager::PasswordManager;
+use crate::models::{Credential, CredentialUpdate, EntryType};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::io::{self, Read, Write};
+
+/// Tag that marks an API key as a container registry credential.
+pub const REGISTRY_TAG: &str = "docker-registry";
+
+/// The message docker expects when a lookup has no result.
+const NOT_FOUND: &str = "credentials not found in native keychain";
+
+/// The credential payload exchanged with docker over stdin/stdout.
+#[derive(Debug, Serialize, Deserialize)]
+struct DockerCredential {
+    #[serde(rename = "ServerURL")]
+    server_url: String,
+    #[serde(rename = "Username")]
+    username: String,
+    #[serde(rename = "Secret")]
+    secret: String,
+}
+
+/// Handles one docker credential helper operation.
+/// Errors are reported on stdout with exit status 1, as the helper protocol requires.
+pub fn run(operation: Option<&str>, settings: &Settings) -> Result<(), Box<dyn std::error::Error>> {
+    if let Err(e) = handle(operation, settings) {
+        println!("{}", e);
+        io::stdout().flush()?;
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+fn handle(operation: Option<&str>, settings: &Settings) -> Result<(), Box<dyn std::error::Error>> {
+    match operation {
+        Some("get") => {
+            let server_url = read_stdin()?;
+            let manager = crate::cli::unlock_manager(settings, "docker-credential")?;
+            get(&manager, server_url.trim(), &mut io::stdout())
+        }
+        Some("store") => {
+            let input = read_stdin()?;
+            let manager = crate::cli::unlock_manager(settings, "docker-credential")?;
+            store(&manager, &input)
+        }
+        Some("erase") => {
+            let server_url = read_stdin()?;
+            let manager = crate::cli::unlock_manager(settings, "docker-credential")?;
+            erase(&manager, server_url.trim())
+        }
+        Some("list") => {
+            let manager = crate::cli::unlock_manager(settings, "docker-credential")?;
+            list(&manager, &mut io::stdout())
+        }
+        Some("version") => {
+            println!("password-manager {}", env!("CARGO_PKG_VERSION"));
+            Ok(())
+        }
+        _ => Err("Usage: docker-credential <get|store|erase|list|version>".into()),
+    }
+}
+
+fn read_stdin() -> Result<String, Box<dyn std::error::Error>> {
+    let mut input = String::new();
+    io::stdin().read_to_string(&mut input)?;
+    Ok(input)
+}
+
+/// Normalizes a registry address so `https://registry.example.com/` and `registry.example.com` match.
+fn normalize_server(server_url: &str) -> String {
+    let server = server_url.trim();
+    let server = server.split_once("://").map(|(_, rest)| rest).unwrap_or(server);
+    server.trim_end_matches('/').to_lowercase()
+}
+
+fn is_registry_credential(credential: &Credential) -> bool {
+    credential.entry_type == EntryType::ApiKey && credential.tags.iter().any(|t| t == REGISTRY_TAG)
+}
+
+fn server_url_of(credential: &Credential) -> &str {
+    credential
+        .custom_fields
+        .get("server_url")
+        .map(String::as_str)
+        .unwrap_or(&credential.service)
+}
+
+fn find_registry_credentials(
+    manager: &PasswordManager,
+    server_url: &str,
+) -> Result<Vec<Credential>, Box<dyn std::error::Error>> {
+    let wanted = normalize_server(server_url);
+    Ok(manager
+        .get_credentials()?
+        .into_iter()
+        .filter(is_registry_credential)
+        .filter(|c| normalize_server(server_url_of(c)) == wanted)
+        .collect())
+}
+
+fn get<W: Write>(manager: &PasswordManager, server_url: &str, out: &mut W) -> Result<(), Box<dyn std::error::Error>> {
+    let credential = find_registry_credentials(manager, server_url)?
+        .into_iter()
+        .filter(|c| c.is_active)
+        // Secrets shared read-only are only handed out by `password-manager run`.
+        .filter(|c| c.can_reveal())
+        .max_by_key(|c| c.updated_at)
+        .ok_or(NOT_FOUND)?;
+    manager.record_use(&credential.id)?;
+    let response = DockerCredential {
+        server_url: server_url_of(&credential).to_string(),
+        username: credential.username.clone(),
+        secret: String::from_utf8_lossy(&credential.secret).to_string(),
+    };
+    serde_json::to_writer(&mut *out, &response)?;
+    writeln!(out)?;
+    Ok(())
+}
+
+fn store(manager: &PasswordManager, input: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let request: DockerCredential = serde_json::from_str(input)?;
+    if request.server_url.trim().is_empty() {
+        return Err("no credentials server URL".into());
+    }
+
+    let existing = find_registry_credentials(manager, &request.server_url)?
+        .into_iter()
+        .find(|c| c.username == request.username);
+    if let Some(credential) = existing {
+        let update = CredentialUpdate {
+            secret: Some(request.secret.into_bytes()),
+            is_active: Some(true),
+            ..Default::default()
+        };
+        return manager.update_credential(&credential.id, update);
+    }
+
+    let mut credential = Credential::new_api_key(
+        normalize_server(&request.server_url),
+        request.username,
+        request.secret.into_bytes(),
+        "Stored by docker credential helper".to_string(),
+        true,
+        vec![REGISTRY_TAG.to_string()],
+    );
+    credential.custom_fields = HashMap::from([("server_url".to_string(), request.server_url)]);
+    manager.add_credential(credential)
+}
+
+/// Moves the registry's credentials to the trash, where they can be restored from.
+fn erase(manager: &PasswordManager, server_url: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let targets: Vec<String> = find_registry_credentials(manager, server_url)?.into_iter().map(|c| c.id).collect();
+    if targets.is_empty() {
+        return Err(NOT_FOUND.into());
+    }
+    manager.trash(&targets)?;
+    Ok(())
+}
+
+fn list<W: Write>(manager: &PasswordManager, out: &mut W) -> Result<(), Box<dyn std::error::Error>> {
+    let entries: BTreeMap<String, String> = manager
+        .get_credentials()?
+        .into_iter()
+        .filter(is_registry_credential)
+        .map(|c| (server_url_of(&c).to_string(), c.username.clone()))
+        .collect();
+    serde_json::to_writer(&mut *out, &entries)?;
+    writeln!(out)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stored(manager: &PasswordManager, server_url: &str, username: &str, secret: &str) {
+        let request = DockerCredential { server_url: server_url.to_string(), username: username.to_string(), secret: secret.to_string() };
+        store(manager, &serde_json::to_string(&request).unwrap()).unwrap();
+    }
+
+    #[test]
+    fn payload_uses_docker_field_names() {
+        let json = r#"{"ServerURL":"https://registry.example.com","Username":"ci","Secret":"token"}"#;
+        let parsed: DockerCredential = serde_json::from_str(json).unwrap();
+        assert_eq!(parsed.server_url, "https://registry.example.com");
+        assert_eq!(parsed.username, "ci");
+        assert_eq!(parsed.secret, "token");
+        assert_eq!(serde_json::to_string(&parsed).unwrap(), json);
+    }
+
+    #[test]
+    fn stored_credentials_come_back_from_get_and_list() {
+        let manager = crate::manager::test_manager();
+        stored(&manager, "https://registry.example.com/", "ci", "token-1");
+
+        let mut out = Vec::new();
+        get(&manager, "registry.example.com", &mut out).unwrap();
+        let answer: DockerCredential = serde_json::from_slice(&out).unwrap();
+        assert_eq!(answer.server_url, "https://registry.example.com/");
+        assert_eq!(answer.username, "ci");
+        assert_eq!(answer.secret, "token-1");
+
+        stored(&manager, "registry.example.com", "ci", "token-2");
+        let mut out = Vec::new();
+        list(&manager, &mut out).unwrap();
+        let listed: BTreeMap<String, String> = serde_json::from_slice(&out).unwrap();
+        assert_eq!(listed, BTreeMap::from([("https://registry.example.com/".to_string(), "ci".to_string())]));
+    }
+
+    #[test]
+    fn erase_moves_to_the_trash() {
+        let manager = crate::manager::test_manager();
+        stored(&manager, "registry.example.com", "ci", "token-1");
+        erase(&manager, "https://registry.example.com").unwrap();
+        assert!(get(&manager, "registry.example.com", &mut Vec::new()).is_err());
+        assert_eq!(manager.get_trash().unwrap().len(), 1);
+        assert!(erase(&manager, "registry.example.com").is_err());
+    }
+}
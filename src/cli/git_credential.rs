@@ -0,0 +1,275 @@
+use crate::config::Settings;
+use crate::manager::PasswordManager;
+use crate::models::{Credential, CredentialUpdate, EntryType};
+use std::collections::HashMap;
+use std::io::{self, BufRead, Write};
+
+/// The attributes git sends to a credential helper.
+#[derive(Debug, Default)]
+struct GitRequest {
+    protocol: Option<String>,
+    host: Option<String>,
+    path: Option<String>,
+    username: Option<String>,
+    password: Option<String>,
+}
+
+impl GitRequest {
+    /// Parses `key=value` lines up to the first blank line, as described in git-credential(1).
+    fn parse<R: BufRead>(reader: R) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut request = GitRequest::default();
+        for line in reader.lines() {
+            let line = line?;
+            if line.is_empty() {
+                break;
+            }
+            let (key, value) = match line.split_once('=') {
+                Some(pair) => pair,
+                None => continue,
+            };
+            let value = value.to_string();
+            match key {
+                "protocol" => request.protocol = Some(value),
+                "host" => request.host = Some(value),
+                "path" => request.path = Some(value),
+                "username" => request.username = Some(value),
+                "password" => request.password = Some(value),
+                "url" => request.apply_url(&value),
+                _ => {}
+            }
+        }
+        Ok(request)
+    }
+
+    fn apply_url(&mut self, url: &str) {
+        let rest = match url.split_once("://") {
+            Some((protocol, rest)) => {
+                self.protocol = Some(protocol.to_string());
+                rest
+            }
+            None => url,
+        };
+        let (authority, path) = match rest.split_once('/') {
+            Some((authority, path)) => (authority, Some(path)),
+            None => (rest, None),
+        };
+        let host = match authority.rsplit_once('@') {
+            Some((user, host)) => {
+                if self.username.is_none() && !user.is_empty() {
+                    self.username = Some(user.split(':').next().unwrap_or(user).to_string());
+                }
+                host
+            }
+            None => authority,
+        };
+        self.host = Some(host.to_string());
+        if let Some(path) = path.filter(|p| !p.is_empty()) {
+            self.path = Some(path.to_string());
+        }
+    }
+
+    fn matches(&self, credential: &Credential) -> bool {
+        let host = match &self.host {
+            Some(host) => host.to_lowercase(),
+            None => return false,
+        };
+        if credential.entry_type == EntryType::ApiKey && !credential.is_active {
+            return false;
+        }
+
+        let (service_protocol, service_host) = split_service(&credential.service);
+        let stored_host = credential
+            .custom_fields
+            .get("host")
+            .map(|h| h.to_lowercase())
+            .unwrap_or(service_host);
+        if stored_host != host {
+            return false;
+        }
+
+        let stored_protocol = credential.custom_fields.get("protocol").cloned().or(service_protocol);
+        if let (Some(wanted), Some(stored)) = (&self.protocol, &stored_protocol) {
+            if !wanted.eq_ignore_ascii_case(stored) {
+                return false;
+            }
+        }
+
+        if let (Some(wanted), Some(stored)) = (&self.path, credential.custom_fields.get("path")) {
+            if wanted.trim_matches('/') != stored.trim_matches('/') {
+                return false;
+            }
+        }
+
+        match &self.username {
+            Some(username) => credential.username == *username,
+            None => true,
+        }
+    }
+}
+
+/// Splits a service such as `https://git.example.com/` into its protocol and lowercased host.
+fn split_service(service: &str) -> (Option<String>, String) {
+    let service = service.trim();
+    let (protocol, rest) = match service.split_once("://") {
+        Some((protocol, rest)) => (Some(protocol.to_string()), rest),
+        None => (None, service),
+    };
+    let host = rest.split('/').next().unwrap_or(rest);
+    (protocol, host.to_lowercase())
+}
+
+/// Handles one git credential helper operation, reading the request from stdin.
+pub fn run(operation: &str, settings: &Settings) -> Result<(), Box<dyn std::error::Error>> {
+    let request = GitRequest::parse(io::stdin().lock())?;
+    match operation {
+        "get" => {
+            let manager = crate::cli::unlock_manager(settings, "git-credential")?;
+            get(&manager, &request, &mut io::stdout())
+        }
+        "store" => {
+            let manager = crate::cli::unlock_manager(settings, "git-credential")?;
+            store(&manager, &request)
+        }
+        "erase" => {
+            let manager = crate::cli::unlock_manager(settings, "git-credential")?;
+            erase(&manager, &request)
+        }
+        // Unknown operations must be ignored so newer versions of git keep working.
+        _ => Ok(()),
+    }
+}
+
+fn get<W: Write>(
+    manager: &PasswordManager,
+    request: &GitRequest,
+    out: &mut W,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let best = manager
+        .get_credentials()?
+        .into_iter()
+        .filter(|c| request.matches(c))
+        // Secrets shared read-only are only handed out by `password-manager run`.
+        .filter(|c| c.can_reveal())
+        .max_by_key(|c| c.updated_at);
+    if let Some(credential) = best {
+        manager.record_use(&credential.id)?;
+        writeln!(out, "username={}", credential.username)?;
+        writeln!(out, "password={}", String::from_utf8_lossy(&credential.secret))?;
+    }
+    Ok(())
+}
+
+fn store(manager: &PasswordManager, request: &GitRequest) -> Result<(), Box<dyn std::error::Error>> {
+    let (host, username, password) = match (&request.host, &request.username, &request.password) {
+        (Some(host), Some(username), Some(password)) => (host, username, password),
+        // Git only asks us to store complete credentials; ignore anything else.
+        _ => return Ok(()),
+    };
+
+    let existing = manager
+        .get_credentials()?
+        .into_iter()
+        .find(|c| request.matches(c));
+    if let Some(credential) = existing {
+        if credential.secret != password.as_bytes() {
+            let update = CredentialUpdate { secret: Some(password.as_bytes().to_vec()), ..Default::default() };
+            manager.update_credential(&credential.id, update)?;
+        }
+        return Ok(());
+    }
+
+    let mut custom_fields = HashMap::new();
+    if let Some(protocol) = &request.protocol {
+        custom_fields.insert("protocol".to_string(), protocol.clone());
+    }
+    if let Some(path) = &request.path {
+        custom_fields.insert("path".to_string(), path.clone());
+    }
+    let mut credential = Credential::new_password(
+        host.clone(),
+        username.clone(),
+        password.clone().into_bytes(),
+        "Stored by git credential helper".to_string(),
+        vec!["git".to_string()],
+    );
+    credential.custom_fields = custom_fields;
+    manager.add_credential(credential)
+}
+
+/// Moves the credential git rejected to the trash. Git erases after any failed authentication,
+/// including transient ones, so only an entry with the exact username and password git sent is
+/// touched, and it can be restored.
+fn erase(manager: &PasswordManager, request: &GitRequest) -> Result<(), Box<dyn std::error::Error>> {
+    let password = match (&request.username, &request.password) {
+        (Some(_), Some(password)) => password,
+        _ => return Ok(()),
+    };
+    let targets: Vec<String> = manager
+        .get_credentials()?
+        .into_iter()
+        .filter(|c| request.matches(c) && c.secret == password.as_bytes())
+        .map(|c| c.id)
+        .collect();
+    if !targets.is_empty() {
+        manager.trash(&targets)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(text: &str) -> GitRequest {
+        GitRequest::parse(text.as_bytes()).unwrap()
+    }
+
+    #[test]
+    fn parses_attributes_up_to_the_blank_line() {
+        let parsed = request("protocol=https\nhost=git.example.com\npath=team/repo.git\nusername=alice\npassword=s3cret=x\n\nhost=ignored\n");
+        assert_eq!(parsed.protocol.as_deref(), Some("https"));
+        assert_eq!(parsed.host.as_deref(), Some("git.example.com"));
+        assert_eq!(parsed.path.as_deref(), Some("team/repo.git"));
+        assert_eq!(parsed.username.as_deref(), Some("alice"));
+        assert_eq!(parsed.password.as_deref(), Some("s3cret=x"));
+    }
+
+    #[test]
+    fn parses_url_attribute() {
+        let parsed = request("url=https://alice:pw@git.example.com:8443/team/repo.git\n");
+        assert_eq!(parsed.protocol.as_deref(), Some("https"));
+        assert_eq!(parsed.host.as_deref(), Some("git.example.com:8443"));
+        assert_eq!(parsed.path.as_deref(), Some("team/repo.git"));
+        assert_eq!(parsed.username.as_deref(), Some("alice"));
+    }
+
+    #[test]
+    fn stored_credentials_come_back_from_get() {
+        let manager = crate::manager::test_manager();
+        store(&manager, &request("protocol=https\nhost=git.example.com\nusername=alice\npassword=token-1\n")).unwrap();
+
+        let mut out = Vec::new();
+        get(&manager, &request("protocol=https\nhost=git.example.com\n"), &mut out).unwrap();
+        let answer = request(std::str::from_utf8(&out).unwrap());
+        assert_eq!(answer.username.as_deref(), Some("alice"));
+        assert_eq!(answer.password.as_deref(), Some("token-1"));
+
+        let mut out = Vec::new();
+        get(&manager, &request("protocol=http\nhost=git.example.com\n"), &mut out).unwrap();
+        assert!(out.is_empty(), "a different protocol must not match");
+    }
+
+    #[test]
+    fn erase_needs_the_exact_credential_and_uses_the_trash() {
+        let manager = crate::manager::test_manager();
+        store(&manager, &request("protocol=https\nhost=git.example.com\nusername=alice\npassword=token-1\n")).unwrap();
+
+        erase(&manager, &request("protocol=https\nhost=git.example.com\n")).unwrap();
+        erase(&manager, &request("protocol=https\nhost=git.example.com\nusername=alice\npassword=old\n")).unwrap();
+        assert_eq!(manager.get_credentials().unwrap().len(), 1);
+
+        erase(&manager, &request("protocol=https\nhost=git.example.com\nusername=alice\npassword=token-1\n")).unwrap();
+        assert!(manager.get_credentials().unwrap().is_empty());
+        assert_eq!(manager.get_trash().unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,205 @@
+pub mod audit_log;
+pub mod composite_key;
+pub mod docker_credential;
+pub mod git_credential;
+pub mod run;
+pub mod serve;
+pub mod share;
+pub mod unlock_attempts;
+
+use crate::config::Settings;
+use crate::manager::PasswordManager;
+use crossterm::{
+    event::{self, Event, KeyCode, KeyModifiers},
+    terminal::{disable_raw_mode, enable_raw_mode},
+};
+use std::io::{self, Write};
+use std::path::Path;
+
+/// Environment variable that can supply the master password to non-interactive commands.
+pub const PASSWORD_ENV: &str = "PASSWORD_MANAGER_PASSWORD";
+
+/// Runs a command-line subcommand if one was requested.
+/// Returns `Ok(false)` when no subcommand was given and the TUI should start.
+pub fn run(args: &[String], settings: &Settings) -> Result<bool, Box<dyn std::error::Error>> {
+    // Helpers can also be installed as symlinks named the way git and docker look them up,
+    // e.g. `docker-credential-password-manager`.
+    let program = args
+        .first()
+        .and_then(|arg| Path::new(arg).file_name())
+        .and_then(|name| name.to_str())
+        .unwrap_or_default();
+    if program.starts_with("docker-credential-") {
+        docker_credential::run(args.get(1).map(String::as_str), settings)?;
+        return Ok(true);
+    }
+    if program.starts_with("git-credential-") {
+        let operation = args.get(1).ok_or("Usage: git-credential-password-manager <get|store|erase>")?;
+        git_credential::run(operation, settings)?;
+        return Ok(true);
+    }
+
+    match args.get(1).map(String::as_str) {
+        Some("git-credential") => {
+            let operation = args.get(2).ok_or("Usage: password-manager git-credential <get|store|erase>")?;
+            git_credential::run(operation, settings)?;
+            Ok(true)
+        }
+        Some("docker-credential") => {
+            docker_credential::run(args.get(2).map(String::as_str), settings)?;
+            Ok(true)
+        }
+        Some("run") => {
+            run::run(&args[2..], settings)?;
+            Ok(true)
+        }
+        Some("share") => {
+            share::run(&args[2..], settings)?;
+            Ok(true)
+        }
+        Some("audit-log") => {
+            audit_log::run(&args[2..], settings)?;
+            Ok(true)
+        }
+        Some("composite-key") => {
+            composite_key::run(&args[2..], settings)?;
+            Ok(true)
+        }
+        Some("unlock-attempts") => {
+            unlock_attempts::run(&args[2..], settings)?;
+            Ok(true)
+        }
+        Some("serve") => {
+            serve::run(&args[2..])?;
+            Ok(true)
+        }
+        Some("help") | Some("--help") | Some("-h") => {
+            print_usage();
+            Ok(true)
+        }
+        Some(other) => Err(format!("Unknown command '{}'. Run with --help for usage.", other).into()),
+        None => Ok(false),
+    }
+}
+
+fn print_usage() {
+    println!("Usage: password-manager [OPTIONS] [COMMAND]");
+    println!();
+    println!("Without a command the interactive TUI is started.");
+    println!();
+    println!("Options (override the settings file for this run):");
+    println!("  --config <PATH>                  Settings file (default: {})", Settings::default_path().display());
+    println!("  --theme <NAME>                   Color theme");
+    println!("  --keymap <NAME>                  Key bindings: default, vim or emacs");
+    println!("  --preview-pane <true|false>      Preview the selected entry beside the list");
+    println!("  --inactivity-timeout <SECS>      Lock after this many idle seconds");
+    println!("  --clipboard-timeout <SECS>       Clear copied values after this many seconds");
+    println!("  --export-path <PATH>             File used for export and import");
+    println!("  --kdf-iterations <N>             PBKDF2 iterations; the vault is re-keyed at unlock");
+    println!("  --key-file <PATH>                Key file to unlock with");
+    println!("  --challenge-response-file <PATH> Challenge-response secret file to unlock with");
+    println!();
+    println!("Commands:");
+    println!("  git-credential <get|store|erase>            Act as a git credential helper; with the sled backend");
+    println!("                                              it fails while the TUI has the vault open");
+    println!("  docker-credential <get|store|erase|list>    Act as a docker credential helper; with the sled backend");
+    println!("                                              it fails while the TUI has the vault open");
+    println!("  share key                                   Print this vault's public key for teammates");
+    println!("  run --env <VAR>=<CREDENTIAL>... -- <COMMAND> [ARGS]...");
+    println!("                                              Run a command with secrets in its environment");
+    println!("  share create <FILE> --name <NAME> [--tag <TAG>] [--as <NAME>] [--member <NAME>[:<ROLE>]=<KEY>]...");
+    println!("                                              Share the credentials with a tag in a collection file");
+    println!("  share update <FILE>                         Write the tagged credentials to the collection again");
+    println!("  share add-member <FILE> <NAME> <KEY> [ROLE] Add a member and re-key the collection");
+    println!("  share remove-member <FILE> <NAME>           Remove a member and re-key the collection");
+    println!("  share set-role <FILE> <NAME> <ROLE>         Change a member's role: read-only, can-reveal, can-edit or admin");
+    println!("  share show <FILE>                           List a collection's members and credentials");
+    println!("  share import <FILE> [--from <KEY>]          Import a collection into this vault; the first import");
+    println!("                                              needs the public key of the member who wrote it");
+    println!("  audit-log [list|verify]                     Show the audit log, or only check it is intact");
+    println!("  audit-log export <FILE>                     Write the audit log as JSON for compliance reviews");
+    println!("  composite-key [show]                        Show what unlocks the vault besides the password");
+    println!("  composite-key generate <FILE>               Write a new random key file or challenge-response file");
+    println!("  composite-key set [--key-file <FILE>] [--challenge-response-file <FILE>]");
+    println!("                                              Re-key the vault to need the password and exactly these files");
+    println!("  unlock-attempts [show|reset]                Show failed unlocks, or clear them and leave read-only mode");
+    println!("  serve [--listen ADDR] [--dir PATH] [--token TOKEN]");
+    println!("                                              Run a sync server (default address 127.0.0.1:8750;");
+    println!("                                              other addresses need a token)");
+    println!("  help                                        Show this message");
+}
+
+/// Opens the existing vault for a non-interactive command, named `source` in the audit log.
+/// The master password is taken from `PASSWORD_MANAGER_PASSWORD` or prompted for on the terminal.
+pub fn unlock_manager(settings: &Settings, source: &str) -> Result<PasswordManager, Box<dyn std::error::Error>> {
+    let mut manager = open_manager(settings, source)?;
+    unlock(&mut manager)?;
+    Ok(manager)
+}
+
+/// The existing vault, set up from the settings but still locked.
+pub fn open_manager(settings: &Settings, source: &str) -> Result<PasswordManager, Box<dyn std::error::Error>> {
+    let mut manager = PasswordManager::new(settings.vault.open()?);
+    manager.set_sync(settings.sync.clone());
+    manager.set_lockout(settings.lockout.clone());
+    manager.set_key_files(settings.key_files());
+    manager.set_audit_source(source);
+    if !manager.vault_exists()? {
+        return Err("No vault found. Run password-manager once to create one.".into());
+    }
+    Ok(manager)
+}
+
+/// Unlocks a vault from [`open_manager`] as [`unlock_manager`] does.
+pub fn unlock(manager: &mut PasswordManager) -> Result<(), Box<dyn std::error::Error>> {
+    let password = match std::env::var(PASSWORD_ENV) {
+        Ok(password) if !password.is_empty() => password,
+        _ => prompt_password("Master password: ")?,
+    };
+    if !manager.unlock(&password)? {
+        return Err("Invalid password".into());
+    }
+    match manager.failures_before_unlock() {
+        0 => {}
+        1 => eprintln!("Warning: 1 failed unlock attempt since the last unlock."),
+        failures => eprintln!("Warning: {} failed unlock attempts since the last unlock.", failures),
+    }
+    if manager.is_read_only() {
+        eprintln!("The vault is read-only after too many failed unlocks; see 'password-manager unlock-attempts'.");
+    }
+    // Helpers still answer from the local vault when the sync folder is unavailable.
+    if let Some(Err(e)) = manager.take_sync_report() {
+        eprintln!("Sync failed: {}", e);
+    }
+    Ok(())
+}
+
+/// Reads a password from the controlling terminal without echoing it.
+/// Stdin is left untouched so helper protocols can still be read from it.
+pub fn prompt_password(prompt: &str) -> Result<String, Box<dyn std::error::Error>> {
+    eprint!("{}", prompt);
+    io::stderr().flush()?;
+    enable_raw_mode()?;
+    let mut password = String::new();
+    let result = loop {
+        match event::read() {
+            Ok(Event::Key(key)) => match key.code {
+                KeyCode::Enter => break Ok(()),
+                KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                    break Err("Cancelled".into())
+                }
+                KeyCode::Esc => break Err("Cancelled".into()),
+                KeyCode::Backspace => {
+                    password.pop();
+                }
+                KeyCode::Char(c) => password.push(c),
+                _ => {}
+            },
+            Ok(_) => {}
+            Err(e) => break Err(Box::new(e) as Box<dyn std::error::Error>),
+        }
+    };
+    disable_raw_mode()?;
+    eprintln!();
+    result.map(|_| password)
+}
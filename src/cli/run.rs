@@ -0,0 +1,68 @@
+use crate::config::Settings;
+use std::process::Command;
+use zeroize::Zeroizing;
+
+const USAGE: &str = "Usage: password-manager run --env <VAR>=<CREDENTIAL>... -- <COMMAND> [ARGS]...";
+
+/// Runs `password-manager run`: starts a command with secrets in its environment instead of
+/// copying them or writing them to a file. Exits with the command's exit code.
+///
+/// A credential is named by its id or its service. This works for credentials shared read-only too,
+/// which is not a security boundary: the command gets the secret and can print it, as `printenv`
+/// would.
+pub fn run(args: &[String], settings: &Settings) -> Result<(), Box<dyn std::error::Error>> {
+    let mut variables = Vec::new();
+    let mut iter = args.iter();
+    let command = loop {
+        match iter.next().map(String::as_str) {
+            Some("--env") => {
+                let value = iter.next().ok_or(USAGE)?;
+                let (name, credential) = value.split_once('=').ok_or("--env takes <VAR>=<CREDENTIAL>")?;
+                if !is_variable_name(name) {
+                    return Err(format!("'{}' is not a valid environment variable name", name).into());
+                }
+                variables.push((name.to_string(), credential.to_string()));
+            }
+            Some("--") => break iter.as_slice(),
+            _ => return Err(USAGE.into()),
+        }
+    };
+    let (program, program_args) = command.split_first().ok_or(USAGE)?;
+    if variables.is_empty() {
+        return Err(USAGE.into());
+    }
+
+    let mut environment = Vec::with_capacity(variables.len());
+    {
+        let manager = crate::cli::unlock_manager(settings, "run")?;
+        let credentials = manager.get_credentials()?;
+        for (name, wanted) in variables {
+            let matches: Vec<_> = credentials
+                .iter()
+                .filter(|c| c.id == wanted || c.service.eq_ignore_ascii_case(&wanted))
+                .collect();
+            let credential = match matches.as_slice() {
+                [credential] => credential,
+                [] => return Err(format!("No credential called '{}'", wanted).into()),
+                _ => return Err(format!("Several credentials are called '{}'; use the id of one", wanted).into()),
+            };
+            let secret = Zeroizing::new(manager.secret_for_injection(&credential.id)?);
+            let secret = std::str::from_utf8(&secret).map_err(|_| format!("The secret of '{}' is not text", wanted))?;
+            environment.push((name, Zeroizing::new(secret.to_string())));
+        }
+        // The vault is closed before the command starts, so the command can use the helpers itself.
+    }
+
+    let status = Command::new(program)
+        .args(program_args)
+        .envs(environment.iter().map(|(name, value)| (name.as_str(), value.as_str())))
+        .status()
+        .map_err(|e| format!("Could not run {}: {}", program, e))?;
+    drop(environment);
+    std::process::exit(status.code().unwrap_or(1));
+}
+
+fn is_variable_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars.next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_') && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
@@ -0,0 +1,319 @@
+use crate::sync::{is_valid_device, split_entries, FolderStore, SyncStore};
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::PathBuf;
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::Duration;
+
+/// Environment variable that can supply the access token instead of `--token`.
+pub const TOKEN_ENV: &str = "PASSWORD_MANAGER_SYNC_TOKEN";
+
+const DEFAULT_LISTEN: &str = "127.0.0.1:8750";
+
+/// Largest request body accepted; a whole compacted log has to fit.
+const MAX_BODY: usize = 64 * 1024 * 1024;
+
+/// Connections handled at once. Further ones wait to be accepted until one finishes.
+const MAX_CONNECTIONS: usize = 8;
+
+/// A request as far as the server cares about it.
+struct Request {
+    method: String,
+    path: String,
+    query: HashMap<String, String>,
+    authorization: Option<String>,
+    content_length: usize,
+    body: Vec<u8>,
+}
+
+/// Runs the sync server until it is killed.
+///
+/// The server keeps the same files as a sync folder. It only ever receives the encrypted key file
+/// and change logs, so it needs neither the master password nor a vault of its own.
+///
+/// Without a token anyone who can connect could replace the key file and every device's log, so
+/// the server only listens on a loopback address then. The token is sent with each request over
+/// plain HTTP, in the clear; beyond a trusted network the server belongs behind a TLS proxy.
+pub fn run(args: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+    let mut listen = DEFAULT_LISTEN.to_string();
+    let mut dir = dirs::data_dir()
+        .unwrap_or_else(|| PathBuf::from("."))
+        .join("password_manager")
+        .join("sync-server");
+    let mut token = std::env::var(TOKEN_ENV).unwrap_or_default();
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        let mut value = || iter.next().cloned().ok_or_else(|| format!("{} needs a value", arg));
+        match arg.as_str() {
+            "--listen" => listen = value()?,
+            "--dir" => dir = PathBuf::from(value()?),
+            "--token" => token = value()?,
+            other => return Err(format!("Unknown serve option '{}'. Run with --help for usage.", other).into()),
+        }
+    }
+
+    let listener = TcpListener::bind(&listen).map_err(|e| format!("Could not listen on {}: {}", listen, e))?;
+    let address = listener.local_addr()?;
+    if token.is_empty() && !address.ip().is_loopback() {
+        return Err(format!(
+            "Refusing to listen on {} without a token: anyone who can reach it could overwrite the change logs. Set one with --token or {}.",
+            address, TOKEN_ENV
+        )
+        .into());
+    }
+    std::fs::create_dir_all(&dir)?;
+    eprintln!("Serving sync data from {} on http://{}", dir.display(), address);
+    if token.is_empty() {
+        eprintln!("Warning: no token set; any program on this machine can overwrite the change logs.");
+    }
+
+    let store = Arc::new(Mutex::new(FolderStore::new(dir)));
+    let token = Arc::new(token);
+    let slots = Arc::new(Slots::new(MAX_CONNECTIONS));
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(_) => continue,
+        };
+        let slot = Slots::acquire(&slots);
+        let (store, token) = (Arc::clone(&store), Arc::clone(&token));
+        std::thread::spawn(move || {
+            let _slot = slot;
+            let _ = handle_connection(stream, &store, &token);
+        });
+    }
+    Ok(())
+}
+
+/// Counts the connections being handled, to keep them under a limit.
+struct Slots {
+    free: Mutex<usize>,
+    released: Condvar,
+}
+
+/// A connection's place in [`Slots`], given back when dropped.
+struct Slot(Arc<Slots>);
+
+impl Slots {
+    fn new(count: usize) -> Self {
+        Self { free: Mutex::new(count), released: Condvar::new() }
+    }
+
+    /// Waits until fewer than the limit are in use and takes a slot.
+    fn acquire(slots: &Arc<Slots>) -> Slot {
+        let mut free = slots.free.lock().unwrap_or_else(|e| e.into_inner());
+        while *free == 0 {
+            free = slots.released.wait(free).unwrap_or_else(|e| e.into_inner());
+        }
+        *free -= 1;
+        Slot(Arc::clone(slots))
+    }
+}
+
+impl Drop for Slot {
+    fn drop(&mut self) {
+        *self.0.free.lock().unwrap_or_else(|e| e.into_inner()) += 1;
+        self.0.released.notify_one();
+    }
+}
+
+fn handle_connection(stream: TcpStream, store: &Mutex<FolderStore>, token: &str) -> std::io::Result<()> {
+    stream.set_read_timeout(Some(Duration::from_secs(30)))?;
+    stream.set_write_timeout(Some(Duration::from_secs(30)))?;
+    let mut writer = stream.try_clone()?;
+    let mut reader = BufReader::new(stream);
+    // The token is checked before the body is read, so requests without it can't make the server
+    // take in a large body.
+    let (status, body) = match read_head(&mut reader) {
+        Ok(request) if !token.is_empty() && !authorized(&request, token) => (401, b"missing or wrong token".to_vec()),
+        Ok(mut request) => match read_body(&mut reader, &mut request) {
+            Ok(()) => {
+                let store = store.lock().unwrap_or_else(|e| e.into_inner());
+                match respond(&request, &store) {
+                    Ok(response) => response,
+                    Err(e) => (500, e.to_string().into_bytes()),
+                }
+            }
+            Err(e) => (400, e.into_bytes()),
+        },
+        Err(e) => (400, e.into_bytes()),
+    };
+    let reason = match status {
+        200 => "OK",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        _ => "Internal Server Error",
+    };
+    write!(
+        writer,
+        "HTTP/1.1 {} {}\r\nContent-Length: {}\r\nContent-Type: application/octet-stream\r\nConnection: close\r\n\r\n",
+        status,
+        reason,
+        body.len()
+    )?;
+    writer.write_all(&body)?;
+    writer.flush()
+}
+
+fn authorized(request: &Request, token: &str) -> bool {
+    let given = request
+        .authorization
+        .as_deref()
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .unwrap_or_default();
+    ring::constant_time::verify_slices_are_equal(given.trim().as_bytes(), token.as_bytes()).is_ok()
+}
+
+/// Reads the request line and headers, leaving the body for [`read_body`].
+fn read_head<R: BufRead>(reader: &mut R) -> Result<Request, String> {
+    let mut line = String::new();
+    reader.read_line(&mut line).map_err(|e| e.to_string())?;
+    let mut parts = line.split_whitespace();
+    let (method, target) = match (parts.next(), parts.next()) {
+        (Some(method), Some(target)) => (method.to_string(), target.to_string()),
+        _ => return Err("malformed request line".to_string()),
+    };
+
+    let mut content_length = 0;
+    let mut authorization = None;
+    loop {
+        line.clear();
+        if reader.read_line(&mut line).map_err(|e| e.to_string())? == 0 {
+            return Err("connection closed in the headers".to_string());
+        }
+        let header = line.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            match name.trim().to_ascii_lowercase().as_str() {
+                "content-length" => content_length = value.trim().parse().map_err(|_| "bad Content-Length")?,
+                "authorization" => authorization = Some(value.trim().to_string()),
+                _ => {}
+            }
+        }
+    }
+    if content_length > MAX_BODY {
+        return Err("request body too large".to_string());
+    }
+
+    let (path, query) = match target.split_once('?') {
+        Some((path, query)) => (path.to_string(), query),
+        None => (target, ""),
+    };
+    let query = query
+        .split('&')
+        .filter_map(|pair| pair.split_once('='))
+        .map(|(key, value)| (key.to_string(), value.to_string()))
+        .collect();
+    Ok(Request { method, path, query, authorization, content_length, body: Vec::new() })
+}
+
+/// Reads the body announced in the headers. Memory grows with the bytes that actually arrive,
+/// not with the length a client claims.
+fn read_body<R: BufRead>(reader: &mut R, request: &mut Request) -> Result<(), String> {
+    let expected = request.content_length as u64;
+    reader.take(expected).read_to_end(&mut request.body).map_err(|e| e.to_string())?;
+    if request.body.len() as u64 != expected {
+        return Err("connection closed in the body".to_string());
+    }
+    Ok(())
+}
+
+/// Answers one API call:
+///
+/// - `GET /v1/key`, `PUT /v1/key`: the encrypted sync key file; a `PUT` keeps an existing one.
+/// - `GET /v1/logs`: every device's log length as JSON.
+/// - `GET /v1/logs/<device>?from=N`: a log from byte `N` on.
+/// - `POST /v1/logs/<device>`: appends whole entries to a log.
+/// - `PUT /v1/logs/<device>`: replaces a log after it was compacted.
+fn respond(request: &Request, store: &FolderStore) -> Result<(u16, Vec<u8>), Box<dyn std::error::Error>> {
+    let path = request.path.trim_end_matches('/');
+    if path == "/v1/key" {
+        return match request.method.as_str() {
+            "GET" => Ok(store.load_key_file()?.map_or((404, Vec::new()), |key| (200, key))),
+            "PUT" if request.body.is_empty() => Ok((400, b"empty key file".to_vec())),
+            "PUT" => Ok((200, store.create_key_file(&request.body)?)),
+            _ => Ok((405, Vec::new())),
+        };
+    }
+    if path == "/v1/logs" {
+        return match request.method.as_str() {
+            "GET" => Ok((200, serde_json::to_vec(&store.logs()?)?)),
+            _ => Ok((405, Vec::new())),
+        };
+    }
+    let device = match path.strip_prefix("/v1/logs/") {
+        Some(device) if is_valid_device(device) => device,
+        Some(_) => return Ok((400, b"invalid device name".to_vec())),
+        None => return Ok((404, Vec::new())),
+    };
+    match request.method.as_str() {
+        "GET" => {
+            if !store.logs()?.contains_key(device) {
+                return Ok((404, Vec::new()));
+            }
+            let from = request.query.get("from").and_then(|from| from.parse().ok()).unwrap_or(0);
+            Ok((200, store.read_log(device, from)?))
+        }
+        "POST" | "PUT" => {
+            // Only whole entries are stored, so a dropped connection can't leave a log that
+            // every reader chokes on.
+            if split_entries(&request.body).1 != request.body.len() {
+                return Ok((400, b"body is not a sequence of whole log entries".to_vec()));
+            }
+            if request.method == "POST" {
+                store.append_log(device, &request.body)?;
+            } else {
+                store.replace_log(device, &request.body)?;
+            }
+            Ok((200, Vec::new()))
+        }
+        _ => Ok((405, Vec::new())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn public_addresses_need_a_token() {
+        let dir = std::env::temp_dir().join(format!("password-manager-test-{}", uuid::Uuid::new_v4().simple()));
+        let args: Vec<String> = ["--listen", "0.0.0.0:0", "--dir", &dir.display().to_string(), "--token", ""]
+            .iter()
+            .map(|arg| arg.to_string())
+            .collect();
+        let error = run(&args).unwrap_err().to_string();
+        assert!(error.contains("without a token"), "{}", error);
+        assert!(!dir.exists());
+    }
+
+    #[test]
+    fn reads_the_head_then_the_announced_body() {
+        let data = b"POST /v1/logs/laptop?from=4 HTTP/1.1\r\nAuthorization: Bearer secret\r\nContent-Length: 5\r\n\r\nhello, and more";
+        let mut reader = BufReader::new(&data[..]);
+        let mut request = read_head(&mut reader).unwrap();
+        assert_eq!((request.method.as_str(), request.path.as_str()), ("POST", "/v1/logs/laptop"));
+        assert_eq!(request.query.get("from").map(String::as_str), Some("4"));
+        assert!(request.body.is_empty());
+        assert!(authorized(&request, "secret"));
+        assert!(!authorized(&request, "other"));
+        read_body(&mut reader, &mut request).unwrap();
+        assert_eq!(request.body, b"hello");
+    }
+
+    #[test]
+    fn rejects_oversized_and_cut_off_bodies() {
+        let data = format!("PUT /v1/key HTTP/1.1\r\nContent-Length: {}\r\n\r\n", MAX_BODY + 1);
+        assert!(read_head(&mut BufReader::new(data.as_bytes())).is_err());
+
+        let data = b"PUT /v1/key HTTP/1.1\r\nContent-Length: 100\r\n\r\nshort";
+        let mut reader = BufReader::new(&data[..]);
+        let mut request = read_head(&mut reader).unwrap();
+        assert!(read_body(&mut reader, &mut request).is_err());
+    }
+}
@@ -0,0 +1,256 @@
+use crate::config::Settings;
+use crate::crypto::CryptoService;
+use crate::manager::PasswordManager;
+use crate::models::{Credential, Role};
+use crate::sharing::{self, Member, SharedCollection};
+use std::path::Path;
+
+const USAGE: &str = "Usage: password-manager share <key|create|update|add-member|remove-member|set-role|show|import> ...";
+
+/// Runs `password-manager share <operation> ...`.
+pub fn run(args: &[String], settings: &Settings) -> Result<(), Box<dyn std::error::Error>> {
+    let operation = args.first().ok_or(USAGE)?;
+    if !["key", "create", "update", "add-member", "remove-member", "set-role", "show", "import"].contains(&operation.as_str()) {
+        return Err(USAGE.into());
+    }
+    let args = &args[1..];
+    let manager = crate::cli::unlock_manager(settings, "share")?;
+    let secret = manager.sharing_key()?;
+    let me = sharing::encode_public_key(&CryptoService::x25519_public_key(&secret)?);
+    match operation.as_str() {
+        "key" => {
+            println!("{}", me);
+            Ok(())
+        }
+        "create" => create(&manager, &secret, args),
+        "update" => {
+            let path = file_argument(args)?;
+            let collection = SharedCollection::load(path)?;
+            let opened = collection.open(&secret)?;
+            require_role(&collection, &me, Role::CanEdit)?;
+            let credentials = tagged(&manager, &collection.tag)?;
+            let count = credentials.len();
+            reseal(&collection, &opened.key, collection.key_generation, collection.members.clone(), &credentials, &secret)?
+                .save(path)?;
+            println!("Updated '{}' with {} credentials tagged '{}'.", collection.name, count, collection.tag);
+            Ok(())
+        }
+        "add-member" => {
+            let (path, name, key, role) = match args {
+                [path, name, key] => (Path::new(path), name, key, Role::ReadOnly),
+                [path, name, key, role] => (Path::new(path), name, key, Role::parse(role)?),
+                _ => return Err("Usage: password-manager share add-member <FILE> <NAME> <PUBLIC-KEY> [ROLE]".into()),
+            };
+            let member = parse_member(name, key, role)?;
+            let collection = SharedCollection::load(path)?;
+            let opened = collection.open(&secret)?;
+            require_role(&collection, &me, Role::Admin)?;
+            if let Some(existing) = collection.members.iter().find(|m| m.name == member.name || m.public_key == member.public_key) {
+                return Err(format!("'{}' is already a member", existing.name).into());
+            }
+            let mut members = collection.members.clone();
+            members.push(member.clone());
+            rekey(&collection, members, &opened.credentials, &secret)?.save(path)?;
+            println!("Added {} to '{}' as {} and re-keyed it.", member.name, collection.name, member.role.name());
+            Ok(())
+        }
+        "remove-member" => {
+            let (path, name) = match args {
+                [path, name] => (Path::new(path), name),
+                _ => return Err("Usage: password-manager share remove-member <FILE> <NAME>".into()),
+            };
+            let collection = SharedCollection::load(path)?;
+            let opened = collection.open(&secret)?;
+            require_role(&collection, &me, Role::Admin)?;
+            let removed = collection.members.iter().find(|m| &m.name == name).ok_or_else(|| format!("'{}' is not a member", name))?;
+            if removed.public_key == me {
+                return Err("You can't remove yourself; ask another member to do it".into());
+            }
+            let members = collection.members.iter().filter(|m| &m.name != name).cloned().collect();
+            rekey(&collection, members, &opened.credentials, &secret)?.save(path)?;
+            println!("Removed {} from '{}' and re-keyed it.", name, collection.name);
+            println!("They can still read copies they already have; change the secrets they knew.");
+            Ok(())
+        }
+        "set-role" => {
+            let (path, name, role) = match args {
+                [path, name, role] => (Path::new(path), name, Role::parse(role)?),
+                _ => return Err("Usage: password-manager share set-role <FILE> <NAME> <ROLE>".into()),
+            };
+            let collection = SharedCollection::load(path)?;
+            let opened = collection.open(&secret)?;
+            require_role(&collection, &me, Role::Admin)?;
+            let mut members = collection.members.clone();
+            let member = members.iter_mut().find(|m| &m.name == name).ok_or_else(|| format!("'{}' is not a member", name))?;
+            member.role = role;
+            if !members.iter().any(|m| m.role == Role::Admin) {
+                return Err("A collection needs at least one admin".into());
+            }
+            reseal(&collection, &opened.key, collection.key_generation, members, &opened.credentials, &secret)?.save(path)?;
+            println!("{} is now {} in '{}'.", name, role.name(), collection.name);
+            Ok(())
+        }
+        "show" => {
+            let collection = SharedCollection::load(file_argument(args)?)?;
+            let opened = collection.open(&secret)?;
+            println!("{} (tag '{}'), written by {} ({})", collection.name, collection.tag, opened.writer.name, collection.writer);
+            println!("Members:");
+            for member in &collection.members {
+                println!("  {:<16} {:<10} {}", member.name, member.role.name(), member.public_key);
+            }
+            println!("Credentials:");
+            for credential in &opened.credentials {
+                println!("  {} - {}", credential.service, credential.username);
+            }
+            Ok(())
+        }
+        "import" => {
+            let (path, from) = match args {
+                [path] => (Path::new(path), None),
+                [path, flag, key] if flag == "--from" => (Path::new(path), Some(sharing::encode_public_key(&sharing::decode_public_key(key)?))),
+                _ => return Err("Usage: password-manager share import <FILE> [--from <KEY>]".into()),
+            };
+            let collection = SharedCollection::load(path)?;
+            let opened = collection.open(&secret)?;
+            let total = opened.credentials.len();
+            let changed = manager.import_shared(&collection, opened.credentials, from.as_deref())?;
+            println!(
+                "Imported '{}' written by {}: {} credentials, {} new or changed.",
+                collection.name, opened.writer.name, total, changed
+            );
+            Ok(())
+        }
+        _ => Err(USAGE.into()),
+    }
+}
+
+/// `share create <FILE> --name <NAME> [--tag <TAG>] [--as <NAME>] [--member <NAME>[:<ROLE>]=<KEY>]...`
+fn create(manager: &PasswordManager, secret: &[u8], args: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+    let path = Path::new(args.first().ok_or("Usage: password-manager share create <FILE> --name <NAME> [--tag <TAG>] [--as <NAME>] [--member <NAME>[:<ROLE>]=<KEY>]...")?);
+    if path.exists() {
+        return Err(format!("{} already exists; use 'share update' to change it", path.display()).into());
+    }
+    let mut name = String::new();
+    let mut tag = String::new();
+    let mut own_name = std::env::var("USER").unwrap_or_else(|_| "me".to_string());
+    let mut others = Vec::new();
+    let mut iter = args[1..].iter();
+    while let Some(arg) = iter.next() {
+        let mut value = || iter.next().cloned().ok_or_else(|| format!("{} needs a value", arg));
+        match arg.as_str() {
+            "--name" => name = value()?,
+            "--tag" => tag = value()?,
+            "--as" => own_name = value()?,
+            "--member" => {
+                let value = value()?;
+                let (member, key) = value.split_once('=').ok_or("--member takes <NAME>[:<ROLE>]=<PUBLIC-KEY>")?;
+                let (member_name, role) = match member.split_once(':') {
+                    Some((member_name, role)) => (member_name, Role::parse(role)?),
+                    None => (member, Role::ReadOnly),
+                };
+                others.push(parse_member(member_name, key, role)?);
+            }
+            other => return Err(format!("Unknown share option '{}'", other).into()),
+        }
+    }
+    if name.trim().is_empty() {
+        return Err("A shared collection needs a --name".into());
+    }
+    if tag.trim().is_empty() {
+        tag = name.clone();
+    }
+    let mut members = vec![parse_member(&own_name, &sharing::encode_public_key(&CryptoService::x25519_public_key(secret)?), Role::Admin)?];
+    for member in others {
+        if members.iter().any(|m| m.name == member.name || m.public_key == member.public_key) {
+            return Err(format!("'{}' is listed twice", member.name).into());
+        }
+        members.push(member);
+    }
+    let credentials = tagged(manager, &tag)?;
+    let collection = SharedCollection::seal(
+        uuid::Uuid::new_v4().to_string(),
+        name.trim().to_string(),
+        tag.trim().to_string(),
+        1,
+        &CryptoService::generate_key(),
+        members,
+        &credentials,
+        secret,
+    )?;
+    collection.save(path)?;
+    println!(
+        "Created '{}' with {} credentials tagged '{}' for {} members.",
+        collection.name,
+        credentials.len(),
+        collection.tag,
+        collection.members.len()
+    );
+    Ok(())
+}
+
+fn file_argument(args: &[String]) -> Result<&Path, Box<dyn std::error::Error>> {
+    match args {
+        [path] => Ok(Path::new(path)),
+        _ => Err("Expected the collection file".into()),
+    }
+}
+
+fn parse_member(name: &str, key: &str, role: Role) -> Result<Member, Box<dyn std::error::Error>> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err("Member names can't be empty".into());
+    }
+    let public_key = sharing::encode_public_key(&sharing::decode_public_key(key)?);
+    Ok(Member { name: name.to_string(), public_key, role })
+}
+
+/// Fails unless this vault's member has at least `role`. Other members would refuse the result anyway.
+fn require_role(collection: &SharedCollection, me: &str, role: Role) -> Result<(), Box<dyn std::error::Error>> {
+    match collection.roster().role_of(me) {
+        Some(own) if own >= role => Ok(()),
+        Some(own) => Err(format!("You are {} in '{}'; this needs {}", own.name(), collection.name, role.name()).into()),
+        None => Err("You are not a member of this collection".into()),
+    }
+}
+
+/// The credentials in this vault tagged `tag`, ready to share.
+fn tagged(manager: &PasswordManager, tag: &str) -> Result<Vec<Credential>, Box<dyn std::error::Error>> {
+    let mut credentials: Vec<Credential> = manager
+        .get_credentials()?
+        .iter()
+        .filter(|c| c.tags.iter().any(|t| t.eq_ignore_ascii_case(tag)))
+        .map(sharing::shareable)
+        .collect();
+    credentials.sort_by_key(|c| c.service.to_lowercase());
+    Ok(credentials)
+}
+
+fn reseal(
+    collection: &SharedCollection,
+    key: &[u8],
+    key_generation: u64,
+    members: Vec<Member>,
+    credentials: &[Credential],
+    secret: &[u8],
+) -> Result<SharedCollection, Box<dyn std::error::Error>> {
+    SharedCollection::seal(
+        collection.id.clone(),
+        collection.name.clone(),
+        collection.tag.clone(),
+        key_generation,
+        key,
+        members,
+        credentials,
+        secret,
+    )
+}
+
+/// Writes the collection under a new key, after the members changed.
+fn rekey(
+    collection: &SharedCollection,
+    members: Vec<Member>,
+    credentials: &[Credential],
+    secret: &[u8],
+) -> Result<SharedCollection, Box<dyn std::error::Error>> {
+    reseal(collection, &CryptoService::generate_key(), collection.key_generation + 1, members, credentials, secret)
+}
@@ -0,0 +1,37 @@
+use crate::config::Settings;
+use crate::lockout;
+use chrono::Utc;
+
+const USAGE: &str = "Usage: password-manager unlock-attempts [show|reset]";
+
+/// Runs `password-manager unlock-attempts`: shows the failed unlocks since the last successful
+/// one, which needs no password, or unlocks the vault to clear them and leave read-only mode.
+pub fn run(args: &[String], settings: &Settings) -> Result<(), Box<dyn std::error::Error>> {
+    match args {
+        [] => show(settings),
+        [operation] if operation == "show" => show(settings),
+        [operation] if operation == "reset" => {
+            let mut manager = crate::cli::unlock_manager(settings, "unlock-attempts")?;
+            manager.reset_failed_unlocks()?;
+            println!("Cleared the failed unlock attempts; the vault can be changed again.");
+            Ok(())
+        }
+        _ => Err(USAGE.into()),
+    }
+}
+
+fn show(settings: &Settings) -> Result<(), Box<dyn std::error::Error>> {
+    let manager = crate::cli::open_manager(settings, "unlock-attempts")?;
+    let failed = manager.failed_unlocks()?;
+    println!("Failed attempts since the last unlock: {}", failed.count);
+    if let Some(last) = failed.last {
+        println!("Last failed attempt: {}", last.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M:%S"));
+    }
+    if let Some(wait) = lockout::retry_after(&failed, Utc::now()) {
+        println!("The next attempt is allowed in {} seconds.", wait.as_secs() + 1);
+    }
+    if failed.read_only {
+        println!("The vault is read-only; run 'password-manager unlock-attempts reset' to allow changes again.");
+    }
+    Ok(())
+}
@@ -0,0 +1,305 @@
+use std::io::Write;
+use std::process::{Command, Stdio};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+use zeroize::Zeroizing;
+
+/// The mechanisms tried, in order, to reach the system clipboard.
+enum Backend {
+    Arboard(arboard::Clipboard),
+    WlCopy,
+    Xclip,
+    /// A clipboard that only exists in the test.
+    #[cfg(test)]
+    Fake(Arc<Mutex<String>>),
+}
+
+impl Backend {
+    fn name(&self) -> &'static str {
+        match self {
+            Backend::Arboard(_) => "arboard",
+            Backend::WlCopy => "wl-copy",
+            Backend::Xclip => "xclip",
+            #[cfg(test)]
+            Backend::Fake(_) => "fake",
+        }
+    }
+
+    /// Whether copies are marked for clipboard managers to leave out of their history.
+    /// `wl-copy` and `xclip` offer a single type per copy, so there is no room for the hint.
+    fn excludes_from_history(&self) -> bool {
+        matches!(self, Backend::Arboard(_))
+    }
+
+    fn set(&mut self, text: &str) -> Result<(), Box<dyn std::error::Error>> {
+        match self {
+            Backend::Arboard(clipboard) => set_excluded_from_history(clipboard, text),
+            Backend::WlCopy => pipe_to_command("wl-copy", &[], text),
+            Backend::Xclip => pipe_to_command("xclip", &["-selection", "clipboard"], text),
+            #[cfg(test)]
+            Backend::Fake(clipboard) => {
+                *clipboard.lock().unwrap() = text.to_string();
+                Ok(())
+            }
+        }
+    }
+
+    fn get(&mut self) -> Result<String, Box<dyn std::error::Error>> {
+        match self {
+            Backend::Arboard(clipboard) => Ok(clipboard.get_text()?),
+            Backend::WlCopy => read_from_command("wl-paste", &["--no-newline"]),
+            Backend::Xclip => read_from_command("xclip", &["-selection", "clipboard", "-o"]),
+            #[cfg(test)]
+            Backend::Fake(clipboard) => Ok(clipboard.lock().unwrap().clone()),
+        }
+    }
+
+    fn clear(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        match self {
+            Backend::Arboard(clipboard) => Ok(clipboard.clear()?),
+            Backend::WlCopy => run_command("wl-copy", &["--clear"]),
+            Backend::Xclip => pipe_to_command("xclip", &["-selection", "clipboard"], ""),
+            #[cfg(test)]
+            Backend::Fake(clipboard) => {
+                clipboard.lock().unwrap().clear();
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Marks the copy so clipboard managers that follow the platform convention leave it out of their history.
+#[cfg(all(unix, not(any(target_os = "macos", target_os = "android", target_os = "emscripten"))))]
+fn set_excluded_from_history(clipboard: &mut arboard::Clipboard, text: &str) -> Result<(), Box<dyn std::error::Error>> {
+    use arboard::SetExtLinux;
+    Ok(clipboard.set().exclude_from_history().text(text.to_owned())?)
+}
+
+#[cfg(windows)]
+fn set_excluded_from_history(clipboard: &mut arboard::Clipboard, text: &str) -> Result<(), Box<dyn std::error::Error>> {
+    use arboard::SetExtWindows;
+    Ok(clipboard.set().exclude_from_history().text(text.to_owned())?)
+}
+
+#[cfg(target_os = "macos")]
+fn set_excluded_from_history(clipboard: &mut arboard::Clipboard, text: &str) -> Result<(), Box<dyn std::error::Error>> {
+    use arboard::SetExtApple;
+    Ok(clipboard.set().exclude_from_history().text(text.to_owned())?)
+}
+
+fn run_command(program: &str, args: &[&str]) -> Result<(), Box<dyn std::error::Error>> {
+    let status = Command::new(program)
+        .args(args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(format!("{} exited with {}", program, status).into())
+    }
+}
+
+fn pipe_to_command(program: &str, args: &[&str], text: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let mut child = Command::new(program)
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()?;
+    child.stdin.take().ok_or("Could not open stdin")?.write_all(text.as_bytes())?;
+    let status = child.wait()?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(format!("{} exited with {}", program, status).into())
+    }
+}
+
+fn read_from_command(program: &str, args: &[&str]) -> Result<String, Box<dyn std::error::Error>> {
+    let output = Command::new(program).args(args).stdin(Stdio::null()).stderr(Stdio::null()).output()?;
+    if output.status.success() {
+        Ok(String::from_utf8_lossy(&output.stdout).to_string())
+    } else {
+        Err(format!("{} exited with {}", program, output.status).into())
+    }
+}
+
+/// How a copy reached the clipboard.
+pub struct Copied {
+    pub backend: &'static str,
+    /// Whether clipboard managers that follow the platform convention keep it out of their history.
+    pub excluded_from_history: bool,
+}
+
+/// The clipboard backend together with what was last copied through it.
+#[derive(Default)]
+struct State {
+    backend: Option<Backend>,
+    /// Counts copies, so a timer can tell that a newer copy has replaced its value.
+    generation: u64,
+    /// The last copied value, until it is cleared.
+    copied: Option<Zeroizing<String>>,
+}
+
+impl State {
+    /// Clears the last copy if it is copy number `generation`, or whichever it is for `None`.
+    /// The clipboard is only cleared if it still holds that copy.
+    fn clear(&mut self, generation: Option<u64>) {
+        // A newer copy owns the clipboard now and has its own timer.
+        if generation.is_some_and(|generation| generation != self.generation) {
+            return;
+        }
+        let (Some(copied), Some(backend)) = (self.copied.take(), self.backend.as_mut()) else {
+            return;
+        };
+        let current = backend.get().map(Zeroizing::new);
+        if matches!(&current, Ok(current) if **current == *copied) {
+            let _ = backend.clear();
+        }
+    }
+}
+
+/// Copies secrets to the system clipboard and clears them again after a timeout.
+///
+/// The first backend that works is remembered. The clipboard is only cleared if it still holds
+/// what we put there, so anything the user copied in the meantime is left alone.
+pub struct ClipboardService {
+    state: Arc<Mutex<State>>,
+}
+
+impl ClipboardService {
+    pub fn new() -> Self {
+        Self { state: Arc::new(Mutex::new(State::default())) }
+    }
+
+    /// Copies `text` and schedules it to be cleared after `clear_after`.
+    pub fn copy(&self, text: &str, clear_after: Duration) -> Result<Copied, Box<dyn std::error::Error>> {
+        let mut state = self.state.lock().map_err(|_| "Clipboard lock poisoned")?;
+        let backend = match state.backend.take() {
+            Some(mut backend) => match backend.set(text) {
+                Ok(()) => backend,
+                // The remembered backend stopped working.
+                Err(_) => Self::connect(text)?,
+            },
+            None => Self::connect(text)?,
+        };
+        let copied = Copied { backend: backend.name(), excluded_from_history: backend.excludes_from_history() };
+        state.backend = Some(backend);
+        state.generation += 1;
+        state.copied = Some(Zeroizing::new(text.to_string()));
+        let generation = state.generation;
+        drop(state);
+
+        let shared_state = Arc::clone(&self.state);
+        thread::spawn(move || {
+            thread::sleep(clear_after);
+            if let Ok(mut state) = shared_state.lock() {
+                state.clear(Some(generation));
+            }
+        });
+        Ok(copied)
+    }
+
+    /// Clears the last copy now if the clipboard still holds it. `wl-copy` and `xclip` keep
+    /// serving a copy after the program exits, so this runs on the way out.
+    pub fn clear_now(&self) {
+        if let Ok(mut state) = self.state.lock() {
+            state.clear(None);
+        }
+    }
+
+    /// Finds the first backend that can set the clipboard, setting `text` on the way.
+    fn connect(text: &str) -> Result<Backend, Box<dyn std::error::Error>> {
+        let mut errors = Vec::new();
+        let mut candidates = vec![Backend::WlCopy, Backend::Xclip];
+        match arboard::Clipboard::new() {
+            Ok(clipboard) => candidates.insert(0, Backend::Arboard(clipboard)),
+            Err(e) => errors.push(format!("arboard: {}", e)),
+        }
+        for mut backend in candidates {
+            match backend.set(text) {
+                Ok(()) => return Ok(backend),
+                Err(e) => errors.push(format!("{}: {}", backend.name(), e)),
+            }
+        }
+        Err(format!("No clipboard available ({})", errors.join("; ")).into())
+    }
+}
+
+impl Default for ClipboardService {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fake_service() -> (ClipboardService, Arc<Mutex<String>>) {
+        let clipboard = Arc::new(Mutex::new(String::new()));
+        let service = ClipboardService::new();
+        service.state.lock().unwrap().backend = Some(Backend::Fake(Arc::clone(&clipboard)));
+        (service, clipboard)
+    }
+
+    fn wait_for(clipboard: &Mutex<String>, expected: &str) {
+        for _ in 0..200 {
+            if *clipboard.lock().unwrap() == expected {
+                return;
+            }
+            thread::sleep(Duration::from_millis(5));
+        }
+        panic!("clipboard holds {:?}, not {:?}", clipboard.lock().unwrap(), expected);
+    }
+
+    #[test]
+    fn copies_are_cleared_after_the_timeout() {
+        let (service, clipboard) = fake_service();
+        let copied = service.copy("hunter2", Duration::from_millis(20)).unwrap();
+        assert_eq!(copied.backend, "fake");
+        assert!(!copied.excluded_from_history);
+        assert_eq!(*clipboard.lock().unwrap(), "hunter2");
+        wait_for(&clipboard, "");
+    }
+
+    #[test]
+    fn content_copied_elsewhere_is_left_alone() {
+        let (service, clipboard) = fake_service();
+        service.copy("hunter2", Duration::from_secs(60)).unwrap();
+        *clipboard.lock().unwrap() = "copied by the user".to_string();
+
+        service.state.lock().unwrap().clear(Some(1));
+        assert_eq!(*clipboard.lock().unwrap(), "copied by the user");
+    }
+
+    #[test]
+    fn an_older_timer_leaves_a_newer_copy_alone() {
+        let (service, clipboard) = fake_service();
+        service.copy("first", Duration::from_secs(60)).unwrap();
+        service.copy("second", Duration::from_secs(60)).unwrap();
+
+        // The first copy's timer fires; the second copy is still on the clipboard.
+        service.state.lock().unwrap().clear(Some(1));
+        assert_eq!(*clipboard.lock().unwrap(), "second");
+
+        service.state.lock().unwrap().clear(Some(2));
+        assert_eq!(*clipboard.lock().unwrap(), "");
+    }
+
+    #[test]
+    fn clear_now_clears_the_last_copy_before_its_timer() {
+        let (service, clipboard) = fake_service();
+        service.copy("hunter2", Duration::from_secs(60)).unwrap();
+        service.clear_now();
+        assert_eq!(*clipboard.lock().unwrap(), "");
+
+        // Once cleared, the user's own copies are safe from a late clear.
+        *clipboard.lock().unwrap() = "hunter2".to_string();
+        service.clear_now();
+        assert_eq!(*clipboard.lock().unwrap(), "hunter2");
+    }
+}
@@ -0,0 +1,311 @@
+use crate::crypto::DEFAULT_KDF_ITERATIONS;
+use crate::keyfile::KeyFiles;
+use crate::lockout::LockoutSettings;
+use crate::storage::VaultSettings;
+use crate::sync::SyncSettings;
+use crate::ui::keymap::{KeyOverrides, Keymap};
+use crate::ui::theme::ThemeRegistry;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// User preferences, read from `config.toml` in the config directory.
+///
+/// Every key is optional; missing keys take their default, so a partial file is fine.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Settings {
+    pub theme: String,
+    /// Seconds without input before the vault locks itself.
+    pub inactivity_timeout_secs: u64,
+    /// Seconds before a copied value is cleared from the clipboard.
+    pub clipboard_timeout_secs: u64,
+    /// Where export (`E`) writes to and import (`I`) reads from.
+    pub export_path: String,
+    /// PBKDF2 iterations for the master key. A change is applied by re-keying the vault at the next unlock.
+    pub kdf_iterations: u32,
+    /// Key file given with the master password, for vaults that need one; see `composite-key`.
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub key_file: String,
+    /// File holding the challenge-response secret, for vaults that need one.
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub challenge_response_file: String,
+    /// Key binding preset: `default`, `vim` or `emacs`.
+    pub keymap: String,
+    /// Show the selected entry next to the list on wide terminals.
+    pub preview_pane: bool,
+    /// Storage backend and location of the vault, e.g. `[vault] backend = "file"`.
+    pub vault: VaultSettings,
+    /// Shared folder or sync server to sync the vault through, e.g. `[sync] server = "http://127.0.0.1:8750"`.
+    pub sync: SyncSettings,
+    /// What happens after too many failed unlocks, e.g. `[lockout] after = 10` and `action = "read-only"`.
+    pub lockout: LockoutSettings,
+    /// Per-screen overrides on top of the preset, e.g. `[keys.main] export = "X"`.
+    #[serde(skip_serializing_if = "KeyOverrides::is_empty")]
+    pub keys: KeyOverrides,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            theme: "dark".to_string(),
+            inactivity_timeout_secs: 5 * 60,
+            clipboard_timeout_secs: 30,
+            export_path: "vault_export.json".to_string(),
+            kdf_iterations: DEFAULT_KDF_ITERATIONS,
+            key_file: String::new(),
+            challenge_response_file: String::new(),
+            keymap: "default".to_string(),
+            preview_pane: true,
+            vault: VaultSettings::default(),
+            sync: SyncSettings::default(),
+            lockout: LockoutSettings::default(),
+            keys: KeyOverrides::new(),
+        }
+    }
+}
+
+pub const INACTIVITY_TIMEOUT_RANGE: std::ops::RangeInclusive<u64> = 30..=24 * 60 * 60;
+pub const CLIPBOARD_TIMEOUT_RANGE: std::ops::RangeInclusive<u64> = 5..=10 * 60;
+/// The settings file isn't encrypted, so its lowest cost is the default: anyone who can edit it
+/// must not be able to have the next unlock re-key the vault at a weaker cost.
+pub const KDF_ITERATIONS_RANGE: std::ops::RangeInclusive<u32> = DEFAULT_KDF_ITERATIONS..=10_000_000;
+
+impl Settings {
+    /// The default location, `<config dir>/password_manager/config.toml`.
+    pub fn default_path() -> PathBuf {
+        dirs::config_dir()
+            .unwrap_or_else(|| PathBuf::from("."))
+            .join("password_manager")
+            .join("config.toml")
+    }
+
+    /// Reads and validates the settings file. A missing file yields the defaults.
+    pub fn load(path: &Path, themes: &ThemeRegistry) -> Result<Self, Box<dyn std::error::Error>> {
+        let text = match std::fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(e) => return Err(format!("Could not read {}: {}", path.display(), e).into()),
+        };
+        let settings: Settings = toml::from_str(&text).map_err(|e| {
+            let line = e.span().map(|span| text[..span.start].lines().count().max(1)).unwrap_or(1);
+            format!("Invalid settings in {} (line {}): {}", path.display(), line, e.message())
+        })?;
+        settings
+            .validate(themes)
+            .map_err(|e| format!("Invalid settings in {}: {}", path.display(), e))?;
+        Ok(settings)
+    }
+
+    pub fn save(&self, path: &Path, themes: &ThemeRegistry) -> Result<(), Box<dyn std::error::Error>> {
+        self.validate(themes)?;
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        std::fs::write(path, toml::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    pub fn validate(&self, themes: &ThemeRegistry) -> Result<(), String> {
+        if themes.get(&self.theme).is_none() {
+            return Err(format!("unknown theme '{}' (available: {})", self.theme, themes.names().join(", ")));
+        }
+        if !INACTIVITY_TIMEOUT_RANGE.contains(&self.inactivity_timeout_secs) {
+            return Err(format!(
+                "inactivity_timeout_secs must be between {} and {}",
+                INACTIVITY_TIMEOUT_RANGE.start(),
+                INACTIVITY_TIMEOUT_RANGE.end()
+            ));
+        }
+        if !CLIPBOARD_TIMEOUT_RANGE.contains(&self.clipboard_timeout_secs) {
+            return Err(format!(
+                "clipboard_timeout_secs must be between {} and {}",
+                CLIPBOARD_TIMEOUT_RANGE.start(),
+                CLIPBOARD_TIMEOUT_RANGE.end()
+            ));
+        }
+        if self.export_path.trim().is_empty() {
+            return Err("export_path must not be empty".to_string());
+        }
+        if !KDF_ITERATIONS_RANGE.contains(&self.kdf_iterations) {
+            return Err(format!(
+                "kdf_iterations must be between {} and {}",
+                KDF_ITERATIONS_RANGE.start(),
+                KDF_ITERATIONS_RANGE.end()
+            ));
+        }
+        self.sync.validate()?;
+        self.lockout.validate()?;
+        Keymap::build(&self.keymap, &self.keys)?;
+        Ok(())
+    }
+
+    /// Sets one setting from its text form, as typed on the command line or the settings screen.
+    pub fn set(&mut self, key: &str, value: &str) -> Result<(), String> {
+        let value = value.trim();
+        let number = |name: &str| format!("{} must be a whole number", name);
+        match key {
+            "theme" => self.theme = value.to_string(),
+            "inactivity_timeout_secs" => {
+                self.inactivity_timeout_secs = value.parse().map_err(|_| number(key))?
+            }
+            "clipboard_timeout_secs" => self.clipboard_timeout_secs = value.parse().map_err(|_| number(key))?,
+            "export_path" => self.export_path = value.to_string(),
+            "kdf_iterations" => self.kdf_iterations = value.parse().map_err(|_| number(key))?,
+            "key_file" => self.key_file = value.to_string(),
+            "challenge_response_file" => self.challenge_response_file = value.to_string(),
+            "keymap" => self.keymap = value.to_string(),
+            "preview_pane" => {
+                self.preview_pane = match value {
+                    "true" | "on" | "yes" => true,
+                    "false" | "off" | "no" => false,
+                    _ => return Err(format!("{} must be true or false", key)),
+                }
+            }
+            _ => return Err(format!("unknown setting '{}'", key)),
+        }
+        Ok(())
+    }
+
+    /// The text form of one setting, the inverse of [`Settings::set`].
+    pub fn get(&self, key: &str) -> String {
+        match key {
+            "theme" => self.theme.clone(),
+            "inactivity_timeout_secs" => self.inactivity_timeout_secs.to_string(),
+            "clipboard_timeout_secs" => self.clipboard_timeout_secs.to_string(),
+            "export_path" => self.export_path.clone(),
+            "kdf_iterations" => self.kdf_iterations.to_string(),
+            "key_file" => self.key_file.clone(),
+            "challenge_response_file" => self.challenge_response_file.clone(),
+            "keymap" => self.keymap.clone(),
+            "preview_pane" => self.preview_pane.to_string(),
+            _ => String::new(),
+        }
+    }
+
+    /// The key files to unlock with.
+    pub fn key_files(&self) -> KeyFiles {
+        KeyFiles::new(&self.key_file, &self.challenge_response_file)
+    }
+
+    /// What to write to the settings file after editing `self`: values still as the command line
+    /// set them (`command_line`, as key and value) keep their value from `file`, since command-line
+    /// options apply to one run only.
+    pub fn to_save(&self, file: &Settings, command_line: &[(&'static str, String)]) -> Settings {
+        let mut saved = self.clone();
+        for (key, value) in command_line {
+            if saved.get(key) == *value {
+                // The file's value was valid when it was loaded.
+                let _ = saved.set(key, &file.get(key));
+            }
+        }
+        saved
+    }
+}
+
+/// The setting keys in the order the settings screen lists them, with a short description.
+pub const SETTING_KEYS: [(&str, &str); 9] = [
+    ("theme", "Color theme"),
+    ("keymap", "Key bindings: default, vim or emacs"),
+    ("preview_pane", "Preview the selected entry beside the list"),
+    ("inactivity_timeout_secs", "Lock after this many idle seconds"),
+    ("clipboard_timeout_secs", "Clear copied values after this many seconds"),
+    ("export_path", "File used by export (E) and import (I)"),
+    ("kdf_iterations", "PBKDF2 iterations, applied at the next unlock"),
+    ("key_file", "Key file to unlock with; empty for none"),
+    ("challenge_response_file", "Challenge-response secret file; empty for none"),
+];
+
+/// Global options that override the settings file for one run.
+#[derive(Debug, Default)]
+pub struct Overrides {
+    pub config_path: Option<PathBuf>,
+    pub values: Vec<(String, String)>,
+}
+
+impl Overrides {
+    /// Pulls `--config PATH` and `--<setting> VALUE` options off the front of the arguments,
+    /// returning them together with the remaining arguments (program name first).
+    pub fn parse(args: &[String]) -> Result<(Self, Vec<String>), Box<dyn std::error::Error>> {
+        let mut overrides = Overrides::default();
+        let mut rest = args.iter().take(1).cloned().collect::<Vec<_>>();
+        let mut iter = args.iter().skip(1);
+        while let Some(arg) = iter.next() {
+            let option = match arg.strip_prefix("--") {
+                Some(option) if !option.is_empty() && option != "help" => option,
+                _ => {
+                    rest.push(arg.clone());
+                    rest.extend(iter.cloned());
+                    break;
+                }
+            };
+            let (name, inline_value) = match option.split_once('=') {
+                Some((name, value)) => (name, Some(value.to_string())),
+                None => (option, None),
+            };
+            let value = match inline_value {
+                Some(value) => value,
+                None => iter.next().cloned().ok_or_else(|| format!("--{} needs a value", name))?,
+            };
+            let key = name.replace('-', "_");
+            if key == "config" {
+                overrides.config_path = Some(PathBuf::from(value));
+            } else if let Some((key, _)) = SETTING_KEYS.iter().find(|(k, _)| *k == key || k.trim_end_matches("_secs") == key) {
+                overrides.values.push((key.to_string(), value));
+            } else {
+                return Err(format!("Unknown option --{}. Run with --help for usage.", name).into());
+            }
+        }
+        Ok((overrides, rest))
+    }
+
+    pub fn config_path(&self) -> PathBuf {
+        self.config_path.clone().unwrap_or_else(Settings::default_path)
+    }
+
+    /// Applies the overrides on top of the settings loaded from the file, validating the result.
+    pub fn apply(&self, file: &Settings, themes: &ThemeRegistry) -> Result<Settings, Box<dyn std::error::Error>> {
+        let mut settings = file.clone();
+        for (key, value) in &self.values {
+            settings.set(key, value).map_err(|e| format!("Invalid --{}: {}", key.replace('_', "-"), e))?;
+        }
+        settings.validate(themes)?;
+        Ok(settings)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn command_line_values_are_not_saved_unless_edited() {
+        let themes = ThemeRegistry::default();
+        let file = Settings { clipboard_timeout_secs: 45, ..Settings::default() };
+        let (overrides, rest) =
+            Overrides::parse(&args(&["pm", "--kdf-iterations", "200000", "--clipboard-timeout=10", "audit-log"])).unwrap();
+        assert_eq!(rest, args(&["pm", "audit-log"]));
+        let in_effect = overrides.apply(&file, &themes).unwrap();
+        assert_eq!((in_effect.kdf_iterations, in_effect.clipboard_timeout_secs), (200_000, 10));
+
+        let command_line = vec![("kdf_iterations", "200000".to_string()), ("clipboard_timeout_secs", "10".to_string())];
+        let mut edited = in_effect.clone();
+        edited.clipboard_timeout_secs = 20;
+        edited.inactivity_timeout_secs = 60;
+        let saved = edited.to_save(&file, &command_line);
+        assert_eq!(saved.kdf_iterations, DEFAULT_KDF_ITERATIONS);
+        assert_eq!(saved.clipboard_timeout_secs, 20);
+        assert_eq!(saved.inactivity_timeout_secs, 60);
+    }
+
+    #[test]
+    fn kdf_cost_cannot_go_below_the_default() {
+        let themes = ThemeRegistry::default();
+        let weak = Settings { kdf_iterations: DEFAULT_KDF_ITERATIONS - 1, ..Settings::default() };
+        assert!(weak.validate(&themes).unwrap_err().contains("kdf_iterations"));
+        assert!(Settings::default().validate(&themes).is_ok());
+    }
+}
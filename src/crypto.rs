@@ -1,14 +1,17 @@
-use ring::{aead, pbkdf2, rand::{self, SecureRandom}};
+use ring::{aead, digest, hkdf, hmac, pbkdf2, rand::{self, SecureRandom}};
 use std::num::NonZeroU32;
 
+/// PBKDF2 iterations used by vaults that predate the `kdf_iterations` setting.
+pub const DEFAULT_KDF_ITERATIONS: u32 = 100_000;
+
 pub struct CryptoService;
 
 impl CryptoService {
-    pub fn derive_key(password: &[u8], salt: &[u8]) -> Vec<u8> {
+    pub fn derive_key(password: &[u8], salt: &[u8], iterations: u32) -> Vec<u8> {
         let mut key = [0u8; 32];
         pbkdf2::derive(
             pbkdf2::PBKDF2_HMAC_SHA256,
-            NonZeroU32::new(100_000).unwrap(),
+            NonZeroU32::new(iterations.max(1)).unwrap(),
             salt,
             password,
             &mut key,
@@ -19,6 +22,16 @@ impl CryptoService {
     pub fn encrypt(
         data: &[u8],
         key: &[u8],
+    ) -> Result<(Vec<u8>, Vec<u8>), Box<dyn std::error::Error>> {
+        Self::encrypt_with_aad(data, key, &[])
+    }
+
+    /// Like [`CryptoService::encrypt`], but the ciphertext only decrypts with the same `aad`,
+    /// e.g. the id of the record it is stored under.
+    pub fn encrypt_with_aad(
+        data: &[u8],
+        key: &[u8],
+        aad: &[u8],
     ) -> Result<(Vec<u8>, Vec<u8>), Box<dyn std::error::Error>> {
         let unbound_key = aead::UnboundKey::new(&aead::AES_256_GCM, key)
             .map_err(|_| "Key error")?;
@@ -38,7 +51,7 @@ impl CryptoService {
         let mut in_out = data.to_vec();
         
         // Encrypt and get the tag separately
-        let tag = sealing_key.seal_in_place_separate_tag(nonce, aead::Aad::empty(), &mut in_out)
+        let tag = sealing_key.seal_in_place_separate_tag(nonce, aead::Aad::from(aad), &mut in_out)
             .map_err(|_| "Encryption error")?;
             
         // Append the tag to the ciphertext
@@ -51,6 +64,15 @@ impl CryptoService {
         ciphertext: &[u8],
         nonce: &[u8],
         key: &[u8],
+    ) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        Self::decrypt_with_aad(ciphertext, nonce, key, &[])
+    }
+
+    pub fn decrypt_with_aad(
+        ciphertext: &[u8],
+        nonce: &[u8],
+        key: &[u8],
+        aad: &[u8],
     ) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
         if nonce.len() != 12 {
             return Err("Invalid nonce length".into());
@@ -72,12 +94,72 @@ impl CryptoService {
         let mut in_out = ciphertext.to_vec();
         
         // Decrypt in place
-        let plaintext = opening_key.open_in_place(nonce, aead::Aad::empty(), &mut in_out)
+        let plaintext = opening_key.open_in_place(nonce, aead::Aad::from(aad), &mut in_out)
             .map_err(|_| "Decryption error")?;
             
         Ok(plaintext.to_vec())
     }
 
+    /// HMAC-SHA256 of `data` under a subkey of `key`, so the encryption key is never used for MACs directly.
+    pub fn mac(key: &[u8], data: &[u8]) -> Vec<u8> {
+        hmac::sign(&Self::mac_key(key), data).as_ref().to_vec()
+    }
+
+    pub fn verify_mac(key: &[u8], data: &[u8], tag: &[u8]) -> bool {
+        hmac::verify(&Self::mac_key(key), data, tag).is_ok()
+    }
+
+    fn mac_key(key: &[u8]) -> hmac::Key {
+        let subkey = hmac::sign(&hmac::Key::new(hmac::HMAC_SHA256, key), b"password-manager manifest");
+        hmac::Key::new(hmac::HMAC_SHA256, subkey.as_ref())
+    }
+
+    /// Plain HMAC-SHA256 of `challenge` under `secret`, as a challenge-response token computes it.
+    pub fn challenge_response(secret: &[u8], challenge: &[u8]) -> Vec<u8> {
+        hmac::sign(&hmac::Key::new(hmac::HMAC_SHA256, secret), challenge).as_ref().to_vec()
+    }
+
+    pub fn digest(data: &[u8]) -> Vec<u8> {
+        digest::digest(&digest::SHA256, data).as_ref().to_vec()
+    }
+
+    /// A random 256-bit key, e.g. for a shared collection.
+    pub fn generate_key() -> Vec<u8> {
+        Self::generate_salt()
+    }
+
+    /// A new X25519 private key.
+    pub fn generate_x25519_secret() -> Vec<u8> {
+        Self::generate_key()
+    }
+
+    pub fn x25519_public_key(secret: &[u8]) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        let secret = Self::x25519_secret(secret)?;
+        Ok(x25519_dalek::PublicKey::from(&secret).as_bytes().to_vec())
+    }
+
+    /// An AES key only the holders of `secret` and of the private key behind `public` can derive:
+    /// X25519 followed by HKDF-SHA256, with `info` binding the key to its purpose.
+    pub fn x25519_shared_key(secret: &[u8], public: &[u8], info: &[u8]) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        let secret = Self::x25519_secret(secret)?;
+        let public: [u8; 32] = public.try_into().map_err(|_| "Invalid public key")?;
+        let shared = secret.diffie_hellman(&x25519_dalek::PublicKey::from(public));
+        if !shared.was_contributory() {
+            return Err("Invalid public key".into());
+        }
+        let prk = hkdf::Salt::new(hkdf::HKDF_SHA256, b"password-manager x25519").extract(shared.as_bytes());
+        let info = [info];
+        let okm = prk.expand(&info, hkdf::HKDF_SHA256).map_err(|_| "Key derivation error")?;
+        let mut key = [0u8; 32];
+        okm.fill(&mut key).map_err(|_| "Key derivation error")?;
+        Ok(key.to_vec())
+    }
+
+    fn x25519_secret(secret: &[u8]) -> Result<x25519_dalek::StaticSecret, Box<dyn std::error::Error>> {
+        let secret: [u8; 32] = secret.try_into().map_err(|_| "Invalid private key")?;
+        Ok(x25519_dalek::StaticSecret::from(secret))
+    }
+
     pub fn generate_salt() -> Vec<u8> {
         // Generate salt using SystemRandom
         let mut salt = [0u8; 32];
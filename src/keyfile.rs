@@ -0,0 +1,152 @@
+use crate::crypto::CryptoService;
+use crate::models::KdfParams;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use zeroize::Zeroizing;
+
+/// The files that, together with the master password, unlock a vault that needs them.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct KeyFiles {
+    /// Any file, used byte for byte.
+    pub key_file: Option<PathBuf>,
+    /// A file holding the secret of an HMAC challenge-response, in place of a hardware token.
+    pub challenge_response_file: Option<PathBuf>,
+}
+
+impl KeyFiles {
+    /// Key files from their paths as written in the settings; empty paths mean none.
+    pub fn new(key_file: &str, challenge_response_file: &str) -> Self {
+        let path = |path: &str| Some(path.trim()).filter(|path| !path.is_empty()).map(PathBuf::from);
+        Self { key_file: path(key_file), challenge_response_file: path(challenge_response_file) }
+    }
+
+    /// The secret the master key is derived from, for a vault with `kdf` and `salt`.
+    ///
+    /// For a vault that needs neither file this is the password itself, as before key files.
+    /// Otherwise the SHA-256 digests of the password and the key file and the challenge-response
+    /// secret's answer to the salt are hashed together, the way KeePass builds its composite key.
+    /// The salt changes whenever the vault is re-keyed, and so does the answer.
+    pub fn composite_key(&self, password: &str, kdf: &KdfParams, salt: &[u8]) -> Result<Zeroizing<Vec<u8>>, Box<dyn std::error::Error>> {
+        if !kdf.key_file && !kdf.challenge_response {
+            return Ok(Zeroizing::new(password.as_bytes().to_vec()));
+        }
+        let mut parts = Zeroizing::new(CryptoService::digest(password.as_bytes()));
+        if kdf.key_file {
+            let path = self.key_file.as_ref().ok_or("This vault needs its key file as well as the password")?;
+            parts.extend(CryptoService::digest(&read_secret(path, "key file")?));
+        }
+        if kdf.challenge_response {
+            let path = self
+                .challenge_response_file
+                .as_ref()
+                .ok_or("This vault needs its challenge-response file as well as the password")?;
+            let challenge = [b"password-manager challenge".as_slice(), salt].concat();
+            parts.extend(CryptoService::challenge_response(&read_secret(path, "challenge-response file")?, &challenge));
+        }
+        Ok(Zeroizing::new(CryptoService::digest(&parts)))
+    }
+}
+
+fn read_secret(path: &Path, what: &str) -> Result<Zeroizing<Vec<u8>>, Box<dyn std::error::Error>> {
+    let data = Zeroizing::new(std::fs::read(path).map_err(|e| format!("Could not read the {} {}: {}", what, path.display(), e))?);
+    if data.is_empty() {
+        return Err(format!("The {} {} is empty", what, path.display()).into());
+    }
+    Ok(data)
+}
+
+/// Writes a new random secret, usable as a key file or a challenge-response file. An existing
+/// file is never overwritten, since a vault may need it.
+pub fn generate(path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    let mut file = options.open(path).map_err(|e| format!("Could not create {}: {}", path.display(), e))?;
+    let secret = Zeroizing::new(CryptoService::generate_key());
+    let text: Zeroizing<String> = Zeroizing::new(secret.iter().map(|byte| format!("{:02x}", byte)).collect());
+    writeln!(file, "{}", text.as_str())?;
+    file.sync_all()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_file(contents: &[u8]) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("password-manager-test-{}", uuid::Uuid::new_v4().simple()));
+        std::fs::write(&path, contents).unwrap();
+        path
+    }
+
+    fn kdf(key_file: bool, challenge_response: bool) -> KdfParams {
+        KdfParams { iterations: 1_000, key_file, challenge_response }
+    }
+
+    #[test]
+    fn password_alone_is_used_as_it_is() {
+        let secret = KeyFiles::default().composite_key("correct horse", &kdf(false, false), b"salt").unwrap();
+        assert_eq!(secret.as_slice(), b"correct horse");
+        // Files given for a vault that doesn't need them are left out.
+        let files = KeyFiles { key_file: Some(PathBuf::from("/nonexistent")), challenge_response_file: None };
+        assert_eq!(files.composite_key("correct horse", &kdf(false, false), b"salt").unwrap().as_slice(), b"correct horse");
+    }
+
+    #[test]
+    fn key_file_contents_change_the_key() {
+        let (one, other) = (temp_file(b"first secret"), temp_file(b"second secret"));
+        let with = |path: &PathBuf, password: &str| {
+            let files = KeyFiles { key_file: Some(path.clone()), challenge_response_file: None };
+            files.composite_key(password, &kdf(true, false), b"salt").unwrap()
+        };
+        let key = with(&one, "correct horse");
+        assert_eq!(key.len(), 32);
+        assert_eq!(key, with(&one, "correct horse"));
+        assert_ne!(key, with(&other, "correct horse"));
+        assert_ne!(key, with(&one, "battery staple"));
+        // The salt only matters to the challenge-response.
+        let files = KeyFiles { key_file: Some(one.clone()), challenge_response_file: None };
+        assert_eq!(key, files.composite_key("correct horse", &kdf(true, false), b"other salt").unwrap());
+        std::fs::remove_file(one).unwrap();
+        std::fs::remove_file(other).unwrap();
+    }
+
+    #[test]
+    fn challenge_response_answers_the_salt() {
+        let (secret, key_file) = (temp_file(b"hmac secret"), temp_file(b"key file"));
+        let files = KeyFiles { key_file: Some(key_file.clone()), challenge_response_file: Some(secret.clone()) };
+        let both = files.composite_key("correct horse", &kdf(true, true), b"salt").unwrap();
+        assert_eq!(both, files.composite_key("correct horse", &kdf(true, true), b"salt").unwrap());
+        assert_ne!(both, files.composite_key("correct horse", &kdf(true, true), b"new salt").unwrap());
+        assert_ne!(both, files.composite_key("correct horse", &kdf(true, false), b"salt").unwrap());
+        assert_ne!(both, files.composite_key("correct horse", &kdf(false, true), b"salt").unwrap());
+        std::fs::remove_file(secret).unwrap();
+        std::fs::remove_file(key_file).unwrap();
+    }
+
+    #[test]
+    fn missing_or_empty_files_are_errors() {
+        let needs_key_file = KeyFiles::default().composite_key("correct horse", &kdf(true, false), b"salt");
+        assert!(needs_key_file.unwrap_err().to_string().contains("needs its key file"));
+        let needs_secret = KeyFiles::default().composite_key("correct horse", &kdf(false, true), b"salt");
+        assert!(needs_secret.unwrap_err().to_string().contains("challenge-response file"));
+
+        let empty = temp_file(b"");
+        let files = KeyFiles { key_file: Some(empty.clone()), challenge_response_file: None };
+        assert!(files.composite_key("correct horse", &kdf(true, false), b"salt").unwrap_err().to_string().contains("is empty"));
+        std::fs::remove_file(&empty).unwrap();
+        assert!(files.composite_key("correct horse", &kdf(true, false), b"salt").unwrap_err().to_string().contains("Could not read"));
+    }
+
+    #[test]
+    fn generated_files_are_never_overwritten() {
+        let path = std::env::temp_dir().join(format!("password-manager-test-{}", uuid::Uuid::new_v4().simple()));
+        generate(&path).unwrap();
+        let contents = std::fs::read(&path).unwrap();
+        assert_eq!(contents.len(), 65);
+        assert!(generate(&path).is_err());
+        assert_eq!(std::fs::read(&path).unwrap(), contents);
+        std::fs::remove_file(path).unwrap();
+    }
+}
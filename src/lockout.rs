@@ -0,0 +1,109 @@
+use crate::models::FailedUnlocks;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// Failed unlocks allowed before each further attempt has to wait.
+const FREE_ATTEMPTS: u32 = 2;
+
+/// The longest wait between attempts.
+const MAX_DELAY: Duration = Duration::from_secs(15 * 60);
+
+/// What happens once the failed unlocks in a row reach the threshold.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum LockoutAction {
+    /// Only the delays.
+    #[default]
+    None,
+    /// The vault still opens with the right password, but nothing can be changed or exported.
+    ReadOnly,
+    /// The vault and its audit log are deleted.
+    Wipe,
+}
+
+/// The `[lockout]` table of the settings file.
+///
+/// Both the delays and the threshold only hold against someone going through this program. The
+/// count is stored in the clear and without a MAC, because it has to work before the vault is
+/// unlocked; anyone who can edit the vault's files can reset it, and can copy the files to guess
+/// offline anyway. Read-only mode only guards against a guessed password: whoever knows the
+/// password can end it with `unlock-attempts reset`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LockoutSettings {
+    /// Failed unlocks in a row that trigger `action`.
+    pub after: u32,
+    pub action: LockoutAction,
+}
+
+impl Default for LockoutSettings {
+    fn default() -> Self {
+        Self { after: 10, action: LockoutAction::None }
+    }
+}
+
+impl LockoutSettings {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.after < 3 {
+            return Err("lockout after must be at least 3, so a few typos can't trigger it".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// How long after the last failure the next attempt has to wait: nothing for the first few, then
+/// 2, 4, 8... seconds, up to a quarter of an hour.
+pub fn delay(count: u32) -> Duration {
+    match count.checked_sub(FREE_ATTEMPTS) {
+        None | Some(0) => Duration::ZERO,
+        Some(extra) => Duration::from_secs(1u64 << extra.min(20)).min(MAX_DELAY),
+    }
+}
+
+/// How long until another attempt is allowed, if one has to wait.
+pub fn retry_after(failed: &FailedUnlocks, now: DateTime<Utc>) -> Option<Duration> {
+    let last = failed.last?;
+    let until = last + chrono::Duration::from_std(delay(failed.count)).ok()?;
+    (until > now).then(|| (until - now).to_std().unwrap_or_default())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn failed(count: u32, last: DateTime<Utc>) -> FailedUnlocks {
+        FailedUnlocks { count, last: Some(last), read_only: false }
+    }
+
+    #[test]
+    fn delays_double_after_the_free_attempts() {
+        let delays: Vec<u64> = (0..=6).map(|count| delay(count).as_secs()).collect();
+        assert_eq!(delays, [0, 0, 0, 2, 4, 8, 16]);
+    }
+
+    #[test]
+    fn delays_stop_growing_at_the_cap() {
+        assert_eq!(delay(11), Duration::from_secs(512));
+        assert_eq!(delay(12), MAX_DELAY);
+        assert_eq!(delay(40), MAX_DELAY);
+        assert_eq!(delay(u32::MAX), MAX_DELAY);
+    }
+
+    #[test]
+    fn retry_waits_out_the_delay_from_the_last_failure() {
+        let now = Utc::now();
+        let ago = |seconds| now - chrono::Duration::seconds(seconds);
+        assert_eq!(retry_after(&failed(2, now), now), None);
+        assert_eq!(retry_after(&failed(4, ago(1)), now), Some(Duration::from_secs(3)));
+        assert_eq!(retry_after(&failed(4, ago(4)), now), None);
+        assert_eq!(retry_after(&failed(40, ago(60)), now), Some(MAX_DELAY - Duration::from_secs(60)));
+    }
+
+    #[test]
+    fn a_reset_count_never_waits() {
+        assert_eq!(retry_after(&FailedUnlocks::default(), Utc::now()), None);
+        let reset = FailedUnlocks { read_only: true, ..FailedUnlocks::default() };
+        assert_eq!(retry_after(&reset, Utc::now()), None);
+    }
+}
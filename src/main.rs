@@ -1,30 +1,53 @@
+mod audit;
+mod cli;
+mod clipboard;
+mod config;
 mod crypto;
+mod keyfile;
+mod lockout;
 mod storage;
 mod models;
 mod manager;
+mod otp;
+mod search;
+mod sharing;
+mod sync;
 mod ui;
 
 use std::io;
 use crossterm::{
+    event::{DisableBracketedPaste, DisableMouseCapture, EnableBracketedPaste, EnableMouseCapture},
     terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
     ExecutableCommand,
 };
 use tui::{backend::CrosstermBackend, Terminal};
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let args: Vec<String> = std::env::args().collect();
+    let (overrides, args) = config::Overrides::parse(&args)?;
+    // Load settings before taking over the terminal so errors in the file are readable.
+    // Commands need them too, for the vault's storage backend.
+    let themes = ui::theme::ThemeRegistry::load(&ui::theme::ThemeRegistry::dir_for(&overrides.config_path()));
+    let file_settings = config::Settings::load(&overrides.config_path(), &themes)?;
+    let settings = overrides.apply(&file_settings, &themes)?;
+    if cli::run(&args, &settings)? {
+        return Ok(());
+    }
+
     // Setup terminal
     enable_raw_mode()?;
-    io::stderr().execute(EnterAlternateScreen)?;
+    io::stderr().execute(EnterAlternateScreen)?.execute(EnableBracketedPaste)?.execute(EnableMouseCapture)?;
     let backend = CrosstermBackend::new(io::stderr());
     let mut terminal = Terminal::new(backend)?;
 
     // Create app and run it
-    let mut app = ui::app::App::new()?;
+    let mut app = ui::app::App::new(settings, file_settings, overrides.config_path(), themes)?;
     let res = ui::run_app(&mut terminal, &mut app);
+    app.clipboard.clear_now();
 
     // Restore terminal
     disable_raw_mode()?;
-    io::stderr().execute(LeaveAlternateScreen)?;
+    io::stderr().execute(DisableMouseCapture)?.execute(DisableBracketedPaste)?.execute(LeaveAlternateScreen)?;
     terminal.show_cursor()?;
 
     if let Err(err) = res {
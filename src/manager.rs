@@ -1,44 +1,238 @@
-use crate::models::{Credential, EncryptedVault};
-use crate::storage::StorageService;
-use crate::crypto::CryptoService;
+use crate::audit::{self, AuditAction, AuditEntry, AuditLog};
+use crate::keyfile::KeyFiles;
+use crate::lockout::{self, LockoutAction, LockoutSettings};
+use crate::models::{normalize_folder, Credential, CredentialFields, CredentialUpdate, EncryptedRecord, EncryptedVault, FailedUnlocks, KdfParams, LegacyCredential, ShareOrigin, VaultIndex, VersionVector};
+use crate::storage::{VaultStore, VaultWrite};
+use crate::crypto::{CryptoService, DEFAULT_KDF_ITERATIONS};
+use crate::otp;
+use crate::sharing::{self, Roster, SharedCollection};
+use crate::sync::{self, ChangeEntry, SyncChannel, SyncReport, SyncSettings, SyncStore};
+use chrono::Utc;
+use std::cmp::Ordering;
 use std::collections::HashMap;
 use std::sync::{Arc, Mutex};
 
 pub struct PasswordManager {
-    storage: StorageService,
+    storage: Box<dyn VaultStore>,
     credentials: Arc<Mutex<HashMap<String, Credential>>>,
+    /// The stored index, kept so a save only has to encrypt the records that changed.
+    index: Mutex<VaultIndex>,
     master_key: Option<Vec<u8>>,
     salt: Option<Vec<u8>>,
+    /// Parameters the current key was derived with.
+    kdf: KdfParams,
+    /// Iterations wanted by the settings; the vault is re-keyed at unlock if they differ.
+    target_kdf_iterations: Option<u32>,
+    /// The files given with the master password.
+    key_files: KeyFiles,
+    /// The files the vault should need from now on; it is re-keyed at unlock to need them.
+    target_key_files: Option<KeyFiles>,
+    sync_settings: SyncSettings,
+    /// The connection to the other devices while unlocked, if sync is set up and the last sync got
+    /// far enough to open it.
+    sync: Option<SyncChannel>,
+    /// The versions this device last wrote to its change log, by credential id.
+    logged: Mutex<HashMap<String, VersionVector>>,
+    last_sync: Mutex<Option<Result<SyncReport, String>>>,
+    /// Set when pushing or pulling after a save fails. Syncing then stops until the next unlock so
+    /// that later saves don't each wait on a store that can't be reached.
+    sync_failed: Mutex<bool>,
+    /// Recorded in the audit log as the part of the program acting on the vault.
+    audit_source: String,
+    lockout: LockoutSettings,
+    /// Failed unlocks between the last two successful ones.
+    failures_before_unlock: u32,
+    /// Set at unlock while the lockout's read-only action is in force.
+    read_only: bool,
 }
 
 impl PasswordManager {
-    pub fn new() -> Result<Self, Box<dyn std::error::Error>> {
-        Ok(Self {
-            storage: StorageService::new()?,
+    pub fn new(storage: Box<dyn VaultStore>) -> Self {
+        Self {
+            storage,
             credentials: Arc::new(Mutex::new(HashMap::new())),
+            index: Mutex::new(VaultIndex::default()),
             master_key: None,
             salt: None,
-        })
+            kdf: default_kdf(),
+            target_kdf_iterations: None,
+            key_files: KeyFiles::default(),
+            target_key_files: None,
+            sync_settings: SyncSettings::default(),
+            sync: None,
+            logged: Mutex::new(HashMap::new()),
+            last_sync: Mutex::new(None),
+            sync_failed: Mutex::new(false),
+            audit_source: "tui".to_string(),
+            lockout: LockoutSettings::default(),
+            failures_before_unlock: 0,
+            read_only: false,
+        }
+    }
+
+    /// Names the part of the program using the vault in the audit log, e.g. `git-credential`.
+    pub fn set_audit_source(&mut self, source: &str) {
+        self.audit_source = source.to_string();
+    }
+
+    /// Sets what happens after too many failed unlocks.
+    pub fn set_lockout(&mut self, settings: LockoutSettings) {
+        self.lockout = settings;
+    }
+
+    /// Sets up syncing through a shared folder or a sync server. Changes are pulled at unlock and
+    /// after every save, and pushed as they are saved.
+    pub fn set_sync(&mut self, settings: SyncSettings) {
+        self.sync_settings = settings;
+    }
+
+    /// The outcome of the sync done by the last unlock, if sync is set up, or the error that stopped
+    /// syncing after a save since. A failed sync leaves the vault unlocked and usable; the error is
+    /// reported here instead.
+    pub fn take_sync_report(&mut self) -> Option<Result<SyncReport, String>> {
+        self.last_sync.get_mut().unwrap().take()
+    }
+
+    /// Sets the PBKDF2 cost for new vaults and for re-keying the existing one at the next unlock.
+    pub fn set_kdf_iterations(&mut self, iterations: u32) {
+        self.target_kdf_iterations = Some(iterations);
+    }
+
+    /// Sets the key file and challenge-response file to unlock with. A new vault needs the ones given.
+    pub fn set_key_files(&mut self, key_files: KeyFiles) {
+        self.key_files = key_files;
+    }
+
+    /// Re-keys the vault at the next unlock to need exactly `key_files` besides the password.
+    pub fn set_target_key_files(&mut self, key_files: KeyFiles) {
+        self.target_key_files = Some(key_files);
+    }
+
+    /// The stored key derivation parameters, which say what unlocks the vault. Readable while locked.
+    pub fn kdf_params(&self) -> Result<Option<KdfParams>, Box<dyn std::error::Error>> {
+        self.storage.load_kdf_params()
+    }
+
+    pub fn vault_exists(&self) -> Result<bool, Box<dyn std::error::Error>> {
+        self.storage.vault_exists()
+    }
+
+    /// Failed unlocks since the last successful one. Readable while locked, for the lock screen.
+    pub fn failed_unlocks(&self) -> Result<FailedUnlocks, Box<dyn std::error::Error>> {
+        self.storage.load_failed_unlocks()
+    }
+
+    /// Failed unlocks between the last successful unlock and this one.
+    pub fn failures_before_unlock(&self) -> u32 {
+        self.failures_before_unlock
+    }
+
+    /// Whether the vault was opened read-only because of too many failed unlocks.
+    pub fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+
+    /// Clears the failed unlock count and leaves read-only mode.
+    pub fn reset_failed_unlocks(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        if self.master_key.is_none() {
+            return Err("Vault is locked".into());
+        }
+        self.storage.save_failed_unlocks(&FailedUnlocks::default())?;
+        self.read_only = false;
+        Ok(())
     }
 
+    /// Opens the vault, or creates it if there is none. Returns `false` for a wrong password.
+    ///
+    /// Wrong passwords are counted in the store. After a few, each attempt has to wait longer than
+    /// the one before (see [`lockout::delay`]), and once the count reaches the lockout threshold the
+    /// vault becomes read-only or is deleted, as the settings say.
     pub fn unlock(&mut self, password: &str) -> Result<bool, Box<dyn std::error::Error>> {
-        // Try to load existing vault
-        if let Some(vault) = self.storage.load_vault()? {
-            let key = CryptoService::derive_key(password.as_bytes(), &vault.salt);
+        let mut failed = self.storage.load_failed_unlocks()?;
+        if let Some(wait) = lockout::retry_after(&failed, Utc::now()) {
+            return Err(format!("Too many failed attempts. Try again in {} seconds.", wait.as_secs() + 1).into());
+        }
+        if !self.open_vault(password)? {
+            failed.count += 1;
+            failed.last = Some(Utc::now());
+            if failed.count >= self.lockout.after {
+                match self.lockout.action {
+                    LockoutAction::None => {}
+                    LockoutAction::ReadOnly => failed.read_only = true,
+                    LockoutAction::Wipe => {
+                        self.reset()?;
+                        return Err(format!("{} failed attempts in a row: the vault was deleted", failed.count).into());
+                    }
+                }
+            }
+            self.storage.save_failed_unlocks(&failed)?;
+            return Ok(false);
+        }
+        self.failures_before_unlock = failed.count;
+        self.read_only = failed.read_only;
+        if failed.count > 0 {
+            self.storage.save_failed_unlocks(&FailedUnlocks { read_only: failed.read_only, ..FailedUnlocks::default() })?;
+        }
+        let mut detail = Vec::new();
+        if failed.count > 0 {
+            detail.push(format!("after {} failed attempt{}", failed.count, if failed.count == 1 { "" } else { "s" }));
+        }
+        if self.read_only {
+            detail.push("read-only".to_string());
+        }
+        self.audit(vec![AuditEntry::new(AuditAction::Unlock, "", "", detail.join(", "), &self.audit_source)])?;
+        // Pulling changes would write to the vault.
+        *self.sync_failed.get_mut().unwrap() = false;
+        *self.last_sync.get_mut().unwrap() = None;
+        if let Some(store) = self.sync_settings.open_store().filter(|_| !self.read_only) {
+            let report = self.sync(store, password).map_err(|e| e.to_string());
+            // A push that failed part way through has stopped syncing; that is the error to report.
+            let last_sync = self.last_sync.get_mut().unwrap();
+            *last_sync = Some(last_sync.take().unwrap_or(report));
+        }
+        Ok(true)
+    }
+
+    fn open_vault(&mut self, password: &str) -> Result<bool, Box<dyn std::error::Error>> {
+        let kdf = self.storage.load_kdf_params()?.unwrap_or_else(default_kdf);
+        if let Some(header) = self.storage.load_index()? {
+            let secret = self.key_files.composite_key(password, &kdf, &header.salt)?;
+            let key = CryptoService::derive_key(&secret, &header.salt, kdf.iterations);
+            let index: VaultIndex = match CryptoService::decrypt(&header.ciphertext, &header.nonce, &key) {
+                Ok(decrypted) => serde_json::from_slice(&decrypted)?,
+                Err(_) => return Ok(false), // Wrong password
+            };
+            *self.credentials.lock().unwrap() = self.read_records(&key, &index)?;
+            *self.index.lock().unwrap() = index;
+            self.master_key = Some(key);
+            self.salt = Some(header.salt);
+            self.kdf = kdf;
+            if let Some(target) = self.target_kdf() {
+                self.rekey(password, target)?;
+            }
+            Ok(true)
+        } else if let Some(vault) = self.storage.load_legacy_vault()? {
+            let key = CryptoService::derive_key(password.as_bytes(), &vault.salt, kdf.iterations);
             
             // Try to decrypt the vault with this key
             match CryptoService::decrypt(&vault.ciphertext, &vault.nonce, &key) {
                 Ok(decrypted) => {
-                    match bincode::deserialize(&decrypted) {
+                    match decode_credentials(&decrypted) {
                         Ok(credentials) => {
                             *self.credentials.lock().unwrap() = credentials;
                             self.master_key = Some(key);
                             self.salt = Some(vault.salt.clone());
+                            self.kdf = kdf;
+                            match self.target_kdf() {
+                                Some(target) => self.rekey(password, target)?,
+                                // Rewrites the blob as one record per credential.
+                                None => self.save()?,
+                            }
                             Ok(true)
                         },
                         Err(_) => {
                             // If we can't deserialize the decrypted data, clear the vault
-                            self.storage.clear()?;
+                            self.storage.reset()?;
                             Ok(false)
                         }
                     }
@@ -46,142 +240,632 @@ impl PasswordManager {
                 Err(_) => Ok(false) // Wrong password
             }
         } else {
-            // New vault - create with this password
+            // New vault - create with this password and the key files given
+            let key_files = self.target_key_files.take().unwrap_or_else(|| self.key_files.clone());
+            let kdf = KdfParams {
+                iterations: self.target_kdf_iterations.unwrap_or(DEFAULT_KDF_ITERATIONS),
+                key_file: key_files.key_file.is_some(),
+                challenge_response: key_files.challenge_response_file.is_some(),
+            };
             let salt = CryptoService::generate_salt();
-            let key = CryptoService::derive_key(password.as_bytes(), &salt);
-            self.master_key = Some(key.clone());
-            self.salt = Some(salt.clone());
+            let secret = key_files.composite_key(password, &kdf, &salt)?;
+            let key = CryptoService::derive_key(&secret, &salt, kdf.iterations);
+            self.master_key = Some(key);
+            self.salt = Some(salt);
+            self.kdf = kdf;
+            self.key_files = key_files;
+            *self.index.lock().unwrap() = VaultIndex::default();
             
             // Initialize and save an empty vault
-            let credentials = self.credentials.lock().unwrap();
-            let serialized = bincode::serialize(&*credentials)?;
-            let (nonce, ciphertext) = CryptoService::encrypt(&serialized, &key)?;
-            let vault = EncryptedVault {
-                salt,
-                nonce,
-                ciphertext,
-            };
-            self.storage.save_vault(&vault)?;
+            self.save()?;
             Ok(true)
         }
     }
 
-    pub fn save(&self) -> Result<(), Box<dyn std::error::Error>> {
-        if let (Some(ref key), Some(ref salt)) = (&self.master_key, &self.salt) {
+    /// The parameters to re-key the open vault with, if the settings or the target key files ask
+    /// for different ones.
+    fn target_kdf(&self) -> Option<KdfParams> {
+        let mut target = self.kdf.clone();
+        if let Some(iterations) = self.target_kdf_iterations {
+            target.iterations = iterations;
+        }
+        if let Some(key_files) = &self.target_key_files {
+            target.key_file = key_files.key_file.is_some();
+            target.challenge_response = key_files.challenge_response_file.is_some();
+            // A different file may stand in for the same factor, so this always re-keys.
+            return Some(target);
+        }
+        (target != self.kdf).then_some(target)
+    }
+
+    /// Checks the stored records against the manifest and the index, then decrypts them.
+    ///
+    /// A record that is missing, not listed, or differs from its digest (e.g. an older copy put back)
+    /// fails the unlock rather than silently dropping or reviving a credential.
+    fn read_records(&self, key: &[u8], index: &VaultIndex) -> Result<HashMap<String, Credential>, Box<dyn std::error::Error>> {
+        let manifest = self.storage.load_manifest()?.ok_or("The vault manifest is missing")?;
+        if !CryptoService::verify_mac(key, &manifest_data(index), &manifest) {
+            return Err("The vault manifest does not match its index; the vault may have been tampered with".into());
+        }
+        let records = self.storage.load_records()?;
+        if let Some(id) = records.keys().find(|id| !index.records.contains_key(*id)) {
+            return Err(format!("Record {} is not listed in the vault manifest", id).into());
+        }
+        let mut credentials = HashMap::new();
+        for (id, digest) in &index.records {
+            let record = records.get(id).ok_or_else(|| format!("Record {} is missing from the vault", id))?;
+            if record_digest(record) != *digest {
+                return Err(format!("Record {} does not match the vault manifest (modified or rolled back)", id).into());
+            }
+            let decrypted = CryptoService::decrypt_with_aad(&record.ciphertext, &record.nonce, key, id.as_bytes())
+                .map_err(|_| format!("Record {} could not be decrypted", id))?;
+            credentials.insert(id.clone(), serde_json::from_slice(&decrypted)?);
+        }
+        Ok(credentials)
+    }
+
+    /// Derives a new key with a fresh salt and re-encrypts the vault with it, using the target key
+    /// files if there are any.
+    fn rekey(&mut self, password: &str, kdf: KdfParams) -> Result<(), Box<dyn std::error::Error>> {
+        let key_files = self.target_key_files.clone().unwrap_or_else(|| self.key_files.clone());
+        let salt = CryptoService::generate_salt();
+        let secret = key_files.composite_key(password, &kdf, &salt)?;
+        let key = CryptoService::derive_key(&secret, &salt, kdf.iterations);
+        let previous = (self.master_key.replace(key), self.salt.replace(salt), std::mem::replace(&mut self.kdf, kdf));
+        if let Err(e) = self.save() {
+            // Keep using the old key; the stored vault was not replaced.
+            (self.master_key, self.salt, self.kdf) = previous;
+            return Err(e);
+        }
+        self.key_files = key_files;
+        self.target_key_files = None;
+        Ok(())
+    }
+
+    /// Starts a batch of changes that is saved once, on [`Transaction::commit`].
+    pub fn transaction(&self) -> Result<Transaction<'_>, Box<dyn std::error::Error>> {
+        if self.master_key.is_none() {
+            return Err("Vault is locked".into());
+        }
+        if self.read_only {
+            return Err(READ_ONLY.into());
+        }
+        Ok(Transaction {
+            manager: self,
+            staged: HashMap::new(),
+            tombstones: HashMap::new(),
+            conflicts: HashMap::new(),
+            collections: HashMap::new(),
+            external: false,
+            usage: false,
+        })
+    }
+
+    /// Merges the change logs of the other devices into the vault, then writes the local changes
+    /// they have not seen to this device's log.
+    fn sync(&mut self, store: Box<dyn SyncStore>, password: &str) -> Result<SyncReport, Box<dyn std::error::Error>> {
+        self.sync = None;
+        let device = match self.sync_settings.device.as_str() {
+            "" => self.device_id()?,
+            device => device.to_string(),
+        };
+        let channel = SyncChannel::open(store, &device, password, &self.key_files, &self.kdf)?;
+
+        // Credentials from before sync was set up have no versions yet, and without them other
+        // devices could not tell them apart from ones they already have.
+        let unversioned: Vec<String> = {
             let credentials = self.credentials.lock().unwrap();
-            let serialized = bincode::serialize(&*credentials)?;
-            let (nonce, ciphertext) = CryptoService::encrypt(&serialized, key)?;
-            let vault = EncryptedVault {
-                salt: salt.clone(),
-                nonce,
-                ciphertext,
-            };
-            self.storage.save_vault(&vault)?;
-            Ok(())
+            credentials.values().filter(|c| c.versions.is_empty()).map(|c| c.id.clone()).collect()
+        };
+        let mut transaction = self.transaction()?;
+        for id in &unversioned {
+            transaction.update(id, |credential| sync::bump(&mut credential.versions, &device));
+        }
+        transaction.commit()?;
+
+        self.logged.lock().unwrap().clear();
+        self.sync = Some(channel);
+        let received = self.pull()?;
+
+        // Once most of the log is superseded history, replace it with the current state.
+        let own_entries = self.sync.as_ref().map_or(0, SyncChannel::own_entries);
+        let live = self.credentials.lock().unwrap().len() + self.index.lock().unwrap().tombstones.len();
+        let sent = if own_entries > 2 * live + 64 {
+            self.rewrite_log()?
         } else {
-            Err("Vault is locked".into())
+            self.push_changes(None)?
+        };
+        Ok(SyncReport { received, sent, conflicts: self.index.lock().unwrap().conflicts.len() })
+    }
+
+    /// Merges the log entries written since the last pull, this device's own included, into the
+    /// vault. Returns how many credentials changed.
+    fn pull(&self) -> Result<usize, Box<dyn std::error::Error>> {
+        let channel = match self.channel() {
+            Some(channel) => channel,
+            None => return Ok(0),
+        };
+        let logs = channel.read_new()?;
+        let mut transaction = self.transaction()?;
+        transaction.external = true;
+        let mut logged = Vec::new();
+        let mut received = 0;
+        for log in logs {
+            for entry in log.entries {
+                if log.device == channel.device() {
+                    logged.push((entry.id.clone(), entry.versions.clone()));
+                }
+                let (local_versions, local) = transaction.sync_state(&entry.id);
+                if let Some(merged) = sync::merge(&local_versions, local.as_ref(), &entry) {
+                    let superseded = sync::compare(&entry.versions, &local_versions) == Some(Ordering::Greater);
+                    transaction.apply_merge(&entry.id, merged, superseded);
+                    received += 1;
+                }
+            }
+        }
+        transaction.finish(false)?;
+        self.logged.lock().unwrap().extend(logged);
+        Ok(received)
+    }
+
+    /// The connection to the other devices, unless sync is off or stopped after a failure.
+    fn channel(&self) -> Option<&SyncChannel> {
+        self.sync.as_ref().filter(|_| !*self.sync_failed.lock().unwrap())
+    }
+
+    /// Stops syncing until the next unlock and keeps the error for [`Self::take_sync_report`].
+    fn stop_sync(&self, error: Box<dyn std::error::Error>) {
+        *self.sync_failed.lock().unwrap() = true;
+        *self.last_sync.lock().unwrap() = Some(Err(error.to_string()));
+    }
+
+    /// This device's name in the change logs, generated and stored in the vault on first use.
+    fn device_id(&self) -> Result<String, Box<dyn std::error::Error>> {
+        let device = self.index.lock().unwrap().device.clone();
+        if !device.is_empty() {
+            return Ok(device);
+        }
+        let device = uuid::Uuid::new_v4().simple().to_string();
+        self.write_records(&[], false, |index| index.device = device.clone())?;
+        Ok(device)
+    }
+
+    /// The change log entry for the current state of a credential, or its tombstone once purged.
+    fn change_entry(&self, id: &str) -> Option<ChangeEntry> {
+        if let Some(credential) = self.credentials.lock().unwrap().get(id) {
+            return Some(ChangeEntry { id: id.to_string(), versions: credential.versions.clone(), credential: Some(credential.clone()) });
+        }
+        let index = self.index.lock().unwrap();
+        let versions = index.tombstones.get(id)?;
+        Some(ChangeEntry { id: id.to_string(), versions: versions.clone(), credential: None })
+    }
+
+    fn all_ids(&self) -> Vec<String> {
+        let mut ids: Vec<String> = self.credentials.lock().unwrap().keys().cloned().collect();
+        ids.extend(self.index.lock().unwrap().tombstones.keys().cloned());
+        ids
+    }
+
+    /// Appends the credentials in `ids` (or all of them) whose versions this device has not logged
+    /// yet to its change log. Returns how many entries were written.
+    fn push_changes(&self, ids: Option<&[String]>) -> Result<usize, Box<dyn std::error::Error>> {
+        let channel = match self.channel() {
+            Some(channel) => channel,
+            None => return Ok(0),
+        };
+        let ids = match ids {
+            Some(ids) => ids.to_vec(),
+            None => self.all_ids(),
+        };
+        let mut logged = self.logged.lock().unwrap();
+        let entries: Vec<ChangeEntry> = ids
+            .iter()
+            .filter_map(|id| self.change_entry(id))
+            .filter(|entry| logged.get(&entry.id) != Some(&entry.versions))
+            .collect();
+        channel.append(&entries)?;
+        for entry in &entries {
+            logged.insert(entry.id.clone(), entry.versions.clone());
+        }
+        Ok(entries.len())
+    }
+
+    /// Replaces this device's change log with the current state of every credential.
+    fn rewrite_log(&self) -> Result<usize, Box<dyn std::error::Error>> {
+        let channel = match self.channel() {
+            Some(channel) => channel,
+            None => return Ok(0),
+        };
+        let entries: Vec<ChangeEntry> = self.all_ids().iter().filter_map(|id| self.change_entry(id)).collect();
+        channel.rewrite(&entries)?;
+        *self.logged.lock().unwrap() = entries.iter().map(|e| (e.id.clone(), e.versions.clone())).collect();
+        Ok(entries.len())
+    }
+
+    /// This vault's private key for shared collections, generated and stored on first use.
+    pub fn sharing_key(&self) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        if self.master_key.is_none() {
+            return Err("Vault is locked".into());
         }
+        let key = self.index.lock().unwrap().share_key.clone();
+        if !key.is_empty() {
+            return Ok(key);
+        }
+        let key = CryptoService::generate_x25519_secret();
+        self.write_records(&[], false, |index| index.share_key = key.clone())?;
+        Ok(key)
     }
 
-    pub fn add_password(
+    /// Adds the credentials of a shared collection opened with [`PasswordManager::sharing_key`],
+    /// replacing the local copies of ones imported before. Folders, favorites and usage counters of
+    /// the local copies are kept. Returns how many changed.
+    ///
+    /// Once a collection has been imported, later versions are only accepted from members whose
+    /// role allowed their changes in the version imported before. Nothing vouches for the members
+    /// and roles of the first version but its writer, so its writer's public key has to be given as
+    /// `from`, having been checked with them some other way.
+    pub fn import_shared(
         &self,
-        service: String,
-        username: String,
-        password: String,
-        notes: String,
-        tags: Vec<String>,
-    ) -> Result<(), Box<dyn std::error::Error>> {
+        collection: &SharedCollection,
+        credentials: Vec<Credential>,
+        from: Option<&str>,
+    ) -> Result<usize, Box<dyn std::error::Error>> {
+        let me = sharing::encode_public_key(&CryptoService::x25519_public_key(&self.sharing_key()?)?);
+        let roster = collection.roster();
+        let role = roster.role_of(&me).ok_or("You are not a member of this collection")?;
+        let known = self.index.lock().unwrap().collections.get(&collection.id).cloned();
+        match (known, from) {
+            (_, Some(from)) if from != collection.writer => {
+                return Err(format!("The collection was written by {}, not by the key given", collection.writer).into());
+            }
+            (Some(known), _) => known.check_successor(collection)?,
+            (None, Some(_)) => {}
+            (None, None) => {
+                return Err(format!(
+                    "This is the first import of '{}', so its members and roles are only as its writer says. Check the writer's public key with them, then import with --from {}",
+                    collection.name, collection.writer
+                )
+                .into());
+            }
+        }
+        let origin = ShareOrigin { collection: collection.id.clone(), name: collection.name.clone(), role };
+        let mut transaction = self.transaction()?;
+        transaction.external = true;
+        for mut credential in credentials {
+            credential.versions.clear();
+            credential.shared = Some(origin.clone());
+            if let Some(local) = transaction.get(&credential.id) {
+                credential.folder = local.folder.clone();
+                credential.favorite = local.favorite;
+                credential.last_used_at = local.last_used_at;
+                credential.use_count = local.use_count;
+                credential.versions = local.versions.clone();
+                if serde_json::to_value(&credential)? == serde_json::to_value(&local)? {
+                    continue;
+                }
+            }
+            transaction.insert(credential);
+        }
+        transaction.collections.insert(collection.id.clone(), roster);
+        let changed = transaction.commit()?;
+        let detail = format!("shared collection '{}', {} new or changed", collection.name, changed);
+        self.audit(vec![AuditEntry::new(AuditAction::Import, "", "", detail, &self.audit_source)])?;
+        Ok(changed)
+    }
+
+    /// The secret of a credential for handing to another program, recorded as a use. Unlike the
+    /// credentials returned by [`PasswordManager::get_credentials`], this includes secrets the
+    /// vault may not reveal.
+    pub fn secret_for_injection(&self, id: &str) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
         if self.master_key.is_none() {
             return Err("Vault is locked".into());
         }
+        let secret = self.credentials.lock().unwrap().get(id).ok_or("Credential not found")?.secret.clone();
+        self.record_usage(id)?;
+        self.record_use(id)?;
+        Ok(secret)
+    }
 
-        let credential = Credential::new_password(service, username, password.into_bytes(), notes, tags);
-        self.credentials
-            .lock()
-            .unwrap()
-            .insert(credential.id.clone(), credential);
-        self.save()?;
-        Ok(())
+    /// Records in the audit log that a credential's secret was shown on screen.
+    pub fn record_reveal(&self, id: &str) -> Result<(), Box<dyn std::error::Error>> {
+        self.audit_credential(AuditAction::Reveal, id, String::new())
     }
 
-    pub fn add_api_key(
-        &self,
-        service: String,
-        account_name: String,
-        api_key: String,
-        notes: String,
-        is_active: bool,
-        tags: Vec<String>,
-    ) -> Result<(), Box<dyn std::error::Error>> {
+    /// Records in the audit log that a field of a credential was copied, e.g. `username`.
+    pub fn record_copy(&self, id: &str, field: &str) -> Result<(), Box<dyn std::error::Error>> {
+        self.audit_credential(AuditAction::Copy, id, field.to_string())
+    }
+
+    /// Records in the audit log that a credential's secret was handed to another program.
+    pub fn record_use(&self, id: &str) -> Result<(), Box<dyn std::error::Error>> {
+        self.audit_credential(AuditAction::Use, id, String::new())
+    }
+
+    fn audit_credential(&self, action: AuditAction, id: &str, detail: String) -> Result<(), Box<dyn std::error::Error>> {
+        let service = self.credentials.lock().unwrap().get(id).map(|c| c.service.clone()).ok_or("Credential not found")?;
+        self.audit(vec![AuditEntry::new(action, id, &service, detail, &self.audit_source)])
+    }
+
+    /// Appends entries to the audit log.
+    fn audit(&self, entries: Vec<AuditEntry>) -> Result<(), Box<dyn std::error::Error>> {
+        if entries.is_empty() {
+            return Ok(());
+        }
+        let key = self.audit_key()?;
+        let head = self.storage.load_audit_head()?;
+        let (records, head) = audit::seal(&key, head.as_ref(), entries)?;
+        self.storage
+            .append_audit(&records, &head)
+            .map_err(|e| format!("Could not write the audit log: {}", e))?;
+        // Recorded after the append, so a save that fails in between leaves the index behind the
+        // log, which verifies, rather than ahead of it.
+        self.write_records(&[], false, |index| index.audit_count = head.count)
+    }
+
+    /// The key of the audit log, generated and stored in the vault on first use.
+    fn audit_key(&self) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
         if self.master_key.is_none() {
             return Err("Vault is locked".into());
         }
+        let key = self.index.lock().unwrap().audit_key.clone();
+        if !key.is_empty() {
+            return Ok(key);
+        }
+        let key = CryptoService::generate_key();
+        self.write_records(&[], false, |index| index.audit_key = key.clone())?;
+        Ok(key)
+    }
 
-        let credential = Credential::new_api_key(service, account_name, api_key.into_bytes(), notes, is_active, tags);
-        self.credentials
-            .lock()
-            .unwrap()
-            .insert(credential.id.clone(), credential);
-        self.save()?;
+    /// The audit log, decrypted and checked for entries that were changed, removed or cut off.
+    pub fn audit_log(&self) -> Result<AuditLog, Box<dyn std::error::Error>> {
+        let key = self.audit_key()?;
+        let recorded = self.index.lock().unwrap().audit_count;
+        Ok(audit::verify(&key, &self.storage.load_audit()?, self.storage.load_audit_head()?.as_ref(), recorded))
+    }
+
+    /// Sync conflicts waiting to be resolved: the credential as it is now (`None` if it has since
+    /// been purged) and the version that lost.
+    pub fn get_conflicts(&self) -> Vec<(Option<Credential>, Credential)> {
+        let credentials = self.credentials.lock().unwrap();
+        let index = self.index.lock().unwrap();
+        index
+            .conflicts
+            .iter()
+            .map(|(id, other)| (credentials.get(id).map(Credential::redacted), other.redacted()))
+            .collect()
+    }
+
+    /// Settles a sync conflict by keeping the current version or switching to the one that lost.
+    /// Switching counts as a new edit, so it replaces the current version on the other devices too.
+    pub fn resolve_conflict(&self, id: &str, use_other: bool) -> Result<(), Box<dyn std::error::Error>> {
+        let other = self.index.lock().unwrap().conflicts.get(id).cloned().ok_or("No such conflict")?;
+        let mut transaction = self.transaction()?;
+        if use_other {
+            let mut credential = other;
+            credential.versions = transaction.sync_state(id).0;
+            credential.touch();
+            transaction.insert(credential);
+        }
+        transaction.conflicts.insert(id.to_string(), None);
+        transaction.commit()?;
         Ok(())
     }
 
-    pub fn remove_credential(&self, id: &str) -> Result<(), Box<dyn std::error::Error>> {
+    /// Re-encrypts and writes every record. Used for new vaults, re-keying and converting old ones;
+    /// other changes go through [`Transaction::commit`].
+    pub fn save(&self) -> Result<(), Box<dyn std::error::Error>> {
+        let ids: Vec<String> = self.credentials.lock().unwrap().keys().cloned().collect();
+        self.write_records(&ids, true, |_| {})
+    }
+
+    /// Writes the records of `ids`, removing those no longer in the vault, and the index after
+    /// `edit` has been applied to it. The in-memory index is only replaced once the write succeeds.
+    fn write_records<F: FnOnce(&mut VaultIndex)>(&self, ids: &[String], replace_all: bool, edit: F) -> Result<(), Box<dyn std::error::Error>> {
+        let (key, salt) = match (&self.master_key, &self.salt) {
+            (Some(key), Some(salt)) => (key, salt),
+            _ => return Err("Vault is locked".into()),
+        };
+        let mut index = self.index.lock().unwrap().clone();
+        if replace_all {
+            index.records.clear();
+        }
+        edit(&mut index);
+        let mut records = Vec::with_capacity(ids.len());
+        {
+            let credentials = self.credentials.lock().unwrap();
+            for id in ids {
+                match credentials.get(id) {
+                    Some(credential) => {
+                        let (nonce, ciphertext) =
+                            CryptoService::encrypt_with_aad(&serde_json::to_vec(credential)?, key, id.as_bytes())?;
+                        let record = EncryptedRecord { nonce, ciphertext };
+                        index.records.insert(id.clone(), record_digest(&record));
+                        records.push((id.clone(), Some(record)));
+                    }
+                    None => {
+                        index.records.remove(id);
+                        records.push((id.clone(), None));
+                    }
+                }
+            }
+        }
+        index.generation += 1;
+        let (nonce, ciphertext) = CryptoService::encrypt(&serde_json::to_vec(&index)?, key)?;
+        let header = EncryptedVault { salt: salt.clone(), nonce, ciphertext };
+        let manifest = CryptoService::mac(key, &manifest_data(&index));
+        self.storage.write(VaultWrite {
+            index: &header,
+            manifest: &manifest,
+            kdf: &self.kdf,
+            records,
+            replace_all,
+        })?;
+        *self.index.lock().unwrap() = index;
+        Ok(())
+    }
+
+    pub fn add_password(&self, fields: CredentialFields) -> Result<(), Box<dyn std::error::Error>> {
         if self.master_key.is_none() {
             return Err("Vault is locked".into());
         }
 
-        self.credentials.lock().unwrap().remove(id);
-        self.save()?;
-        Ok(())
+        let mut credential =
+            Credential::new_password(fields.service, fields.username, fields.secret.into_bytes(), fields.notes, fields.tags);
+        credential.folder = normalize_folder(&fields.folder);
+        credential.custom_fields = fields.custom_fields;
+        self.add_credential(credential)
     }
 
-    pub fn update_credential(
-        &self,
-        id: &str,
-        service: Option<String>,
-        username: Option<String>,
-        secret: Option<String>,
-        notes: Option<String>,
-        is_active: Option<bool>,
-        tags: Option<Vec<String>>,
-        custom_fields: Option<HashMap<String, String>>,
-    ) -> Result<(), Box<dyn std::error::Error>> {
+    /// Adds an API key; `fields.username` is the account name.
+    pub fn add_api_key(&self, fields: CredentialFields) -> Result<(), Box<dyn std::error::Error>> {
         if self.master_key.is_none() {
             return Err("Vault is locked".into());
         }
 
-        // Scope the lock to release it before saving
-        let updated = {
-            let mut credentials = self.credentials.lock().unwrap();
-            if let Some(credential) = credentials.get_mut(id) {
-                credential.update(
-                    service,
-                    username,
-                    secret.map(|s| s.into_bytes()),
-                    notes,
-                    is_active,
-                    tags,
-                    custom_fields,
-                );
-                true
-            } else {
-                false
+        let mut credential = Credential::new_api_key(
+            fields.service,
+            fields.username,
+            fields.secret.into_bytes(),
+            fields.notes,
+            fields.is_active,
+            fields.tags,
+        );
+        credential.folder = normalize_folder(&fields.folder);
+        credential.custom_fields = fields.custom_fields;
+        self.add_credential(credential)
+    }
+
+    pub fn add_credential(&self, credential: Credential) -> Result<(), Box<dyn std::error::Error>> {
+        let mut transaction = self.transaction()?;
+        transaction.insert(credential);
+        transaction.commit()?;
+        Ok(())
+    }
+
+    pub fn update_credential(&self, id: &str, update: CredentialUpdate) -> Result<(), Box<dyn std::error::Error>> {
+        self.modify_credential(id, |credential| {
+            // Secrets that can't be revealed were left out of the credential the form was filled
+            // from, so they must not be taken from it.
+            let hidden = (!credential.can_reveal())
+                .then(|| (credential.secret.clone(), credential.custom_fields.get(otp::OTP_FIELD).cloned()));
+            credential.update(update);
+            if let Some((secret, otp_secret)) = hidden {
+                credential.secret = secret;
+                if let Some(otp_secret) = otp_secret {
+                    credential.custom_fields.insert(otp::OTP_FIELD.to_string(), otp_secret);
+                }
             }
-        };
+        })
+    }
 
-        if updated {
-            self.save()
-        } else {
-            Err("Credential not found".into())
+    /// Counts a use of the credential; does nothing while the vault is read-only.
+    pub fn record_usage(&self, id: &str) -> Result<(), Box<dyn std::error::Error>> {
+        if self.read_only {
+            return Ok(());
         }
+        let mut transaction = self.transaction()?;
+        transaction.usage = true;
+        if !transaction.update(id, |credential| credential.record_usage()) {
+            return Err("Credential not found".into());
+        }
+        transaction.commit()?;
+        Ok(())
+    }
+
+    pub fn set_favorite(&self, id: &str, favorite: bool) -> Result<(), Box<dyn std::error::Error>> {
+        self.modify_credential(id, |credential| credential.favorite = favorite)
+    }
+
+    fn modify_credential<F: FnOnce(&mut Credential)>(&self, id: &str, change: F) -> Result<(), Box<dyn std::error::Error>> {
+        let mut transaction = self.transaction()?;
+        if !transaction.update(id, change) {
+            return Err("Credential not found".into());
+        }
+        transaction.commit()?;
+        Ok(())
+    }
+
+    /// Applies `change` to each credential in `ids` and saves once. Ids not in the vault are skipped;
+    /// returns how many credentials were changed.
+    fn modify_credentials<F: FnMut(&mut Credential)>(&self, ids: &[String], mut change: F) -> Result<usize, Box<dyn std::error::Error>> {
+        let mut transaction = self.transaction()?;
+        for id in ids {
+            transaction.update(id, &mut change);
+        }
+        transaction.commit()
+    }
+
+    pub fn add_tag(&self, ids: &[String], tag: &str) -> Result<usize, Box<dyn std::error::Error>> {
+        let tag = tag.trim();
+        if tag.is_empty() {
+            return Err("Tag cannot be empty".into());
+        }
+        self.modify_credentials(ids, |credential| {
+            if !credential.tags.iter().any(|t| t == tag) {
+                credential.tags.push(tag.to_string());
+                credential.touch();
+            }
+        })
+    }
+
+    pub fn remove_tag(&self, ids: &[String], tag: &str) -> Result<usize, Box<dyn std::error::Error>> {
+        let tag = tag.trim();
+        self.modify_credentials(ids, |credential| {
+            let before = credential.tags.len();
+            credential.tags.retain(|t| t != tag);
+            if credential.tags.len() != before {
+                credential.touch();
+            }
+        })
+    }
+
+    pub fn move_to_folder(&self, ids: &[String], folder: &str) -> Result<usize, Box<dyn std::error::Error>> {
+        let folder = normalize_folder(folder);
+        self.modify_credentials(ids, |credential| {
+            credential.folder = folder.clone();
+            credential.touch();
+        })
+    }
+
+    pub fn set_active(&self, ids: &[String], active: bool) -> Result<usize, Box<dyn std::error::Error>> {
+        self.modify_credentials(ids, |credential| {
+            credential.is_active = active;
+            credential.touch();
+        })
     }
 
+    /// Moves credentials to the trash, where they stay until restored or purged.
+    pub fn trash(&self, ids: &[String]) -> Result<usize, Box<dyn std::error::Error>> {
+        let now = Utc::now();
+        self.modify_credentials(ids, |credential| credential.deleted_at = Some(now))
+    }
+
+    pub fn restore(&self, ids: &[String]) -> Result<usize, Box<dyn std::error::Error>> {
+        self.modify_credentials(ids, |credential| credential.deleted_at = None)
+    }
+
+    /// Deletes credentials for good, trashed or not, with a single save.
+    pub fn purge(&self, ids: &[String]) -> Result<usize, Box<dyn std::error::Error>> {
+        let mut transaction = self.transaction()?;
+        for id in ids {
+            transaction.remove(id);
+        }
+        transaction.commit()
+    }
+
+    /// The credentials in use, leaving out the trash.
     pub fn get_credentials(&self) -> Result<Vec<Credential>, Box<dyn std::error::Error>> {
         if self.master_key.is_none() {
             return Err("Vault is locked".into());
         }
-        Ok(self.credentials.lock().unwrap().values().cloned().collect())
+        Ok(self.credentials.lock().unwrap().values().filter(|c| !c.is_trashed()).map(Credential::redacted).collect())
+    }
+
+    pub fn get_trash(&self) -> Result<Vec<Credential>, Box<dyn std::error::Error>> {
+        if self.master_key.is_none() {
+            return Err("Vault is locked".into());
+        }
+        Ok(self.credentials.lock().unwrap().values().filter(|c| c.is_trashed()).map(Credential::redacted).collect())
     }
 
     // This function is currently unused but is kept for potential future features
@@ -191,12 +875,15 @@ impl PasswordManager {
         if self.master_key.is_none() {
             return Err("Vault is locked".into());
         }
-        Ok(self.credentials.lock().unwrap().get(id).cloned())
+        Ok(self.credentials.lock().unwrap().get(id).map(Credential::redacted))
     }
 
     pub fn reset(&mut self) -> Result<(), Box<dyn std::error::Error>> {
         self.storage.reset()?;
         self.credentials.lock().unwrap().clear();
+        *self.index.lock().unwrap() = VaultIndex::default();
+        self.logged.lock().unwrap().clear();
+        self.sync = None;
         self.master_key = None;
         self.salt = None;
         Ok(())
@@ -204,31 +891,562 @@ impl PasswordManager {
 
     pub fn lock(&mut self) {
         self.credentials.lock().unwrap().clear();
+        *self.index.lock().unwrap() = VaultIndex::default();
+        self.logged.lock().unwrap().clear();
+        self.sync = None;
         self.master_key = None;
         self.salt = None;
+        self.read_only = false;
     }
 
     pub fn export_vault(&self, path: &str) -> Result<(), Box<dyn std::error::Error>> {
+        let credentials = self.get_credentials()?;
+        self.write_export(path, &credentials)
+    }
+
+    /// Exports only the credentials in `ids`, in the same format as [`PasswordManager::export_vault`].
+    pub fn export_credentials(&self, ids: &[String], path: &str) -> Result<usize, Box<dyn std::error::Error>> {
         if self.master_key.is_none() {
             return Err("Vault is locked".into());
         }
-        let credentials = self.get_credentials()?;
-        let json = serde_json::to_string_pretty(&credentials)?;
-        std::fs::write(path, json)?;
-        Ok(())
+        let credentials: Vec<Credential> = {
+            let vault = self.credentials.lock().unwrap();
+            ids.iter().filter_map(|id| vault.get(id).map(Credential::redacted)).collect()
+        };
+        self.write_export(path, &credentials)?;
+        Ok(credentials.len())
     }
 
+    /// Imports an export file. The credentials become this vault's own: share origins and sync
+    /// versions in the file are ignored, and credentials shared with this vault that it can't edit
+    /// are left as their collection has them.
     pub fn import_vault(&mut self, path: &str) -> Result<(), Box<dyn std::error::Error>> {
         if self.master_key.is_none() {
             return Err("Vault is locked".into());
         }
         let json = std::fs::read_to_string(path)?;
         let credentials: Vec<Credential> = serde_json::from_str(&json)?;
-        let mut vault = self.credentials.lock().unwrap();
-        for credential in credentials {
-            vault.insert(credential.id.clone(), credential);
+        let mut transaction = self.transaction()?;
+        for mut credential in credentials {
+            let (versions, existing) = transaction.sync_state(&credential.id);
+            if existing.is_some_and(|existing| !existing.can_edit()) {
+                continue;
+            }
+            credential.shared = None;
+            // Versions as stored, so the import is counted as a local edit.
+            credential.versions = versions;
+            transaction.insert(credential);
         }
-        self.save()?;
-        Ok(())
+        let count = transaction.commit()?;
+        let detail = format!("{} credentials from {}", count, path);
+        self.audit(vec![AuditEntry::new(AuditAction::Import, "", "", detail, &self.audit_source)])
+    }
+
+    /// Writes credentials to a plaintext export file, recording each one in the audit log.
+    fn write_export(&self, path: &str, credentials: &[Credential]) -> Result<(), Box<dyn std::error::Error>> {
+        if self.read_only {
+            return Err(READ_ONLY.into());
+        }
+        let json = serde_json::to_string_pretty(credentials)?;
+        std::fs::write(path, json)?;
+        let entries = credentials
+            .iter()
+            .map(|c| AuditEntry::new(AuditAction::Export, &c.id, &c.service, format!("to {}", path), &self.audit_source))
+            .collect();
+        self.audit(entries)
+    }
+}
+
+/// An unlocked vault in memory, cheap to derive, for tests.
+#[cfg(test)]
+pub(crate) fn test_manager() -> PasswordManager {
+    let mut manager = PasswordManager::new(Box::new(crate::storage::MemoryStore::default()));
+    manager.set_kdf_iterations(1_000);
+    assert!(manager.unlock("correct horse").unwrap());
+    manager
+}
+
+/// Parameters of vaults from before they were stored: the default cost and the password alone.
+fn default_kdf() -> KdfParams {
+    KdfParams { iterations: DEFAULT_KDF_ITERATIONS, key_file: false, challenge_response: false }
+}
+
+pub const READ_ONLY: &str = "The vault is read-only after too many failed unlocks; run 'password-manager unlock-attempts reset' to allow changes again";
+
+/// A batch of changes to the vault, committed with a single save.
+///
+/// Changes are staged and only reach the vault on [`Transaction::commit`]; dropping the transaction
+/// discards them. If the save fails, the in-memory vault is rolled back as well.
+pub struct Transaction<'a> {
+    manager: &'a PasswordManager,
+    /// Staged changes by id: the new credential, or `None` to remove it.
+    staged: HashMap<String, Option<Credential>>,
+    /// Versions to record for removed credentials, when they come from another device.
+    tombstones: HashMap<String, VersionVector>,
+    /// Conflicts to record (`Some`) or clear (`None`).
+    conflicts: HashMap<String, Option<Credential>>,
+    /// Members of shared collections to remember, by collection id.
+    collections: HashMap<String, Roster>,
+    /// The changes come from a shared collection or another device, so they may change credentials
+    /// this vault can't edit itself.
+    external: bool,
+    /// Only usage counters change. They stay on this device rather than counting as an edit, and
+    /// reach the other devices along with the next real one.
+    usage: bool,
+}
+
+impl Transaction<'_> {
+    /// The credential as it would be after this transaction.
+    pub fn get(&self, id: &str) -> Option<Credential> {
+        match self.staged.get(id) {
+            Some(staged) => staged.clone(),
+            None => self.manager.credentials.lock().unwrap().get(id).cloned(),
+        }
+    }
+
+    /// Adds a credential, replacing any with the same id.
+    pub fn insert(&mut self, credential: Credential) {
+        self.staged.insert(credential.id.clone(), Some(credential));
+    }
+
+    /// Applies `change` to a credential. Returns `false` if there is no credential with that id.
+    pub fn update<F: FnOnce(&mut Credential)>(&mut self, id: &str, change: F) -> bool {
+        match self.get(id) {
+            Some(mut credential) => {
+                change(&mut credential);
+                self.insert(credential);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Removes a credential. Returns `false` if there is no credential with that id.
+    pub fn remove(&mut self, id: &str) -> bool {
+        let exists = self.get(id).is_some();
+        if exists {
+            self.staged.insert(id.to_string(), None);
+        }
+        exists
+    }
+
+    /// The versions and current state of a credential, counting staged changes and purges.
+    fn sync_state(&self, id: &str) -> (VersionVector, Option<Credential>) {
+        match self.get(id) {
+            Some(credential) => (credential.versions.clone(), Some(credential)),
+            None => {
+                let versions = match self.tombstones.get(id) {
+                    Some(versions) => Some(versions.clone()),
+                    None => self.manager.index.lock().unwrap().tombstones.get(id).cloned(),
+                };
+                (versions.unwrap_or_default(), None)
+            }
+        }
+    }
+
+    /// Stages the result of merging another device's change. `superseded` means the other device
+    /// had already seen the local version, so any conflict recorded for it is settled.
+    fn apply_merge(&mut self, id: &str, merged: sync::Merged, superseded: bool) {
+        match merged.credential {
+            Some(credential) => {
+                self.tombstones.remove(id);
+                self.staged.insert(id.to_string(), Some(credential));
+            }
+            None => {
+                self.tombstones.insert(id.to_string(), merged.versions);
+                self.staged.insert(id.to_string(), None);
+            }
+        }
+        if let Some(conflict) = merged.conflict {
+            self.conflicts.insert(id.to_string(), Some(conflict));
+        } else if superseded {
+            self.conflicts.insert(id.to_string(), None);
+        }
+    }
+
+    /// Applies the staged changes and saves the vault once. Returns how many credentials changed.
+    ///
+    /// When the vault syncs, each local edit counts as one more change by this device in the
+    /// credential's versions. Changes merged from other devices already carry their versions.
+    /// After saving, the changes are pushed and those of the other devices pulled in.
+    pub fn commit(self) -> Result<usize, Box<dyn std::error::Error>> {
+        self.finish(true)
+    }
+
+    /// Commits, pulling afterwards only if `pull` is set so a pull's own commit does not recurse.
+    fn finish(mut self, pull: bool) -> Result<usize, Box<dyn std::error::Error>> {
+        let count = self.staged.len();
+        if count == 0 && self.conflicts.is_empty() && self.collections.is_empty() {
+            return Ok(0);
+        }
+        if !self.external {
+            let credentials = self.manager.credentials.lock().unwrap();
+            for (id, change) in &self.staged {
+                if let (Some(old), Some(new)) = (credentials.get(id), change) {
+                    if !old.can_edit() && !same_shared_content(old, new) {
+                        return Err(format!("'{}' is shared with you and you can't edit it", old.service).into());
+                    }
+                }
+            }
+        }
+        let device = self.manager.channel().filter(|_| !self.usage).map(|channel| channel.device().to_string());
+        // Scope the lock to release it before saving
+        let previous: Vec<(String, Option<Credential>)> = {
+            let mut credentials = self.manager.credentials.lock().unwrap();
+            let mut previous = Vec::with_capacity(count);
+            for (id, change) in std::mem::take(&mut self.staged) {
+                let old_versions = credentials.get(&id).map(|c| c.versions.clone()).unwrap_or_default();
+                let old = match change {
+                    Some(mut credential) => {
+                        if let Some(device) = &device {
+                            if credential.versions == old_versions {
+                                sync::bump(&mut credential.versions, device);
+                            }
+                        }
+                        credentials.insert(id.clone(), credential)
+                    }
+                    None => {
+                        let old = credentials.remove(&id);
+                        if old.is_some() && !self.tombstones.contains_key(&id) {
+                            let mut versions = old_versions;
+                            if let Some(device) = &device {
+                                sync::bump(&mut versions, device);
+                            }
+                            self.tombstones.insert(id.clone(), versions);
+                        }
+                        old
+                    }
+                };
+                previous.push((id, old));
+            }
+            previous
+        };
+        // Changes from elsewhere were recorded where they were made.
+        let audit_entries: Vec<AuditEntry> = if self.external {
+            Vec::new()
+        } else {
+            let credentials = self.manager.credentials.lock().unwrap();
+            previous
+                .iter()
+                .filter_map(|(id, old)| audit_change(old.as_ref(), credentials.get(id), &self.manager.audit_source))
+                .collect()
+        };
+        let ids: Vec<String> = previous.iter().map(|(id, _)| id.clone()).collect();
+        let revived: Vec<&String> = ids.iter().filter(|id| !self.tombstones.contains_key(*id)).collect();
+        let edit = |index: &mut VaultIndex| {
+            for id in revived {
+                index.tombstones.remove(id);
+            }
+            index.tombstones.extend(self.tombstones.clone());
+            for (id, conflict) in &self.conflicts {
+                match conflict {
+                    Some(conflict) => index.conflicts.insert(id.clone(), conflict.clone()),
+                    None => index.conflicts.remove(id),
+                };
+            }
+            index.collections.extend(self.collections.clone());
+        };
+        if let Err(e) = self.manager.write_records(&ids, false, edit) {
+            let mut credentials = self.manager.credentials.lock().unwrap();
+            for (id, old) in previous {
+                match old {
+                    Some(credential) => credentials.insert(id, credential),
+                    None => credentials.remove(&id),
+                };
+            }
+            return Err(e);
+        }
+        if self.usage {
+            return Ok(count);
+        }
+        // A change that does not reach the log now is written at the next unlock, as are any made
+        // while syncing is stopped.
+        let synced = self.manager.push_changes(Some(&ids)).and_then(|_| if pull { self.manager.pull() } else { Ok(0) });
+        if let Err(e) = synced {
+            self.manager.stop_sync(e);
+        }
+        self.manager
+            .audit(audit_entries)
+            .map_err(|e| format!("The changes were saved, but: {}", e))?;
+        Ok(count)
+    }
+}
+
+/// Whether two versions of a credential agree on everything a shared collection holds.
+fn same_shared_content(a: &Credential, b: &Credential) -> bool {
+    a.entry_type == b.entry_type
+        && a.service == b.service
+        && a.username == b.username
+        && a.secret == b.secret
+        && a.notes == b.notes
+        && a.custom_fields == b.custom_fields
+        && a.is_active == b.is_active
+        && a.shared == b.shared
+}
+
+/// The audit log entry for a local change to a credential, unless only its usage counters or sync
+/// versions changed; copies and reveals are recorded on their own.
+fn audit_change(old: Option<&Credential>, new: Option<&Credential>, source: &str) -> Option<AuditEntry> {
+    let (action, credential, detail) = match (old, new) {
+        (None, Some(new)) => (AuditAction::Add, new, String::new()),
+        (Some(old), None) => (AuditAction::Delete, old, "deleted for good".to_string()),
+        (Some(old), Some(new)) if !old.is_trashed() && new.is_trashed() => (AuditAction::Delete, new, "moved to the trash".to_string()),
+        (Some(old), Some(new)) if old.is_trashed() && !new.is_trashed() => (AuditAction::Restore, new, String::new()),
+        (Some(old), Some(new)) => {
+            let fields = [
+                (old.entry_type != new.entry_type, "type"),
+                (old.service != new.service, "service"),
+                (old.username != new.username, "username"),
+                (old.secret != new.secret, "secret"),
+                (old.notes != new.notes, "notes"),
+                (old.tags != new.tags, "tags"),
+                (old.folder != new.folder, "folder"),
+                (old.favorite != new.favorite, "favorite"),
+                (old.is_active != new.is_active, "active"),
+                (old.custom_fields != new.custom_fields, "custom fields"),
+            ];
+            let changed: Vec<&str> = fields.iter().filter(|(changed, _)| *changed).map(|(_, name)| *name).collect();
+            if changed.is_empty() {
+                return None;
+            }
+            (AuditAction::Edit, new, format!("changed {}", changed.join(", ")))
+        }
+        (None, None) => return None,
+    };
+    Some(AuditEntry::new(action, &credential.id, &credential.service, detail, source))
+}
+
+/// What the manifest MAC covers: the index generation and every record id with its digest, in id order.
+fn manifest_data(index: &VaultIndex) -> Vec<u8> {
+    let mut data = b"manifest v1".to_vec();
+    data.extend_from_slice(&index.generation.to_le_bytes());
+    for (id, digest) in &index.records {
+        data.extend_from_slice(&(id.len() as u64).to_le_bytes());
+        data.extend_from_slice(id.as_bytes());
+        data.extend_from_slice(digest);
+    }
+    data
+}
+
+fn record_digest(record: &EncryptedRecord) -> Vec<u8> {
+    CryptoService::digest(&[record.nonce.as_slice(), record.ciphertext.as_slice()].concat())
+}
+
+/// Reads the contents of a single-blob vault, which are JSON or, from earlier versions, bincode.
+/// Records are stored as JSON so new credential fields can be added with serde defaults.
+fn decode_credentials(data: &[u8]) -> Result<HashMap<String, Credential>, Box<dyn std::error::Error>> {
+    if let Ok(credentials) = serde_json::from_slice(data) {
+        return Ok(credentials);
+    }
+    let legacy: HashMap<String, LegacyCredential> = bincode::deserialize(data)?;
+    Ok(legacy.into_iter().map(|(id, c)| (id, c.into())).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn add_github(manager: &PasswordManager) -> String {
+        let credential = Credential::new_password("github".into(), "me".into(), b"hunter2".to_vec(), String::new(), Vec::new());
+        let id = credential.id.clone();
+        manager.add_credential(credential).unwrap();
+        id
+    }
+
+    /// Writes `records` behind the manager's back, keeping the stored index and, unless given, the manifest.
+    fn tamper(manager: &PasswordManager, manifest: Option<Vec<u8>>, records: Vec<(String, Option<EncryptedRecord>)>) {
+        let index = manager.storage.load_index().unwrap().unwrap();
+        let manifest = manifest.unwrap_or_else(|| manager.storage.load_manifest().unwrap().unwrap());
+        let kdf = manager.storage.load_kdf_params().unwrap().unwrap();
+        manager.storage.write(VaultWrite { index: &index, manifest: &manifest, kdf: &kdf, records, replace_all: false }).unwrap();
+    }
+
+    #[test]
+    fn exports_with_read_only_shared_credentials_import() {
+        use crate::models::Role;
+        let manager = test_manager();
+        add_github(&manager);
+        let mut shared = Credential::new_password("stripe".into(), "ops".into(), b"sk_live".to_vec(), String::new(), Vec::new());
+        shared.shared = Some(ShareOrigin { collection: "team".into(), name: "Team".into(), role: Role::ReadOnly });
+        let shared_id = shared.id.clone();
+        manager.add_credential(shared).unwrap();
+        let path = std::env::temp_dir().join(format!("password-manager-test-{}.json", uuid::Uuid::new_v4().simple()));
+        let path = path.to_str().unwrap();
+        manager.export_vault(path).unwrap();
+
+        // The redacted copy in the file doesn't replace the one shared with this vault.
+        let mut same = manager;
+        same.import_vault(path).unwrap();
+        let kept = same.get_credential(&shared_id).unwrap().unwrap();
+        assert!(kept.shared.is_some());
+        assert_eq!(same.credentials.lock().unwrap()[&shared_id].secret, b"sk_live");
+
+        let mut other = test_manager();
+        other.import_vault(path).unwrap();
+        std::fs::remove_file(path).unwrap();
+        let imported = other.get_credential(&shared_id).unwrap().unwrap();
+        assert_eq!(imported.shared, None);
+        assert!(imported.can_edit());
+        assert_eq!(other.get_credentials().unwrap().len(), 2);
+    }
+
+    fn unlock_error(manager: &mut PasswordManager) -> String {
+        manager.lock();
+        manager.unlock("correct horse").unwrap_err().to_string()
+    }
+
+    #[test]
+    fn failed_unlocks_wait_and_a_successful_one_resets_the_count() {
+        let mut manager = test_manager();
+        manager.lock();
+        for _ in 0..3 {
+            assert!(!manager.unlock("wrong").unwrap());
+        }
+        assert!(manager.unlock("correct horse").unwrap_err().to_string().contains("Too many failed attempts"));
+
+        // Once the wait is over the right password still opens the vault, and the count starts over.
+        let mut failed = manager.storage.load_failed_unlocks().unwrap();
+        failed.last = failed.last.map(|last| last - chrono::Duration::seconds(3));
+        manager.storage.save_failed_unlocks(&failed).unwrap();
+        assert!(manager.unlock("correct horse").unwrap());
+        assert_eq!(manager.failures_before_unlock(), 3);
+        assert_eq!(manager.storage.load_failed_unlocks().unwrap(), FailedUnlocks::default());
+    }
+
+    #[test]
+    fn untouched_vault_unlocks() {
+        let mut manager = test_manager();
+        let id = add_github(&manager);
+        manager.lock();
+        assert!(manager.unlock("correct horse").unwrap());
+        assert_eq!(manager.get_credential(&id).unwrap().unwrap().service, "github");
+    }
+
+    #[test]
+    fn edited_manifest_is_detected() {
+        let mut manager = test_manager();
+        add_github(&manager);
+        let mut manifest = manager.storage.load_manifest().unwrap().unwrap();
+        manifest[0] ^= 1;
+        tamper(&manager, Some(manifest), Vec::new());
+        assert!(unlock_error(&mut manager).contains("manifest does not match"));
+    }
+
+    #[test]
+    fn rolled_back_record_is_detected() {
+        let mut manager = test_manager();
+        let id = add_github(&manager);
+        let old = manager.storage.load_records().unwrap().remove(&id).unwrap();
+        manager.set_favorite(&id, true).unwrap();
+        tamper(&manager, None, vec![(id.clone(), Some(old))]);
+        assert!(unlock_error(&mut manager).contains("modified or rolled back"));
+    }
+
+    #[test]
+    fn removed_and_added_records_are_detected() {
+        let mut manager = test_manager();
+        let id = add_github(&manager);
+        let record = manager.storage.load_records().unwrap().remove(&id).unwrap();
+        tamper(&manager, None, vec![(id.clone(), None)]);
+        assert!(unlock_error(&mut manager).contains("missing from the vault"));
+
+        tamper(&manager, None, vec![(id, Some(record.clone())), ("extra".to_string(), Some(record))]);
+        assert!(unlock_error(&mut manager).contains("not listed in the vault manifest"));
+    }
+
+    #[test]
+    fn vault_index_counts_the_audit_log() {
+        let mut manager = test_manager();
+        let id = add_github(&manager);
+        manager.record_reveal(&id).unwrap();
+        manager.lock();
+        assert!(manager.unlock("correct horse").unwrap());
+        let log = manager.audit_log().unwrap();
+        assert!(log.problem.is_none(), "{:?}", log.problem);
+        // Unlock, add, reveal, unlock.
+        assert_eq!(log.entries.len(), 4);
+        assert_eq!(manager.index.lock().unwrap().audit_count, 4);
+    }
+
+    fn member(manager: &PasswordManager, name: &str, role: crate::models::Role) -> sharing::Member {
+        let public_key = sharing::encode_public_key(&CryptoService::x25519_public_key(&manager.sharing_key().unwrap()).unwrap());
+        sharing::Member { name: name.to_string(), public_key, role }
+    }
+
+    #[test]
+    fn first_import_needs_the_writer_key() {
+        use crate::models::Role;
+        let (alice, bob, mallory) = (test_manager(), test_manager(), test_manager());
+        let members = vec![member(&alice, "alice", Role::Admin), member(&bob, "bob", Role::ReadOnly), member(&mallory, "mallory", Role::ReadOnly)];
+        let key = CryptoService::generate_key();
+        let seal = |members: Vec<sharing::Member>, writer: &PasswordManager| {
+            SharedCollection::seal("ops".into(), "Ops".into(), "ops".into(), 1, &key, members, &[], &writer.sharing_key().unwrap()).unwrap()
+        };
+
+        // Mallory holds the collection key too, and makes herself an admin.
+        let mut forged_members = members.clone();
+        forged_members[2].role = Role::Admin;
+        let forged = seal(forged_members, &mallory);
+        let opened = forged.open(&bob.sharing_key().unwrap()).unwrap();
+        assert!(bob.import_shared(&forged, opened.credentials, None).is_err());
+        let alice_key = member(&alice, "alice", Role::Admin).public_key;
+        assert!(bob.import_shared(&forged, Vec::new(), Some(&alice_key)).is_err());
+
+        let genuine = seal(members, &alice);
+        bob.import_shared(&genuine, Vec::new(), Some(&alice_key)).unwrap();
+        // Later versions are checked against the members imported before instead.
+        assert!(bob.import_shared(&forged, Vec::new(), None).is_err());
+        bob.import_shared(&genuine, Vec::new(), None).unwrap();
+    }
+
+    #[test]
+    fn usage_is_not_synced_as_an_edit() {
+        let folder = std::env::temp_dir().join(format!("password-manager-test-{}", uuid::Uuid::new_v4().simple()));
+        std::fs::create_dir_all(&folder).unwrap();
+        let mut manager = PasswordManager::new(Box::new(crate::storage::MemoryStore::default()));
+        manager.set_kdf_iterations(1_000);
+        manager.set_sync(SyncSettings { folder: folder.display().to_string(), device: "laptop".to_string(), ..Default::default() });
+        assert!(manager.unlock("correct horse").unwrap());
+        assert!(manager.take_sync_report().unwrap().is_ok());
+
+        let id = add_github(&manager);
+        let versions = manager.get_credential(&id).unwrap().unwrap().versions;
+        let log = std::fs::metadata(folder.join("laptop.log")).unwrap().len();
+
+        manager.record_usage(&id).unwrap();
+        let used = manager.get_credential(&id).unwrap().unwrap();
+        assert_eq!(used.use_count, 1);
+        assert_eq!(used.versions, versions);
+        assert_eq!(std::fs::metadata(folder.join("laptop.log")).unwrap().len(), log);
+
+        std::fs::remove_dir_all(&folder).unwrap();
+    }
+
+    #[test]
+    fn failed_sync_after_a_save_stops_syncing_until_the_next_unlock() {
+        let folder = std::env::temp_dir().join(format!("password-manager-test-{}", uuid::Uuid::new_v4().simple()));
+        let mut manager = PasswordManager::new(Box::new(crate::storage::MemoryStore::default()));
+        manager.set_kdf_iterations(1_000);
+        manager.set_sync(SyncSettings { folder: folder.display().to_string(), device: "laptop".to_string(), ..Default::default() });
+        assert!(manager.unlock("correct horse").unwrap());
+        assert!(manager.take_sync_report().unwrap().is_ok());
+
+        // A directory in the way of the log, so it can no longer be written.
+        let log = folder.join("laptop.log");
+        std::fs::remove_file(&log).ok();
+        std::fs::create_dir(&log).unwrap();
+        let id = add_github(&manager);
+        assert!(manager.take_sync_report().unwrap().is_err());
+        manager.set_favorite(&id, true).unwrap();
+        assert!(manager.take_sync_report().is_none(), "no further attempts after the first failure");
+
+        // The change reaches the log at the next unlock.
+        std::fs::remove_dir(&log).unwrap();
+        manager.lock();
+        assert!(manager.unlock("correct horse").unwrap());
+        assert_eq!(manager.take_sync_report().unwrap().unwrap().sent, 1);
+        assert!(std::fs::metadata(&log).unwrap().len() > 0);
+
+        std::fs::remove_dir_all(&folder).unwrap();
     }
 }
@@ -1,6 +1,6 @@
 use serde::{Deserialize, Serialize};
 use chrono::{DateTime, Utc};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub enum EntryType {
@@ -21,6 +21,104 @@ pub struct Credential {
     pub custom_fields: HashMap<String, String>,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
+    /// Slash-separated folder path such as `Work/Infra`; empty for the top level.
+    #[serde(default)]
+    pub folder: String,
+    #[serde(default)]
+    pub favorite: bool,
+    /// When the secret was last copied or revealed.
+    #[serde(default)]
+    pub last_used_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub use_count: u64,
+    /// When the credential was moved to the trash. Trashed credentials are hidden until restored or purged.
+    #[serde(default)]
+    pub deleted_at: Option<DateTime<Utc>>,
+    /// How many times each syncing device has changed the credential; see [`crate::sync`].
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub versions: VersionVector,
+    /// The shared collection the credential was imported from; see [`crate::sharing`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub shared: Option<ShareOrigin>,
+}
+
+/// Edit counters by device id. Of two versions of a credential, the one whose counters are all
+/// at least as high has seen every change the other has; otherwise they were edited concurrently.
+pub type VersionVector = BTreeMap<String, u64>;
+
+/// What a member of a shared collection may do, from least to most.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Role {
+    /// Sees the entries and can use secrets through `password-manager run`, but the password manager
+    /// doesn't show or copy them. This only limits what is displayed: the member's vault holds the
+    /// secrets, and a command started with `run` can print them.
+    ReadOnly,
+    CanReveal,
+    /// Can also write the collection's credentials.
+    CanEdit,
+    /// Can also change the members and their roles.
+    Admin,
+}
+
+impl Role {
+    pub const ALL: [Role; 4] = [Role::ReadOnly, Role::CanReveal, Role::CanEdit, Role::Admin];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Role::ReadOnly => "read-only",
+            Role::CanReveal => "can-reveal",
+            Role::CanEdit => "can-edit",
+            Role::Admin => "admin",
+        }
+    }
+
+    pub fn parse(name: &str) -> Result<Self, String> {
+        Role::ALL.into_iter().find(|role| role.name() == name.trim()).ok_or_else(|| {
+            let names: Vec<&str> = Role::ALL.iter().map(|role| role.name()).collect();
+            format!("unknown role '{}'; use one of {}", name, names.join(", "))
+        })
+    }
+
+    pub fn is_admin(&self) -> bool {
+        *self == Role::Admin
+    }
+}
+
+/// Where a credential imported from a shared collection came from, and the role this vault had in it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ShareOrigin {
+    /// Id of the collection.
+    pub collection: String,
+    pub name: String,
+    pub role: Role,
+}
+
+/// The fields of a new credential as entered in the add form.
+#[derive(Debug, Clone, Default)]
+pub struct CredentialFields {
+    pub service: String,
+    pub username: String,
+    pub secret: String,
+    pub notes: String,
+    /// Only kept for API keys; passwords are always active.
+    pub is_active: bool,
+    pub tags: Vec<String>,
+    pub folder: String,
+    pub custom_fields: HashMap<String, String>,
+}
+
+/// Changes to an existing credential; fields left `None` are kept as they are.
+#[derive(Debug, Clone, Default)]
+pub struct CredentialUpdate {
+    pub service: Option<String>,
+    pub username: Option<String>,
+    pub secret: Option<Vec<u8>>,
+    pub notes: Option<String>,
+    pub is_active: Option<bool>,
+    pub tags: Option<Vec<String>>,
+    pub custom_fields: Option<HashMap<String, String>>,
+    pub folder: Option<String>,
 }
 
 impl Credential {
@@ -38,6 +136,13 @@ impl Credential {
             custom_fields: HashMap::new(),
             created_at: now,
             updated_at: now,
+            folder: String::new(),
+            favorite: false,
+            last_used_at: None,
+            use_count: 0,
+            deleted_at: None,
+            versions: VersionVector::new(),
+            shared: None,
         }
     }
 
@@ -55,34 +160,127 @@ impl Credential {
             custom_fields: HashMap::new(),
             created_at: now,
             updated_at: now,
+            folder: String::new(),
+            favorite: false,
+            last_used_at: None,
+            use_count: 0,
+            deleted_at: None,
+            versions: VersionVector::new(),
+            shared: None,
         }
     }
 
-    pub fn update(&mut self, service: Option<String>, username: Option<String>, secret: Option<Vec<u8>>, 
-                 notes: Option<String>, is_active: Option<bool>, tags: Option<Vec<String>>, custom_fields: Option<HashMap<String, String>>) {
-        if let Some(s) = service {
+    pub fn update(&mut self, update: CredentialUpdate) {
+        if let Some(s) = update.service {
             self.service = s;
         }
-        if let Some(u) = username {
+        if let Some(u) = update.username {
             self.username = u;
         }
-        if let Some(p) = secret {
+        if let Some(p) = update.secret {
             self.secret = p;
         }
-        if let Some(n) = notes {
+        if let Some(n) = update.notes {
             self.notes = n;
         }
-        if let Some(a) = is_active {
+        if let Some(a) = update.is_active {
             self.is_active = a;
         }
-        if let Some(t) = tags {
+        if let Some(t) = update.tags {
             self.tags = t;
         }
-        if let Some(cf) = custom_fields {
+        if let Some(cf) = update.custom_fields {
             self.custom_fields = cf;
         }
+        if let Some(f) = update.folder {
+            self.folder = normalize_folder(&f);
+        }
+        self.touch();
+    }
+
+    /// Marks the credential as edited now.
+    pub fn touch(&mut self) {
         self.updated_at = Utc::now();
     }
+
+    pub fn is_trashed(&self) -> bool {
+        self.deleted_at.is_some()
+    }
+
+    /// Notes that the secret was copied or revealed. This is not an edit, so `updated_at` is left alone.
+    pub fn record_usage(&mut self) {
+        self.last_used_at = Some(Utc::now());
+        self.use_count += 1;
+    }
+
+    /// Whether the secret may be shown or copied. Only credentials shared read-only can't.
+    pub fn can_reveal(&self) -> bool {
+        self.shared.as_ref().is_none_or(|shared| shared.role >= Role::CanReveal)
+    }
+
+    /// Whether the shared content may be changed: everything but the folder, tags, favorite mark
+    /// and usage counters, which only matter in this vault.
+    pub fn can_edit(&self) -> bool {
+        self.shared.as_ref().is_none_or(|shared| shared.role >= Role::CanEdit)
+    }
+
+    /// A copy for display and export: a secret that can't be revealed is left out, together with
+    /// the one-time password secret.
+    pub fn redacted(&self) -> Credential {
+        let mut credential = self.clone();
+        if !credential.can_reveal() {
+            credential.secret.clear();
+            credential.custom_fields.remove(crate::otp::OTP_FIELD);
+        }
+        credential
+    }
+
+    /// Custom field keys in the order the view screen numbers them.
+    pub fn sorted_custom_field_keys(&self) -> Vec<&str> {
+        let mut keys: Vec<&str> = self.custom_fields.keys().map(String::as_str).collect();
+        keys.sort_unstable();
+        keys
+    }
+}
+
+/// Cleans up a user-entered folder path: `" Work / Infra/ "` becomes `"Work/Infra"`.
+pub fn normalize_folder(folder: &str) -> String {
+    folder
+        .split('/')
+        .map(str::trim)
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// The credential layout written by versions that stored the vault with bincode.
+/// Kept only so those vaults can still be opened; they are re-saved in the current format.
+#[derive(Debug, Clone, Deserialize)]
+pub struct LegacyCredential {
+    pub id: String,
+    pub entry_type: EntryType,
+    pub service: String,
+    pub username: String,
+    pub secret: Vec<u8>,
+    pub notes: String,
+    pub tags: Vec<String>,
+    pub is_active: bool,
+    pub custom_fields: HashMap<String, String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl From<LegacyCredential> for Credential {
+    fn from(legacy: LegacyCredential) -> Self {
+        let mut credential = Credential::new_password(legacy.service, legacy.username, legacy.secret, legacy.notes, legacy.tags);
+        credential.id = legacy.id;
+        credential.entry_type = legacy.entry_type;
+        credential.is_active = legacy.is_active;
+        credential.custom_fields = legacy.custom_fields;
+        credential.created_at = legacy.created_at;
+        credential.updated_at = legacy.updated_at;
+        credential
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -91,3 +289,72 @@ pub struct EncryptedVault {
     pub nonce: Vec<u8>,
     pub ciphertext: Vec<u8>,
 }
+
+/// One credential, encrypted on its own so a save only rewrites the records that changed.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EncryptedRecord {
+    pub nonce: Vec<u8>,
+    pub ciphertext: Vec<u8>,
+}
+
+/// The vault's table of contents, stored encrypted: every record id with a digest of its
+/// ciphertext, so a missing, replaced or rolled-back record is noticed at unlock.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct VaultIndex {
+    /// Incremented on every save.
+    pub generation: u64,
+    pub records: BTreeMap<String, Vec<u8>>,
+    /// Id of this device in the sync folder, generated the first time the vault syncs.
+    #[serde(default)]
+    pub device: String,
+    /// Versions of purged credentials, so an older copy arriving through sync does not bring them back.
+    #[serde(default)]
+    pub tombstones: BTreeMap<String, VersionVector>,
+    /// The version that lost a sync conflict, by credential id, until the user picks one.
+    #[serde(default)]
+    pub conflicts: BTreeMap<String, Credential>,
+    /// Members of each shared collection imported so far, by collection id, to check later versions against.
+    #[serde(default)]
+    pub collections: BTreeMap<String, crate::sharing::Roster>,
+    /// X25519 private key for shared collections, generated the first time the vault shares.
+    #[serde(default)]
+    pub share_key: Vec<u8>,
+    /// Key of the audit log, generated with the first entry. Kept here rather than derived from the
+    /// master key so the log stays readable when the vault is re-keyed.
+    #[serde(default)]
+    pub audit_key: Vec<u8>,
+    /// How many entries the audit log had after the last append. The log and its head are stored
+    /// outside the index, so putting back an older copy of both would otherwise go unnoticed.
+    #[serde(default)]
+    pub audit_count: u64,
+}
+
+/// The end of the audit log as of its last append: how many entries it had and the digest of the
+/// last one, under a MAC so entries cut off the end are noticed. See [`crate::audit`].
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct AuditHead {
+    pub count: u64,
+    pub last: Vec<u8>,
+    pub mac: Vec<u8>,
+}
+
+/// Failed unlocks since the last successful one, kept unencrypted next to the vault so they can
+/// be counted before it is unlocked. See [`crate::lockout`].
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct FailedUnlocks {
+    pub count: u32,
+    pub last: Option<DateTime<Utc>>,
+    /// Set when the threshold was reached with the read-only action; cleared by
+    /// `password-manager unlock-attempts reset`.
+    pub read_only: bool,
+}
+
+/// Key derivation parameters, stored next to the vault so the cost can change without a new format.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct KdfParams {
+    pub iterations: u32,
+    /// The master password is combined with a key file before derivation.
+    pub key_file: bool,
+    /// The master password is combined with the response of a challenge-response secret.
+    pub challenge_response: bool,
+}
@@ -0,0 +1,344 @@
+use crate::models::{Credential, EntryType};
+use chrono::{DateTime, Utc};
+use std::collections::HashMap;
+
+/// A parsed search query such as `github tag:prod type:apikey user:admin folder:work active:false`.
+///
+/// Bare words are matched fuzzily against the service, username, tags and notes.
+/// Field filters narrow the results down and must all match.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SearchQuery {
+    pub terms: Vec<String>,
+    pub services: Vec<String>,
+    pub users: Vec<String>,
+    pub tags: Vec<String>,
+    pub notes: Vec<String>,
+    pub folders: Vec<String>,
+    pub entry_type: Option<EntryType>,
+    pub active: Option<bool>,
+    /// Set when a filter value cannot match anything, e.g. `type:foo`.
+    pub unsatisfiable: bool,
+}
+
+impl SearchQuery {
+    pub fn parse(input: &str) -> Self {
+        let mut query = SearchQuery::default();
+        for token in input.split_whitespace() {
+            let token = token.to_lowercase();
+            let (field, value) = match token.split_once(':') {
+                Some((field, value)) => (field, value),
+                None => {
+                    query.terms.push(token);
+                    continue;
+                }
+            };
+            // An empty value means the filter is still being typed; ignore it until it has one.
+            if value.is_empty() {
+                continue;
+            }
+            match field {
+                "service" | "name" | "s" => query.services.push(value.to_string()),
+                "user" | "username" | "u" => query.users.push(value.to_string()),
+                "tag" | "t" => query.tags.push(value.to_string()),
+                "notes" | "note" | "n" => query.notes.push(value.to_string()),
+                "folder" | "in" | "f" => query.folders.push(value.to_string()),
+                // Values match by prefix so a partially typed filter still narrows the list.
+                "type" => {
+                    if "apikey".starts_with(value) || "api_key".starts_with(value) || "key".starts_with(value) {
+                        query.entry_type = Some(EntryType::ApiKey);
+                    } else if "password".starts_with(value) {
+                        query.entry_type = Some(EntryType::Password);
+                    } else {
+                        query.unsatisfiable = true;
+                    }
+                }
+                "active" => {
+                    if "true".starts_with(value) || "yes".starts_with(value) {
+                        query.active = Some(true);
+                    } else if "false".starts_with(value) || "no".starts_with(value) {
+                        query.active = Some(false);
+                    } else {
+                        query.unsatisfiable = true;
+                    }
+                }
+                _ => query.terms.push(token.clone()),
+            }
+        }
+        query
+    }
+
+    pub fn is_empty(&self) -> bool {
+        *self == SearchQuery::default()
+    }
+}
+
+/// Scores how well `pattern` matches `text` as a fuzzy subsequence.
+/// Both arguments are expected to be lowercase. Returns `None` when not every character matches.
+pub fn fuzzy_score(pattern: &str, text: &str) -> Option<i64> {
+    if pattern.is_empty() {
+        return Some(0);
+    }
+    let text: Vec<char> = text.chars().collect();
+    let mut score = 0i64;
+    let mut position = 0usize;
+    let mut previous_match: Option<usize> = None;
+
+    for wanted in pattern.chars() {
+        let found = (position..text.len()).find(|&i| text[i] == wanted)?;
+        score += 1;
+        if previous_match.is_some_and(|p| p + 1 == found) {
+            // Consecutive characters are worth much more than scattered ones.
+            score += 5;
+        }
+        if found == 0 || !text[found - 1].is_alphanumeric() {
+            // Characters at the start of a word.
+            score += 8;
+        }
+        if let Some(previous) = previous_match {
+            score -= (found - previous - 1).min(5) as i64;
+        }
+        previous_match = Some(found);
+        position = found + 1;
+    }
+
+    let pattern_len = pattern.chars().count();
+    if text.len() >= pattern_len && text.iter().zip(pattern.chars()).all(|(a, b)| *a == b) {
+        // Prefix match.
+        score += 15;
+    }
+    if text.len() == pattern_len {
+        score += 10;
+    }
+    Some(score)
+}
+
+/// Scores `pattern` against free text such as notes, where a scattered fuzzy match would hit
+/// nearly every entry: the pattern has to appear as typed, and counts more at the start of a word.
+pub fn substring_score(pattern: &str, text: &str) -> Option<i64> {
+    text.match_indices(pattern)
+        .map(|(i, _)| {
+            let mut score = 6 * pattern.chars().count() as i64;
+            if text[..i].chars().next_back().is_none_or(|c| !c.is_alphanumeric()) {
+                score += 8;
+            }
+            score
+        })
+        .max()
+}
+
+/// The searchable, pre-lowercased view of a credential.
+struct IndexedEntry {
+    service: String,
+    username: String,
+    tags: Vec<String>,
+    notes: String,
+    folder: String,
+    entry_type: EntryType,
+    is_active: bool,
+    updated_at: DateTime<Utc>,
+}
+
+impl IndexedEntry {
+    fn from_credential(credential: &Credential) -> Self {
+        Self {
+            service: credential.service.to_lowercase(),
+            username: credential.username.to_lowercase(),
+            tags: credential.tags.iter().map(|t| t.to_lowercase()).collect(),
+            notes: credential.notes.to_lowercase(),
+            folder: credential.folder.to_lowercase(),
+            entry_type: credential.entry_type.clone(),
+            is_active: credential.is_active,
+            updated_at: credential.updated_at,
+        }
+    }
+
+    fn score(&self, query: &SearchQuery) -> Option<i64> {
+        if query.unsatisfiable {
+            return None;
+        }
+        if let Some(entry_type) = &query.entry_type {
+            if self.entry_type != *entry_type {
+                return None;
+            }
+        }
+        if let Some(active) = query.active {
+            if self.is_active != active {
+                return None;
+            }
+        }
+        if !query.services.iter().all(|s| self.service.contains(s.as_str()))
+            || !query.users.iter().all(|u| self.username.contains(u.as_str()))
+            || !query.tags.iter().all(|t| self.tags.iter().any(|tag| tag.contains(t.as_str())))
+            || !query.notes.iter().all(|n| self.notes.contains(n.as_str()))
+            || !query.folders.iter().all(|f| self.folder.contains(f.as_str()))
+        {
+            return None;
+        }
+
+        let mut total = 0;
+        for term in &query.terms {
+            let best = [
+                fuzzy_score(term, &self.service).map(|s| s * 3),
+                fuzzy_score(term, &self.username).map(|s| s * 2),
+                self.tags.iter().filter_map(|t| fuzzy_score(term, t)).max().map(|s| s * 2),
+                substring_score(term, &self.notes),
+            ]
+            .into_iter()
+            .flatten()
+            .max()?;
+            total += best;
+        }
+        Some(total)
+    }
+}
+
+/// An in-memory index over the unlocked credentials.
+///
+/// Entries are only re-indexed when their `updated_at` changes, and a query that extends the
+/// previous one only re-scores the previous results, so typing stays fast on large vaults.
+#[derive(Default)]
+pub struct SearchIndex {
+    entries: HashMap<String, IndexedEntry>,
+    last_query: String,
+    last_results: Option<Vec<String>>,
+}
+
+impl SearchIndex {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Brings the index in line with `credentials`, re-indexing only what changed.
+    pub fn sync(&mut self, credentials: &[Credential]) {
+        let mut changed = false;
+        let mut seen = std::collections::HashSet::with_capacity(credentials.len());
+        for credential in credentials {
+            seen.insert(credential.id.as_str());
+            let stale = self
+                .entries
+                .get(&credential.id)
+                .is_none_or(|entry| entry.updated_at != credential.updated_at);
+            if stale {
+                self.entries.insert(credential.id.clone(), IndexedEntry::from_credential(credential));
+                changed = true;
+            }
+        }
+        let before = self.entries.len();
+        self.entries.retain(|id, _| seen.contains(id.as_str()));
+        if changed || before != self.entries.len() {
+            self.last_results = None;
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.last_query.clear();
+        self.last_results = None;
+    }
+
+    /// Returns the ids matching `input`, best match first.
+    /// An empty query returns `None`, meaning every credential matches.
+    pub fn search(&mut self, input: &str) -> Option<Vec<String>> {
+        let query = SearchQuery::parse(input);
+        if query.is_empty() {
+            self.last_query.clear();
+            self.last_results = None;
+            return None;
+        }
+
+        let narrowing = !self.last_query.is_empty() && input.starts_with(self.last_query.as_str());
+        let mut scored: Vec<(i64, &str, &String)> = match (&self.last_results, narrowing) {
+            (Some(previous), true) => previous
+                .iter()
+                .filter_map(|id| self.entries.get_key_value(id))
+                .filter_map(|(id, entry)| entry.score(&query).map(|s| (s, entry.service.as_str(), id)))
+                .collect(),
+            _ => self
+                .entries
+                .iter()
+                .filter_map(|(id, entry)| entry.score(&query).map(|s| (s, entry.service.as_str(), id)))
+                .collect(),
+        };
+        scored.sort_by(|a, b| b.0.cmp(&a.0).then_with(|| a.1.cmp(b.1)));
+        let results: Vec<String> = scored.into_iter().map(|(_, _, id)| id.clone()).collect();
+
+        self.last_query = input.to_string();
+        self.last_results = Some(results.clone());
+        Some(results)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_terms_and_filters() {
+        let query = SearchQuery::parse("GitHub tag:Prod u:admin folder:work/ci type:api active:no notes:rotate");
+        assert_eq!(query.terms, vec!["github"]);
+        assert_eq!(query.tags, vec!["prod"]);
+        assert_eq!(query.users, vec!["admin"]);
+        assert_eq!(query.folders, vec!["work/ci"]);
+        assert_eq!(query.notes, vec!["rotate"]);
+        assert_eq!(query.entry_type, Some(EntryType::ApiKey));
+        assert_eq!(query.active, Some(false));
+        assert!(!query.unsatisfiable);
+    }
+
+    #[test]
+    fn filters_being_typed_are_ignored() {
+        assert!(SearchQuery::parse("tag: ").is_empty());
+        assert_eq!(SearchQuery::parse("type:p").entry_type, Some(EntryType::Password));
+    }
+
+    #[test]
+    fn unknown_values_and_fields() {
+        assert!(SearchQuery::parse("type:foo").unsatisfiable);
+        assert!(SearchQuery::parse("active:maybe").unsatisfiable);
+        assert_eq!(SearchQuery::parse("https://example.com").terms, vec!["https://example.com"]);
+    }
+
+    #[test]
+    fn fuzzy_score_prefers_prefixes_and_requires_every_character() {
+        assert_eq!(fuzzy_score("gxb", "gitlab"), None);
+        let prefix = fuzzy_score("git", "github").unwrap();
+        let scattered = fuzzy_score("git", "digital").unwrap();
+        assert!(prefix > scattered);
+        assert!(fuzzy_score("github", "github").unwrap() > prefix);
+    }
+
+    #[test]
+    fn notes_match_only_as_typed() {
+        assert_eq!(substring_score("rte", "rotate every month"), None);
+        let word_start = substring_score("rot", "rotate every month").unwrap();
+        let inside = substring_score("rot", "the carrot").unwrap();
+        assert!(word_start > inside);
+    }
+
+    #[test]
+    fn search_ranks_and_filters() {
+        let github = Credential::new_password("GitHub".into(), "alice".into(), b"x".to_vec(), String::new(), vec!["prod".into()]);
+        let gitlab = Credential::new_password("GitLab".into(), "bob".into(), b"x".to_vec(), String::new(), vec![]);
+        let digital = Credential::new_api_key("DigitalOcean".into(), "alice".into(), b"x".to_vec(), String::new(), true, vec![]);
+        let mut index = SearchIndex::new();
+        index.sync(&[github.clone(), gitlab.clone(), digital.clone()]);
+
+        assert_eq!(index.search(""), None);
+        // Equal scores fall back to the service name.
+        assert_eq!(index.search("git").unwrap(), vec![github.id.clone(), gitlab.id.clone(), digital.id.clone()]);
+        assert_eq!(index.search("git tag:prod").unwrap(), vec![github.id.clone()]);
+        assert_eq!(index.search("u:alice type:key").unwrap(), vec![digital.id.clone()]);
+        assert!(index.search("type:foo").unwrap().is_empty());
+    }
+
+    #[test]
+    fn short_terms_do_not_match_scattered_letters_in_notes() {
+        let noted = Credential::new_password("Bank".into(), "carol".into(), b"x".to_vec(), "ask the office about a new card".into(), vec![]);
+        let mut index = SearchIndex::new();
+        index.sync(std::slice::from_ref(&noted));
+
+        assert!(index.search("tan").unwrap().is_empty());
+        assert_eq!(index.search("offi").unwrap(), vec![noted.id.clone()]);
+        assert_eq!(index.search("bank").unwrap(), vec![noted.id]);
+    }
+}
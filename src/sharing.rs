@@ -0,0 +1,244 @@
+use crate::crypto::CryptoService;
+use crate::models::{Credential, EncryptedRecord, Role};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/// Prefix of a public key written out for teammates, so a stray string is not mistaken for one.
+const PUBLIC_KEY_PREFIX: &str = "pmshare1:";
+
+const FORMAT: u32 = 1;
+
+/// A teammate a collection is shared with.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Member {
+    pub name: String,
+    /// X25519 public key as written by [`encode_public_key`].
+    pub public_key: String,
+    /// Left out for admins, so collections written before there were roles, when every member
+    /// could do everything, still open.
+    #[serde(default = "admin", skip_serializing_if = "Role::is_admin")]
+    pub role: Role,
+}
+
+fn admin() -> Role {
+    Role::Admin
+}
+
+/// The members of a collection as a vault last imported it. A later version is only accepted
+/// if a member allowed to make its changes wrote it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Roster {
+    pub key_generation: u64,
+    pub members: Vec<Member>,
+}
+
+impl Roster {
+    pub fn role_of(&self, public_key: &str) -> Option<Role> {
+        self.members.iter().find(|member| member.public_key == public_key).map(|member| member.role)
+    }
+
+    /// Checks that `collection` may follow the version this roster was taken from: it is not older,
+    /// its writer could edit, and only an admin changed the members or their roles.
+    pub fn check_successor(&self, collection: &SharedCollection) -> Result<(), Box<dyn std::error::Error>> {
+        if collection.key_generation < self.key_generation {
+            return Err("This is an older version of the collection than the one imported before".into());
+        }
+        let name = collection.writer_name();
+        let role = self.role_of(&collection.writer);
+        if collection.members != self.members && role != Some(Role::Admin) {
+            return Err(format!("{} changed the members of the collection without being an admin", name).into());
+        }
+        if role < Some(Role::CanEdit) {
+            return Err(format!("{} changed the collection without being allowed to edit it", name).into());
+        }
+        Ok(())
+    }
+}
+
+/// Credentials shared with a team, stored as a file that can be passed around by any means.
+///
+/// The credentials are encrypted with a random collection key, and that key is encrypted for each
+/// member with a key derived from the writer's and the member's X25519 keys. Only the writer and
+/// that member can derive it, so a member who opens the collection also knows who wrote it and
+/// that nobody has changed it since. Changing the members re-keys the collection, so a removed
+/// member cannot read later versions.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SharedCollection {
+    pub format: u32,
+    pub id: String,
+    pub name: String,
+    /// Tag of the credentials the collection holds, in the vault it is written from.
+    pub tag: String,
+    /// Incremented every time the collection is re-keyed.
+    pub key_generation: u64,
+    /// Public key of the member who wrote this version.
+    pub writer: String,
+    pub members: Vec<Member>,
+    /// The collection key for each member, by public key.
+    keys: BTreeMap<String, EncryptedRecord>,
+    contents: EncryptedRecord,
+}
+
+/// The parts of a collection the wrapped keys and the contents are bound to.
+#[derive(Serialize)]
+struct Header<'a> {
+    format: u32,
+    id: &'a str,
+    name: &'a str,
+    tag: &'a str,
+    key_generation: u64,
+    writer: &'a str,
+    members: &'a [Member],
+}
+
+/// A collection decrypted by one of its members.
+pub struct OpenedCollection {
+    pub key: Vec<u8>,
+    pub writer: Member,
+    pub credentials: Vec<Credential>,
+}
+
+impl SharedCollection {
+    /// Encrypts `credentials` under `key` for every member. `secret` is the writer's private key,
+    /// and the writer has to be one of the members.
+    #[allow(clippy::too_many_arguments)]
+    pub fn seal(
+        id: String,
+        name: String,
+        tag: String,
+        key_generation: u64,
+        key: &[u8],
+        members: Vec<Member>,
+        credentials: &[Credential],
+        secret: &[u8],
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let writer = encode_public_key(&CryptoService::x25519_public_key(secret)?);
+        if !members.iter().any(|member| member.public_key == writer) {
+            return Err("You are not a member of this collection".into());
+        }
+        let mut collection = SharedCollection {
+            format: FORMAT,
+            id,
+            name,
+            tag,
+            key_generation,
+            writer,
+            members,
+            keys: BTreeMap::new(),
+            contents: EncryptedRecord { nonce: Vec::new(), ciphertext: Vec::new() },
+        };
+        let header = collection.header()?;
+        let (nonce, ciphertext) = CryptoService::encrypt_with_aad(&serde_json::to_vec(credentials)?, key, &header)?;
+        collection.contents = EncryptedRecord { nonce, ciphertext };
+        let aad = collection.key_aad(&header);
+        for member in &collection.members {
+            let wrapping_key = wrapping_key(secret, &collection.writer, &member.public_key, &member.public_key)?;
+            let (nonce, ciphertext) = CryptoService::encrypt_with_aad(key, &wrapping_key, &aad)?;
+            collection.keys.insert(member.public_key.clone(), EncryptedRecord { nonce, ciphertext });
+        }
+        Ok(collection)
+    }
+
+    pub fn roster(&self) -> Roster {
+        Roster { key_generation: self.key_generation, members: self.members.clone() }
+    }
+
+    fn writer_name(&self) -> &str {
+        self.members
+            .iter()
+            .find(|member| member.public_key == self.writer)
+            .map_or("Someone who is not a member", |member| member.name.as_str())
+    }
+
+    /// Decrypts the collection with the private key of one of its members.
+    pub fn open(&self, secret: &[u8]) -> Result<OpenedCollection, Box<dyn std::error::Error>> {
+        if self.format != FORMAT {
+            return Err(format!("Unsupported shared collection format {}", self.format).into());
+        }
+        let me = encode_public_key(&CryptoService::x25519_public_key(secret)?);
+        let wrapped = self.keys.get(&me).ok_or("You are not a member of this collection")?;
+        let writer = self
+            .members
+            .iter()
+            .find(|member| member.public_key == self.writer)
+            .ok_or("The collection was written by someone who is not a member")?
+            .clone();
+        if writer.role < Role::CanEdit {
+            return Err(format!("The collection was written by {}, who may not edit it", writer.name).into());
+        }
+        let header = self.header()?;
+        let wrapping_key = wrapping_key(secret, &self.writer, &me, &self.writer)?;
+        let key = CryptoService::decrypt_with_aad(&wrapped.ciphertext, &wrapped.nonce, &wrapping_key, &self.key_aad(&header))
+            .map_err(|_| "The collection was changed or damaged since it was written")?;
+        let contents = CryptoService::decrypt_with_aad(&self.contents.ciphertext, &self.contents.nonce, &key, &header)
+            .map_err(|_| "The collection contents could not be decrypted")?;
+        Ok(OpenedCollection { key, writer, credentials: serde_json::from_slice(&contents)? })
+    }
+
+    pub fn load(path: &Path) -> Result<Self, Box<dyn std::error::Error>> {
+        let json = std::fs::read_to_string(path).map_err(|e| format!("Could not read {}: {}", path.display(), e))?;
+        Ok(serde_json::from_str(&json)?)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+        std::fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    fn header(&self) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        Ok(serde_json::to_vec(&Header {
+            format: self.format,
+            id: &self.id,
+            name: &self.name,
+            tag: &self.tag,
+            key_generation: self.key_generation,
+            writer: &self.writer,
+            members: &self.members,
+        })?)
+    }
+
+    /// The wrapped keys also cover the encrypted contents, so another member cannot swap in
+    /// contents of their own while the collection still names the original writer.
+    fn key_aad(&self, header: &[u8]) -> Vec<u8> {
+        let mut aad = header.to_vec();
+        aad.extend_from_slice(&CryptoService::digest(&self.contents.ciphertext));
+        aad
+    }
+}
+
+/// The key that wraps the collection key for `member`. The writer derives it from their private key
+/// and the member's public key, the member the other way round; `other` is the public key of
+/// whichever side `secret` does not belong to.
+fn wrapping_key(secret: &[u8], writer: &str, member: &str, other: &str) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    let info = format!("password-manager shared collection key\n{}\n{}", writer, member);
+    CryptoService::x25519_shared_key(secret, &decode_public_key(other)?, info.as_bytes())
+}
+
+/// A copy of `credential` to share: without the counters and flags that only mean something in
+/// the sharer's own vault.
+pub fn shareable(credential: &Credential) -> Credential {
+    let mut credential = credential.clone();
+    credential.favorite = false;
+    credential.last_used_at = None;
+    credential.use_count = 0;
+    credential.versions.clear();
+    credential.shared = None;
+    credential
+}
+
+pub fn encode_public_key(key: &[u8]) -> String {
+    let hex: String = key.iter().map(|byte| format!("{:02x}", byte)).collect();
+    format!("{}{}", PUBLIC_KEY_PREFIX, hex)
+}
+
+pub fn decode_public_key(text: &str) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    let invalid = || format!("'{}' is not a public key; they start with {}", text, PUBLIC_KEY_PREFIX);
+    let hex = text.trim().strip_prefix(PUBLIC_KEY_PREFIX).ok_or_else(invalid)?;
+    if hex.len() != 64 || !hex.is_ascii() {
+        return Err(invalid().into());
+    }
+    (0..32)
+        .map(|i| u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16).map_err(|_| invalid().into()))
+        .collect()
+}
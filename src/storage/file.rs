@@ -0,0 +1,174 @@
+use super::{VaultContents, VaultStore, VaultWrite};
+use crate::models::{AuditHead, EncryptedRecord, EncryptedVault, FailedUnlocks, KdfParams};
+use fs2::FileExt;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::Write;
+use std::path::PathBuf;
+
+/// Identifies vault files, followed by a format version byte.
+const MAGIC: &[u8] = b"PMVAULT";
+const VERSION: u8 = 1;
+
+/// The whole vault in one file, which file sync tools can carry between machines.
+///
+/// Records are encrypted individually as in the other stores; the file just holds them next to
+/// the index, manifest and audit log. Saves go to a temporary file that is renamed over the vault, so a
+/// crash or a sync tool reading mid-save never sees a half-written file. The file is read on
+/// every load, picking up copies a sync tool put in place while the program was running.
+/// Saves hold an exclusive lock on `<vault>.lock` from reading the file to renaming the new one
+/// over it, so two processes saving at once don't drop each other's changes.
+pub struct FileStore {
+    path: PathBuf,
+}
+
+impl FileStore {
+    pub fn new(path: PathBuf) -> Self {
+        Self { path }
+    }
+
+    fn read(&self) -> Result<VaultContents, Box<dyn std::error::Error>> {
+        let data = match std::fs::read(&self.path) {
+            Ok(data) => data,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(VaultContents::default()),
+            Err(e) => return Err(format!("Could not read {}: {}", self.path.display(), e).into()),
+        };
+        match data.strip_prefix(MAGIC).and_then(|rest| rest.split_first()) {
+            Some((&VERSION, body)) => Ok(bincode::deserialize(body)?),
+            Some((version, _)) => {
+                Err(format!("{} uses vault format {}, which this version cannot read", self.path.display(), version).into())
+            }
+            None => Err(format!("{} is not a vault file", self.path.display()).into()),
+        }
+    }
+
+    /// Waits for other saves to the vault to finish. The lock is released when the file is dropped.
+    fn lock(&self) -> Result<File, Box<dyn std::error::Error>> {
+        let mut lock_name = self.path.file_name().unwrap_or_default().to_os_string();
+        lock_name.push(".lock");
+        let file = File::create(self.path.with_file_name(lock_name))?;
+        file.lock_exclusive()
+            .map_err(|e| format!("Could not lock {}: {}", self.path.display(), e))?;
+        Ok(file)
+    }
+
+    /// Writes `contents` to a temporary file and renames it over the vault.
+    fn replace(&self, contents: &VaultContents) -> Result<(), Box<dyn std::error::Error>> {
+        let mut temp_name = self.path.file_name().unwrap_or_default().to_os_string();
+        temp_name.push(".tmp");
+        let temp_path = self.path.with_file_name(temp_name);
+        let mut file = File::create(&temp_path)?;
+        file.write_all(MAGIC)?;
+        file.write_all(&[VERSION])?;
+        file.write_all(&bincode::serialize(contents)?)?;
+        file.sync_all()?;
+        std::fs::rename(&temp_path, &self.path)?;
+        Ok(())
+    }
+}
+
+impl VaultStore for FileStore {
+    fn vault_exists(&self) -> Result<bool, Box<dyn std::error::Error>> {
+        Ok(self.path.exists())
+    }
+
+    fn write(&self, write: VaultWrite) -> Result<(), Box<dyn std::error::Error>> {
+        let _lock = self.lock()?;
+        let mut contents = self.read()?;
+        contents.apply(write);
+        self.replace(&contents)
+    }
+
+    fn append_audit(&self, entries: &[EncryptedRecord], head: &AuditHead) -> Result<(), Box<dyn std::error::Error>> {
+        let _lock = self.lock()?;
+        let mut contents = self.read()?;
+        contents.append_audit(entries, head);
+        self.replace(&contents)
+    }
+
+    fn load_kdf_params(&self) -> Result<Option<KdfParams>, Box<dyn std::error::Error>> {
+        Ok(self.read()?.kdf)
+    }
+
+    fn load_index(&self) -> Result<Option<EncryptedVault>, Box<dyn std::error::Error>> {
+        Ok(self.read()?.index)
+    }
+
+    fn load_manifest(&self) -> Result<Option<Vec<u8>>, Box<dyn std::error::Error>> {
+        Ok(self.read()?.manifest)
+    }
+
+    fn load_records(&self) -> Result<HashMap<String, EncryptedRecord>, Box<dyn std::error::Error>> {
+        Ok(self.read()?.records)
+    }
+
+    fn load_audit_head(&self) -> Result<Option<AuditHead>, Box<dyn std::error::Error>> {
+        Ok(self.read()?.audit_head)
+    }
+
+    fn load_audit(&self) -> Result<Vec<EncryptedRecord>, Box<dyn std::error::Error>> {
+        Ok(self.read()?.audit)
+    }
+
+    fn load_failed_unlocks(&self) -> Result<FailedUnlocks, Box<dyn std::error::Error>> {
+        Ok(self.read()?.failed_unlocks)
+    }
+
+    fn save_failed_unlocks(&self, failed: &FailedUnlocks) -> Result<(), Box<dyn std::error::Error>> {
+        let _lock = self.lock()?;
+        let mut contents = self.read()?;
+        contents.failed_unlocks = failed.clone();
+        self.replace(&contents)
+    }
+
+    fn reset(&self) -> Result<(), Box<dyn std::error::Error>> {
+        match std::fs::remove_file(&self.path) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::checks;
+
+    fn temp_path() -> PathBuf {
+        std::env::temp_dir().join(format!("password-manager-test-{}.pmv", uuid::Uuid::new_v4().simple()))
+    }
+
+    fn remove(path: &std::path::Path) {
+        std::fs::remove_file(path).unwrap();
+        let mut lock_name = path.file_name().unwrap().to_os_string();
+        lock_name.push(".lock");
+        std::fs::remove_file(path.with_file_name(lock_name)).unwrap();
+    }
+
+    #[test]
+    fn credentials_survive_reopening() {
+        let path = temp_path();
+        checks::round_trip(|| Box::new(FileStore::new(path.clone())));
+        remove(&path);
+    }
+
+    #[test]
+    fn edited_records_are_detected() {
+        let path = temp_path();
+        checks::tampering_is_detected(|| Box::new(FileStore::new(path.clone())));
+        remove(&path);
+    }
+
+    #[test]
+    fn other_formats_are_refused() {
+        let path = temp_path();
+        std::fs::write(&path, [MAGIC, &[VERSION + 1]].concat()).unwrap();
+        let error = FileStore::new(path.clone()).load_index().err().unwrap().to_string();
+        assert!(error.contains("vault format 2"), "{}", error);
+
+        std::fs::write(&path, b"not a vault").unwrap();
+        let error = FileStore::new(path.clone()).load_index().err().unwrap().to_string();
+        assert!(error.contains("is not a vault file"), "{}", error);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
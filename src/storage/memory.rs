@@ -0,0 +1,64 @@
+use super::{VaultContents, VaultStore, VaultWrite};
+use crate::models::{AuditHead, EncryptedRecord, EncryptedVault, FailedUnlocks, KdfParams};
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+/// A vault that only lives as long as the process, for trying things out and for tests.
+#[derive(Debug, Default)]
+pub struct MemoryStore {
+    contents: Mutex<VaultContents>,
+}
+
+impl VaultStore for MemoryStore {
+    fn vault_exists(&self) -> Result<bool, Box<dyn std::error::Error>> {
+        Ok(self.contents.lock().unwrap().index.is_some())
+    }
+
+    fn write(&self, write: VaultWrite) -> Result<(), Box<dyn std::error::Error>> {
+        self.contents.lock().unwrap().apply(write);
+        Ok(())
+    }
+
+    fn load_kdf_params(&self) -> Result<Option<KdfParams>, Box<dyn std::error::Error>> {
+        Ok(self.contents.lock().unwrap().kdf.clone())
+    }
+
+    fn load_index(&self) -> Result<Option<EncryptedVault>, Box<dyn std::error::Error>> {
+        Ok(self.contents.lock().unwrap().index.clone())
+    }
+
+    fn load_manifest(&self) -> Result<Option<Vec<u8>>, Box<dyn std::error::Error>> {
+        Ok(self.contents.lock().unwrap().manifest.clone())
+    }
+
+    fn load_records(&self) -> Result<HashMap<String, EncryptedRecord>, Box<dyn std::error::Error>> {
+        Ok(self.contents.lock().unwrap().records.clone())
+    }
+
+    fn append_audit(&self, entries: &[EncryptedRecord], head: &AuditHead) -> Result<(), Box<dyn std::error::Error>> {
+        self.contents.lock().unwrap().append_audit(entries, head);
+        Ok(())
+    }
+
+    fn load_audit_head(&self) -> Result<Option<AuditHead>, Box<dyn std::error::Error>> {
+        Ok(self.contents.lock().unwrap().audit_head.clone())
+    }
+
+    fn load_audit(&self) -> Result<Vec<EncryptedRecord>, Box<dyn std::error::Error>> {
+        Ok(self.contents.lock().unwrap().audit.clone())
+    }
+
+    fn load_failed_unlocks(&self) -> Result<FailedUnlocks, Box<dyn std::error::Error>> {
+        Ok(self.contents.lock().unwrap().failed_unlocks.clone())
+    }
+
+    fn save_failed_unlocks(&self, failed: &FailedUnlocks) -> Result<(), Box<dyn std::error::Error>> {
+        self.contents.lock().unwrap().failed_unlocks = failed.clone();
+        Ok(())
+    }
+
+    fn reset(&self) -> Result<(), Box<dyn std::error::Error>> {
+        *self.contents.lock().unwrap() = VaultContents::default();
+        Ok(())
+    }
+}
@@ -0,0 +1,223 @@
+mod file;
+mod memory;
+mod sled;
+mod sqlite;
+
+pub use self::file::FileStore;
+pub use self::memory::MemoryStore;
+pub use self::sled::SledStore;
+pub use self::sqlite::SqliteStore;
+
+use crate::models::{AuditHead, EncryptedRecord, EncryptedVault, FailedUnlocks, KdfParams};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+/// Everything one save writes. Stores apply it atomically: a reader sees all of it or none of it.
+pub struct VaultWrite<'a> {
+    /// The encrypted [`crate::models::VaultIndex`] together with the salt.
+    pub index: &'a EncryptedVault,
+    pub manifest: &'a [u8],
+    pub kdf: &'a KdfParams,
+    /// Changed records by id; `None` removes the record.
+    pub records: Vec<(String, Option<EncryptedRecord>)>,
+    /// Drop every record not in `records` first, as when the vault is re-keyed.
+    pub replace_all: bool,
+}
+
+/// Where a vault's encrypted records, index and manifest are kept.
+///
+/// Stores only ever see ciphertext; encryption and the manifest check live in
+/// [`crate::manager::PasswordManager`], so every backend gets the same tamper detection.
+pub trait VaultStore: Send {
+    fn vault_exists(&self) -> Result<bool, Box<dyn std::error::Error>>;
+
+    fn write(&self, write: VaultWrite) -> Result<(), Box<dyn std::error::Error>>;
+
+    /// The stored key derivation parameters, or `None` for vaults written before they were recorded.
+    fn load_kdf_params(&self) -> Result<Option<KdfParams>, Box<dyn std::error::Error>>;
+
+    fn load_index(&self) -> Result<Option<EncryptedVault>, Box<dyn std::error::Error>>;
+
+    fn load_manifest(&self) -> Result<Option<Vec<u8>>, Box<dyn std::error::Error>>;
+
+    /// Every stored record by id, still encrypted.
+    fn load_records(&self) -> Result<HashMap<String, EncryptedRecord>, Box<dyn std::error::Error>>;
+
+    /// A single-blob vault from before records were stored separately. Only sled vaults have one.
+    fn load_legacy_vault(&self) -> Result<Option<EncryptedVault>, Box<dyn std::error::Error>> {
+        Ok(None)
+    }
+
+    /// Adds entries to the end of the audit log and replaces its head, both or neither.
+    fn append_audit(&self, entries: &[EncryptedRecord], head: &AuditHead) -> Result<(), Box<dyn std::error::Error>>;
+
+    fn load_audit_head(&self) -> Result<Option<AuditHead>, Box<dyn std::error::Error>>;
+
+    /// The audit log in the order it was written, still encrypted.
+    fn load_audit(&self) -> Result<Vec<EncryptedRecord>, Box<dyn std::error::Error>>;
+
+    /// Failed unlocks since the last successful one, kept unencrypted so they count while locked.
+    fn load_failed_unlocks(&self) -> Result<FailedUnlocks, Box<dyn std::error::Error>>;
+
+    fn save_failed_unlocks(&self, failed: &FailedUnlocks) -> Result<(), Box<dyn std::error::Error>>;
+
+    /// Deletes the vault and its audit log.
+    fn reset(&self) -> Result<(), Box<dyn std::error::Error>>;
+}
+
+/// A whole vault as one value, kept by the in-memory store and serialized by the file store.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct VaultContents {
+    kdf: Option<KdfParams>,
+    index: Option<EncryptedVault>,
+    manifest: Option<Vec<u8>>,
+    records: HashMap<String, EncryptedRecord>,
+    audit: Vec<EncryptedRecord>,
+    audit_head: Option<AuditHead>,
+    failed_unlocks: FailedUnlocks,
+}
+
+impl VaultContents {
+    fn apply(&mut self, write: VaultWrite) {
+        if write.replace_all {
+            self.records.clear();
+        }
+        for (id, record) in write.records {
+            match record {
+                Some(record) => self.records.insert(id, record),
+                None => self.records.remove(&id),
+            };
+        }
+        self.index = Some(write.index.clone());
+        self.manifest = Some(write.manifest.to_vec());
+        self.kdf = Some(write.kdf.clone());
+    }
+
+    fn append_audit(&mut self, entries: &[EncryptedRecord], head: &AuditHead) {
+        self.audit.extend_from_slice(entries);
+        self.audit_head = Some(head.clone());
+    }
+}
+
+/// The storage backends a vault can use.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Backend {
+    /// A sled database directory.
+    #[default]
+    Sled,
+    /// One file, easy to sync with Dropbox, Syncthing and the like.
+    File,
+    Sqlite,
+    /// Nothing is written to disk; the vault is gone when the program exits.
+    Memory,
+}
+
+impl Backend {
+    /// The vault location used when the settings leave `path` empty.
+    fn default_path(self) -> PathBuf {
+        let data_dir = dirs::data_dir()
+            .unwrap_or_else(|| PathBuf::from("."))
+            .join("password_manager");
+        match self {
+            Backend::Sled | Backend::Memory => data_dir.join("vault.db"),
+            Backend::File => data_dir.join("vault.pmv"),
+            Backend::Sqlite => data_dir.join("vault.sqlite3"),
+        }
+    }
+}
+
+/// Which backend a vault uses and where it lives, the `[vault]` table of the settings file.
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct VaultSettings {
+    pub backend: Backend,
+    /// File or directory of the vault; empty for the backend's default in the data directory.
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub path: String,
+}
+
+impl VaultSettings {
+    pub fn path(&self) -> PathBuf {
+        if self.path.trim().is_empty() {
+            self.backend.default_path()
+        } else {
+            PathBuf::from(self.path.trim())
+        }
+    }
+
+    pub fn open(&self) -> Result<Box<dyn VaultStore>, Box<dyn std::error::Error>> {
+        let path = self.path();
+        if self.backend != Backend::Memory {
+            if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+                std::fs::create_dir_all(dir)?;
+            }
+        }
+        let store: Box<dyn VaultStore> = match self.backend {
+            Backend::Sled => Box::new(SledStore::open(&path)?),
+            Backend::File => Box::new(FileStore::new(path)),
+            Backend::Sqlite => Box::new(SqliteStore::open(&path)?),
+            Backend::Memory => Box::new(MemoryStore::default()),
+        };
+        Ok(store)
+    }
+}
+
+/// Checks shared by the persistent backends' tests, each given a function that opens the same vault again.
+#[cfg(test)]
+pub(crate) mod checks {
+    use super::{VaultStore, VaultWrite};
+    use crate::manager::PasswordManager;
+    use crate::models::Credential;
+
+    fn unlock(store: Box<dyn VaultStore>) -> Result<PasswordManager, Box<dyn std::error::Error>> {
+        let mut manager = PasswordManager::new(store);
+        manager.set_kdf_iterations(1_000);
+        assert!(manager.unlock("correct horse")?);
+        Ok(manager)
+    }
+
+    fn add(manager: &PasswordManager, service: &str) -> String {
+        let credential = Credential::new_password(service.into(), "me".into(), b"hunter2".to_vec(), String::new(), Vec::new());
+        let id = credential.id.clone();
+        manager.add_credential(credential).unwrap();
+        id
+    }
+
+    /// Credentials written, edited and deleted are found as they were left after reopening.
+    pub fn round_trip(open: impl Fn() -> Box<dyn VaultStore>) {
+        let manager = unlock(open()).unwrap();
+        let github = add(&manager, "github");
+        let gitlab = add(&manager, "gitlab");
+        manager.set_favorite(&github, true).unwrap();
+        manager.purge(std::slice::from_ref(&gitlab)).unwrap();
+        drop(manager);
+
+        let manager = unlock(open()).unwrap();
+        let credential = manager.get_credential(&github).unwrap().unwrap();
+        assert_eq!((credential.service.as_str(), credential.secret.as_slice()), ("github", b"hunter2".as_slice()));
+        assert!(credential.favorite);
+        assert!(manager.get_credential(&gitlab).unwrap().is_none());
+        assert_eq!(manager.get_credentials().unwrap().len(), 1);
+    }
+
+    /// A record changed in the store behind the vault's back stops it unlocking.
+    pub fn tampering_is_detected(open: impl Fn() -> Box<dyn VaultStore>) {
+        let manager = unlock(open()).unwrap();
+        let id = add(&manager, "github");
+        drop(manager);
+
+        let store = open();
+        let mut record = store.load_records().unwrap().remove(&id).unwrap();
+        record.ciphertext[0] ^= 1;
+        let index = store.load_index().unwrap().unwrap();
+        let manifest = store.load_manifest().unwrap().unwrap();
+        let kdf = store.load_kdf_params().unwrap().unwrap();
+        store.write(VaultWrite { index: &index, manifest: &manifest, kdf: &kdf, records: vec![(id, Some(record))], replace_all: false }).unwrap();
+        drop(store);
+
+        let error = unlock(open()).err().expect("a tampered vault unlocked").to_string();
+        assert!(error.contains("modified or rolled back"), "{}", error);
+    }
+}
@@ -0,0 +1,189 @@
+use super::{VaultStore, VaultWrite};
+use crate::models::{AuditHead, EncryptedRecord, EncryptedVault, FailedUnlocks, KdfParams};
+use std::collections::HashMap;
+use std::path::Path;
+
+/// Prefix of the per-credential record keys, followed by the credential id.
+const RECORD_PREFIX: &[u8] = b"record/";
+
+/// Prefix of the audit log entry keys, followed by the entry number in big-endian so they sort in order.
+const AUDIT_PREFIX: &[u8] = b"audit/";
+
+/// The vault in sled, one key per credential:
+///
+/// - `index`: the encrypted index and the key derivation salt
+/// - `manifest`: a MAC over the index, checked before any record is trusted
+/// - `kdf`: the key derivation parameters
+/// - `record/<id>`: one encrypted credential
+/// - `audit/<n>`, `audit_head`: the audit log
+/// - `failed_unlocks`: failed unlocks since the last successful one
+///
+/// Vaults written by earlier versions keep everything in a single `vault` blob; they are read
+/// through [`VaultStore::load_legacy_vault`] and converted on the first save.
+pub struct SledStore {
+    db: sled::Db,
+}
+
+impl SledStore {
+    /// Opens the database, which sled locks for as long as it is open: while the TUI has the
+    /// vault open, helpers started by git or docker can't open it too.
+    pub fn open(path: &Path) -> Result<Self, Box<dyn std::error::Error>> {
+        match sled::open(path) {
+            Ok(db) => Ok(Self { db }),
+            Err(sled::Error::Io(e)) if e.to_string().starts_with("could not acquire lock") => Err(format!(
+                "The vault at {} is open in another process. Close the password manager there and try again.",
+                path.display()
+            )
+            .into()),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+impl VaultStore for SledStore {
+    fn vault_exists(&self) -> Result<bool, Box<dyn std::error::Error>> {
+        Ok(self.db.contains_key(b"index")? || self.db.contains_key(b"vault")?)
+    }
+
+    fn write(&self, write: VaultWrite) -> Result<(), Box<dyn std::error::Error>> {
+        let mut batch = sled::Batch::default();
+        if write.replace_all {
+            for key in self.db.scan_prefix(RECORD_PREFIX).keys() {
+                batch.remove(key?);
+            }
+            batch.remove(&b"vault"[..]);
+        }
+        for (id, record) in &write.records {
+            let key = record_key(id);
+            match record {
+                Some(record) => batch.insert(key, bincode::serialize(record)?),
+                None => batch.remove(key),
+            }
+        }
+        batch.insert(&b"index"[..], bincode::serialize(write.index)?);
+        batch.insert(&b"manifest"[..], write.manifest);
+        batch.insert(&b"kdf"[..], bincode::serialize(write.kdf)?);
+        self.db.apply_batch(batch)?;
+        self.db.flush()?;
+        Ok(())
+    }
+
+    fn load_kdf_params(&self) -> Result<Option<KdfParams>, Box<dyn std::error::Error>> {
+        match self.db.get(b"kdf")? {
+            Some(data) => Ok(Some(bincode::deserialize(&data)?)),
+            None => Ok(None),
+        }
+    }
+
+    fn load_index(&self) -> Result<Option<EncryptedVault>, Box<dyn std::error::Error>> {
+        match self.db.get(b"index")? {
+            Some(data) => Ok(Some(bincode::deserialize(&data)?)),
+            None => Ok(None),
+        }
+    }
+
+    fn load_manifest(&self) -> Result<Option<Vec<u8>>, Box<dyn std::error::Error>> {
+        Ok(self.db.get(b"manifest")?.map(|data| data.to_vec()))
+    }
+
+    fn load_records(&self) -> Result<HashMap<String, EncryptedRecord>, Box<dyn std::error::Error>> {
+        let mut records = HashMap::new();
+        for entry in self.db.scan_prefix(RECORD_PREFIX) {
+            let (key, data) = entry?;
+            let id = String::from_utf8(key[RECORD_PREFIX.len()..].to_vec())?;
+            records.insert(id, bincode::deserialize(&data)?);
+        }
+        Ok(records)
+    }
+
+    fn append_audit(&self, entries: &[EncryptedRecord], head: &AuditHead) -> Result<(), Box<dyn std::error::Error>> {
+        let mut batch = sled::Batch::default();
+        let first = head.count - entries.len() as u64;
+        for (n, entry) in (first..).zip(entries) {
+            batch.insert([AUDIT_PREFIX, &n.to_be_bytes()].concat(), bincode::serialize(entry)?);
+        }
+        batch.insert(&b"audit_head"[..], bincode::serialize(head)?);
+        self.db.apply_batch(batch)?;
+        self.db.flush()?;
+        Ok(())
+    }
+
+    fn load_audit_head(&self) -> Result<Option<AuditHead>, Box<dyn std::error::Error>> {
+        match self.db.get(b"audit_head")? {
+            Some(data) => Ok(Some(bincode::deserialize(&data)?)),
+            None => Ok(None),
+        }
+    }
+
+    fn load_audit(&self) -> Result<Vec<EncryptedRecord>, Box<dyn std::error::Error>> {
+        self.db
+            .scan_prefix(AUDIT_PREFIX)
+            .values()
+            .map(|data| Ok(bincode::deserialize(&data?)?))
+            .collect()
+    }
+
+    fn load_failed_unlocks(&self) -> Result<FailedUnlocks, Box<dyn std::error::Error>> {
+        match self.db.get(b"failed_unlocks")? {
+            Some(data) => Ok(bincode::deserialize(&data)?),
+            None => Ok(FailedUnlocks::default()),
+        }
+    }
+
+    fn save_failed_unlocks(&self, failed: &FailedUnlocks) -> Result<(), Box<dyn std::error::Error>> {
+        self.db.insert(b"failed_unlocks", bincode::serialize(failed)?)?;
+        self.db.flush()?;
+        Ok(())
+    }
+
+    fn load_legacy_vault(&self) -> Result<Option<EncryptedVault>, Box<dyn std::error::Error>> {
+        match self.db.get(b"vault")? {
+            Some(data) => {
+                match bincode::deserialize(&data) {
+                    Ok(vault) => Ok(Some(vault)),
+                    Err(e) => {
+                        // If we can't deserialize, clear the corrupt data
+                        eprintln!("Error deserializing vault: {}", e);
+                        self.db.remove(b"vault")?;
+                        Ok(None)
+                    }
+                }
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn reset(&self) -> Result<(), Box<dyn std::error::Error>> {
+        self.db.clear()?;
+        self.db.flush()?;
+        Ok(())
+    }
+}
+
+fn record_key(id: &str) -> Vec<u8> {
+    [RECORD_PREFIX, id.as_bytes()].concat()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn a_vault_open_elsewhere_is_reported_as_such() {
+        let path = std::env::temp_dir().join(format!("password-manager-test-{}", uuid::Uuid::new_v4().simple()));
+        let first = SledStore::open(&path).unwrap();
+        let error = SledStore::open(&path).err().unwrap().to_string();
+        assert!(error.contains("open in another process"), "{}", error);
+
+        // Sled's background flusher can hold the lock for a moment after the last handle is dropped.
+        drop(first);
+        let reopened = (0..50).any(|_| {
+            SledStore::open(&path).is_ok() || {
+                std::thread::sleep(std::time::Duration::from_millis(20));
+                false
+            }
+        });
+        assert!(reopened);
+        std::fs::remove_dir_all(&path).unwrap();
+    }
+}
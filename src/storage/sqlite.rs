@@ -0,0 +1,165 @@
+use super::{VaultStore, VaultWrite};
+use crate::models::{AuditHead, EncryptedRecord, EncryptedVault, FailedUnlocks, KdfParams};
+use rusqlite::{params, Connection, OptionalExtension};
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Mutex;
+
+/// The vault in an SQLite database: the index, manifest and key derivation parameters in `meta`,
+/// one row per credential in `records`, the audit log in `audit`. Each save is one SQL transaction.
+pub struct SqliteStore {
+    conn: Mutex<Connection>,
+}
+
+impl SqliteStore {
+    pub fn open(path: &Path) -> Result<Self, Box<dyn std::error::Error>> {
+        let conn = Connection::open(path)?;
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS meta (key TEXT PRIMARY KEY, value BLOB NOT NULL);
+             CREATE TABLE IF NOT EXISTS records (id TEXT PRIMARY KEY, nonce BLOB NOT NULL, ciphertext BLOB NOT NULL);
+             CREATE TABLE IF NOT EXISTS audit (seq INTEGER PRIMARY KEY, nonce BLOB NOT NULL, ciphertext BLOB NOT NULL);",
+        )?;
+        Ok(Self { conn: Mutex::new(conn) })
+    }
+
+    fn load_meta(&self, key: &str) -> Result<Option<Vec<u8>>, Box<dyn std::error::Error>> {
+        let conn = self.conn.lock().unwrap();
+        Ok(conn
+            .query_row("SELECT value FROM meta WHERE key = ?1", [key], |row| row.get(0))
+            .optional()?)
+    }
+}
+
+impl VaultStore for SqliteStore {
+    fn vault_exists(&self) -> Result<bool, Box<dyn std::error::Error>> {
+        Ok(self.load_meta("index")?.is_some())
+    }
+
+    fn write(&self, write: VaultWrite) -> Result<(), Box<dyn std::error::Error>> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        if write.replace_all {
+            tx.execute("DELETE FROM records", [])?;
+        }
+        for (id, record) in &write.records {
+            match record {
+                Some(record) => tx.execute(
+                    "INSERT OR REPLACE INTO records (id, nonce, ciphertext) VALUES (?1, ?2, ?3)",
+                    params![id, record.nonce, record.ciphertext],
+                )?,
+                None => tx.execute("DELETE FROM records WHERE id = ?1", [id])?,
+            };
+        }
+        let meta = [
+            ("index", bincode::serialize(write.index)?),
+            ("manifest", write.manifest.to_vec()),
+            ("kdf", bincode::serialize(write.kdf)?),
+        ];
+        for (key, value) in meta {
+            tx.execute("INSERT OR REPLACE INTO meta (key, value) VALUES (?1, ?2)", params![key, value])?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    fn load_kdf_params(&self) -> Result<Option<KdfParams>, Box<dyn std::error::Error>> {
+        match self.load_meta("kdf")? {
+            Some(data) => Ok(Some(bincode::deserialize(&data)?)),
+            None => Ok(None),
+        }
+    }
+
+    fn load_index(&self) -> Result<Option<EncryptedVault>, Box<dyn std::error::Error>> {
+        match self.load_meta("index")? {
+            Some(data) => Ok(Some(bincode::deserialize(&data)?)),
+            None => Ok(None),
+        }
+    }
+
+    fn load_manifest(&self) -> Result<Option<Vec<u8>>, Box<dyn std::error::Error>> {
+        self.load_meta("manifest")
+    }
+
+    fn load_records(&self) -> Result<HashMap<String, EncryptedRecord>, Box<dyn std::error::Error>> {
+        let conn = self.conn.lock().unwrap();
+        let mut statement = conn.prepare("SELECT id, nonce, ciphertext FROM records")?;
+        let rows = statement.query_map([], |row| {
+            Ok((row.get(0)?, EncryptedRecord { nonce: row.get(1)?, ciphertext: row.get(2)? }))
+        })?;
+        let mut records = HashMap::new();
+        for row in rows {
+            let (id, record) = row?;
+            records.insert(id, record);
+        }
+        Ok(records)
+    }
+
+    fn append_audit(&self, entries: &[EncryptedRecord], head: &AuditHead) -> Result<(), Box<dyn std::error::Error>> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        let first = head.count - entries.len() as u64;
+        for (seq, entry) in (first..).zip(entries) {
+            tx.execute(
+                "INSERT OR REPLACE INTO audit (seq, nonce, ciphertext) VALUES (?1, ?2, ?3)",
+                params![seq as i64, entry.nonce, entry.ciphertext],
+            )?;
+        }
+        tx.execute("INSERT OR REPLACE INTO meta (key, value) VALUES ('audit_head', ?1)", [bincode::serialize(head)?])?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    fn load_audit_head(&self) -> Result<Option<AuditHead>, Box<dyn std::error::Error>> {
+        match self.load_meta("audit_head")? {
+            Some(data) => Ok(Some(bincode::deserialize(&data)?)),
+            None => Ok(None),
+        }
+    }
+
+    fn load_audit(&self) -> Result<Vec<EncryptedRecord>, Box<dyn std::error::Error>> {
+        let conn = self.conn.lock().unwrap();
+        let mut statement = conn.prepare("SELECT nonce, ciphertext FROM audit ORDER BY seq")?;
+        let rows = statement.query_map([], |row| Ok(EncryptedRecord { nonce: row.get(0)?, ciphertext: row.get(1)? }))?;
+        Ok(rows.collect::<Result<_, _>>()?)
+    }
+
+    fn load_failed_unlocks(&self) -> Result<FailedUnlocks, Box<dyn std::error::Error>> {
+        match self.load_meta("failed_unlocks")? {
+            Some(data) => Ok(bincode::deserialize(&data)?),
+            None => Ok(FailedUnlocks::default()),
+        }
+    }
+
+    fn save_failed_unlocks(&self, failed: &FailedUnlocks) -> Result<(), Box<dyn std::error::Error>> {
+        self.conn.lock().unwrap().execute(
+            "INSERT OR REPLACE INTO meta (key, value) VALUES ('failed_unlocks', ?1)",
+            [bincode::serialize(failed)?],
+        )?;
+        Ok(())
+    }
+
+    fn reset(&self) -> Result<(), Box<dyn std::error::Error>> {
+        self.conn.lock().unwrap().execute_batch("DELETE FROM records; DELETE FROM meta; DELETE FROM audit;")?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::checks;
+
+    #[test]
+    fn credentials_survive_reopening() {
+        let path = std::env::temp_dir().join(format!("password-manager-test-{}.sqlite3", uuid::Uuid::new_v4().simple()));
+        checks::round_trip(|| Box::new(SqliteStore::open(&path).unwrap()));
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn edited_records_are_detected() {
+        let path = std::env::temp_dir().join(format!("password-manager-test-{}.sqlite3", uuid::Uuid::new_v4().simple()));
+        checks::tampering_is_detected(|| Box::new(SqliteStore::open(&path).unwrap()));
+        std::fs::remove_file(&path).unwrap();
+    }
+}
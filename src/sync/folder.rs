@@ -0,0 +1,95 @@
+use super::{is_valid_device, SyncStore};
+use std::collections::BTreeMap;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+/// Change logs in a directory: `sync.key` and one `<device>.log` per device.
+///
+/// Devices never write each other's files, so the directory can be shared by any tool that copies
+/// files around. The sync server keeps its data in the same layout.
+pub struct FolderStore {
+    dir: PathBuf,
+}
+
+impl FolderStore {
+    pub fn new(dir: PathBuf) -> Self {
+        Self { dir }
+    }
+
+    fn log_path(&self, device: &str) -> Result<PathBuf, Box<dyn std::error::Error>> {
+        if !is_valid_device(device) {
+            return Err(format!("Invalid device name '{}'", device).into());
+        }
+        Ok(self.dir.join(format!("{}.log", device)))
+    }
+}
+
+impl SyncStore for FolderStore {
+    fn load_key_file(&self) -> Result<Option<Vec<u8>>, Box<dyn std::error::Error>> {
+        match std::fs::read(self.dir.join("sync.key")) {
+            Ok(data) => Ok(Some(data)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn create_key_file(&self, data: &[u8]) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        if let Some(existing) = self.load_key_file()? {
+            return Ok(existing);
+        }
+        std::fs::create_dir_all(&self.dir)?;
+        write_atomically(&self.dir.join("sync.key"), data)?;
+        Ok(data.to_vec())
+    }
+
+    fn logs(&self) -> Result<BTreeMap<String, u64>, Box<dyn std::error::Error>> {
+        let mut logs = BTreeMap::new();
+        let entries = match std::fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(logs),
+            Err(e) => return Err(format!("Could not read {}: {}", self.dir.display(), e).into()),
+        };
+        for entry in entries {
+            let entry = entry?;
+            let path = entry.path();
+            if path.extension().is_some_and(|ext| ext == "log") {
+                if let Some(device) = path.file_stem().and_then(|stem| stem.to_str()).filter(|d| is_valid_device(d)) {
+                    logs.insert(device.to_string(), entry.metadata()?.len());
+                }
+            }
+        }
+        Ok(logs)
+    }
+
+    fn read_log(&self, device: &str, from: u64) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        let mut file = std::fs::File::open(self.log_path(device)?)?;
+        file.seek(SeekFrom::Start(from))?;
+        let mut data = Vec::new();
+        file.read_to_end(&mut data)?;
+        Ok(data)
+    }
+
+    fn append_log(&self, device: &str, data: &[u8]) -> Result<(), Box<dyn std::error::Error>> {
+        std::fs::create_dir_all(&self.dir)?;
+        let mut file = std::fs::OpenOptions::new().create(true).append(true).open(self.log_path(device)?)?;
+        file.write_all(data)?;
+        file.sync_all()?;
+        Ok(())
+    }
+
+    fn replace_log(&self, device: &str, data: &[u8]) -> Result<(), Box<dyn std::error::Error>> {
+        write_atomically(&self.log_path(device)?, data)
+    }
+}
+
+/// Writes to a temporary file that is then renamed over `path`, so readers never see half a file.
+fn write_atomically(path: &Path, data: &[u8]) -> Result<(), Box<dyn std::error::Error>> {
+    let mut temp_name = path.file_name().unwrap_or_default().to_os_string();
+    temp_name.push(".tmp");
+    let temp_path = path.with_file_name(temp_name);
+    let mut file = std::fs::File::create(&temp_path)?;
+    file.write_all(data)?;
+    file.sync_all()?;
+    std::fs::rename(&temp_path, path)?;
+    Ok(())
+}
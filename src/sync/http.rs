@@ -0,0 +1,119 @@
+use super::SyncStore;
+use std::collections::BTreeMap;
+use std::io::{Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+/// How long to wait for the server before giving up, so an unreachable server doesn't hold up saves.
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Change logs kept by a sync server (`password-manager serve`), spoken to over plain HTTP/1.1.
+///
+/// Everything sent is already encrypted; the optional token only keeps strangers from writing
+/// to the server.
+pub struct HttpStore {
+    /// `host:port` from the URL.
+    address: String,
+    /// Path prefix from the URL, without a trailing slash.
+    prefix: String,
+    token: String,
+}
+
+impl HttpStore {
+    /// Takes a URL such as `http://127.0.0.1:8750` or `http://sync.lan/passwords`.
+    pub fn new(url: &str, token: &str) -> Self {
+        let rest = url.trim_start_matches("http://");
+        let (address, prefix) = match rest.find('/') {
+            Some(i) => (&rest[..i], rest[i..].trim_end_matches('/')),
+            None => (rest, ""),
+        };
+        let address = if address.contains(':') { address.to_string() } else { format!("{}:80", address) };
+        Self { address, prefix: prefix.to_string(), token: token.to_string() }
+    }
+
+    /// Sends one request and returns the status code and body.
+    fn request(&self, method: &str, path: &str, body: &[u8]) -> Result<(u16, Vec<u8>), Box<dyn std::error::Error>> {
+        let unreachable = |e: std::io::Error| format!("Could not reach the sync server at {}: {}", self.address, e);
+        let address = self
+            .address
+            .to_socket_addrs()
+            .map_err(unreachable)?
+            .next()
+            .ok_or_else(|| format!("Could not resolve {}", self.address))?;
+        let mut stream = TcpStream::connect_timeout(&address, TIMEOUT).map_err(unreachable)?;
+        stream.set_read_timeout(Some(TIMEOUT))?;
+        stream.set_write_timeout(Some(TIMEOUT))?;
+        let host = self.address.trim_end_matches(":80");
+        let mut head = format!(
+            "{} {}{} HTTP/1.1\r\nHost: {}\r\nContent-Length: {}\r\nConnection: close\r\n",
+            method,
+            self.prefix,
+            path,
+            host,
+            body.len()
+        );
+        if !self.token.is_empty() {
+            head.push_str(&format!("Authorization: Bearer {}\r\n", self.token));
+        }
+        head.push_str("\r\n");
+        stream.write_all(head.as_bytes())?;
+        stream.write_all(body)?;
+        let mut response = Vec::new();
+        stream.read_to_end(&mut response)?;
+
+        let end = response
+            .windows(4)
+            .position(|w| w == b"\r\n\r\n")
+            .ok_or("Malformed response from the sync server")?;
+        let head = String::from_utf8_lossy(&response[..end]);
+        let status = head
+            .split_whitespace()
+            .nth(1)
+            .and_then(|code| code.parse().ok())
+            .ok_or("Malformed response from the sync server")?;
+        let body = response[end + 4..].to_vec();
+        Ok((status, body))
+    }
+
+    /// Like [`HttpStore::request`], but turns anything other than 2xx into an error.
+    fn expect_success(&self, method: &str, path: &str, body: &[u8]) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        let (status, body) = self.request(method, path, body)?;
+        match status {
+            200..=299 => Ok(body),
+            401 => Err("The sync server rejected the token".into()),
+            _ => Err(format!("The sync server answered {}: {}", status, String::from_utf8_lossy(&body).trim()).into()),
+        }
+    }
+}
+
+impl SyncStore for HttpStore {
+    fn load_key_file(&self) -> Result<Option<Vec<u8>>, Box<dyn std::error::Error>> {
+        match self.request("GET", "/v1/key", &[])? {
+            (404, _) => Ok(None),
+            (200, body) => Ok(Some(body)),
+            _ => self.expect_success("GET", "/v1/key", &[]).map(Some),
+        }
+    }
+
+    fn create_key_file(&self, data: &[u8]) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        self.expect_success("PUT", "/v1/key", data)
+    }
+
+    fn logs(&self) -> Result<BTreeMap<String, u64>, Box<dyn std::error::Error>> {
+        Ok(serde_json::from_slice(&self.expect_success("GET", "/v1/logs", &[])?)?)
+    }
+
+    fn read_log(&self, device: &str, from: u64) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        self.expect_success("GET", &format!("/v1/logs/{}?from={}", device, from), &[])
+    }
+
+    fn append_log(&self, device: &str, data: &[u8]) -> Result<(), Box<dyn std::error::Error>> {
+        self.expect_success("POST", &format!("/v1/logs/{}", device), data)?;
+        Ok(())
+    }
+
+    fn replace_log(&self, device: &str, data: &[u8]) -> Result<(), Box<dyn std::error::Error>> {
+        self.expect_success("PUT", &format!("/v1/logs/{}", device), data)?;
+        Ok(())
+    }
+}
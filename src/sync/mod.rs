@@ -0,0 +1,514 @@
+mod folder;
+mod http;
+
+pub use self::folder::FolderStore;
+pub use self::http::HttpStore;
+
+use crate::crypto::CryptoService;
+use crate::keyfile::KeyFiles;
+use crate::models::{Credential, EncryptedRecord, KdfParams, VersionVector};
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashMap};
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+/// Decrypts with the sync key; used to tell a wrong master password from a damaged log.
+const KEY_CHECK: &[u8] = b"password-manager sync";
+
+/// The `[sync]` table of the settings file.
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SyncSettings {
+    /// Directory shared between devices, e.g. a Syncthing folder or a USB stick.
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub folder: String,
+    /// URL of a sync server started with `password-manager serve`, instead of a folder.
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub server: String,
+    /// Access token the server was started with.
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub token: String,
+    /// Name of this device's change log; empty for an id generated when the vault first syncs.
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub device: String,
+}
+
+impl SyncSettings {
+    pub fn validate(&self) -> Result<(), String> {
+        if !self.folder.trim().is_empty() && !self.server.trim().is_empty() {
+            return Err("sync folder and sync server cannot both be set".to_string());
+        }
+        if !self.server.trim().is_empty() && !self.server.trim().starts_with("http://") {
+            return Err("sync server must be an http:// URL".to_string());
+        }
+        if !self.device.is_empty() && !is_valid_device(&self.device) {
+            return Err("sync device may only contain letters, digits, '.', '-' and '_'".to_string());
+        }
+        Ok(())
+    }
+}
+
+pub fn is_valid_device(device: &str) -> bool {
+    !device.is_empty() && !device.starts_with('.') && device.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '_'))
+}
+
+/// One change to a credential as written to a device's log: its new version, or `None` once purged.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChangeEntry {
+    pub id: String,
+    pub versions: VersionVector,
+    pub credential: Option<Credential>,
+}
+
+/// The entries of one device's change log, in the order they were written.
+pub struct DeviceLog {
+    pub device: String,
+    pub entries: Vec<ChangeEntry>,
+}
+
+/// What the last sync did, for the unlock message.
+#[derive(Debug, Clone, Default)]
+pub struct SyncReport {
+    /// Credentials changed by other devices.
+    pub received: usize,
+    /// Local changes written to this device's log.
+    pub sent: usize,
+    /// Conflicts waiting to be resolved, including earlier ones.
+    pub conflicts: usize,
+}
+
+/// Salt, cost and extra factors of the key the change logs are encrypted with, shared by every device.
+#[derive(Serialize, Deserialize)]
+struct SyncKeyFile {
+    salt: Vec<u8>,
+    kdf: KdfParams,
+    check: EncryptedRecord,
+}
+
+/// Where the change logs live. Stores only ever handle the encrypted key file and log bytes.
+///
+/// A log is a sequence of entries, each a little-endian `u32` length followed by that many bytes.
+/// Its length in bytes doubles as its version: readers remember how far they got and ask for the rest.
+pub trait SyncStore: Send {
+    fn load_key_file(&self) -> Result<Option<Vec<u8>>, Box<dyn std::error::Error>>;
+
+    /// Stores the key file unless another device got there first; returns whichever is stored.
+    fn create_key_file(&self, data: &[u8]) -> Result<Vec<u8>, Box<dyn std::error::Error>>;
+
+    /// Every device with a log, and the log's length.
+    fn logs(&self) -> Result<BTreeMap<String, u64>, Box<dyn std::error::Error>>;
+
+    /// A device's log from byte `from` on.
+    fn read_log(&self, device: &str, from: u64) -> Result<Vec<u8>, Box<dyn std::error::Error>>;
+
+    fn append_log(&self, device: &str, data: &[u8]) -> Result<(), Box<dyn std::error::Error>>;
+
+    fn replace_log(&self, device: &str, data: &[u8]) -> Result<(), Box<dyn std::error::Error>>;
+}
+
+impl SyncSettings {
+    /// The store the settings point at; `None` when sync is off.
+    pub fn open_store(&self) -> Option<Box<dyn SyncStore>> {
+        if !self.server.trim().is_empty() {
+            Some(Box::new(HttpStore::new(self.server.trim(), &self.token)))
+        } else if !self.folder.trim().is_empty() {
+            Some(Box::new(FolderStore::new(PathBuf::from(self.folder.trim()))))
+        } else {
+            None
+        }
+    }
+}
+
+/// The complete entries at the start of `data`, and how many bytes they take up. A cut-off entry at
+/// the end is a log still being written or copied in; it is picked up next time.
+pub fn split_entries(data: &[u8]) -> (Vec<&[u8]>, usize) {
+    let mut entries = Vec::new();
+    let mut offset = 0;
+    while data.len() - offset >= 4 {
+        let len = u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap()) as usize;
+        if data.len() - offset - 4 < len {
+            break;
+        }
+        entries.push(&data[offset + 4..offset + 4 + len]);
+        offset += 4 + len;
+    }
+    (entries, offset)
+}
+
+/// A device's connection to the other devices' change logs, through a [`SyncStore`].
+///
+/// Each vault has its own salt, so the logs use a key derived from the master password and a salt
+/// kept in the store; every device must use the same master password. The store is set up with the
+/// cost and key files of the first vault that syncs through it, and every device then needs the
+/// same key files too. Stores never see the key.
+pub struct SyncChannel {
+    store: Box<dyn SyncStore>,
+    device: String,
+    key: Vec<u8>,
+    /// How far each device's log has been read.
+    offsets: Mutex<HashMap<String, u64>>,
+    /// How many entries of this device's own log have been read, to know when to compact it.
+    own_entries: Mutex<usize>,
+}
+
+impl SyncChannel {
+    /// Connects to the store, setting up the key file on first use with the vault's `kdf`: its
+    /// cost, and whether the key files are part of the key.
+    pub fn open(
+        store: Box<dyn SyncStore>,
+        device: &str,
+        password: &str,
+        key_files: &KeyFiles,
+        kdf: &KdfParams,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let data = match store.load_key_file()? {
+            Some(data) => data,
+            None => {
+                let salt = CryptoService::generate_salt();
+                let secret = key_files.composite_key(password, kdf, &salt)?;
+                let key = CryptoService::derive_key(&secret, &salt, kdf.iterations);
+                let (nonce, ciphertext) = CryptoService::encrypt(KEY_CHECK, &key)?;
+                let file = SyncKeyFile { salt, kdf: kdf.clone(), check: EncryptedRecord { nonce, ciphertext } };
+                store.create_key_file(&bincode::serialize(&file)?)?
+            }
+        };
+        let file: SyncKeyFile = bincode::deserialize(&data)?;
+        let secret = key_files
+            .composite_key(password, &file.kdf, &file.salt)
+            .map_err(|e| format!("The sync store was set up with key files: {}", e))?;
+        let key = CryptoService::derive_key(&secret, &file.salt, file.kdf.iterations);
+        CryptoService::decrypt(&file.check.ciphertext, &file.check.nonce, &key)
+            .map_err(|_| "The sync store was set up with a different master password or key file")?;
+        Ok(Self {
+            store,
+            device: device.to_string(),
+            key,
+            offsets: Mutex::new(HashMap::new()),
+            own_entries: Mutex::new(0),
+        })
+    }
+
+    pub fn device(&self) -> &str {
+        &self.device
+    }
+
+    pub fn own_entries(&self) -> usize {
+        *self.own_entries.lock().unwrap()
+    }
+
+    /// The entries added to every device's log, this one's included, since the last call.
+    pub fn read_new(&self) -> Result<Vec<DeviceLog>, Box<dyn std::error::Error>> {
+        let mut offsets = self.offsets.lock().unwrap();
+        let mut logs = Vec::new();
+        for (device, len) in self.store.logs()? {
+            if !is_valid_device(&device) {
+                continue;
+            }
+            let mut offset = offsets.get(&device).copied().unwrap_or(0);
+            if len == offset {
+                continue;
+            }
+            if len < offset {
+                // The log was compacted; read it again from the start.
+                offset = 0;
+                if device == self.device {
+                    *self.own_entries.lock().unwrap() = 0;
+                }
+            }
+            let data = self.store.read_log(&device, offset)?;
+            let (records, consumed) = split_entries(&data);
+            let mut entries = Vec::with_capacity(records.len());
+            for record in records {
+                let record: EncryptedRecord = bincode::deserialize(record)?;
+                let decrypted = CryptoService::decrypt_with_aad(&record.ciphertext, &record.nonce, &self.key, device.as_bytes())
+                    .map_err(|_| format!("The change log of device '{}' could not be decrypted", device))?;
+                entries.push(serde_json::from_slice(&decrypted)?);
+            }
+            if device == self.device {
+                *self.own_entries.lock().unwrap() += entries.len();
+            }
+            offsets.insert(device.clone(), offset + consumed as u64);
+            logs.push(DeviceLog { device, entries });
+        }
+        Ok(logs)
+    }
+
+    /// Appends entries to this device's log.
+    pub fn append(&self, entries: &[ChangeEntry]) -> Result<(), Box<dyn std::error::Error>> {
+        if entries.is_empty() {
+            return Ok(());
+        }
+        self.store.append_log(&self.device, &self.encode(entries)?)
+    }
+
+    /// Replaces this device's log with `entries`, dropping the history it no longer needs.
+    pub fn rewrite(&self, entries: &[ChangeEntry]) -> Result<(), Box<dyn std::error::Error>> {
+        self.store.replace_log(&self.device, &self.encode(entries)?)?;
+        // Read the new log from the start next time, which also counts its entries again.
+        self.offsets.lock().unwrap().remove(&self.device);
+        *self.own_entries.lock().unwrap() = 0;
+        Ok(())
+    }
+
+    fn encode(&self, entries: &[ChangeEntry]) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        let mut data = Vec::new();
+        for entry in entries {
+            let (nonce, ciphertext) =
+                CryptoService::encrypt_with_aad(&serde_json::to_vec(entry)?, &self.key, self.device.as_bytes())?;
+            let record = bincode::serialize(&EncryptedRecord { nonce, ciphertext })?;
+            data.extend_from_slice(&(record.len() as u32).to_le_bytes());
+            data.extend_from_slice(&record);
+        }
+        Ok(data)
+    }
+}
+
+/// Counts one more change by `device`.
+pub fn bump(versions: &mut VersionVector, device: &str) {
+    *versions.entry(device.to_string()).or_insert(0) += 1;
+}
+
+/// `Less` if `b` has seen every change in `a`, `Greater` the other way round, and `None` if each
+/// has changes the other has not seen.
+pub fn compare(a: &VersionVector, b: &VersionVector) -> Option<Ordering> {
+    let (mut less, mut greater) = (false, false);
+    for device in a.keys().chain(b.keys()) {
+        let (x, y) = (a.get(device).copied().unwrap_or(0), b.get(device).copied().unwrap_or(0));
+        less |= x < y;
+        greater |= x > y;
+    }
+    match (less, greater) {
+        (false, false) => Some(Ordering::Equal),
+        (true, false) => Some(Ordering::Less),
+        (false, true) => Some(Ordering::Greater),
+        (true, true) => None,
+    }
+}
+
+/// The versions that have seen every change of both `a` and `b`.
+pub fn join(a: &VersionVector, b: &VersionVector) -> VersionVector {
+    let mut joined = a.clone();
+    for (device, &count) in b {
+        let entry = joined.entry(device.clone()).or_insert(0);
+        *entry = (*entry).max(count);
+    }
+    joined
+}
+
+/// The outcome of merging a version from another device into the local one.
+#[derive(Debug)]
+pub struct Merged {
+    pub versions: VersionVector,
+    pub credential: Option<Credential>,
+    /// The edit that lost, if both sides changed the credential in different ways.
+    pub conflict: Option<Credential>,
+}
+
+/// Merges `remote` into the local state of the same credential. Returns `None` when the local
+/// state already includes it.
+///
+/// Concurrent edits are settled the same way on every device, whichever side is local: an edit
+/// beats a purge, and of two edits the later `updated_at` wins, with ties broken on the content.
+/// The losing edit is returned so it can be offered to the user.
+pub fn merge(local_versions: &VersionVector, local: Option<&Credential>, remote: &ChangeEntry) -> Option<Merged> {
+    match compare(&remote.versions, local_versions) {
+        Some(Ordering::Less) | Some(Ordering::Equal) => None,
+        Some(Ordering::Greater) => {
+            let mut credential = remote.credential.clone();
+            if let (Some(credential), Some(local)) = (credential.as_mut(), local) {
+                combine_usage(credential, local);
+            }
+            Some(Merged { versions: remote.versions.clone(), credential, conflict: None })
+        }
+        None => {
+            let versions = join(local_versions, &remote.versions);
+            let (credential, conflict) = match (local, remote.credential.as_ref()) {
+                (None, None) => (None, None),
+                (Some(only), None) | (None, Some(only)) => (Some(only.clone()), None),
+                (Some(local), Some(remote)) => {
+                    let (local_key, remote_key) = (content_key(local), content_key(remote));
+                    let (mut winner, loser) = match (local.updated_at, &local_key).cmp(&(remote.updated_at, &remote_key)) {
+                        Ordering::Less => (remote.clone(), local),
+                        _ => (local.clone(), remote),
+                    };
+                    combine_usage(&mut winner, loser);
+                    let conflict = (local_key != remote_key).then(|| loser.clone());
+                    (Some(winner), conflict)
+                }
+            };
+            let credential = credential.map(|mut credential| {
+                credential.versions = versions.clone();
+                credential
+            });
+            Some(Merged { versions, credential, conflict })
+        }
+    }
+}
+
+/// Copying a secret only changes its usage counters, which are not edits of their own; they are
+/// combined with the other side's instead of being replaced or conflicting.
+fn combine_usage(credential: &mut Credential, other: &Credential) {
+    credential.last_used_at = credential.last_used_at.max(other.last_used_at);
+    credential.use_count = credential.use_count.max(other.use_count);
+}
+
+/// The credential's content, leaving out its versions and usage counters. Goes through
+/// `serde_json::Value`, whose objects keep their keys sorted, so custom fields come out in the same
+/// order on every device.
+fn content_key(credential: &Credential) -> String {
+    let mut credential = credential.clone();
+    credential.versions.clear();
+    credential.last_used_at = None;
+    credential.use_count = 0;
+    serde_json::to_value(&credential).map(|value| value.to_string()).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{Duration, Utc};
+
+    fn versions(counts: &[(&str, u64)]) -> VersionVector {
+        counts.iter().map(|(device, count)| (device.to_string(), *count)).collect()
+    }
+
+    fn credential(versions: &VersionVector) -> Credential {
+        let mut credential = Credential::new_password("github".into(), "me".into(), b"hunter2".to_vec(), String::new(), Vec::new());
+        credential.id = "id".to_string();
+        credential.versions = versions.clone();
+        credential
+    }
+
+    fn entry(credential: Option<&Credential>, versions: &VersionVector) -> ChangeEntry {
+        ChangeEntry { id: "id".to_string(), versions: versions.clone(), credential: credential.cloned() }
+    }
+
+    fn temp_folder() -> PathBuf {
+        std::env::temp_dir().join(format!("password-manager-test-{}", uuid::Uuid::new_v4().simple()))
+    }
+
+    fn open(folder: &std::path::Path, password: &str, key_files: &KeyFiles, kdf: &KdfParams) -> Result<SyncChannel, String> {
+        SyncChannel::open(Box::new(FolderStore::new(folder.to_path_buf())), "laptop", password, key_files, kdf).map_err(|e| e.to_string())
+    }
+
+    #[test]
+    fn sync_key_uses_the_vault_cost_and_key_files() {
+        let folder = temp_folder();
+        let key_file = folder.with_extension("key");
+        std::fs::write(&key_file, b"key file").unwrap();
+        let files = KeyFiles { key_file: Some(key_file.clone()), challenge_response_file: None };
+        let kdf = KdfParams { iterations: 1_000, key_file: true, challenge_response: false };
+        open(&folder, "correct horse", &files, &kdf).unwrap();
+
+        let stored: SyncKeyFile = bincode::deserialize(&std::fs::read(folder.join("sync.key")).unwrap()).unwrap();
+        assert_eq!(stored.kdf, kdf);
+        // Other devices follow the stored parameters, whatever their own vault uses.
+        let plain = KdfParams { iterations: 2_000, key_file: false, challenge_response: false };
+        open(&folder, "correct horse", &files, &plain).unwrap();
+        assert!(open(&folder, "correct horse", &KeyFiles::default(), &plain).err().unwrap().contains("set up with key files"));
+        assert!(open(&folder, "battery staple", &files, &plain).err().unwrap().contains("different master password"));
+        std::fs::write(&key_file, b"another key file").unwrap();
+        assert!(open(&folder, "correct horse", &files, &plain).err().unwrap().contains("different master password"));
+
+        std::fs::remove_dir_all(&folder).unwrap();
+        std::fs::remove_file(&key_file).unwrap();
+    }
+
+    #[test]
+    fn compare_orders_version_vectors() {
+        let a = versions(&[("laptop", 2), ("phone", 1)]);
+        assert_eq!(compare(&a, &a), Some(Ordering::Equal));
+        assert_eq!(compare(&a, &versions(&[("laptop", 2), ("phone", 2)])), Some(Ordering::Less));
+        // A device missing from a vector has made no changes.
+        assert_eq!(compare(&a, &versions(&[("laptop", 2)])), Some(Ordering::Greater));
+        assert_eq!(compare(&VersionVector::new(), &versions(&[("phone", 1)])), Some(Ordering::Less));
+        assert_eq!(compare(&a, &versions(&[("laptop", 3), ("phone", 0)])), None);
+    }
+
+    #[test]
+    fn join_takes_the_highest_count_of_each_device() {
+        let joined = join(&versions(&[("laptop", 3), ("phone", 1)]), &versions(&[("phone", 2), ("tablet", 1)]));
+        assert_eq!(joined, versions(&[("laptop", 3), ("phone", 2), ("tablet", 1)]));
+    }
+
+    #[test]
+    fn newer_versions_replace_older_ones() {
+        let old = versions(&[("laptop", 1)]);
+        let new = versions(&[("laptop", 1), ("phone", 1)]);
+        let mut local = credential(&old);
+        local.use_count = 5;
+        let mut remote = credential(&new);
+        remote.service = "GitHub".to_string();
+
+        assert!(merge(&new, Some(&remote), &entry(Some(&local), &old)).is_none());
+        assert!(merge(&new, Some(&remote), &entry(Some(&remote), &new)).is_none());
+
+        let merged = merge(&old, Some(&local), &entry(Some(&remote), &new)).unwrap();
+        let credential = merged.credential.unwrap();
+        assert_eq!(credential.service, "GitHub");
+        assert_eq!(credential.use_count, 5, "usage counted on this device is kept");
+        assert_eq!(merged.versions, new);
+        assert!(merged.conflict.is_none());
+    }
+
+    #[test]
+    fn concurrent_edits_agree_on_the_winner_and_keep_the_loser() {
+        let (a, b) = (versions(&[("laptop", 2)]), versions(&[("laptop", 1), ("phone", 1)]));
+        let mut laptop = credential(&a);
+        laptop.service = "laptop edit".to_string();
+        let mut phone = credential(&b);
+        phone.service = "phone edit".to_string();
+        phone.updated_at = laptop.updated_at + Duration::seconds(1);
+
+        let on_laptop = merge(&a, Some(&laptop), &entry(Some(&phone), &b)).unwrap();
+        let on_phone = merge(&b, Some(&phone), &entry(Some(&laptop), &a)).unwrap();
+        for merged in [&on_laptop, &on_phone] {
+            assert_eq!(merged.credential.as_ref().unwrap().service, "phone edit");
+            assert_eq!(merged.conflict.as_ref().unwrap().service, "laptop edit");
+            assert_eq!(merged.versions, versions(&[("laptop", 2), ("phone", 1)]));
+            assert_eq!(merged.credential.as_ref().unwrap().versions, merged.versions);
+        }
+
+        // With the same edit time, the content decides, whichever side is local.
+        phone.updated_at = laptop.updated_at;
+        let on_laptop = merge(&a, Some(&laptop), &entry(Some(&phone), &b)).unwrap();
+        let on_phone = merge(&b, Some(&phone), &entry(Some(&laptop), &a)).unwrap();
+        assert_eq!(on_laptop.credential.unwrap().service, on_phone.credential.unwrap().service);
+    }
+
+    #[test]
+    fn an_edit_beats_a_concurrent_purge() {
+        let (a, b) = (versions(&[("laptop", 2)]), versions(&[("laptop", 1), ("phone", 1)]));
+        let laptop = credential(&a);
+        let merged = merge(&a, Some(&laptop), &entry(None, &b)).unwrap();
+        assert!(merged.credential.is_some());
+        let merged = merge(&b, None, &entry(Some(&laptop), &a)).unwrap();
+        assert!(merged.credential.is_some());
+        assert!(merged.conflict.is_none());
+    }
+
+    #[test]
+    fn same_content_is_not_a_conflict() {
+        let (a, b) = (versions(&[("laptop", 2)]), versions(&[("laptop", 1), ("phone", 1)]));
+        let mut laptop = credential(&a);
+        let mut phone = laptop.clone();
+        phone.versions = b.clone();
+        // Custom fields inserted in a different order, in maps with different hash seeds.
+        for i in 0..20 {
+            laptop.custom_fields.insert(format!("field{}", i), i.to_string());
+        }
+        for i in (0..20).rev() {
+            phone.custom_fields.insert(format!("field{}", i), i.to_string());
+        }
+        laptop.use_count = 3;
+        phone.use_count = 7;
+        phone.last_used_at = Some(Utc::now());
+
+        let merged = merge(&a, Some(&laptop), &entry(Some(&phone), &b)).unwrap();
+        assert!(merged.conflict.is_none());
+        let credential = merged.credential.unwrap();
+        assert_eq!(credential.use_count, 7);
+        assert_eq!(credential.last_used_at, phone.last_used_at);
+    }
+}
@@ -1,7 +1,21 @@
+use crate::audit::AuditLog;
+use crate::clipboard::ClipboardService;
+use crate::config::{Settings, SETTING_KEYS};
+use crate::keyfile::KeyFiles;
 use crate::manager::PasswordManager;
-use crate::models::{Credential, EntryType};
+use crate::models::{normalize_folder, Credential, CredentialFields, CredentialUpdate, EntryType, FailedUnlocks};
+use crate::otp::{self, Totp};
+use crate::search::SearchIndex;
+use crate::ui::editor::{KeyValueEditor, TextEditor};
+use crate::ui::keymap::{Action, Context, KeyChord, Keymap, Resolution};
+use crate::ui::list::{self, ListRow, SortMode};
+use crate::ui::scroll::ScrollState;
+use crossterm::event::{KeyCode, KeyEvent};
 use zxcvbn::zxcvbn;
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
 use std::time::{Duration, Instant};
+use zeroize::Zeroizing;
 
 #[derive(Debug, PartialEq, Clone)]
 #[allow(dead_code)]
@@ -11,11 +25,13 @@ pub enum InputMode {
 }
 
 #[derive(Debug, PartialEq, Clone)]
+#[allow(clippy::enum_variant_names)]
 pub enum View {
     LockScreen,
     Main,
     AddCredential,
     ViewCredential,
+    Settings,
 }
 
 #[derive(Debug, PartialEq, Clone, Copy)]
@@ -25,11 +41,122 @@ pub enum ActiveField {
     Secret,
     Notes,
     Tags,
+    Folder,
     CustomFields,
     IsActive,
 }
 
-use crate::ui::theme::Theme;
+/// Which pane of the main screen receives navigation keys.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum MainFocus {
+    List,
+    Tags,
+}
+
+/// A part of a credential that can be copied from the view screen.
+#[derive(Debug, PartialEq, Clone)]
+pub enum CopyField {
+    Username,
+    Secret,
+    Notes,
+    Otp,
+    /// The custom field with this key.
+    Custom(String),
+}
+
+/// A destructive action held back until the user confirms it.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum PendingAction {
+    ResetVault,
+    DeleteCredential,
+    Import,
+    Export,
+    Bulk(BulkOperation),
+}
+
+/// Something done to all marked credentials at once, from the bulk menu.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum BulkOperation {
+    AddTag,
+    RemoveTag,
+    MoveToFolder,
+    ToggleActive,
+    Trash,
+    Export,
+    Restore,
+    Purge,
+}
+
+impl BulkOperation {
+    /// The action that picks each operation offered for the vault or for the trash, with its label.
+    pub fn menu(trash: bool) -> &'static [(Action, BulkOperation, &'static str)] {
+        if trash {
+            &[
+                (Action::Restore, BulkOperation::Restore, "Restore"),
+                (Action::Delete, BulkOperation::Purge, "Delete permanently"),
+                (Action::Export, BulkOperation::Export, "Export as plaintext JSON"),
+            ]
+        } else {
+            &[
+                (Action::AddTag, BulkOperation::AddTag, "Add a tag"),
+                (Action::RemoveTag, BulkOperation::RemoveTag, "Remove a tag"),
+                (Action::MoveToFolder, BulkOperation::MoveToFolder, "Move to a folder"),
+                (Action::ToggleActive, BulkOperation::ToggleActive, "Toggle API keys active/inactive"),
+                (Action::Delete, BulkOperation::Trash, "Move to the trash"),
+                (Action::Export, BulkOperation::Export, "Export as plaintext JSON"),
+            ]
+        }
+    }
+
+    /// The question asked for operations that need a value typed first.
+    pub fn prompt(self) -> Option<&'static str> {
+        match self {
+            BulkOperation::AddTag => Some("Tag to add"),
+            BulkOperation::RemoveTag => Some("Tag to remove"),
+            BulkOperation::MoveToFolder => Some("Folder (empty for the top level)"),
+            _ => None,
+        }
+    }
+}
+
+/// The bulk action menu. Once an operation that needs a value is picked, it asks for it.
+#[derive(Default)]
+pub struct BulkMenu {
+    pub prompt: Option<(BulkOperation, TextEditor)>,
+}
+
+/// The sync conflicts being reviewed, as they were when the review was opened or last changed.
+pub struct ConflictReview {
+    pub conflicts: Vec<(Option<Credential>, Credential)>,
+    pub cursor: usize,
+}
+
+/// The audit log as read when the viewer was opened, newest entry first.
+pub struct AuditView {
+    pub log: AuditLog,
+    pub scroll: usize,
+}
+
+/// The phrase that has to be typed to wipe the vault.
+pub const RESET_PHRASE: &str = "delete my vault";
+
+/// A modal dialog asking to confirm a [`PendingAction`].
+pub struct Confirmation {
+    pub action: PendingAction,
+    pub title: String,
+    pub message: String,
+    /// Text that must be typed to confirm. Without one, `y` or Enter confirms.
+    pub phrase: Option<&'static str>,
+    pub input: String,
+}
+
+/// Identifies a list row independently of its position, so the selection survives re-sorting and reloads.
+enum RowKey {
+    Folder(String),
+    Credential(String),
+}
+
+use crate::ui::theme::{Theme, ThemeRegistry};
 
 pub struct App {
     pub theme: Theme,
@@ -38,17 +165,40 @@ pub struct App {
     #[allow(dead_code)]
     pub input_mode: InputMode,
     pub master_password: String,
+    /// Key file path typed on the lock screen, prefilled from the settings.
+    pub key_file: String,
+    /// Whether lock screen input goes to the key file instead of the password.
+    pub editing_key_file: bool,
     pub current_view: View,
+    /// Every credential in the unlocked vault.
     pub credentials: Vec<Credential>,
+    /// Indices into `credentials` that match the current search, in display order.
+    pub visible: Vec<usize>,
+    /// The lines of the main list: folder headers and credentials.
+    pub rows: Vec<ListRow>,
+    pub search_index: SearchIndex,
+    positions: HashMap<String, usize>,
+    pub sort_mode: SortMode,
+    pub group_by_folder: bool,
+    pub collapsed_folders: HashSet<String>,
+    /// Every tag in the vault with the number of credentials using it.
+    pub tags: Vec<(String, usize)>,
+    pub tag_filter: Option<String>,
+    pub tag_cursor: usize,
+    pub main_focus: MainFocus,
+    /// Credentials reachable with the number keys, in the order shown in the quick-access sections.
+    pub quick_access: Vec<usize>,
     pub should_quit: bool,
+    /// Position of the selected row within `rows`.
     pub selected_credential: Option<usize>,
     pub selected_id: Option<String>,
-    pub service_input: String,
-    pub username_input: String,
-    pub secret_input: String,
-    pub notes_input: String,
-    pub tags_input: String,
-    pub custom_fields_input: String,
+    pub service_input: TextEditor,
+    pub username_input: TextEditor,
+    pub secret_input: TextEditor,
+    pub notes_input: TextEditor,
+    pub tags_input: TextEditor,
+    pub folder_input: TextEditor,
+    pub custom_fields_input: KeyValueEditor,
     pub search_query: String,
     pub password_strength: Option<u8>,
     pub error_message: Option<String>,
@@ -59,27 +209,90 @@ pub struct App {
     pub entry_type: EntryType,
     pub last_activity: Instant,
     pub inactivity_duration: Duration,
+    pub clipboard: ClipboardService,
+    pub clipboard_timeout: Duration,
+    /// The field the "username, then secret" sequence copies next.
+    pub copy_sequence: Option<CopyField>,
+    /// The settings in effect, including command-line overrides.
+    pub settings: Settings,
+    /// The settings as in the settings file, without command-line overrides.
+    pub file_settings: Settings,
+    /// The settings given on the command line, as key and value; they aren't saved unless edited.
+    pub command_line: Vec<(&'static str, String)>,
+    pub config_path: PathBuf,
+    /// Unsaved edits on the settings screen.
+    pub settings_draft: Settings,
+    pub settings_cursor: usize,
+    /// The value being typed for the selected setting, while editing one.
+    pub settings_input: Option<String>,
+    pub themes: ThemeRegistry,
+    pub keymap: Keymap,
+    /// Keys typed so far towards a multi-key binding such as `g g`.
+    pub pending_keys: Vec<KeyChord>,
+    pub show_help: bool,
+    /// The confirmation dialog on top of the current screen, if any.
+    pub confirmation: Option<Confirmation>,
+    pub list_scroll: ScrollState,
+    pub tags_scroll: ScrollState,
+    pub notes_scroll: ScrollState,
+    pub fields_scroll: ScrollState,
+    /// Ids of the credentials marked for a bulk operation.
+    pub marked: HashSet<String>,
+    pub bulk_menu: Option<BulkMenu>,
+    /// Whether the main screen lists the trash instead of the vault.
+    pub show_trash: bool,
+    /// Sync conflicts waiting to be resolved.
+    pub conflict_count: usize,
+    pub conflict_review: Option<ConflictReview>,
+    pub audit_view: Option<AuditView>,
+    /// Failed unlocks since the last successful one, shown on the lock screen.
+    pub failed_unlocks: FailedUnlocks,
 }
 
 impl App {
-    pub fn new() -> Result<Self, Box<dyn std::error::Error>> {
-        Ok(Self {
+    pub fn new(
+        settings: Settings,
+        file_settings: Settings,
+        config_path: PathBuf,
+        themes: ThemeRegistry,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let command_line = SETTING_KEYS
+            .iter()
+            .filter(|(key, _)| settings.get(key) != file_settings.get(key))
+            .map(|(key, _)| (*key, settings.get(key)))
+            .collect();
+        let mut app = Self {
             theme: Theme::default(),
             current_theme: "dark".to_string(),
-            password_manager: PasswordManager::new()?,
+            password_manager: PasswordManager::new(settings.vault.open()?),
             input_mode: InputMode::Normal,
             master_password: String::new(),
+            key_file: String::new(),
+            editing_key_file: false,
             current_view: View::LockScreen,
             credentials: Vec::new(),
+            visible: Vec::new(),
+            rows: Vec::new(),
+            search_index: SearchIndex::new(),
+            positions: HashMap::new(),
+            sort_mode: SortMode::Name,
+            group_by_folder: true,
+            collapsed_folders: HashSet::new(),
+            tags: Vec::new(),
+            tag_filter: None,
+            tag_cursor: 0,
+            main_focus: MainFocus::List,
+            quick_access: Vec::new(),
             should_quit: false,
             selected_credential: None,
             selected_id: None,
-            service_input: String::new(),
-            username_input: String::new(),
-            secret_input: String::new(),
-            notes_input: String::new(),
-            tags_input: String::new(),
-            custom_fields_input: String::new(),
+            service_input: TextEditor::new(),
+            username_input: TextEditor::new(),
+            secret_input: TextEditor::new(),
+            notes_input: TextEditor::multiline(),
+            tags_input: TextEditor::new(),
+            folder_input: TextEditor::new(),
+            custom_fields_input: KeyValueEditor::default(),
             search_query: String::new(),
             password_strength: None,
             error_message: None,
@@ -90,33 +303,238 @@ impl App {
             entry_type: EntryType::Password,
             last_activity: Instant::now(),
             inactivity_duration: Duration::from_secs(5 * 60), // 5 minutes
-        })
+            clipboard: ClipboardService::new(),
+            clipboard_timeout: Duration::from_secs(30),
+            copy_sequence: None,
+            settings_draft: settings.clone(),
+            settings,
+            file_settings,
+            command_line,
+            config_path,
+            settings_cursor: 0,
+            settings_input: None,
+            themes,
+            keymap: Keymap::default(),
+            pending_keys: Vec::new(),
+            show_help: false,
+            confirmation: None,
+            list_scroll: ScrollState::default(),
+            tags_scroll: ScrollState::default(),
+            notes_scroll: ScrollState::default(),
+            fields_scroll: ScrollState::default(),
+            marked: HashSet::new(),
+            bulk_menu: None,
+            show_trash: false,
+            conflict_count: 0,
+            conflict_review: None,
+            audit_view: None,
+            failed_unlocks: FailedUnlocks::default(),
+        };
+        app.apply_settings();
+        app.refresh_failed_unlocks();
+        if !app.themes.errors.is_empty() {
+            app.error_message = Some(format!("Skipped theme files: {}", app.themes.errors.join("; ")));
+        }
+        Ok(app)
+    }
+
+    /// Pushes `self.settings` into the parts of the app that depend on them.
+    fn apply_settings(&mut self) {
+        self.theme = self.themes.get(&self.settings.theme).unwrap_or_default();
+        self.current_theme = self.settings.theme.clone();
+        self.inactivity_duration = Duration::from_secs(self.settings.inactivity_timeout_secs);
+        self.clipboard_timeout = Duration::from_secs(self.settings.clipboard_timeout_secs);
+        self.password_manager.set_kdf_iterations(self.settings.kdf_iterations);
+        self.password_manager.set_sync(self.settings.sync.clone());
+        self.password_manager.set_lockout(self.settings.lockout.clone());
+        self.key_file = self.settings.key_file.clone();
+        self.keymap = Keymap::build(&self.settings.keymap, &self.settings.keys).unwrap_or_default();
+        self.pending_keys.clear();
     }
 
     pub fn next_theme(&mut self) {
-        if self.current_theme == "dark" {
-            self.current_theme = "light".to_string();
-            self.theme = Theme::light();
-        } else {
-            self.current_theme = "dark".to_string();
-            self.theme = Theme::dark();
+        let name = self.themes.cycle(&self.current_theme, 1).to_string();
+        self.theme = self.themes.get(&name).unwrap_or_default();
+        self.settings.theme = name.clone();
+        self.settings_draft.theme = name.clone();
+        self.current_theme = name;
+    }
+
+    /// Shows or hides the preview pane for this session; saving the settings keeps the choice.
+    pub fn toggle_preview(&mut self) {
+        self.settings.preview_pane = !self.settings.preview_pane;
+        self.settings_draft.preview_pane = self.settings.preview_pane;
+    }
+
+    pub fn open_settings(&mut self) {
+        self.settings_draft = self.settings.clone();
+        self.settings_cursor = 0;
+        self.settings_input = None;
+        self.current_view = View::Settings;
+    }
+
+    /// Starts editing the selected setting, prefilled with its current value.
+    pub fn edit_selected_setting(&mut self) {
+        let (key, _) = SETTING_KEYS[self.settings_cursor];
+        self.settings_input = Some(self.settings_draft.get(key));
+    }
+
+    /// Applies the value being typed to the draft.
+    pub fn commit_setting_input(&mut self) {
+        if let Some(input) = self.settings_input.take() {
+            let (key, _) = SETTING_KEYS[self.settings_cursor];
+            let mut draft = self.settings_draft.clone();
+            match draft.set(key, &input).and_then(|_| draft.validate(&self.themes)) {
+                Ok(()) => self.settings_draft = draft,
+                Err(e) => {
+                    self.error_message = Some(e);
+                    self.settings_input = Some(input);
+                }
+            }
+        }
+    }
+
+    /// Whether the selected setting is picked from a list (theme, keymap, preview pane) rather than typed.
+    pub fn selected_setting_is_choice(&self) -> bool {
+        matches!(SETTING_KEYS[self.settings_cursor].0, "theme" | "keymap" | "preview_pane")
+    }
+
+    /// Steps through the choices of the selected choice setting, previewing the theme straight away.
+    pub fn cycle_draft_choice(&mut self, step: isize) {
+        match SETTING_KEYS[self.settings_cursor].0 {
+            "theme" => {
+                let name = self.themes.cycle(&self.settings_draft.theme, step).to_string();
+                self.theme = self.themes.get(&name).unwrap_or_default();
+                self.settings_draft.theme = name;
+            }
+            "keymap" => {
+                let count = Keymap::PRESETS.len() as isize;
+                let position = Keymap::PRESETS.iter().position(|p| *p == self.settings_draft.keymap).unwrap_or(0) as isize;
+                self.settings_draft.keymap = Keymap::PRESETS[(position + step).rem_euclid(count) as usize].to_string();
+            }
+            "preview_pane" => self.settings_draft.preview_pane = !self.settings_draft.preview_pane,
+            _ => {}
         }
     }
 
+    /// The keymap context for the screen and focus the user is in.
+    pub fn key_context(&self) -> Context {
+        if self.bulk_menu.is_some() {
+            return Context::Bulk;
+        }
+        if self.conflict_review.is_some() {
+            return Context::Conflicts;
+        }
+        if self.audit_view.is_some() {
+            return Context::Audit;
+        }
+        match self.current_view {
+            View::LockScreen => Context::Lock,
+            View::Main if self.main_focus == MainFocus::Tags => Context::Tags,
+            View::Main => Context::Main,
+            View::ViewCredential => Context::View,
+            View::AddCredential => Context::Form,
+            View::Settings => Context::Settings,
+        }
+    }
+
+    /// Feeds a key press to the keymap and returns the action once a complete binding has been typed.
+    pub fn resolve_key(&mut self, context: Context, key: KeyEvent) -> Option<Action> {
+        self.pending_keys.push(KeyChord::from(key));
+        match self.keymap.resolve(context, &self.pending_keys) {
+            Resolution::Action(action) => {
+                self.pending_keys.clear();
+                Some(action)
+            }
+            Resolution::Pending => None,
+            Resolution::Unbound => {
+                // A key that breaks off a sequence may still be a binding of its own.
+                let retry = self.pending_keys.len() > 1;
+                self.pending_keys.clear();
+                if retry {
+                    self.resolve_key(context, key)
+                } else {
+                    None
+                }
+            }
+        }
+    }
+
+    /// Leaves the settings screen without saving.
+    pub fn discard_settings(&mut self) {
+        self.settings_input = None;
+        self.apply_settings();
+        self.current_view = View::Main;
+    }
+
+    /// Validates the draft, writes it to the settings file and applies it.
+    /// Command-line values the user didn't change keep the file's value in the file.
+    pub fn save_settings(&mut self) {
+        let saved = self.settings_draft.to_save(&self.file_settings, &self.command_line);
+        if let Err(e) = saved.save(&self.config_path, &self.themes) {
+            self.error_message = Some(format!("Could not save settings: {}", e));
+            return;
+        }
+        self.file_settings = saved;
+        let kdf_changed = self.settings_draft.kdf_iterations != self.settings.kdf_iterations;
+        self.settings = self.settings_draft.clone();
+        self.apply_settings();
+        let mut message = format!("Settings saved to {}.", self.config_path.display());
+        if kdf_changed {
+            message.push_str(" The new KDF cost is applied the next time the vault is unlocked.");
+        }
+        self.info_message = Some(message);
+        self.error_message = None;
+    }
+
     pub fn unlock_vault(&mut self) -> Result<(), Box<dyn std::error::Error>> {
         if self.master_password.is_empty() {
             self.error_message = Some("Password cannot be empty".to_string());
             return Ok(());
         }
+        self.password_manager.set_key_files(KeyFiles::new(&self.key_file, &self.settings.challenge_response_file));
         match self.password_manager.unlock(&self.master_password) {
             Ok(true) => {
                 self.current_view = View::Main;
-                self.load_credentials()?;
                 self.error_message = None;
                 self.reset_activity_timer();
+                // Taken before loading the credentials, which reports sync failures of its own.
+                match self.password_manager.take_sync_report() {
+                    Some(Ok(report)) => {
+                        let mut message = format!(
+                            "Synced: {} from other devices, {} sent.",
+                            plural(report.received, "change"),
+                            plural(report.sent, "change")
+                        );
+                        if report.conflicts > 0 {
+                            message.push_str(&format!(
+                                " {} to review ('{}').",
+                                plural(report.conflicts, "conflict"),
+                                self.keymap.keys_for(Context::Main, Action::ReviewConflicts).join("' or '")
+                            ));
+                        }
+                        self.info_message = Some(message);
+                    }
+                    Some(Err(e)) => self.error_message = Some(format!("Sync failed: {}", e)),
+                    None => {}
+                }
+                self.load_credentials()?;
+                let mut warnings = Vec::new();
+                let failures = self.password_manager.failures_before_unlock();
+                if failures > 0 {
+                    warnings.push(format!("{} since the last unlock.", plural(failures as usize, "failed attempt")));
+                }
+                if self.password_manager.is_read_only() {
+                    warnings.push("The vault is read-only after too many failed unlocks; run 'password-manager unlock-attempts reset' to allow changes.".to_string());
+                }
+                if !warnings.is_empty() {
+                    warnings.extend(self.error_message.take());
+                    self.error_message = Some(warnings.join(" "));
+                }
             }
             Ok(false) => {
-                self.error_message = Some("Invalid password".to_string());
+                let message = if self.key_file.trim().is_empty() { "Invalid password" } else { "Invalid password or key file" };
+                self.error_message = Some(message.to_string());
                 self.master_password.clear();
             }
             Err(e) => {
@@ -124,17 +542,26 @@ impl App {
                 self.master_password.clear();
             }
         }
+        self.refresh_failed_unlocks();
         Ok(())
     }
 
+    /// Re-reads the failed unlock count for the lock screen.
+    fn refresh_failed_unlocks(&mut self) {
+        self.failed_unlocks = self.password_manager.failed_unlocks().unwrap_or_default();
+    }
+
     pub fn lock_vault(&mut self) {
         self.password_manager.lock();
         self.master_password.clear();
+        self.editing_key_file = false;
         self.current_view = View::LockScreen;
-        self.credentials.clear();
-        self.selected_credential = None;
+        self.clear_loaded_credentials();
         self.error_message = None;
+        self.copy_sequence = None;
+        self.confirmation = None;
         self.clear_form();
+        self.refresh_failed_unlocks();
     }
 
     pub fn check_inactivity(&mut self) {
@@ -148,36 +575,434 @@ impl App {
     }
 
     pub fn load_credentials(&mut self) -> Result<(), Box<dyn std::error::Error>> {
-        self.credentials = self.password_manager.get_credentials()?;
-        self.filter_credentials();
+        let key = self.selected_key();
+        self.credentials = if self.show_trash {
+            self.password_manager.get_trash()?
+        } else {
+            self.password_manager.get_credentials()?
+        };
+        self.positions = self
+            .credentials
+            .iter()
+            .enumerate()
+            .map(|(i, c)| (c.id.clone(), i))
+            .collect();
+        self.tags = list::tag_counts(&self.credentials);
+        self.conflict_count = self.password_manager.get_conflicts().len();
+        if let Some(Err(e)) = self.password_manager.take_sync_report() {
+            self.error_message = Some(format!("Sync failed: {}. Changes are kept and synced at the next unlock.", e));
+        }
+        if self.tag_filter.as_ref().is_some_and(|tag| !self.tags.iter().any(|(t, _)| t == tag)) {
+            self.tag_filter = None;
+        }
+        self.tag_cursor = self.tag_cursor.min(self.tags.len().saturating_sub(1));
+        let positions = &self.positions;
+        self.marked.retain(|id| positions.contains_key(id));
+        self.search_index.sync(&self.credentials);
+        self.refresh_visible(key);
         Ok(())
     }
 
+    fn clear_loaded_credentials(&mut self) {
+        self.credentials.clear();
+        self.visible.clear();
+        self.rows.clear();
+        self.positions.clear();
+        self.search_index.clear();
+        self.search_query.clear();
+        self.tags.clear();
+        self.tag_filter = None;
+        self.tag_cursor = 0;
+        self.main_focus = MainFocus::List;
+        self.selected_credential = None;
+        self.marked.clear();
+        self.bulk_menu = None;
+        self.show_trash = false;
+        self.conflict_count = 0;
+        self.conflict_review = None;
+        self.audit_view = None;
+    }
+
+    /// Recomputes the list from the search query, tag filter and sort mode, keeping the selection where possible.
     pub fn filter_credentials(&mut self) {
-        let query = self.search_query.to_lowercase();
-        if query.is_empty() {
-            self.credentials = self.password_manager.get_credentials().unwrap_or_default();
-        } else {
-            self.credentials = self.password_manager.get_credentials().unwrap_or_default()
-                .into_iter()
-                .filter(|c| {
-                    c.service.to_lowercase().contains(&query) ||
-                    c.username.to_lowercase().contains(&query) ||
-                    c.tags.iter().any(|t| t.to_lowercase().contains(&query))
+        let key = self.selected_key();
+        self.refresh_visible(key);
+    }
+
+    fn refresh_visible(&mut self, key: Option<RowKey>) {
+        self.visible = match self.search_index.search(&self.search_query) {
+            Some(ids) => ids.iter().filter_map(|id| self.positions.get(id).copied()).collect(),
+            None => {
+                let mut all: Vec<usize> = (0..self.credentials.len()).collect();
+                self.sort_mode.sort(&self.credentials, &mut all);
+                all
+            }
+        };
+        if let Some(tag) = &self.tag_filter {
+            let credentials = &self.credentials;
+            self.visible.retain(|&i| credentials[i].tags.contains(tag));
+        }
+        self.rebuild_rows(key);
+    }
+
+    fn rebuild_rows(&mut self, key: Option<RowKey>) {
+        // Search results stay in ranked order rather than being split across folders.
+        let browsing = self.search_query.trim().is_empty();
+        let group = self.group_by_folder && browsing;
+        self.rows.clear();
+        self.quick_access.clear();
+        if browsing && self.tag_filter.is_none() && !self.show_trash {
+            let (rows, order) = list::quick_access_rows(&self.credentials, &self.visible);
+            self.rows = rows;
+            self.quick_access = order;
+        }
+        self.rows
+            .extend(list::build_rows(&self.credentials, &self.visible, group, &self.collapsed_folders));
+        // A credential can be listed twice (e.g. under Favorites and in its folder); stay on the nearest copy.
+        let previous = self.selected_credential.unwrap_or(0);
+        let kept = key.and_then(|key| {
+            self.rows
+                .iter()
+                .enumerate()
+                .filter(|(_, row)| match (row, &key) {
+                    (ListRow::Folder { path, .. }, RowKey::Folder(wanted)) => path == wanted,
+                    (ListRow::Credential { index, .. }, RowKey::Credential(id)) => self.credentials[*index].id == *id,
+                    _ => false,
                 })
-                .collect();
+                .map(|(position, _)| position)
+                .min_by_key(|position| position.abs_diff(previous))
+        });
+        self.selected_credential = kept.or_else(|| self.rows.iter().position(is_selectable));
+    }
+
+    /// Moves the cursor by `delta` rows, wrapping around and skipping section headers.
+    pub fn move_selection(&mut self, delta: isize) {
+        let len = self.rows.len() as isize;
+        if len == 0 {
+            return;
+        }
+        let mut position = match self.selected_credential {
+            Some(i) => i as isize,
+            None if delta > 0 => -1,
+            None => len,
+        };
+        for _ in 0..len {
+            position = (position + delta).rem_euclid(len);
+            if is_selectable(&self.rows[position as usize]) {
+                self.selected_credential = Some(position as usize);
+                return;
+            }
+        }
+    }
+
+    /// Moves the selection like [`App::move_selection`] but stops at the ends instead of wrapping,
+    /// which suits the mouse wheel.
+    pub fn scroll_selection(&mut self, delta: isize) {
+        let current = match self.selected_credential {
+            Some(i) => i as isize,
+            None => return self.move_selection(delta),
+        };
+        let mut position = current + delta.signum();
+        let mut remaining = delta.abs();
+        while (0..self.rows.len() as isize).contains(&position) {
+            if is_selectable(&self.rows[position as usize]) {
+                self.selected_credential = Some(position as usize);
+                remaining -= 1;
+                if remaining == 0 {
+                    return;
+                }
+            }
+            position += delta.signum();
+        }
+    }
+
+    /// Selects a row by its position in the list. Returns `false` for section headings and positions past the end.
+    pub fn select_row(&mut self, row: usize) -> bool {
+        match self.rows.get(row) {
+            Some(r) if is_selectable(r) => {
+                self.selected_credential = Some(row);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Opens the selected row: a credential is shown, a folder is collapsed or expanded.
+    pub fn open_selected(&mut self) {
+        if let Some(ListRow::Folder { .. }) = self.selected_row() {
+            self.set_selected_folder_collapsed(None);
+        } else if let Some(credential) = self.selected() {
+            self.selected_id = Some(credential.id.clone());
+            self.current_view = View::ViewCredential;
+            self.notes_scroll.reset();
+            self.fields_scroll.reset();
+        }
+    }
+
+    /// Selects the first (or last) selectable row.
+    pub fn select_edge(&mut self, last: bool) {
+        self.selected_credential = None;
+        self.move_selection(if last { -1 } else { 1 });
+    }
+
+    /// Opens the credential bound to a quick-access number key (1-based).
+    pub fn open_quick_access(&mut self, number: usize) {
+        let index = match number.checked_sub(1).and_then(|n| self.quick_access.get(n)) {
+            Some(&index) => index,
+            None => return,
+        };
+        let row = self.rows.iter().position(|row| matches!(row, ListRow::Credential { index: i, .. } if *i == index));
+        if let Some(row) = row {
+            self.selected_credential = Some(row);
+            self.selected_id = Some(self.credentials[index].id.clone());
+            self.current_view = View::ViewCredential;
+            self.notes_scroll.reset();
+            self.fields_scroll.reset();
+        }
+    }
+
+    /// Shows the selected credential's secret once the audit log has recorded it.
+    pub fn reveal_selected_secret(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        let id = match self.selected() {
+            Some(cred) => cred.id.clone(),
+            None => return Ok(()),
+        };
+        if let Err(e) = self.password_manager.record_reveal(&id) {
+            self.error_message = Some(format!("Not shown: {}", e));
+            return Ok(());
+        }
+        self.show_secret = true;
+        self.record_selected_usage()
+    }
+
+    /// Records that the selected credential's secret was copied or revealed.
+    pub fn record_selected_usage(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        if let Some(id) = self.selected().map(|c| c.id.clone()) {
+            self.password_manager.record_usage(&id)?;
+            self.load_credentials()?;
+        }
+        Ok(())
+    }
+
+    /// Copies `text` to the clipboard, reporting the outcome in the status messages instead of failing.
+    pub fn copy_to_clipboard(&mut self, label: &str, text: &str) -> bool {
+        match self.clipboard.copy(text, self.clipboard_timeout) {
+            Ok(copied) => {
+                let history = if copied.excluded_from_history {
+                    String::new()
+                } else {
+                    format!(" Copied with {}, so clipboard managers may keep it in their history.", copied.backend)
+                };
+                self.info_message = Some(format!(
+                    "{} copied to clipboard. It will be cleared in {} seconds.{}",
+                    label,
+                    self.clipboard_timeout.as_secs(),
+                    history
+                ));
+                self.error_message = None;
+                true
+            }
+            Err(e) => {
+                self.error_message = Some(format!("Could not copy to clipboard: {}", e));
+                self.info_message = None;
+                false
+            }
+        }
+    }
+
+    /// Whether the selected credential's secret may be shown or copied. If not, says why.
+    pub fn check_can_reveal(&mut self) -> bool {
+        let service = match self.selected() {
+            Some(cred) if !cred.can_reveal() => cred.service.clone(),
+            _ => return true,
+        };
+        self.error_message = Some(format!("'{}' is shared with you read-only. Use it with `password-manager run`.", service));
+        self.info_message = None;
+        false
+    }
+
+    /// Whether the vault may be changed, which it can't in read-only mode. If not, says why.
+    pub fn check_writable(&mut self) -> bool {
+        if !self.password_manager.is_read_only() {
+            return true;
+        }
+        self.error_message = Some(format!("{}.", crate::manager::READ_ONLY));
+        self.info_message = None;
+        false
+    }
+
+    /// Whether the selected credential may be edited. If not, says why.
+    pub fn check_can_edit(&mut self) -> bool {
+        if !self.check_writable() {
+            return false;
+        }
+        let service = match self.selected() {
+            Some(cred) if !cred.can_edit() => cred.service.clone(),
+            _ => return true,
+        };
+        self.error_message = Some(format!("'{}' is shared with you and you can't edit it.", service));
+        self.info_message = None;
+        false
+    }
+
+    /// Copies one field of the selected credential. Copying the secret or an OTP code counts as using it.
+    pub fn copy_field(&mut self, field: CopyField) -> Result<(), Box<dyn std::error::Error>> {
+        if matches!(field, CopyField::Secret | CopyField::Otp) && !self.check_can_reveal() {
+            return Ok(());
+        }
+        let cred = match self.selected() {
+            Some(cred) => cred,
+            None => return Ok(()),
+        };
+        let id = cred.id.clone();
+        let (label, text) = match &field {
+            CopyField::Username => ("Username".to_string(), Zeroizing::new(cred.username.clone())),
+            CopyField::Secret => ("Secret".to_string(), Zeroizing::new(String::from_utf8_lossy(&cred.secret).to_string())),
+            CopyField::Notes => ("Notes".to_string(), Zeroizing::new(cred.notes.clone())),
+            CopyField::Otp => {
+                let value = match cred.custom_fields.get(otp::OTP_FIELD) {
+                    Some(value) => value,
+                    None => {
+                        self.error_message = Some(format!("No OTP secret. Add a '{}' custom field to enable codes.", otp::OTP_FIELD));
+                        return Ok(());
+                    }
+                };
+                match Totp::parse(value) {
+                    Ok(totp) => ("OTP code".to_string(), Zeroizing::new(totp.now().0)),
+                    Err(e) => {
+                        self.error_message = Some(format!("Invalid OTP secret: {}", e));
+                        return Ok(());
+                    }
+                }
+            }
+            CopyField::Custom(key) => match cred.custom_fields.get(key) {
+                Some(value) => (format!("'{}'", key), Zeroizing::new(value.clone())),
+                None => return Ok(()),
+            },
+        };
+        let audited = match &field {
+            CopyField::Username => "username".to_string(),
+            CopyField::Secret => "secret".to_string(),
+            CopyField::Notes => "notes".to_string(),
+            CopyField::Otp => "one-time password".to_string(),
+            CopyField::Custom(key) => format!("field '{}'", key),
+        };
+        if let Err(e) = self.password_manager.record_copy(&id, &audited) {
+            self.error_message = Some(format!("Not copied: {}", e));
+            self.info_message = None;
+            return Ok(());
+        }
+        if self.copy_to_clipboard(&label, &text) && matches!(field, CopyField::Secret | CopyField::Otp) {
+            self.record_selected_usage()?;
+        }
+        Ok(())
+    }
+
+    /// Copies the custom field shown as number `number` on the view screen.
+    pub fn copy_custom_field(&mut self, number: usize) -> Result<(), Box<dyn std::error::Error>> {
+        let key = self
+            .selected()
+            .and_then(|cred| cred.sorted_custom_field_keys().get(number.wrapping_sub(1)).map(|k| k.to_string()));
+        match key {
+            Some(key) => self.copy_field(CopyField::Custom(key)),
+            None => Ok(()),
+        }
+    }
+
+    /// Starts or advances the "username, then secret" sequence for manual logins.
+    pub fn advance_copy_sequence(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        match self.copy_sequence.take() {
+            None => {
+                self.copy_field(CopyField::Username)?;
+                if self.error_message.is_none() {
+                    self.copy_sequence = Some(CopyField::Secret);
+                    let mut keys = self.keymap.keys_for(Context::View, Action::CopySequence);
+                    // Enter continues the sequence too, unless it is bound to something else.
+                    let enter = KeyChord::from(KeyEvent::from(KeyCode::Enter));
+                    if matches!(self.keymap.resolve(Context::View, &[enter]), Resolution::Unbound) {
+                        keys.push("Enter".to_string());
+                    }
+                    self.info_message =
+                        Some(format!("Username copied. Paste it, then press {} to copy the secret.", keys.join(" or ")));
+                }
+            }
+            Some(field) => self.copy_field(field)?,
+        }
+        Ok(())
+    }
+
+    pub fn toggle_selected_favorite(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        if let Some((id, favorite)) = self.selected().map(|c| (c.id.clone(), c.favorite)) {
+            self.password_manager.set_favorite(&id, !favorite)?;
+            self.load_credentials()?;
+        }
+        Ok(())
+    }
+
+    fn selected_key(&self) -> Option<RowKey> {
+        match self.selected_row()? {
+            ListRow::Folder { path, .. } => Some(RowKey::Folder(path.clone())),
+            ListRow::Credential { index, .. } => self.credentials.get(*index).map(|c| RowKey::Credential(c.id.clone())),
+            ListRow::Section { .. } => None,
+        }
+    }
+
+    pub fn selected_row(&self) -> Option<&ListRow> {
+        self.selected_credential.and_then(|i| self.rows.get(i))
+    }
+
+    /// The credential under the cursor in the main list, if the cursor is not on a folder.
+    pub fn selected(&self) -> Option<&Credential> {
+        match self.selected_row()? {
+            ListRow::Credential { index, .. } => self.credentials.get(*index),
+            ListRow::Folder { .. } | ListRow::Section { .. } => None,
+        }
+    }
+
+    pub fn next_sort_mode(&mut self) {
+        self.sort_mode = self.sort_mode.next();
+        self.filter_credentials();
+    }
+
+    pub fn toggle_folder_view(&mut self) {
+        self.group_by_folder = !self.group_by_folder;
+        self.filter_credentials();
+    }
+
+    /// Collapses or expands the folder under the cursor.
+    pub fn set_selected_folder_collapsed(&mut self, collapsed: Option<bool>) {
+        if let Some(ListRow::Folder { path, collapsed: current, .. }) = self.selected_row().cloned() {
+            let collapse = collapsed.unwrap_or(!current);
+            if collapse {
+                self.collapsed_folders.insert(path);
+            } else {
+                self.collapsed_folders.remove(&path);
+            }
+            self.filter_credentials();
+        }
+    }
+
+    /// Applies the tag under the sidebar cursor as a filter, or clears it if it is already applied.
+    pub fn toggle_tag_filter(&mut self) {
+        if let Some((tag, _)) = self.tags.get(self.tag_cursor) {
+            self.tag_filter = if self.tag_filter.as_ref() == Some(tag) { None } else { Some(tag.clone()) };
+            self.filter_credentials();
         }
     }
 
     pub fn add_credential(&mut self) -> Result<(), Box<dyn std::error::Error>> {
         // Store values locally to avoid multiple borrows
         let entry_type = self.entry_type.clone();
-        let service = self.service_input.clone();
-        let username = self.username_input.clone();
-        let secret = self.secret_input.clone();
-        let notes = self.notes_input.clone();
-        let tags = self.tags_input.split(',').map(|s| s.trim().to_string()).collect();
-        let is_active = self.is_active_input;
+        let fields = CredentialFields {
+            service: self.service_input.text().to_string(),
+            username: self.username_input.text().to_string(),
+            secret: self.secret_input.text().to_string(),
+            notes: self.notes_input.text().to_string(),
+            is_active: self.is_active_input,
+            tags: self.tags_input.text().split(',').map(|s| s.trim().to_string()).collect(),
+            folder: self.folder_input.text().to_string(),
+            custom_fields: self.custom_fields_input.fields()?,
+        };
 
         // Clear form state immediately
         self.clear_form();
@@ -185,23 +1010,10 @@ impl App {
         // Perform the add operation
         match entry_type {
             EntryType::Password => {
-                self.password_manager.add_password(
-                    service,
-                    username,
-                    secret,
-                    notes,
-                    tags,
-                )?;
+                self.password_manager.add_password(fields)?;
             },
             EntryType::ApiKey => {
-                self.password_manager.add_api_key(
-                    service,
-                    username,
-                    secret,
-                    notes,
-                    is_active,
-                    tags,
-                )?;
+                self.password_manager.add_api_key(fields)?;
             }
         }
 
@@ -211,9 +1023,15 @@ impl App {
         Ok(())
     }
 
+    /// Moves the credential being viewed to the trash, or deletes it for good if it is already there.
     pub fn remove_selected_credential(&mut self) -> Result<(), Box<dyn std::error::Error>> {
         if let Some(id) = &self.selected_id {
-            self.password_manager.remove_credential(id)?;
+            let ids = std::slice::from_ref(id);
+            if self.show_trash {
+                self.password_manager.purge(ids)?;
+            } else {
+                self.password_manager.trash(ids)?;
+            }
             self.load_credentials()?;
             self.selected_credential = None;
             self.selected_id = None;
@@ -224,40 +1042,23 @@ impl App {
     pub fn update_selected_credential(&mut self) -> Result<(), Box<dyn std::error::Error>> {
         // Store values locally to avoid multiple borrows
         let id = self.selected_id.as_ref().ok_or("No credential selected")?.clone();
-        let service = self.service_input.clone();
-        let username = self.username_input.clone();
-        let secret = self.secret_input.clone();
-        let notes = self.notes_input.clone();
-        let tags = self.tags_input.split(',').map(|s| s.trim().to_string()).collect();
-        let is_active = self.is_active_input;
-        let custom_fields = self.custom_fields_input
-            .split(',')
-            .filter_map(|s| {
-                let mut parts = s.splitn(2, ':');
-                match (parts.next(), parts.next()) {
-                    (Some(key), Some(value)) => Some((key.trim().to_string(), value.trim().to_string())),
-                    _ => None,
-                }
-            })
-            .collect();
+        let update = CredentialUpdate {
+            service: Some(self.service_input.text().to_string()),
+            username: Some(self.username_input.text().to_string()),
+            secret: Some(self.secret_input.text().as_bytes().to_vec()),
+            notes: Some(self.notes_input.text().to_string()),
+            is_active: Some(self.is_active_input),
+            tags: Some(self.tags_input.text().split(',').map(|s| s.trim().to_string()).collect()),
+            custom_fields: Some(self.custom_fields_input.fields()?),
+            folder: Some(self.folder_input.text().to_string()),
+        };
 
-        // Clear form state immediately
+        // The form is kept if the update fails, so it can be corrected and saved again.
+        self.password_manager.update_credential(&id, update)?;
         self.clear_form();
         self.selected_credential = None;
         self.selected_id = None;
 
-        // Perform the update
-        self.password_manager.update_credential(
-            &id,
-            Some(service),
-            Some(username),
-            Some(secret),
-            Some(notes),
-            Some(is_active),
-            Some(tags),
-            Some(custom_fields),
-        )?;
-
         // Reload credentials
         self.load_credentials()?;
 
@@ -271,6 +1072,7 @@ impl App {
         self.secret_input.clear();
         self.notes_input.clear();
         self.tags_input.clear();
+        self.folder_input.clear();
         self.custom_fields_input.clear();
         self.password_strength = None;
         self.is_active_input = true;
@@ -285,10 +1087,10 @@ impl App {
         self.password_manager.reset()?;
         self.master_password.clear();
         self.current_view = View::LockScreen;
-        self.credentials.clear();
-        self.selected_credential = None;
+        self.clear_loaded_credentials();
         self.error_message = None;
         self.clear_form();
+        self.refresh_failed_unlocks();
         Ok(())
     }
 
@@ -298,7 +1100,8 @@ impl App {
             Some(ActiveField::Username) => Some(ActiveField::Secret),
             Some(ActiveField::Secret) => Some(ActiveField::Notes),
             Some(ActiveField::Notes) => Some(ActiveField::Tags),
-            Some(ActiveField::Tags) => Some(ActiveField::CustomFields),
+            Some(ActiveField::Tags) => Some(ActiveField::Folder),
+            Some(ActiveField::Folder) => Some(ActiveField::CustomFields),
             Some(ActiveField::CustomFields) => {
                 if self.entry_type == EntryType::ApiKey {
                     Some(ActiveField::IsActive)
@@ -311,24 +1114,57 @@ impl App {
         };
     }
 
+    pub fn previous_field(&mut self) {
+        self.active_field = match self.active_field {
+            Some(ActiveField::Service) | None => {
+                if self.entry_type == EntryType::ApiKey {
+                    Some(ActiveField::IsActive)
+                } else {
+                    Some(ActiveField::CustomFields)
+                }
+            }
+            Some(ActiveField::Username) => Some(ActiveField::Service),
+            Some(ActiveField::Secret) => Some(ActiveField::Username),
+            Some(ActiveField::Notes) => Some(ActiveField::Secret),
+            Some(ActiveField::Tags) => Some(ActiveField::Notes),
+            Some(ActiveField::Folder) => Some(ActiveField::Tags),
+            Some(ActiveField::CustomFields) => Some(ActiveField::Folder),
+            Some(ActiveField::IsActive) => Some(ActiveField::CustomFields),
+        };
+    }
+
+    /// The text editor of the active form field. Custom fields and the active flag have their own handling.
+    pub fn active_editor_mut(&mut self) -> Option<&mut TextEditor> {
+        match self.active_field? {
+            ActiveField::Service => Some(&mut self.service_input),
+            ActiveField::Username => Some(&mut self.username_input),
+            ActiveField::Secret => Some(&mut self.secret_input),
+            ActiveField::Notes => Some(&mut self.notes_input),
+            ActiveField::Tags => Some(&mut self.tags_input),
+            ActiveField::Folder => Some(&mut self.folder_input),
+            ActiveField::CustomFields => Some(self.custom_fields_input.current_mut()),
+            ActiveField::IsActive => None,
+        }
+    }
+
     pub fn update_password_strength(&mut self) {
         if self.secret_input.is_empty() {
             self.password_strength = None;
         } else {
-            let entropy = zxcvbn(&self.secret_input, &[]).unwrap();
+            let entropy = zxcvbn(self.secret_input.text(), &[]).unwrap();
             self.password_strength = Some(entropy.score());
         }
     }
 
     pub fn load_selected_credential_for_edit(&mut self) -> Result<(), Box<dyn std::error::Error>> {
-        if let Some(idx) = self.selected_credential {
-            let credential = &self.credentials[idx];
-            self.service_input = credential.service.clone();
-            self.username_input = credential.username.clone();
-            self.secret_input = String::from_utf8(credential.secret.clone()).unwrap_or_default();
-            self.notes_input = credential.notes.clone();
-            self.tags_input = credential.tags.join(", ");
-            self.custom_fields_input = credential.custom_fields.iter().map(|(k, v)| format!("{}:{}", k, v)).collect::<Vec<String>>().join(", ");
+        if let Some(credential) = self.selected().cloned() {
+            self.service_input.set_text(&credential.service);
+            self.username_input.set_text(&credential.username);
+            self.secret_input.set_text(&String::from_utf8(credential.secret.clone()).unwrap_or_default());
+            self.notes_input.set_text(&credential.notes);
+            self.tags_input.set_text(&credential.tags.join(", "));
+            self.folder_input.set_text(&credential.folder);
+            self.custom_fields_input.set_fields(&credential.custom_fields);
             self.is_active_input = credential.is_active;
             self.entry_type = credential.entry_type.clone();
             self.selected_id = Some(credential.id.clone());
@@ -340,8 +1176,289 @@ impl App {
         Ok(())
     }
 
+    /// Opens the confirmation dialog for `action` instead of running it.
+    pub fn request_confirmation(&mut self, action: PendingAction) {
+        let path = self.settings.export_path.as_str();
+        let (title, message, phrase) = match action {
+            PendingAction::ResetVault => (
+                "Reset vault",
+                "This permanently deletes every stored credential and the master password. It cannot be undone."
+                    .to_string(),
+                Some(RESET_PHRASE),
+            ),
+            PendingAction::DeleteCredential => {
+                let credential = match self.selected() {
+                    Some(credential) => credential,
+                    None => return,
+                };
+                let name = if credential.username.is_empty() {
+                    format!("'{}'", credential.service)
+                } else {
+                    format!("'{}' ({})", credential.service, credential.username)
+                };
+                if credential.is_trashed() {
+                    ("Delete permanently", format!("Delete {} for good? This cannot be undone.", name), None)
+                } else {
+                    ("Move to trash", format!("Move {} to the trash? It can be restored from there.", name), None)
+                }
+            }
+            PendingAction::Bulk(operation) => {
+                let count = self.bulk_targets().len();
+                let message = match operation {
+                    BulkOperation::Trash => {
+                        format!("Move {} to the trash? They can be restored from there.", plural(count, "credential"))
+                    }
+                    BulkOperation::Purge => {
+                        format!("Delete {} for good? This cannot be undone.", plural(count, "credential"))
+                    }
+                    BulkOperation::Export => {
+                        let overwrite = if std::path::Path::new(path).exists() { " The existing file is overwritten." } else { "" };
+                        format!(
+                            "WARNING: the export is NOT encrypted. The secrets and notes of {} are written to {} \
+                             as plain JSON, readable by anyone with access to the file.{} Delete it as soon as you are done.",
+                            plural(count, "credential"),
+                            path,
+                            overwrite
+                        )
+                    }
+                    _ => return,
+                };
+                let title = match operation {
+                    BulkOperation::Trash => "Move to trash",
+                    BulkOperation::Purge => "Delete permanently",
+                    _ => "Plaintext export",
+                };
+                (title, message, None)
+            }
+            PendingAction::Import => (
+                "Import credentials",
+                format!(
+                    "Merge the credentials in {} into the vault? Entries with the same id as an existing one replace it.",
+                    path
+                ),
+                None,
+            ),
+            PendingAction::Export => {
+                let overwrite = if std::path::Path::new(path).exists() { " The existing file is overwritten." } else { "" };
+                (
+                    "Plaintext export",
+                    format!(
+                        "WARNING: the export is NOT encrypted. Every password, API key and note is written to {} \
+                         as plain JSON, readable by anyone with access to the file.{} Delete it as soon as you are done.",
+                        path, overwrite
+                    ),
+                    None,
+                )
+            }
+        };
+        self.confirmation = Some(Confirmation {
+            action,
+            title: title.to_string(),
+            message,
+            phrase,
+            input: String::new(),
+        });
+    }
+
+    /// Whether the dialog's confirmation requirement is met.
+    pub fn confirmation_ready(&self) -> bool {
+        match &self.confirmation {
+            Some(confirmation) => confirmation.phrase.is_none_or(|phrase| confirmation.input.trim() == phrase),
+            None => false,
+        }
+    }
+
+    /// Closes the dialog and runs its action.
+    pub fn confirm_pending(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        let confirmation = match self.confirmation.take() {
+            Some(confirmation) => confirmation,
+            None => return Ok(()),
+        };
+        match confirmation.action {
+            PendingAction::ResetVault => {
+                self.reset()?;
+                self.error_message = Some("Password vault has been reset. Create a new master password.".to_string());
+            }
+            PendingAction::DeleteCredential => {
+                self.copy_sequence = None;
+                self.remove_selected_credential()?;
+                self.current_view = View::Main;
+            }
+            PendingAction::Import => self.import_vault()?,
+            PendingAction::Export => self.export_vault()?,
+            PendingAction::Bulk(operation) => self.run_bulk_operation(operation, "")?,
+        }
+        Ok(())
+    }
+
+    /// The credentials a bulk operation applies to: the marked ones, or the selected one if none are marked.
+    pub fn bulk_targets(&self) -> Vec<String> {
+        if self.marked.is_empty() {
+            return self.selected().map(|c| vec![c.id.clone()]).unwrap_or_default();
+        }
+        self.credentials.iter().filter(|c| self.marked.contains(&c.id)).map(|c| c.id.clone()).collect()
+    }
+
+    /// Marks or unmarks the selected credential and moves on to the next row.
+    pub fn toggle_mark(&mut self) {
+        if let Some(id) = self.selected().map(|c| c.id.clone()) {
+            if !self.marked.remove(&id) {
+                self.marked.insert(id);
+            }
+            self.move_selection(1);
+        }
+    }
+
+    /// Marks every listed credential, or clears the marks if they are all marked already.
+    pub fn toggle_mark_all(&mut self) {
+        let listed: Vec<String> = self.visible.iter().map(|&i| self.credentials[i].id.clone()).collect();
+        if listed.iter().all(|id| self.marked.contains(id)) {
+            self.marked.clear();
+        } else {
+            self.marked.extend(listed);
+        }
+    }
+
+    /// Switches the main list between the vault and the trash.
+    pub fn toggle_trash(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        self.show_trash = !self.show_trash;
+        self.marked.clear();
+        self.tag_filter = None;
+        self.selected_credential = None;
+        self.load_credentials()
+    }
+
+    pub fn open_conflicts(&mut self) {
+        let conflicts = self.password_manager.get_conflicts();
+        if conflicts.is_empty() {
+            self.info_message = Some("No sync conflicts.".to_string());
+        } else {
+            self.conflict_review = Some(ConflictReview { conflicts, cursor: 0 });
+        }
+    }
+
+    pub fn open_audit_log(&mut self) {
+        match self.password_manager.audit_log() {
+            Ok(mut log) => {
+                log.entries.reverse();
+                self.audit_view = Some(AuditView { log, scroll: 0 });
+            }
+            Err(e) => self.error_message = Some(format!("Could not read the audit log: {}", e)),
+        }
+    }
+
+    /// Settles the conflict under the cursor, keeping the current version or switching to the other one.
+    pub fn resolve_conflict(&mut self, use_other: bool) -> Result<(), Box<dyn std::error::Error>> {
+        let (id, service) = match &self.conflict_review {
+            Some(review) => match review.conflicts.get(review.cursor) {
+                Some((_, other)) => (other.id.clone(), other.service.clone()),
+                None => return Ok(()),
+            },
+            None => return Ok(()),
+        };
+        if let Err(e) = self.password_manager.resolve_conflict(&id, use_other) {
+            self.error_message = Some(format!("Could not resolve the conflict: {}", e));
+            return Ok(());
+        }
+        self.info_message = Some(if use_other {
+            format!("Switched {} to the other version.", service)
+        } else {
+            format!("Kept the current version of {}.", service)
+        });
+        self.load_credentials()?;
+        let conflicts = self.password_manager.get_conflicts();
+        self.conflict_review = match self.conflict_review.take() {
+            Some(review) if !conflicts.is_empty() => {
+                Some(ConflictReview { cursor: review.cursor.min(conflicts.len() - 1), conflicts })
+            }
+            _ => None,
+        };
+        Ok(())
+    }
+
+    pub fn open_bulk_menu(&mut self) {
+        if self.bulk_targets().is_empty() {
+            self.error_message = Some("Nothing selected. Mark credentials first.".to_string());
+        } else {
+            self.bulk_menu = Some(BulkMenu::default());
+        }
+    }
+
+    /// Starts the operation picked from the bulk menu: asks for a value, asks for confirmation, or runs it.
+    pub fn choose_bulk_operation(&mut self, operation: BulkOperation) -> Result<(), Box<dyn std::error::Error>> {
+        if operation.prompt().is_some() {
+            self.bulk_menu = Some(BulkMenu { prompt: Some((operation, TextEditor::new())) });
+            return Ok(());
+        }
+        self.bulk_menu = None;
+        match operation {
+            BulkOperation::Trash | BulkOperation::Purge | BulkOperation::Export => {
+                self.request_confirmation(PendingAction::Bulk(operation));
+                Ok(())
+            }
+            _ => self.run_bulk_operation(operation, ""),
+        }
+    }
+
+    /// Applies a bulk operation to [`App::bulk_targets`] with a single save, reporting the outcome in the status messages.
+    pub fn run_bulk_operation(&mut self, operation: BulkOperation, value: &str) -> Result<(), Box<dyn std::error::Error>> {
+        self.bulk_menu = None;
+        let ids = self.bulk_targets();
+        let manager = &self.password_manager;
+        let result = match operation {
+            BulkOperation::AddTag => manager
+                .add_tag(&ids, value)
+                .map(|n| format!("Tagged {} with '{}'.", plural(n, "credential"), value.trim())),
+            BulkOperation::RemoveTag => manager
+                .remove_tag(&ids, value)
+                .map(|n| format!("Removed the tag '{}' from {}.", value.trim(), plural(n, "credential"))),
+            BulkOperation::MoveToFolder => manager.move_to_folder(&ids, value).map(|n| {
+                let folder = normalize_folder(value);
+                if folder.is_empty() {
+                    format!("Moved {} to the top level.", plural(n, "credential"))
+                } else {
+                    format!("Moved {} to {}.", plural(n, "credential"), folder)
+                }
+            }),
+            BulkOperation::ToggleActive => {
+                let keys: Vec<&Credential> =
+                    self.credentials.iter().filter(|c| ids.contains(&c.id) && c.entry_type == EntryType::ApiKey).collect();
+                if keys.is_empty() {
+                    Err("None of the selected credentials is an API key.".into())
+                } else {
+                    // Mixed selections become all active first, so pressing it twice deactivates them all.
+                    let active = !keys.iter().all(|c| c.is_active);
+                    let key_ids: Vec<String> = keys.iter().map(|c| c.id.clone()).collect();
+                    manager.set_active(&key_ids, active).map(|n| {
+                        format!("Marked {} {}.", plural(n, "API key"), if active { "active" } else { "inactive" })
+                    })
+                }
+            }
+            BulkOperation::Trash => manager.trash(&ids).map(|n| format!("Moved {} to the trash.", plural(n, "credential"))),
+            BulkOperation::Restore => manager.restore(&ids).map(|n| format!("Restored {}.", plural(n, "credential"))),
+            BulkOperation::Purge => manager.purge(&ids).map(|n| format!("Deleted {} for good.", plural(n, "credential"))),
+            BulkOperation::Export => {
+                let path = self.settings.export_path.clone();
+                manager
+                    .export_credentials(&ids, &path)
+                    .map(|n| format!("Exported {} to {}.", plural(n, "credential"), path))
+            }
+        };
+        match result {
+            Ok(message) => {
+                self.info_message = Some(message);
+                self.error_message = None;
+            }
+            Err(e) => {
+                self.error_message = Some(e.to_string());
+                self.info_message = None;
+            }
+        }
+        self.load_credentials()
+    }
+
     pub fn export_vault(&mut self) -> Result<(), Box<dyn std::error::Error>> {
-        let path = "vault_export.json";
+        let path = self.settings.export_path.as_str();
         match self.password_manager.export_vault(path) {
             Ok(_) => {
                 self.info_message = Some(format!("Vault exported successfully to {}", path));
@@ -356,8 +1473,8 @@ impl App {
     }
 
     pub fn import_vault(&mut self) -> Result<(), Box<dyn std::error::Error>> {
-        let path = "vault_export.json";
-        match self.password_manager.import_vault(path) {
+        let path = self.settings.export_path.clone();
+        match self.password_manager.import_vault(&path) {
             Ok(_) => {
                 self.load_credentials()?;
                 self.info_message = Some(format!("Vault imported successfully from {}", path));
@@ -371,3 +1488,12 @@ impl App {
         Ok(())
     }
 }
+
+/// `1 credential`, `3 credentials`.
+fn plural(count: usize, noun: &str) -> String {
+    format!("{} {}{}", count, noun, if count == 1 { "" } else { "s" })
+}
+
+fn is_selectable(row: &ListRow) -> bool {
+    !matches!(row, ListRow::Section { .. })
+}
@@ -1,13 +1,20 @@
 use tui::{
     backend::Backend,
-    layout::{Alignment, Constraint, Direction, Layout, Rect},
-    style::{Color, Modifier, Style},
+    layout::{Alignment, Constraint, Direction, Layout, Margin, Rect},
+    style::{Modifier, Style},
     text::{Span, Spans},
     widgets::{Block, Borders, Clear, List, ListItem, Paragraph, Wrap},
     Frame,
 };
-use crate::ui::app::{App, InputMode};
-use crate::models::EntryType;
+use crate::config::SETTING_KEYS;
+use crate::lockout;
+use crate::ui::app::{ActiveField, App, AuditView, BulkMenu, BulkOperation, ConflictReview, Confirmation, InputMode, MainFocus};
+use crate::ui::editor::{FieldColumn, KeyValueEditor, TextEditor};
+use crate::ui::keymap::{Action, Context};
+use crate::ui::list::ListRow;
+use crate::ui::scroll::{ScrollState, Scrollbar};
+use crate::models::{Credential, EntryType};
+use crate::otp::{self, Totp};
 
 // Helper function to create a centered block
 fn centered_rect(percent_x: u16, percent_y: u16, r: Rect) -> Rect {
@@ -36,10 +43,21 @@ fn centered_rect(percent_x: u16, percent_y: u16, r: Rect) -> Rect {
         .split(popup_layout[1])[1]
 }
 
+/// Fills `area` with the theme's colors. Drawn under every screen so widgets that don't set a
+/// background still match the theme instead of showing the terminal's own colors.
+pub fn draw_background<B: Backend>(f: &mut Frame<B>, area: Rect, app: &App) {
+    let block = Block::default().style(Style::default().bg(app.theme.background).fg(app.theme.foreground));
+    f.render_widget(block, area);
+}
+
+/// Blanks the area under a popup, keeping the theme background.
+fn clear_area<B: Backend>(f: &mut Frame<B>, area: Rect, app: &App) {
+    f.render_widget(Clear, area);
+    draw_background(f, area, app);
+}
+
 pub fn draw_lock_screen<B: Backend>(f: &mut Frame<B>, app: &App) {
     let size = f.size();
-    let block = Block::default().style(Style::default().bg(app.theme.background));
-    f.render_widget(block, size);
 
     let area = centered_rect(60, 40, size);
     let chunks = Layout::default()
@@ -49,6 +67,7 @@ pub fn draw_lock_screen<B: Backend>(f: &mut Frame<B>, app: &App) {
             [
                 Constraint::Length(3),
                 Constraint::Length(3),
+                Constraint::Length(2),
                 Constraint::Min(1),
                 Constraint::Length(3),
             ]
@@ -63,16 +82,50 @@ pub fn draw_lock_screen<B: Backend>(f: &mut Frame<B>, app: &App) {
     .block(Block::default().borders(Borders::ALL).border_type(tui::widgets::BorderType::Double));
     f.render_widget(title, area);
 
+    let focused = |editing: bool| {
+        let style = Style::default().fg(app.theme.secondary);
+        if editing { style.add_modifier(Modifier::BOLD) } else { style.add_modifier(Modifier::DIM) }
+    };
     let password_input = Paragraph::new(format!("Enter Master Password:\n{}", "*".repeat(app.master_password.len())))
-        .style(Style::default().fg(app.theme.secondary))
+        .style(focused(!app.editing_key_file))
         .alignment(Alignment::Center)
         .wrap(Wrap { trim: true });
     f.render_widget(password_input, chunks[1]);
 
-    let instructions = Paragraph::new("Press Enter to unlock, or Ctrl+R to reset.")
+    let key_file = match app.key_file.as_str() {
+        "" if !app.editing_key_file => "none",
+        path => path,
+    };
+    let key_file_input = Paragraph::new(format!("Key file: {}", key_file))
+        .style(focused(app.editing_key_file))
+        .alignment(Alignment::Center);
+    f.render_widget(key_file_input, chunks[2]);
+
+    let failed = &app.failed_unlocks;
+    if failed.count > 0 || failed.read_only {
+        let mut lines = Vec::new();
+        if failed.count > 0 {
+            let noun = if failed.count == 1 { "attempt" } else { "attempts" };
+            lines.push(Spans::from(format!("{} failed {} since last unlock", failed.count, noun)));
+        }
+        if let Some(wait) = lockout::retry_after(failed, chrono::Utc::now()) {
+            lines.push(Spans::from(format!("Next attempt in {} s", wait.as_secs() + 1)));
+        }
+        if failed.read_only {
+            lines.push(Spans::from("The vault will open read-only"));
+        }
+        let notice = Paragraph::new(lines)
+            .style(Style::default().fg(app.theme.warning))
+            .alignment(Alignment::Center)
+            .wrap(Wrap { trim: true });
+        f.render_widget(notice, chunks[3]);
+    }
+
+    let reset = app.keymap.keys_for(Context::Lock, Action::ResetVault).join(" or ");
+    let instructions = Paragraph::new(format!("Press Enter to unlock, Tab for a key file, or {} to reset.", reset))
         .style(Style::default().fg(app.theme.border))
         .alignment(Alignment::Center);
-    f.render_widget(instructions, chunks[3]);
+    f.render_widget(instructions, chunks[4]);
 
     if let Some(error) = &app.error_message {
         let error_area = centered_rect(50, 20, size);
@@ -82,107 +135,335 @@ pub fn draw_lock_screen<B: Backend>(f: &mut Frame<B>, app: &App) {
             .block(error_block)
             .alignment(Alignment::Center)
             .wrap(Wrap { trim: true });
-        f.render_widget(Clear, error_area); //this clears the background
+        clear_area(f, error_area, app); //this clears the background
         f.render_widget(error_text, error_area);
     }
 }
 
 
+/// Below this height screens drop their title box and outer margin.
+const COMPACT_HEIGHT: u16 = 20;
+/// Below this width the tag sidebar replaces the list while it has focus instead of sitting beside it.
+const SIDEBAR_MIN_WIDTH: u16 = 70;
+/// Below this width the preview pane is left out and the list takes its space.
+const PREVIEW_MIN_WIDTH: u16 = 100;
+
 pub fn draw_main_screen<B: Backend>(f: &mut Frame<B>, app: &App) {
+    let size = f.size();
+    let compact = size.height < COMPACT_HEIGHT;
     let chunks = Layout::default()
         .direction(Direction::Vertical)
-        .margin(1)
+        .margin(if compact { 0 } else { 1 })
         .constraints(
             [
-                Constraint::Length(3),
+                Constraint::Length(if compact { 0 } else { 3 }),
                 Constraint::Length(3),
                 Constraint::Min(1),
                 Constraint::Length(3),
             ]
             .as_ref(),
         )
-        .split(f.size());
+        .split(size);
 
-    let title = Paragraph::new("🔐 Credential Vault")
-        .style(Style::default().fg(app.theme.success).add_modifier(Modifier::BOLD))
-        .alignment(Alignment::Center)
-        .block(Block::default().borders(Borders::ALL).border_type(tui::widgets::BorderType::Rounded));
-    f.render_widget(title, chunks[0]);
+    if !compact {
+        let title = Paragraph::new("🔐 Credential Vault")
+            .style(Style::default().fg(app.theme.success).add_modifier(Modifier::BOLD))
+            .alignment(Alignment::Center)
+            .block(Block::default().borders(Borders::ALL).border_type(tui::widgets::BorderType::Rounded));
+        f.render_widget(title, chunks[0]);
+    }
 
     let search_bar = Paragraph::new(app.search_query.as_ref())
         .style(Style::default().fg(app.theme.secondary))
-        .block(Block::default().borders(Borders::ALL).title("Search (/)"));
+        .block(Block::default().borders(Borders::ALL).title("Search (/) - fuzzy, or tag: type: user: active:"));
     f.render_widget(search_bar, chunks[1]);
 
+    let list_area = if app.tags.is_empty() {
+        app.tags_scroll.hide();
+        chunks[2]
+    } else if size.width < SIDEBAR_MIN_WIDTH {
+        if app.main_focus == MainFocus::Tags {
+            app.list_scroll.hide();
+            draw_tag_sidebar(f, app, chunks[2]);
+            draw_main_help(f, app, chunks[3]);
+            return draw_message_popups(f, app);
+        }
+        app.tags_scroll.hide();
+        chunks[2]
+    } else {
+        let panes = Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints([Constraint::Length(24), Constraint::Min(1)].as_ref())
+            .split(chunks[2]);
+        draw_tag_sidebar(f, app, panes[0]);
+        panes[1]
+    };
+    let list_area = if app.settings.preview_pane && size.width >= PREVIEW_MIN_WIDTH {
+        let panes = Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints([Constraint::Percentage(55), Constraint::Percentage(45)].as_ref())
+            .split(list_area);
+        draw_preview(f, app, panes[1]);
+        panes[0]
+    } else {
+        list_area
+    };
+
+    let list_block = Block::default().borders(Borders::ALL);
+    let list_inner = list_block.inner(list_area);
+    let offset = app.list_scroll.follow(list_inner, app.rows.len(), app.selected_credential);
     let items: Vec<ListItem> = app
-        .credentials
+        .rows
         .iter()
         .enumerate()
-        .map(|(i, cred)| {
+        .skip(offset)
+        .take(list_inner.height as usize)
+        .map(|(i, row)| {
             let style = if Some(i) == app.selected_credential {
                 Style::default().bg(app.theme.highlight_bg).fg(app.theme.highlight_fg).add_modifier(Modifier::BOLD)
             } else {
                 Style::default().fg(app.theme.border)
             };
-            let entry_type_icon = match cred.entry_type {
-                EntryType::Password => "🔑",
-                EntryType::ApiKey => "⚙️",
+            let content = match row {
+                ListRow::Section { title } => Spans::from(Span::styled(
+                    title.clone(),
+                    Style::default().fg(app.theme.accent).add_modifier(Modifier::BOLD | Modifier::UNDERLINED),
+                )),
+                ListRow::Folder { name, depth, count, collapsed, .. } => {
+                    let marker = if *collapsed { "▸" } else { "▾" };
+                    Spans::from(vec![
+                        Span::raw("  ".repeat(*depth)),
+                        Span::styled(format!("{} 📁 {}", marker, name), Style::default().fg(app.theme.secondary).add_modifier(Modifier::BOLD)),
+                        Span::styled(format!(" ({})", count), Style::default().fg(app.theme.border)),
+                    ])
+                }
+                ListRow::Credential { index, depth, shortcut } => {
+                    let cred = &app.credentials[*index];
+                    let entry_type_icon = match cred.entry_type {
+                        EntryType::Password => "🔑",
+                        EntryType::ApiKey => "⚙️",
+                    };
+                    let tags = cred.tags.join(", ");
+                    let prefix = match shortcut {
+                        Some(n) => format!("[{}] ", n),
+                        None => "  ".repeat(*depth),
+                    };
+                    // The mark column only takes space once something is marked.
+                    let mark = match (app.marked.is_empty(), app.marked.contains(&cred.id)) {
+                        (true, _) => "",
+                        (false, true) => "✔ ",
+                        (false, false) => "  ",
+                    };
+                    Spans::from(vec![
+                        Span::styled(mark, Style::default().fg(app.theme.success).add_modifier(Modifier::BOLD)),
+                        Span::styled(prefix, Style::default().fg(app.theme.accent)),
+                        Span::styled(if cred.favorite { "★" } else { " " }, Style::default().fg(app.theme.warning)),
+                        Span::styled(format!("{} ", entry_type_icon), Style::default()),
+                        Span::styled(format!("{:<20}", cred.service), Style::default().fg(app.theme.primary)),
+                        Span::raw(" - "),
+                        Span::styled(format!("{:<20}", cred.username.clone()), Style::default().fg(app.theme.secondary)),
+                        Span::raw(" - "),
+                        Span::styled(tags, Style::default().fg(app.theme.accent)),
+                    ])
+                }
             };
-            let tags = cred.tags.join(", ");
-            let content = Spans::from(vec![
-                Span::styled(format!("{} ", entry_type_icon), Style::default()),
-                Span::styled(format!("{:<20}", cred.service), Style::default().fg(app.theme.primary)),
-                Span::raw(" - "),
-                Span::styled(format!("{:<20}", cred.username.clone()), Style::default().fg(app.theme.secondary)),
-                Span::raw(" - "),
-                Span::styled(tags, Style::default().fg(app.theme.accent)),
-            ]);
             ListItem::new(content).style(style)
         })
         .collect();
 
+    let name = if app.show_trash { "Trash" } else { "Credentials" };
+    let mut list_title = if app.search_query.is_empty() {
+        format!("{} - sorted by {}", name, app.sort_mode.label())
+    } else {
+        format!("{} ({} of {})", name, app.visible.len(), app.credentials.len())
+    };
+    if let Some(tag) = &app.tag_filter {
+        list_title.push_str(&format!(" - tag: {}", tag));
+    }
+    if !app.marked.is_empty() {
+        list_title.push_str(&format!(" - {} marked", app.marked.len()));
+    }
+    if app.conflict_count > 0 {
+        list_title.push_str(&format!(" - ⚠ {} sync conflict{}", app.conflict_count, if app.conflict_count == 1 { "" } else { "s" }));
+    }
     let list = List::new(items)
-        .block(Block::default().borders(Borders::ALL).title("Credentials"))
+        .block(list_block.title(list_title))
         .highlight_style(Style::default().bg(app.theme.highlight_bg).fg(app.theme.highlight_fg));
-    f.render_widget(list, chunks[2]);
+    f.render_widget(list, list_area);
+    let scrollbar = Scrollbar {
+        offset,
+        total: app.rows.len(),
+        visible: list_inner.height as usize,
+        style: Style::default().fg(app.theme.border),
+    };
+    f.render_widget(scrollbar, list_area);
 
+    draw_main_help(f, app, chunks[3]);
+    draw_message_popups(f, app);
+}
+
+/// The selected entry's details beside the list, with the secret masked. Redrawn as the selection moves.
+fn draw_preview<B: Backend>(f: &mut Frame<B>, app: &App, area: Rect) {
+    let label_style = Style::default().fg(app.theme.border);
+    let heading_style = Style::default().fg(app.theme.accent).add_modifier(Modifier::BOLD);
+    let lines = match app.selected_row() {
+        Some(ListRow::Credential { index, .. }) => {
+            let cred = &app.credentials[*index];
+            let details = credential_details(app, cred, false);
+            // The service stands in for the view screen's "View Password" heading.
+            let mut title = vec![Span::styled(cred.service.clone(), heading_style)];
+            title.extend(details.title.into_iter().skip(1));
+            let mut lines = vec![Spans::from(title), Spans::default()];
+            lines.extend(details.fields);
+            if !details.custom_fields.is_empty() {
+                lines.push(Spans::default());
+                lines.push(Spans::from(Span::styled("Custom Fields", heading_style)));
+                lines.extend(
+                    details
+                        .custom_fields
+                        .into_iter()
+                        .map(|(label, value)| Spans::from(vec![Span::styled(label, label_style), Span::raw(value)])),
+                );
+            }
+            if !cred.notes.is_empty() {
+                lines.push(Spans::default());
+                lines.push(Spans::from(Span::styled("Notes", heading_style)));
+                lines.extend(details.notes.into_iter().map(|(_, line)| Spans::from(line)));
+            }
+            lines
+        }
+        Some(ListRow::Folder { path, count, .. }) => vec![
+            Spans::from(Span::styled(format!("📁 {}", path), heading_style)),
+            Spans::from(Span::styled(format!("{} credential{}", count, if *count == 1 { "" } else { "s" }), label_style)),
+        ],
+        Some(ListRow::Section { .. }) | None => vec![Spans::from(Span::styled("Nothing selected", label_style))],
+    };
+    let preview = Paragraph::new(lines)
+        .style(Style::default().fg(app.theme.foreground))
+        .block(Block::default().borders(Borders::ALL).title("Preview"))
+        .wrap(Wrap { trim: false });
+    f.render_widget(preview, area);
+}
+
+fn draw_main_help<B: Backend>(f: &mut Frame<B>, app: &App, area: Rect) {
     let help_text = if app.credentials.is_empty() {
-        "Press 'a' to add your first credential."
+        format!("Press '{}' to add your first credential.", app.keymap.keys_for(Context::Main, Action::Add).join("' or '"))
     } else {
-        "↑/↓: Navigate | Enter: View | a: Add | /: Search | q: Quit"
+        let mut actions = vec![
+                Action::MoveDown,
+                Action::MoveUp,
+                Action::Open,
+                Action::ToggleFavorite,
+                Action::Add,
+                Action::Search,
+                Action::CycleSort,
+                Action::ToggleFolders,
+                Action::TogglePreview,
+                Action::ToggleMark,
+                Action::BulkActions,
+                Action::ToggleTrash,
+                Action::FocusTags,
+                Action::Settings,
+                Action::Quit,
+        ];
+        if app.conflict_count > 0 {
+            actions.insert(0, Action::ReviewConflicts);
+        }
+        let hint = app.keymap.hint(Context::Main, &actions);
+        format!("{} | 1-9: Quick | {}", hint, app.keymap.hint(Context::Global, &[Action::Help]))
     };
 
     let help = Paragraph::new(help_text)
         .style(Style::default().fg(app.theme.border))
         .alignment(Alignment::Center)
         .block(Block::default().borders(Borders::ALL).border_type(tui::widgets::BorderType::Rounded));
-    f.render_widget(help, chunks[3]);
+    f.render_widget(help, area);
 }
 
-fn get_constraints(entry_type: &EntryType) -> Vec<Constraint> {
+fn draw_tag_sidebar<B: Backend>(f: &mut Frame<B>, app: &App, area: Rect) {
+    let focused = app.main_focus == MainFocus::Tags;
+    let block = Block::default().borders(Borders::ALL);
+    let inner = block.inner(area);
+    let offset = app.tags_scroll.follow(inner, app.tags.len(), Some(app.tag_cursor).filter(|_| focused));
+    let items: Vec<ListItem> = app
+        .tags
+        .iter()
+        .enumerate()
+        .skip(offset)
+        .take(inner.height as usize)
+        .map(|(i, (tag, count))| {
+            let applied = app.tag_filter.as_ref() == Some(tag);
+            let style = if focused && i == app.tag_cursor {
+                Style::default().bg(app.theme.highlight_bg).fg(app.theme.highlight_fg)
+            } else if applied {
+                Style::default().fg(app.theme.accent).add_modifier(Modifier::BOLD)
+            } else {
+                Style::default().fg(app.theme.border)
+            };
+            let marker = if applied { "● " } else { "  " };
+            ListItem::new(format!("{}{} ({})", marker, tag, count)).style(style)
+        })
+        .collect();
+    let border_style = if focused {
+        Style::default().fg(app.theme.secondary)
+    } else {
+        Style::default()
+    };
+    let sidebar = List::new(items).block(block.border_style(border_style).title("Tags (Tab)"));
+    f.render_widget(sidebar, area);
+    let scrollbar = Scrollbar { offset, total: app.tags.len(), visible: inner.height as usize, style: border_style };
+    f.render_widget(scrollbar, area);
+}
+
+/// Below this height the form switches to one line per field, without boxes.
+const FORM_COMPACT_HEIGHT: u16 = 40;
+/// Width of the labels in front of one-line fields.
+const LABEL_WIDTH: u16 = 14;
+
+fn get_constraints(app: &App, compact: bool) -> Vec<Constraint> {
+    // In compact mode the title shares the type selector's line.
+    let line = if compact { 1 } else { 3 };
     let mut constraints = vec![
-        Constraint::Length(3), // Title
-        Constraint::Length(3), // Type Selector
-        Constraint::Length(3), // Service
-        Constraint::Length(3), // Username/Account Name
-        Constraint::Length(3), // Secret
-        Constraint::Length(3), // Password Strength
-        Constraint::Min(3),    // Notes (flexible height)
-        Constraint::Length(3), // Tags
-        Constraint::Length(3), // Custom Fields
+        Constraint::Length(if compact { 0 } else { 3 }), // Title
+        Constraint::Length(line), // Type Selector
+        Constraint::Length(line), // Service
+        Constraint::Length(line), // Username/Account Name
+        Constraint::Length(line), // Secret
+        Constraint::Length(line), // Password Strength
+        Constraint::Min(line),    // Notes (flexible height)
+        Constraint::Length(line), // Tags
+        Constraint::Length(line), // Folder
+        Constraint::Length(if compact {
+            app.custom_fields_input.rows.len().clamp(1, 3) as u16 + 1
+        } else {
+            app.custom_fields_input.rows.len().clamp(1, 4) as u16 + 2
+        }), // Custom Fields
     ];
-    if *entry_type == EntryType::ApiKey {
-        constraints.push(Constraint::Length(3)); // Is Active
+    if app.entry_type == EntryType::ApiKey {
+        constraints.push(Constraint::Length(line)); // Is Active
     }
-    constraints.push(Constraint::Length(3)); // Help
+    constraints.push(Constraint::Length(line)); // Help
     constraints
 }
 
+/// A one-line `label value` row, used in place of a boxed field when the form is compact.
+fn draw_labelled_line<B: Backend>(f: &mut Frame<B>, area: Rect, app: &App, label: &str, value: Spans, active: bool) {
+    let label_style = if active {
+        Style::default().fg(app.theme.secondary).add_modifier(Modifier::BOLD)
+    } else {
+        Style::default().fg(app.theme.border)
+    };
+    let mut spans = vec![Span::styled(format!("{:<width$}", label, width = LABEL_WIDTH as usize), label_style)];
+    spans.extend(value.0);
+    f.render_widget(Paragraph::new(Spans::from(spans)), area);
+}
+
 pub fn draw_add_credential_screen<B: Backend>(f: &mut Frame<B>, app: &App) {
-    let constraints = get_constraints(&app.entry_type);
+    let compact = f.size().height < FORM_COMPACT_HEIGHT;
+    let constraints = get_constraints(app, compact);
     let chunks = Layout::default()
         .direction(Direction::Vertical)
-        .margin(2)
+        .margin(if compact { 0 } else { 2 })
         .constraints(constraints.as_slice())
         .split(f.size());
 
@@ -197,70 +478,45 @@ pub fn draw_add_credential_screen<B: Backend>(f: &mut Frame<B>, app: &App) {
             EntryType::ApiKey => "➕ Add New API Key",
         }
     };
-    let title = Paragraph::new(title_text)
-        .style(Style::default().fg(app.theme.primary).add_modifier(Modifier::BOLD))
-        .alignment(Alignment::Center)
-        .block(Block::default().borders(Borders::ALL).border_type(tui::widgets::BorderType::Rounded));
-    f.render_widget(title, chunks[0]);
-
+    let title_style = Style::default().fg(app.theme.primary).add_modifier(Modifier::BOLD);
     let active_style = Style::default().fg(app.theme.secondary).add_modifier(Modifier::BOLD);
-    let _inactive_style = Style::default().fg(app.theme.foreground);
 
     // Entry Type Selector
     let type_text = match app.entry_type {
         EntryType::Password => "Type: Password (press 't' to switch)",
         EntryType::ApiKey => "Type: API Key (press 't' to switch)",
     };
-    let type_paragraph = Paragraph::new(type_text)
-        .style(if app.selected_id.is_some() { Style::default().fg(Color::DarkGray) } else { Style::default() })
-        .block(Block::default().borders(Borders::ALL).title("Entry Type"));
-    f.render_widget(type_paragraph, chunks[1]);
-
-    // Service
-    let service_block = Block::default().borders(Borders::ALL).title("Service");
-    let service = Paragraph::new(app.service_input.as_ref()).block(
-        if app.active_field == Some(crate::ui::app::ActiveField::Service) {
-            service_block.border_style(active_style)
-        } else {
-            service_block
-        },
-    );
-    f.render_widget(service, chunks[2]);
+    let type_style = if app.selected_id.is_some() { Style::default().fg(app.theme.border) } else { Style::default() };
+    if compact {
+        let line = Spans::from(vec![Span::styled(title_text, title_style), Span::raw("   "), Span::styled(type_text, type_style)]);
+        f.render_widget(Paragraph::new(line), chunks[1]);
+    } else {
+        let title = Paragraph::new(title_text)
+            .style(title_style)
+            .alignment(Alignment::Center)
+            .block(Block::default().borders(Borders::ALL).border_type(tui::widgets::BorderType::Rounded));
+        f.render_widget(title, chunks[0]);
+        let type_paragraph = Paragraph::new(type_text)
+            .style(type_style)
+            .block(Block::default().borders(Borders::ALL).title("Entry Type"));
+        f.render_widget(type_paragraph, chunks[1]);
+    }
+
+    draw_text_field(f, chunks[2], app, &app.service_input, "Service", ActiveField::Service, false);
 
     // Username / Account Name
     let username_title = match app.entry_type {
         EntryType::Password => "Username",
         EntryType::ApiKey => "Account Name",
     };
-    let username_block = Block::default().borders(Borders::ALL).title(username_title);
-    let username = Paragraph::new(app.username_input.as_ref()).block(
-        if app.active_field == Some(crate::ui::app::ActiveField::Username) {
-            username_block.border_style(active_style)
-        } else {
-            username_block
-        },
-    );
-    f.render_widget(username, chunks[3]);
+    draw_text_field(f, chunks[3], app, &app.username_input, username_title, ActiveField::Username, false);
 
     // Secret
     let secret_title = match app.entry_type {
         EntryType::Password => "Password",
         EntryType::ApiKey => "API Key",
     };
-    let secret_block = Block::default().borders(Borders::ALL).title(secret_title);
-    let secret_text = if app.show_secret {
-        app.secret_input.clone()
-    } else {
-        "*".repeat(app.secret_input.len())
-    };
-    let secret = Paragraph::new(secret_text).block(
-        if app.active_field == Some(crate::ui::app::ActiveField::Secret) {
-            secret_block.border_style(active_style)
-        } else {
-            secret_block
-        },
-    );
-    f.render_widget(secret, chunks[4]);
+    draw_text_field(f, chunks[4], app, &app.secret_input, secret_title, ActiveField::Secret, !app.show_secret);
 
     // Password Strength
     let strength_block = Block::default().borders(Borders::ALL).title("Password Strength");
@@ -276,59 +532,40 @@ pub fn draw_add_credential_screen<B: Backend>(f: &mut Frame<B>, app: &App) {
     } else {
         ("", Style::default())
     };
-    let strength_meter = Paragraph::new(strength_text)
-        .style(strength_style)
-        .block(strength_block);
-    f.render_widget(strength_meter, chunks[5]);
-
-    // Notes
-    let notes_block = Block::default().borders(Borders::ALL).title("Notes");
-    let notes = Paragraph::new(app.notes_input.as_ref()).wrap(Wrap { trim: true }).block(
-        if app.active_field == Some(crate::ui::app::ActiveField::Notes) {
-            notes_block.border_style(active_style)
-        } else {
-            notes_block
-        },
-    );
-    f.render_widget(notes, chunks[6]);
+    if compact {
+        draw_labelled_line(f, chunks[5], app, "Strength", Spans::from(Span::styled(strength_text, strength_style)), false);
+    } else {
+        let strength_meter = Paragraph::new(strength_text)
+            .style(strength_style)
+            .block(strength_block);
+        f.render_widget(strength_meter, chunks[5]);
+    }
 
-    // Tags
-    let tags_block = Block::default().borders(Borders::ALL).title("Tags (comma-separated)");
-    let tags = Paragraph::new(app.tags_input.as_ref()).block(
-        if app.active_field == Some(crate::ui::app::ActiveField::Tags) {
-            tags_block.border_style(active_style)
-        } else {
-            tags_block
-        },
-    );
-    f.render_widget(tags, chunks[7]);
-
-    // Custom Fields
-    let custom_fields_block = Block::default().borders(Borders::ALL).title("Custom Fields (key:value, comma-separated)");
-    let custom_fields = Paragraph::new(app.custom_fields_input.as_ref()).block(
-        if app.active_field == Some(crate::ui::app::ActiveField::CustomFields) {
-            custom_fields_block.border_style(active_style)
-        } else {
-            custom_fields_block
-        },
-    );
-    f.render_widget(custom_fields, chunks[8]);
+    draw_text_field(f, chunks[6], app, &app.notes_input, "Notes (Enter: new line)", ActiveField::Notes, false);
+    draw_text_field(f, chunks[7], app, &app.tags_input, "Tags (comma-separated)", ActiveField::Tags, false);
+    draw_text_field(f, chunks[8], app, &app.folder_input, "Folder (e.g. Work/Infra)", ActiveField::Folder, false);
+    draw_custom_fields_editor(f, chunks[9], app, &app.custom_fields_input, compact);
 
     let help_chunk_index = if app.entry_type == EntryType::ApiKey {
         // Is Active for API Key
         let is_active_block = Block::default().borders(Borders::ALL).title("Active Status");
         let is_active_text = if app.is_active_input { "✅ Active" } else { "❌ Inactive" };
-        let is_active = Paragraph::new(is_active_text).block(
-            if app.active_field == Some(crate::ui::app::ActiveField::IsActive) {
-                is_active_block.border_style(active_style)
-            } else {
-                is_active_block
-            },
-        );
-        f.render_widget(is_active, chunks[9]);
-        10
+        let active = app.active_field == Some(ActiveField::IsActive);
+        if compact {
+            draw_labelled_line(f, chunks[10], app, "Active Status", Spans::from(is_active_text), active);
+        } else {
+            let is_active = Paragraph::new(is_active_text).block(
+                if active {
+                    is_active_block.border_style(active_style)
+                } else {
+                    is_active_block
+                },
+            );
+            f.render_widget(is_active, chunks[10]);
+        }
+        11
     } else {
-        9
+        10
     };
 
     // Help Text
@@ -336,148 +573,751 @@ pub fn draw_add_credential_screen<B: Backend>(f: &mut Frame<B>, app: &App) {
         InputMode::Normal => "Mode: Normal (Press 'i' to edit)",
         InputMode::Editing => "Mode: Editing (Press 'Esc' to stop)",
     };
-    let help_text = format!("{} | Tab: Next Field | Enter: Save | Esc: Cancel", mode_text);
+    let help_text = format!(
+        "{} | {}",
+        mode_text,
+        app.keymap.hint(Context::Form, &[Action::Insert, Action::NextField, Action::Save, Action::Back])
+    );
     let help = Paragraph::new(help_text)
         .style(Style::default().fg(app.theme.border))
         .alignment(Alignment::Center)
-        .block(Block::default().borders(Borders::ALL).border_type(tui::widgets::BorderType::Rounded));
+        .block(if compact {
+            Block::default()
+        } else {
+            Block::default().borders(Borders::ALL).border_type(tui::widgets::BorderType::Rounded)
+        });
     f.render_widget(help, chunks[help_chunk_index]);
+
+    if let Some(error) = &app.error_message {
+        let error_area = centered_rect(50, 20, f.size());
+        let error_block = Block::default().title("Error").borders(Borders::ALL).border_style(Style::default().fg(app.theme.error));
+        let error_text = Paragraph::new(error.as_str())
+            .style(Style::default().fg(app.theme.error))
+            .block(error_block)
+            .alignment(Alignment::Center)
+            .wrap(Wrap { trim: true });
+        clear_area(f, error_area, app); //this clears the background
+        f.render_widget(error_text, error_area);
+    }
 }
 
-pub fn draw_view_credential_screen<B: Backend>(f: &mut Frame<B>, app: &App) {
-    if let Some(index) = app.selected_credential {
-        if let Some(cred) = app.credentials.get(index) {
-            let constraints = match cred.entry_type {
-                EntryType::Password => vec![
-                    Constraint::Length(3), // Title
-                    Constraint::Length(3), // Service
-                    Constraint::Length(3), // Username
-                    Constraint::Length(3), // Password
-                    Constraint::Min(3),    // Notes
-                    Constraint::Length(3), // Tags
-                    Constraint::Length(3), // Custom Fields
-                    Constraint::Length(3), // Help
-                ],
-                EntryType::ApiKey => vec![
-                    Constraint::Length(3), // Title
-                    Constraint::Length(3), // Service
-                    Constraint::Length(3), // Account Name
-                    Constraint::Length(3), // API Key
-                    Constraint::Min(3),    // Notes
-                    Constraint::Length(3), // Tags
-                    Constraint::Length(3), // Custom Fields
-                    Constraint::Length(3), // Is Active
-                    Constraint::Length(3), // Help
-                ],
-            };
+/// Breaks `text` into lines of at most `width` characters, at a space where possible.
+fn wrap_text(text: &str, width: usize) -> Vec<String> {
+    let width = width.max(1);
+    let mut lines = Vec::new();
+    let mut rest: Vec<char> = text.chars().collect();
+    while rest.len() > width {
+        let split = rest[..=width].iter().rposition(|c| *c == ' ').filter(|&i| i > 0).unwrap_or(width);
+        lines.push(rest[..split].iter().collect());
+        let skip = if rest[split] == ' ' { split + 1 } else { split };
+        rest.drain(..skip);
+    }
+    lines.push(rest.into_iter().collect());
+    lines
+}
 
-            let chunks = Layout::default()
-                .direction(Direction::Vertical)
-                .margin(2)
-                .constraints(constraints.as_slice())
-                .split(f.size());
+/// Draws labelled lines in a bordered pane that scrolls with `scroll`, wrapping long values under
+/// their label and showing a scrollbar when they don't fit.
+fn draw_scrolling_pane<B: Backend>(
+    f: &mut Frame<B>,
+    area: Rect,
+    app: &App,
+    title: &str,
+    lines: &[(String, String)],
+    scroll: &ScrollState,
+) {
+    let block = Block::default().borders(Borders::ALL).title(title);
+    let inner = block.inner(area);
+    let mut wrapped = Vec::new();
+    for (label, value) in lines {
+        let label_width = label.chars().count();
+        for (i, line) in wrap_text(value, (inner.width as usize).saturating_sub(label_width)).into_iter().enumerate() {
+            let prefix = if i == 0 { label.clone() } else { " ".repeat(label_width) };
+            wrapped.push(Spans::from(vec![
+                Span::styled(prefix, Style::default().fg(app.theme.border)),
+                Span::styled(line, Style::default().fg(app.theme.foreground)),
+            ]));
+        }
+    }
+    let total = wrapped.len();
+    let offset = scroll.update(inner, total);
+    let visible: Vec<Spans> = wrapped.into_iter().skip(offset).take(inner.height as usize).collect();
+    f.render_widget(Paragraph::new(visible).block(block), area);
+    let scrollbar = Scrollbar { offset, total, visible: inner.height as usize, style: Style::default().fg(app.theme.border) };
+    f.render_widget(scrollbar, area);
+}
 
-            let title_text = match cred.entry_type {
-                EntryType::Password => "👁️ View Password",
-                EntryType::ApiKey => "👁️ View API Key",
-            };
-            let title = Paragraph::new(title_text)
-                .style(Style::default().fg(app.theme.accent).add_modifier(Modifier::BOLD))
-                .alignment(Alignment::Center)
-                .block(Block::default().borders(Borders::ALL).border_type(tui::widgets::BorderType::Rounded));
-            f.render_widget(title, chunks[0]);
-
-            let service = Paragraph::new(Spans::from(vec![
-                Span::styled("Service: ", Style::default().fg(app.theme.border)),
-                Span::styled(&cred.service, Style::default().fg(app.theme.foreground)),
-            ]))
-            .block(Block::default().borders(Borders::ALL));
-            f.render_widget(service, chunks[1]);
+/// The first character to show so that `cursor` (a character column) fits in `width` columns.
+fn scroll_offset(cursor: usize, width: u16) -> usize {
+    cursor.saturating_sub(width.saturating_sub(1) as usize)
+}
 
-            let username_label = match cred.entry_type {
-                EntryType::Password => "Username",
-                EntryType::ApiKey => "Account Name",
+/// Draws a form field's editor in a bordered box, scrolled so the cursor stays in view.
+/// While the field is being edited the terminal cursor is placed in it.
+fn draw_text_field<B: Backend>(
+    f: &mut Frame<B>,
+    area: Rect,
+    app: &App,
+    editor: &TextEditor,
+    title: &str,
+    field: ActiveField,
+    masked: bool,
+) {
+    let active = app.active_field == Some(field);
+    let active_style = Style::default().fg(app.theme.secondary).add_modifier(Modifier::BOLD);
+    // Boxed when there is room; otherwise the short title (before any parenthesis) becomes a label,
+    // above the text when there are two or more lines and in front of it when there is one.
+    let label = title.split(" (").next().unwrap_or(title);
+    let label_style = if active { active_style } else { Style::default().fg(app.theme.border) };
+    let (block, area) = if area.height >= 3 {
+        let block = Block::default().borders(Borders::ALL).title(title);
+        (if active { block.border_style(active_style) } else { block }, area)
+    } else if area.height == 2 {
+        (Block::default().title(Span::styled(label, label_style)), area)
+    } else {
+        draw_labelled_line(f, area, app, label, Spans::default(), active);
+        let width = LABEL_WIDTH.min(area.width);
+        (Block::default(), Rect { x: area.x + width, width: area.width - width, ..area })
+    };
+    let inner = block.inner(area);
+    let (row, column) = editor.cursor_position();
+    let row_offset = row.saturating_sub(inner.height.saturating_sub(1) as usize);
+    let column_offset = scroll_offset(column, inner.width);
+    let lines: Vec<Spans> = editor
+        .lines()
+        .skip(row_offset)
+        .take(inner.height as usize)
+        .map(|line| {
+            let visible = line.chars().skip(column_offset);
+            let text: String = if masked { visible.map(|_| '*').collect() } else { visible.collect() };
+            Spans::from(text)
+        })
+        .collect();
+    f.render_widget(Paragraph::new(lines).block(block), area);
+    if active && app.input_mode == InputMode::Editing {
+        f.set_cursor(inner.x + (column - column_offset) as u16, inner.y + (row - row_offset) as u16);
+    }
+}
+
+/// Draws the custom fields as `key = value` rows, one editor per cell.
+fn draw_custom_fields_editor<B: Backend>(f: &mut Frame<B>, area: Rect, app: &App, editor: &KeyValueEditor, compact: bool) {
+    let active = app.active_field == Some(ActiveField::CustomFields);
+    let active_style = Style::default().fg(app.theme.secondary).add_modifier(Modifier::BOLD);
+    let title = "Custom Fields (Enter: next cell | C-n: add row | C-d: remove row)";
+    let block = if compact {
+        Block::default().title(Span::styled(title, if active { active_style } else { Style::default().fg(app.theme.border) }))
+    } else if active {
+        Block::default().borders(Borders::ALL).title(title).border_style(active_style)
+    } else {
+        Block::default().borders(Borders::ALL).title(title)
+    };
+    let inner = block.inner(area);
+    let key_width = (inner.width / 3).max(8);
+    let value_width = inner.width.saturating_sub(key_width + 3);
+    let row_offset = editor.row.saturating_sub(inner.height.saturating_sub(1) as usize);
+    let separator = Style::default().fg(app.theme.border);
+    let mut cursor = None;
+    let lines: Vec<Spans> = editor
+        .rows
+        .iter()
+        .enumerate()
+        .skip(row_offset)
+        .take(inner.height as usize)
+        .map(|(i, (key, value))| {
+            let cell = |cell: &TextEditor, column: FieldColumn, width: u16, cursor: &mut Option<(u16, u16)>| {
+                let (_, at) = cell.cursor_position();
+                let editing = i == editor.row && column == editor.column;
+                let offset = if editing { scroll_offset(at, width) } else { 0 };
+                if editing {
+                    let x = if column == FieldColumn::Key { 0 } else { key_width + 3 };
+                    *cursor = Some((x + (at - offset) as u16, (i - row_offset) as u16));
+                }
+                let text: String = cell.text().chars().skip(offset).take(width as usize).collect();
+                format!("{:<width$}", text, width = width as usize)
             };
-            let username = Paragraph::new(Spans::from(vec![
-                Span::styled(format!("{}: ", username_label), Style::default().fg(app.theme.border)),
-                Span::styled(&cred.username, Style::default().fg(app.theme.foreground)),
-            ]))
-            .block(Block::default().borders(Borders::ALL));
-            f.render_widget(username, chunks[2]);
+            let placeholder = key.is_empty() && value.is_empty() && i + 1 == editor.rows.len();
+            let key_text = cell(key, FieldColumn::Key, key_width, &mut cursor);
+            let value_text = cell(value, FieldColumn::Value, value_width, &mut cursor);
+            if placeholder && !(active && i == editor.row) {
+                return Spans::from(Span::styled("(empty row: type a name to add a field)", separator));
+            }
+            Spans::from(vec![Span::raw(key_text), Span::styled(" = ", separator), Span::raw(value_text)])
+        })
+        .collect();
+    f.render_widget(Paragraph::new(lines).block(block), area);
+    if let (true, InputMode::Editing, Some((x, y))) = (active, &app.input_mode, cursor) {
+        f.set_cursor(inner.x + x, inner.y + y);
+    }
+}
+
+/// Below this height the view screen packs the short fields into one box, leaving room for notes.
+const VIEW_COMPACT_HEIGHT: u16 = 34;
+
+/// The parts of a credential shown on the view screen and in the main screen's preview pane.
+struct CredentialDetails {
+    title: Vec<Span<'static>>,
+    /// Service, username, secret, tags and, for API keys, status.
+    fields: Vec<Spans<'static>>,
+    custom_fields: Vec<(String, String)>,
+    notes: Vec<(String, String)>,
+}
+
+/// Lays out a credential for display. The secret and the OTP seed are masked unless `reveal` is set.
+fn credential_details(app: &App, cred: &Credential, reveal: bool) -> CredentialDetails {
+    let label_style = Style::default().fg(app.theme.border);
+    let value_style = Style::default().fg(app.theme.foreground);
+
+    let title_text = match cred.entry_type {
+        EntryType::Password => "👁️ View Password",
+        EntryType::ApiKey => "👁️ View API Key",
+    };
+    let mut title_spans = vec![Span::raw(title_text)];
+    if cred.favorite {
+        title_spans.push(Span::styled(" ★", Style::default().fg(app.theme.warning)));
+    }
+    if let Some(last_used) = cred.last_used_at {
+        title_spans.push(Span::styled(
+            format!("  (used {} times, last {})", cred.use_count, last_used.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M")),
+            Style::default().fg(app.theme.border),
+        ));
+    }
 
-            let secret_label = match cred.entry_type {
-                EntryType::Password => "Password",
-                EntryType::ApiKey => "API Key",
+    let mut service_spans = vec![
+        Span::styled("Service: ", label_style),
+        Span::styled(cred.service.clone(), value_style),
+    ];
+    if !cred.folder.is_empty() {
+        service_spans.push(Span::styled("   Folder: ", label_style));
+        service_spans.push(Span::styled(cred.folder.clone(), value_style));
+    }
+    if let Some(shared) = &cred.shared {
+        service_spans.push(Span::styled("   Shared: ", label_style));
+        service_spans.push(Span::styled(format!("{} ({})", shared.name, shared.role.name()), value_style));
+    }
+
+    let username_label = match cred.entry_type {
+        EntryType::Password => "Username",
+        EntryType::ApiKey => "Account Name",
+    };
+    let username_spans = vec![
+        Span::styled(format!("{}: ", username_label), label_style),
+        Span::styled(cred.username.clone(), value_style),
+    ];
+
+    let secret_label = match cred.entry_type {
+        EntryType::Password => "Password",
+        EntryType::ApiKey => "API Key",
+    };
+    let secret_display = if !cred.can_reveal() {
+        "(hidden, shared read-only)".to_string()
+    } else if reveal {
+        String::from_utf8_lossy(&cred.secret).to_string()
+    } else {
+        "•".repeat(cred.secret.len())
+    };
+    let mut secret_spans = vec![
+        Span::styled(format!("{}: ", secret_label), label_style),
+        Span::styled(secret_display, value_style),
+    ];
+    if let Some(value) = cred.custom_fields.get(otp::OTP_FIELD) {
+        secret_spans.push(Span::styled("   OTP: ", label_style));
+        match Totp::parse(value) {
+            Ok(totp) => {
+                let (code, remaining) = totp.now();
+                secret_spans.push(Span::styled(code, value_style));
+                secret_spans.push(Span::styled(format!(" ({}s)", remaining), label_style));
+            }
+            Err(e) => secret_spans.push(Span::styled(e.to_string(), Style::default().fg(app.theme.error))),
+        }
+    }
+
+    let tags_spans = vec![
+        Span::styled("Tags: ", label_style),
+        Span::styled(cred.tags.join(", "), value_style),
+    ];
+
+    let mut fields = vec![
+        Spans::from(service_spans),
+        Spans::from(username_spans),
+        Spans::from(secret_spans),
+        Spans::from(tags_spans),
+    ];
+    if cred.entry_type == EntryType::ApiKey {
+        let active_text = if cred.is_active { "✅ Active" } else { "❌ Inactive" };
+        fields.push(Spans::from(vec![
+            Span::styled("Status: ", label_style),
+            Span::styled(active_text, value_style),
+        ]));
+    }
+
+    // Numbered so `1`-`9` can copy them; the OTP seed is as sensitive as the secret.
+    let custom_fields: Vec<(String, String)> = cred
+        .sorted_custom_field_keys()
+        .iter()
+        .enumerate()
+        .map(|(i, k)| {
+            let value = if *k == otp::OTP_FIELD && !reveal { "••••••".to_string() } else { cred.custom_fields[*k].clone() };
+            (format!("[{}] {}: ", i + 1, k), value)
+        })
+        .collect();
+    let notes: Vec<(String, String)> = cred.notes.split('\n').map(|line| (String::new(), line.to_string())).collect();
+
+
+    CredentialDetails { title: title_spans, fields, custom_fields, notes }
+}
+
+pub fn draw_view_credential_screen<B: Backend>(f: &mut Frame<B>, app: &App) {
+    let cred = match app.selected() {
+        Some(cred) => cred,
+        None => return,
+    };
+    let CredentialDetails { title: title_spans, fields, custom_fields, notes } = credential_details(app, cred, app.show_secret);
+    let value_style = Style::default().fg(app.theme.foreground);
+
+    let size = f.size();
+    let compact = size.height < VIEW_COMPACT_HEIGHT;
+    let custom_fields_height = custom_fields.len().clamp(1, if compact { 3 } else { 6 }) as u16 + 2;
+    let mut constraints = Vec::new();
+    if compact {
+        constraints.push(Constraint::Length(fields.len() as u16 + 2)); // Service, username, secret, tags, status
+        constraints.push(Constraint::Min(3)); // Notes
+        constraints.push(Constraint::Length(custom_fields_height));
+    } else {
+        constraints.extend([Constraint::Length(3); 4]); // Title, service, username, secret
+        constraints.push(Constraint::Min(3)); // Notes
+        constraints.push(Constraint::Length(3)); // Tags
+        constraints.push(Constraint::Length(custom_fields_height));
+        if cred.entry_type == EntryType::ApiKey {
+            constraints.push(Constraint::Length(3)); // Is Active
+        }
+    }
+    constraints.push(Constraint::Length(3)); // Help
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .margin(if compact { 0 } else { 2 })
+        .constraints(constraints.as_slice())
+        .split(size);
+
+    let (notes_chunk, custom_fields_chunk) = if compact {
+        let summary = Paragraph::new(fields)
+            .style(value_style)
+            .block(Block::default().borders(Borders::ALL).title(Spans::from(title_spans)));
+        f.render_widget(summary, chunks[0]);
+        (chunks[1], chunks[2])
+    } else {
+        let title = Paragraph::new(Spans::from(title_spans))
+            .style(Style::default().fg(app.theme.accent).add_modifier(Modifier::BOLD))
+            .alignment(Alignment::Center)
+            .block(Block::default().borders(Borders::ALL).border_type(tui::widgets::BorderType::Rounded));
+        f.render_widget(title, chunks[0]);
+        // Each field gets its own box, in the order service, username, secret, tags, status.
+        let field_chunks = [chunks[1], chunks[2], chunks[3], chunks[5], chunks[7]];
+        for (spans, area) in fields.into_iter().zip(field_chunks) {
+            f.render_widget(Paragraph::new(spans).block(Block::default().borders(Borders::ALL)), area);
+        }
+        (chunks[4], chunks[6])
+    };
+    draw_scrolling_pane(f, notes_chunk, app, "Notes", &notes, &app.notes_scroll);
+    draw_scrolling_pane(f, custom_fields_chunk, app, "Custom Fields", &custom_fields, &app.fields_scroll);
+    let help_chunk_index = chunks.len() - 1;
+
+    // Credentials shared read-only can't be revealed or copied, and shared ones below the can-edit
+    // role can't be edited, so the actions they don't allow are left out.
+    let reveal_actions = [Action::CopySecret, Action::CopyOtp, Action::CopySequence, Action::ToggleSecret];
+    let actions: Vec<Action> = [
+        Action::CopySecret,
+        Action::CopyUsername,
+        Action::CopyNotes,
+        Action::CopyOtp,
+        Action::CopySequence,
+        Action::ToggleSecret,
+        Action::ToggleFavorite,
+        Action::Edit,
+        Action::Delete,
+        Action::Back,
+    ]
+    .into_iter()
+    .filter(|action| cred.can_reveal() || !reveal_actions.contains(action))
+    .filter(|action| cred.can_edit() || *action != Action::Edit)
+    .collect();
+    let hint = app.keymap.hint(Context::View, &actions);
+    let help = Paragraph::new(format!("{} | 1-9: Copy field", hint))
+        .style(Style::default().fg(app.theme.border))
+        .alignment(Alignment::Center)
+        .block(Block::default().borders(Borders::ALL).border_type(tui::widgets::BorderType::Rounded));
+    f.render_widget(help, chunks[help_chunk_index]);
+
+    draw_message_popups(f, app);
+}
+
+pub fn draw_settings_screen<B: Backend>(f: &mut Frame<B>, app: &App) {
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .margin(2)
+        .constraints([Constraint::Length(3), Constraint::Min(1), Constraint::Length(3)].as_ref())
+        .split(f.size());
+
+    let title = Paragraph::new("⚙️ Settings")
+        .style(Style::default().fg(app.theme.accent).add_modifier(Modifier::BOLD))
+        .alignment(Alignment::Center)
+        .block(Block::default().borders(Borders::ALL).border_type(tui::widgets::BorderType::Rounded));
+    f.render_widget(title, chunks[0]);
+
+    let items: Vec<ListItem> = SETTING_KEYS
+        .iter()
+        .enumerate()
+        .map(|(i, (key, description))| {
+            let selected = i == app.settings_cursor;
+            let value = match (&app.settings_input, selected) {
+                (Some(input), true) => format!("{}▏", input),
+                _ => app.settings_draft.get(key),
             };
-            let secret_display = if app.show_secret {
-                String::from_utf8_lossy(&cred.secret).to_string()
+            let changed = app.settings_draft.get(key) != app.settings.get(key);
+            let style = if selected {
+                Style::default().fg(app.theme.highlight_fg).bg(app.theme.highlight_bg)
             } else {
-                "•".repeat(cred.secret.len())
+                Style::default().fg(app.theme.foreground)
             };
-            let secret = Paragraph::new(Spans::from(vec![
-                Span::styled(format!("{}: ", secret_label), Style::default().fg(app.theme.border)),
-                Span::styled(secret_display, Style::default().fg(app.theme.foreground)),
+            ListItem::new(Spans::from(vec![
+                Span::styled(format!("{:<26}", key), style.add_modifier(Modifier::BOLD)),
+                Span::styled(format!("{:<32}", value), style),
+                Span::styled(if changed { "* " } else { "  " }, Style::default().fg(app.theme.warning)),
+                Span::styled(*description, Style::default().fg(app.theme.border)),
             ]))
-            .block(Block::default().borders(Borders::ALL));
-            f.render_widget(secret, chunks[3]);
+        })
+        .collect();
+    let list = List::new(items).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .title(format!("{} (* = unsaved)", app.config_path.display())),
+    );
+    f.render_widget(list, chunks[1]);
 
-            let notes = Paragraph::new(Spans::from(vec![
-                Span::styled("Notes: ", Style::default().fg(app.theme.border)),
-                Span::styled(&cred.notes, Style::default().fg(app.theme.foreground)),
-            ]))
-            .wrap(Wrap { trim: true })
-            .block(Block::default().borders(Borders::ALL));
-            f.render_widget(notes, chunks[4]);
+    let help_text = if app.settings_input.is_some() {
+        "Enter: Apply | Esc: Cancel".to_string()
+    } else {
+        app.keymap.hint(
+            Context::Settings,
+            &[Action::MoveDown, Action::MoveUp, Action::Open, Action::PreviousValue, Action::NextValue, Action::Save, Action::Back],
+        )
+    };
+    let help = Paragraph::new(help_text)
+        .style(Style::default().fg(app.theme.border))
+        .alignment(Alignment::Center)
+        .block(Block::default().borders(Borders::ALL).border_type(tui::widgets::BorderType::Rounded));
+    f.render_widget(help, chunks[2]);
 
-            let tags = Paragraph::new(Spans::from(vec![
-                Span::styled("Tags: ", Style::default().fg(app.theme.border)),
-                Span::styled(cred.tags.join(", "), Style::default().fg(app.theme.foreground)),
-            ]))
-            .wrap(Wrap { trim: true })
-            .block(Block::default().borders(Borders::ALL));
-            f.render_widget(tags, chunks[5]);
-
-            let custom_fields_display = cred.custom_fields.iter().map(|(k, v)| format!("{}: {}", k, v)).collect::<Vec<String>>().join("\n");
-            let custom_fields = Paragraph::new(Spans::from(vec![
-                Span::styled("Custom Fields: ", Style::default().fg(app.theme.border)),
-                Span::styled(custom_fields_display, Style::default().fg(app.theme.foreground)),
-            ]))
-            .wrap(Wrap { trim: true })
-            .block(Block::default().borders(Borders::ALL));
-            f.render_widget(custom_fields, chunks[6]);
-
-            let help_chunk_index = match cred.entry_type {
-                EntryType::ApiKey => {
-                    let active_text = if cred.is_active { "✅ Active" } else { "❌ Inactive" };
-                    let is_active = Paragraph::new(Spans::from(vec![
-                        Span::styled("Status: ", Style::default().fg(app.theme.border)),
-                        Span::styled(active_text, Style::default().fg(app.theme.foreground)),
-                    ]))
-                    .block(Block::default().borders(Borders::ALL));
-                    f.render_widget(is_active, chunks[7]);
-                    8
-                },
-                EntryType::Password => 7,
-            };
-            
-            let help = Paragraph::new("c: Copy | s: Show/Hide Secret | e: Edit | d: Delete | q/Esc: Back")
-                .style(Style::default().fg(app.theme.border))
-                .alignment(Alignment::Center)
-                .block(Block::default().borders(Borders::ALL).border_type(tui::widgets::BorderType::Rounded));
-            f.render_widget(help, chunks[help_chunk_index]);
-
-            if let Some(info) = &app.info_message {
-                let info_area = centered_rect(50, 20, f.size());
-                let info_block = Block::default().title("Info").borders(Borders::ALL).border_style(Style::default().fg(app.theme.success));
-                let info_text = Paragraph::new(info.as_str())
-                    .style(Style::default().fg(app.theme.success))
-                    .block(info_block)
-                    .alignment(Alignment::Center)
-                    .wrap(Wrap { trim: true });
-                f.render_widget(Clear, info_area); //this clears the background
-                f.render_widget(info_text, info_area);
+    draw_message_popups(f, app);
+}
+
+/// Shows the outcome of the last action, if any, in a popup over the screen.
+fn draw_message_popups<B: Backend>(f: &mut Frame<B>, app: &App) {
+    if let Some(info) = &app.info_message {
+        let info_area = centered_rect(50, 20, f.size());
+        let info_block = Block::default().title("Info").borders(Borders::ALL).border_style(Style::default().fg(app.theme.success));
+        let info_text = Paragraph::new(info.as_str())
+            .style(Style::default().fg(app.theme.success))
+            .block(info_block)
+            .alignment(Alignment::Center)
+            .wrap(Wrap { trim: true });
+        clear_area(f, info_area, app); //this clears the background
+        f.render_widget(info_text, info_area);
+    }
+
+    if let Some(error) = &app.error_message {
+        let error_area = centered_rect(50, 20, f.size());
+        let error_block = Block::default().title("Error").borders(Borders::ALL).border_style(Style::default().fg(app.theme.error));
+        let error_text = Paragraph::new(error.as_str())
+            .style(Style::default().fg(app.theme.error))
+            .block(error_block)
+            .alignment(Alignment::Center)
+            .wrap(Wrap { trim: true });
+        clear_area(f, error_area, app); //this clears the background
+        f.render_widget(error_text, error_area);
+    }
+}
+
+/// Lists every binding for the current screen plus the global ones, drawn over the screen by `?`.
+pub fn draw_help_overlay<B: Backend>(f: &mut Frame<B>, app: &App) {
+    let area = centered_rect(70, 80, f.size());
+    let context = app.key_context();
+    let key_style = Style::default().fg(app.theme.primary).add_modifier(Modifier::BOLD);
+    let mut lines = Vec::new();
+    for scope in [context, Context::Global] {
+        lines.push(Spans::from(Span::styled(
+            format!("{} screen", scope.name()),
+            Style::default().fg(app.theme.accent).add_modifier(Modifier::BOLD),
+        )));
+        for &action in scope.actions() {
+            let keys = app.keymap.keys_for(scope, action);
+            if keys.is_empty() {
+                continue;
+            }
+            lines.push(Spans::from(vec![
+                Span::styled(format!("  {:<16}", keys.join(", ")), key_style),
+                Span::raw(action.description()),
+            ]));
+        }
+        match scope {
+            Context::Main => lines.push(Spans::from(vec![
+                Span::styled(format!("  {:<16}", "1-9"), key_style),
+                Span::raw("Open a favorite or recent entry"),
+            ])),
+            Context::View => {
+                lines.push(Spans::from(vec![
+                    Span::styled(format!("  {:<16}", "1-9"), key_style),
+                    Span::raw("Copy a custom field"),
+                ]));
+                lines.push(Spans::from(vec![
+                    Span::styled(format!("  {:<16}", "Enter"), key_style),
+                    Span::raw("Copy the secret after the username"),
+                ]));
+            }
+            _ => {}
+        }
+        lines.push(Spans::from(""));
+    }
+    lines.push(Spans::from(Span::styled(
+        "Press any key to close",
+        Style::default().fg(app.theme.border),
+    )));
+
+    let help = Paragraph::new(lines)
+        .style(Style::default().fg(app.theme.foreground))
+        .block(
+            Block::default()
+                .title(format!("Keys ({} keymap)", app.keymap.name))
+                .borders(Borders::ALL)
+                .border_style(Style::default().fg(app.theme.accent)),
+        )
+        .wrap(Wrap { trim: false });
+    clear_area(f, area, app);
+    f.render_widget(help, area);
+}
+
+/// A modal yes/no dialog, or one that asks for a phrase to be retyped before a destructive action.
+/// The bulk action menu, or the tag or folder prompt of the operation picked from it.
+pub fn draw_bulk_menu<B: Backend>(f: &mut Frame<B>, app: &App, menu: &BulkMenu) {
+    let count = app.bulk_targets().len();
+    let title = format!("Bulk actions: {} credential{}", count, if count == 1 { "" } else { "s" });
+    let hint_style = Style::default().fg(app.theme.border);
+    let key_style = Style::default().fg(app.theme.primary).add_modifier(Modifier::BOLD);
+    let mut lines = Vec::new();
+    match &menu.prompt {
+        Some((operation, editor)) => {
+            lines.push(Spans::from(format!("{}:", operation.prompt().unwrap_or_default())));
+            lines.push(Spans::from(vec![Span::styled("> ", key_style), Span::raw(editor.text())]));
+            lines.push(Spans::default());
+            lines.push(Spans::from(Span::styled("Enter: Apply | Esc: Back", hint_style)));
+        }
+        None => {
+            for (action, _, label) in BulkOperation::menu(app.show_trash) {
+                let key = app.keymap.keys_for(Context::Bulk, *action).into_iter().next().unwrap_or_default();
+                lines.push(Spans::from(vec![Span::styled(format!(" {}  ", key), key_style), Span::raw(*label)]));
             }
+            lines.push(Spans::default());
+            lines.push(Spans::from(Span::styled(format!(" {}", app.keymap.hint(Context::Bulk, &[Action::Back])), hint_style)));
+        }
+    }
+    let height = lines.len() as u16 + 2;
+    let outer = centered_rect(50, 100, f.size());
+    let area = Rect { y: outer.y + outer.height.saturating_sub(height) / 2, height: height.min(outer.height), ..outer };
+    let block = Block::default()
+        .title(title)
+        .borders(Borders::ALL)
+        .border_style(Style::default().fg(app.theme.accent));
+    let inner = block.inner(area);
+    clear_area(f, area, app);
+    f.render_widget(Paragraph::new(lines).block(block), area);
+    if let Some((_, editor)) = &menu.prompt {
+        let (_, column) = editor.cursor_position();
+        let x = (inner.x + 2 + column as u16).min(inner.right().saturating_sub(1));
+        f.set_cursor(x, inner.y + 1);
+    }
+}
+
+/// Shows both versions of the conflict under the cursor side by side, highlighting what differs.
+pub fn draw_conflict_review<B: Backend>(f: &mut Frame<B>, app: &App, review: &ConflictReview) {
+    let (current, other) = match review.conflicts.get(review.cursor) {
+        Some(conflict) => conflict,
+        None => return,
+    };
+    let title = format!("Sync conflict {} of {}: {}", review.cursor + 1, review.conflicts.len(), other.service);
+    let area = centered_rect(80, 80, f.size());
+    let block = Block::default()
+        .title(title)
+        .borders(Borders::ALL)
+        .border_style(Style::default().fg(app.theme.warning));
+    let inner = block.inner(area);
+    clear_area(f, area, app);
+    f.render_widget(block, area);
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Min(1), Constraint::Length(2)])
+        .split(inner);
+    let columns = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([Constraint::Percentage(50), Constraint::Percentage(50)])
+        .split(chunks[0]);
+
+    let fields = |c: &Credential| -> Vec<(&'static str, String)> {
+        vec![
+            ("Service", c.service.clone()),
+            ("Username", c.username.clone()),
+            ("Secret", "•".repeat(c.secret.len().min(12))),
+            ("Folder", c.folder.clone()),
+            ("Tags", c.tags.join(", ")),
+            ("Notes", c.notes.lines().next().unwrap_or_default().to_string()),
+            ("Fields", c.sorted_custom_field_keys().join(", ")),
+            ("Status", if c.is_trashed() { "In the trash".to_string() } else { "In the vault".to_string() }),
+            ("Updated", c.updated_at.format("%Y-%m-%d %H:%M").to_string()),
+        ]
+    };
+    let other_fields = fields(other);
+    let current_fields = current.as_ref().map(fields);
+    // The secret is compared by value even though only its length is shown.
+    let differs = |i: usize| match (&current_fields, current) {
+        (Some(current_fields), Some(current)) => {
+            current_fields[i].1 != other_fields[i].1 || (i == 2 && current.secret != other.secret)
         }
+        _ => true,
+    };
+    let label_style = Style::default().fg(app.theme.secondary);
+    let changed_style = Style::default().fg(app.theme.accent).add_modifier(Modifier::BOLD);
+    let side = |heading: &str, values: Option<&Vec<(&'static str, String)>>| -> Vec<Spans<'static>> {
+        let mut lines = vec![Spans::from(Span::styled(heading.to_string(), Style::default().fg(app.theme.primary).add_modifier(Modifier::BOLD))), Spans::default()];
+        match values {
+            Some(values) => lines.extend(values.iter().enumerate().map(|(i, (label, value))| {
+                let (style, value) = match differs(i) {
+                    // Secrets are never shown here, so say when they differ.
+                    true if *label == "Secret" => (changed_style, format!("{} (differs)", value)),
+                    true => (changed_style, value.clone()),
+                    false => (Style::default().fg(app.theme.foreground), value.clone()),
+                };
+                Spans::from(vec![Span::styled(format!("{:<10}", label), label_style), Span::styled(value, style)])
+            })),
+            None => lines.push(Spans::from("Deleted for good on this device.")),
+        }
+        lines
+    };
+    f.render_widget(
+        Paragraph::new(side("Current", current_fields.as_ref())).wrap(Wrap { trim: false }),
+        columns[0].inner(&Margin { vertical: 0, horizontal: 1 }),
+    );
+    f.render_widget(
+        Paragraph::new(side("Other version", Some(&other_fields))).wrap(Wrap { trim: false }),
+        columns[1].inner(&Margin { vertical: 0, horizontal: 1 }),
+    );
+    let hint = app.keymap.hint(
+        Context::Conflicts,
+        &[Action::KeepCurrent, Action::UseOther, Action::MoveDown, Action::MoveUp, Action::Back],
+    );
+    f.render_widget(
+        Paragraph::new(Span::styled(hint, Style::default().fg(app.theme.border))).alignment(Alignment::Center),
+        chunks[1],
+    );
+}
+
+/// Lists the audit log, newest entry first, under a line saying whether its chain verified.
+pub fn draw_audit_log<B: Backend>(f: &mut Frame<B>, app: &App, view: &AuditView) {
+    let area = centered_rect(90, 80, f.size());
+    let (status, status_style) = match &view.log.problem {
+        None => (
+            format!("Chain verified: {} entries, nothing changed or removed.", view.log.entries.len()),
+            Style::default().fg(app.theme.success),
+        ),
+        Some(problem) => (
+            format!("Verification failed: {}. Showing the entries up to there.", problem),
+            Style::default().fg(app.theme.error).add_modifier(Modifier::BOLD),
+        ),
+    };
+    let block = Block::default()
+        .title("Audit log")
+        .borders(Borders::ALL)
+        .border_style(Style::default().fg(if view.log.problem.is_some() { app.theme.error } else { app.theme.accent }));
+    let inner = block.inner(area);
+    clear_area(f, area, app);
+    f.render_widget(block, area);
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Length(2), Constraint::Min(1), Constraint::Length(1)])
+        .split(inner.inner(&Margin { vertical: 0, horizontal: 1 }));
+    f.render_widget(Paragraph::new(Span::styled(status, status_style)).wrap(Wrap { trim: true }), chunks[0]);
+
+    let label_style = Style::default().fg(app.theme.secondary);
+    let lines: Vec<Spans> = view
+        .log
+        .entries
+        .iter()
+        .skip(view.scroll)
+        .take(chunks[1].height as usize)
+        .map(|(entry, _)| {
+            Spans::from(vec![
+                Span::styled(format!("{:>5} ", entry.seq + 1), label_style),
+                Span::styled(entry.at.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M:%S ").to_string(), label_style),
+                Span::styled(format!("{:<8}", entry.action.name()), Style::default().fg(app.theme.primary)),
+                Span::styled(format!("{:<24}", entry.service), Style::default().fg(app.theme.foreground)),
+                Span::styled(format!("{} ", entry.detail), Style::default().fg(app.theme.foreground)),
+                Span::styled(format!("[{} via {}]", entry.user, entry.source), Style::default().fg(app.theme.border)),
+            ])
+        })
+        .collect();
+    if lines.is_empty() {
+        f.render_widget(Paragraph::new("The audit log is empty."), chunks[1]);
+    } else {
+        f.render_widget(Paragraph::new(lines), chunks[1]);
     }
+    let hint = format!(
+        "{} | `password-manager audit-log export` writes JSON",
+        app.keymap.hint(Context::Audit, &[Action::ScrollDown, Action::ScrollUp, Action::PageDown, Action::PageUp, Action::Back])
+    );
+    f.render_widget(
+        Paragraph::new(Span::styled(hint, Style::default().fg(app.theme.border))).alignment(Alignment::Center),
+        chunks[2],
+    );
 }
 
+pub fn draw_confirm_dialog<B: Backend>(f: &mut Frame<B>, app: &App, confirmation: &Confirmation) {
+    let area = centered_rect(60, 40, f.size());
+    let mut lines = vec![
+        Spans::from(Span::styled(confirmation.message.as_str(), Style::default().fg(app.theme.foreground))),
+        Spans::from(""),
+    ];
+    match confirmation.phrase {
+        Some(phrase) => {
+            let input_style = if app.confirmation_ready() {
+                Style::default().fg(app.theme.success)
+            } else {
+                Style::default().fg(app.theme.error)
+            };
+            lines.push(Spans::from(vec![
+                Span::raw("Type "),
+                Span::styled(phrase, Style::default().fg(app.theme.warning).add_modifier(Modifier::BOLD)),
+                Span::raw(" to confirm:"),
+            ]));
+            lines.push(Spans::from(Span::styled(format!("> {}▏", confirmation.input), input_style)));
+            lines.push(Spans::from(""));
+            lines.push(Spans::from(Span::styled(
+                "Enter: Confirm | Esc: Cancel",
+                Style::default().fg(app.theme.border),
+            )));
+        }
+        None => lines.push(Spans::from(Span::styled(
+            "y/Enter: Confirm | n/Esc: Cancel",
+            Style::default().fg(app.theme.border),
+        ))),
+    }
+
+    let dialog = Paragraph::new(lines)
+        .alignment(Alignment::Center)
+        .wrap(Wrap { trim: true })
+        .block(
+            Block::default()
+                .title(confirmation.title.as_str())
+                .borders(Borders::ALL)
+                .border_type(tui::widgets::BorderType::Double)
+                .border_style(Style::default().fg(app.theme.warning)),
+        );
+    clear_area(f, area, app);
+    f.render_widget(dialog, area);
+}
@@ -0,0 +1,484 @@
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use std::collections::HashMap;
+
+/// An editable text buffer with a cursor, used for the form fields.
+///
+/// Single-line editors turn pasted line breaks into spaces; multiline ones keep them and use
+/// Enter, Up and Down for lines.
+#[derive(Debug, Clone, Default)]
+pub struct TextEditor {
+    text: String,
+    /// Byte offset into `text`, always on a char boundary.
+    cursor: usize,
+    multiline: bool,
+}
+
+impl TextEditor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn multiline() -> Self {
+        Self { multiline: true, ..Self::default() }
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.text.is_empty()
+    }
+
+    /// Replaces the contents and puts the cursor at the end.
+    pub fn set_text(&mut self, text: &str) {
+        self.text.clear();
+        self.cursor = 0;
+        self.insert_str(text);
+    }
+
+    pub fn clear(&mut self) {
+        self.text.clear();
+        self.cursor = 0;
+    }
+
+    pub fn insert_char(&mut self, c: char) {
+        let c = match c {
+            '\n' if self.multiline => '\n',
+            '\n' | '\t' => ' ',
+            c if c.is_control() => return,
+            c => c,
+        };
+        self.text.insert(self.cursor, c);
+        self.cursor += c.len_utf8();
+    }
+
+    /// Inserts pasted text at the cursor, normalizing `\r\n` line endings.
+    pub fn insert_str(&mut self, text: &str) {
+        for c in text.replace("\r\n", "\n").replace('\r', "\n").chars() {
+            self.insert_char(c);
+        }
+    }
+
+    /// Handles an editing key. Returns `false` for keys the editor has no use for, so the caller
+    /// can treat them as commands (e.g. Tab, or Up on the first line).
+    pub fn handle_key(&mut self, key: KeyEvent) -> bool {
+        let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+        let alt = key.modifiers.contains(KeyModifiers::ALT);
+        match key.code {
+            KeyCode::Char('a') if ctrl => self.move_line_start(),
+            KeyCode::Char('e') if ctrl => self.move_line_end(),
+            KeyCode::Char('w') if ctrl => self.delete_word_before(),
+            KeyCode::Char('u') if ctrl => self.delete_to_line_start(),
+            KeyCode::Char('k') if ctrl => self.delete_to_line_end(),
+            KeyCode::Char('b') if alt => self.move_word_left(),
+            KeyCode::Char('f') if alt => self.move_word_right(),
+            KeyCode::Char('d') if alt => self.delete_word_after(),
+            KeyCode::Char(c) if !ctrl && !alt => self.insert_char(c),
+            KeyCode::Enter if self.multiline => self.insert_char('\n'),
+            KeyCode::Backspace if ctrl || alt => self.delete_word_before(),
+            KeyCode::Backspace => self.backspace(),
+            KeyCode::Delete if ctrl || alt => self.delete_word_after(),
+            KeyCode::Delete => self.delete(),
+            KeyCode::Left if ctrl || alt => self.move_word_left(),
+            KeyCode::Right if ctrl || alt => self.move_word_right(),
+            KeyCode::Left => self.cursor = self.prev_boundary(self.cursor),
+            KeyCode::Right => self.cursor = self.next_boundary(self.cursor),
+            KeyCode::Home => self.move_line_start(),
+            KeyCode::End => self.move_line_end(),
+            KeyCode::Up if self.multiline => return self.move_vertically(-1),
+            KeyCode::Down if self.multiline => return self.move_vertically(1),
+            _ => return false,
+        }
+        true
+    }
+
+    /// The cursor as (line, column), counted in characters.
+    pub fn cursor_position(&self) -> (usize, usize) {
+        let before = &self.text[..self.cursor];
+        let line = before.matches('\n').count();
+        let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+        (line, before[line_start..].chars().count())
+    }
+
+    pub fn lines(&self) -> impl Iterator<Item = &str> {
+        self.text.split('\n')
+    }
+
+    pub fn at_start(&self) -> bool {
+        self.cursor == 0
+    }
+
+    pub fn at_end(&self) -> bool {
+        self.cursor == self.text.len()
+    }
+
+    pub fn move_to_start(&mut self) {
+        self.cursor = 0;
+    }
+
+    pub fn move_to_end(&mut self) {
+        self.cursor = self.text.len();
+    }
+
+    fn prev_boundary(&self, from: usize) -> usize {
+        self.text[..from].char_indices().next_back().map_or(0, |(i, _)| i)
+    }
+
+    fn next_boundary(&self, from: usize) -> usize {
+        self.text[from..].chars().next().map_or(from, |c| from + c.len_utf8())
+    }
+
+    fn line_start(&self) -> usize {
+        self.text[..self.cursor].rfind('\n').map_or(0, |i| i + 1)
+    }
+
+    fn line_end(&self) -> usize {
+        self.text[self.cursor..].find('\n').map_or(self.text.len(), |i| self.cursor + i)
+    }
+
+    fn move_line_start(&mut self) {
+        self.cursor = self.line_start();
+    }
+
+    fn move_line_end(&mut self) {
+        self.cursor = self.line_end();
+    }
+
+    /// Start of the word before the cursor, skipping any whitespace directly before it.
+    fn word_start_before(&self) -> usize {
+        let mut chars = self.text[..self.cursor].char_indices().rev().peekable();
+        while chars.next_if(|(_, c)| c.is_whitespace()).is_some() {}
+        let mut start = chars.peek().map_or(0, |(i, _)| *i);
+        for (i, c) in chars {
+            if c.is_whitespace() {
+                break;
+            }
+            start = i;
+        }
+        start
+    }
+
+    /// End of the word after the cursor, skipping any whitespace directly after it.
+    fn word_end_after(&self) -> usize {
+        let rest = &self.text[self.cursor..];
+        let mut chars = rest.char_indices().peekable();
+        while chars.next_if(|(_, c)| c.is_whitespace()).is_some() {}
+        for (i, c) in chars {
+            if c.is_whitespace() {
+                return self.cursor + i;
+            }
+        }
+        self.text.len()
+    }
+
+    fn move_word_left(&mut self) {
+        self.cursor = self.word_start_before();
+    }
+
+    fn move_word_right(&mut self) {
+        self.cursor = self.word_end_after();
+    }
+
+    fn move_vertically(&mut self, step: isize) -> bool {
+        let (line, column) = self.cursor_position();
+        let target = line as isize + step;
+        if target < 0 {
+            return false;
+        }
+        let mut offset = 0;
+        for (i, text) in self.text.split('\n').enumerate() {
+            if i == target as usize {
+                let within = text.char_indices().nth(column).map_or(text.len(), |(j, _)| j);
+                self.cursor = offset + within;
+                return true;
+            }
+            offset += text.len() + 1;
+        }
+        false
+    }
+
+    fn backspace(&mut self) {
+        if self.cursor > 0 {
+            let start = self.prev_boundary(self.cursor);
+            self.text.replace_range(start..self.cursor, "");
+            self.cursor = start;
+        }
+    }
+
+    fn delete(&mut self) {
+        let end = self.next_boundary(self.cursor);
+        self.text.replace_range(self.cursor..end, "");
+    }
+
+    fn delete_word_before(&mut self) {
+        let start = self.word_start_before();
+        self.text.replace_range(start..self.cursor, "");
+        self.cursor = start;
+    }
+
+    fn delete_word_after(&mut self) {
+        let end = self.word_end_after();
+        self.text.replace_range(self.cursor..end, "");
+    }
+
+    fn delete_to_line_start(&mut self) {
+        let start = self.line_start();
+        self.text.replace_range(start..self.cursor, "");
+        self.cursor = start;
+    }
+
+    fn delete_to_line_end(&mut self) {
+        let end = self.line_end();
+        self.text.replace_range(self.cursor..end, "");
+    }
+}
+
+/// Which half of a custom field row the cursor is in.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum FieldColumn {
+    Key,
+    Value,
+}
+
+/// An editable list of key/value rows for a credential's custom fields.
+///
+/// There is always at least one row; rows left entirely empty are dropped when saving.
+#[derive(Debug, Clone)]
+pub struct KeyValueEditor {
+    pub rows: Vec<(TextEditor, TextEditor)>,
+    pub row: usize,
+    pub column: FieldColumn,
+}
+
+impl Default for KeyValueEditor {
+    fn default() -> Self {
+        Self { rows: vec![(TextEditor::new(), TextEditor::new())], row: 0, column: FieldColumn::Key }
+    }
+}
+
+impl KeyValueEditor {
+    /// Loads the fields sorted by key, with an empty row at the end for adding one.
+    pub fn set_fields(&mut self, fields: &HashMap<String, String>) {
+        let mut keys: Vec<&String> = fields.keys().collect();
+        keys.sort();
+        self.rows = keys
+            .into_iter()
+            .map(|key| {
+                let (mut k, mut v) = (TextEditor::new(), TextEditor::new());
+                k.set_text(key);
+                v.set_text(&fields[key]);
+                (k, v)
+            })
+            .collect();
+        self.rows.push((TextEditor::new(), TextEditor::new()));
+        self.row = 0;
+        self.column = FieldColumn::Key;
+    }
+
+    pub fn clear(&mut self) {
+        *self = Self::default();
+    }
+
+    /// The fields as entered. Keys are trimmed; a value without a key or a repeated key is an error.
+    pub fn fields(&self) -> Result<HashMap<String, String>, String> {
+        let mut fields = HashMap::new();
+        for (key, value) in &self.rows {
+            let name = key.text().trim();
+            if name.is_empty() {
+                if value.text().trim().is_empty() {
+                    continue;
+                }
+                return Err(format!("Custom field value '{}' has no name", value.text()));
+            }
+            if fields.insert(name.to_string(), value.text().to_string()).is_some() {
+                return Err(format!("Custom field '{}' appears more than once", name));
+            }
+        }
+        Ok(fields)
+    }
+
+    pub fn current_mut(&mut self) -> &mut TextEditor {
+        let (key, value) = &mut self.rows[self.row];
+        match self.column {
+            FieldColumn::Key => key,
+            FieldColumn::Value => value,
+        }
+    }
+
+    /// Handles an editing key, returning `false` for keys it has no use for (e.g. Down on the last row).
+    ///
+    /// Enter moves from a key to its value and from a value to the next row, adding one at the end.
+    /// `Ctrl+N` inserts a row below and `Ctrl+D` removes the current one.
+    pub fn handle_key(&mut self, key: KeyEvent) -> bool {
+        let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+        match key.code {
+            KeyCode::Enter => match self.column {
+                FieldColumn::Key => self.column = FieldColumn::Value,
+                FieldColumn::Value => {
+                    if self.row + 1 == self.rows.len() {
+                        self.rows.push((TextEditor::new(), TextEditor::new()));
+                    }
+                    self.row += 1;
+                    self.column = FieldColumn::Key;
+                }
+            },
+            KeyCode::Char('n') if ctrl => {
+                self.rows.insert(self.row + 1, (TextEditor::new(), TextEditor::new()));
+                self.row += 1;
+                self.column = FieldColumn::Key;
+            }
+            KeyCode::Char('d') if ctrl => {
+                self.rows.remove(self.row);
+                if self.rows.is_empty() {
+                    self.rows.push((TextEditor::new(), TextEditor::new()));
+                }
+                self.row = self.row.min(self.rows.len() - 1);
+            }
+            KeyCode::Up if self.row > 0 => self.row -= 1,
+            KeyCode::Down if self.row + 1 < self.rows.len() => self.row += 1,
+            KeyCode::Up | KeyCode::Down => return false,
+            // Arrowing past the edge of a key or value crosses into the other column.
+            KeyCode::Right if self.column == FieldColumn::Key && self.current_mut().at_end() => {
+                self.column = FieldColumn::Value;
+                self.current_mut().move_to_start();
+            }
+            KeyCode::Left if self.column == FieldColumn::Value && self.current_mut().at_start() => {
+                self.column = FieldColumn::Key;
+                self.current_mut().move_to_end();
+            }
+            _ => return self.current_mut().handle_key(key),
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn press(editor: &mut TextEditor, code: KeyCode, modifiers: KeyModifiers) -> bool {
+        editor.handle_key(KeyEvent::new(code, modifiers))
+    }
+
+    fn editor(text: &str) -> TextEditor {
+        let mut editor = TextEditor::new();
+        editor.set_text(text);
+        editor
+    }
+
+    #[test]
+    fn moves_and_edits_across_multibyte_characters() {
+        let mut editor = editor("naïve 🔑");
+        assert_eq!(editor.cursor_position(), (0, 7));
+        press(&mut editor, KeyCode::Backspace, KeyModifiers::NONE);
+        assert_eq!(editor.text(), "naïve ");
+
+        press(&mut editor, KeyCode::Home, KeyModifiers::NONE);
+        for _ in 0..3 {
+            press(&mut editor, KeyCode::Right, KeyModifiers::NONE);
+        }
+        assert_eq!(editor.cursor_position(), (0, 3));
+        press(&mut editor, KeyCode::Left, KeyModifiers::NONE);
+        press(&mut editor, KeyCode::Delete, KeyModifiers::NONE);
+        assert_eq!(editor.text(), "nave ");
+        press(&mut editor, KeyCode::Char('ï'), KeyModifiers::NONE);
+        assert_eq!(editor.text(), "naïve ");
+        assert_eq!(editor.cursor_position(), (0, 3));
+
+        // Nothing to delete or move past at either end.
+        press(&mut editor, KeyCode::End, KeyModifiers::NONE);
+        press(&mut editor, KeyCode::Delete, KeyModifiers::NONE);
+        press(&mut editor, KeyCode::Right, KeyModifiers::NONE);
+        assert!(editor.at_end());
+        editor.move_to_start();
+        press(&mut editor, KeyCode::Backspace, KeyModifiers::NONE);
+        press(&mut editor, KeyCode::Left, KeyModifiers::NONE);
+        assert_eq!(editor.text(), "naïve ");
+        assert!(editor.at_start());
+    }
+
+    #[test]
+    fn word_motions_skip_whitespace_and_stop_at_words() {
+        let mut editor = editor("über  café ok");
+        press(&mut editor, KeyCode::Left, KeyModifiers::CONTROL);
+        assert_eq!(editor.cursor_position(), (0, 11));
+        press(&mut editor, KeyCode::Char('b'), KeyModifiers::ALT);
+        assert_eq!(editor.cursor_position(), (0, 6));
+        press(&mut editor, KeyCode::Left, KeyModifiers::CONTROL);
+        assert!(editor.at_start());
+        press(&mut editor, KeyCode::Right, KeyModifiers::CONTROL);
+        assert_eq!(editor.cursor_position(), (0, 4));
+        press(&mut editor, KeyCode::Char('f'), KeyModifiers::ALT);
+        assert_eq!(editor.cursor_position(), (0, 10));
+
+        press(&mut editor, KeyCode::Char('w'), KeyModifiers::CONTROL);
+        assert_eq!(editor.text(), "über   ok");
+        press(&mut editor, KeyCode::Char('d'), KeyModifiers::ALT);
+        assert_eq!(editor.text(), "über  ");
+        press(&mut editor, KeyCode::Char('u'), KeyModifiers::CONTROL);
+        assert_eq!(editor.text(), "");
+    }
+
+    #[test]
+    fn single_line_editors_flatten_pasted_lines() {
+        let mut editor = TextEditor::new();
+        editor.insert_str("user\r\nname\tx\u{7}");
+        assert_eq!(editor.text(), "user name x");
+        assert!(!press(&mut editor, KeyCode::Enter, KeyModifiers::NONE));
+        assert!(!press(&mut editor, KeyCode::Up, KeyModifiers::NONE));
+    }
+
+    #[test]
+    fn multiline_editors_move_between_lines() {
+        let mut editor = TextEditor::multiline();
+        editor.insert_str("first line\r\nño\nthird");
+        assert_eq!(editor.lines().collect::<Vec<_>>(), ["first line", "ño", "third"]);
+        assert_eq!(editor.cursor_position(), (2, 5));
+
+        // The column is kept where the line is long enough, and clamped where it isn't.
+        assert!(press(&mut editor, KeyCode::Up, KeyModifiers::NONE));
+        assert_eq!(editor.cursor_position(), (1, 2));
+        assert!(press(&mut editor, KeyCode::Up, KeyModifiers::NONE));
+        assert_eq!(editor.cursor_position(), (0, 2));
+        assert!(!press(&mut editor, KeyCode::Up, KeyModifiers::NONE));
+
+        press(&mut editor, KeyCode::Char('k'), KeyModifiers::CONTROL);
+        assert_eq!(editor.text(), "fi\nño\nthird");
+        press(&mut editor, KeyCode::Enter, KeyModifiers::NONE);
+        assert_eq!(editor.text(), "fi\n\nño\nthird");
+        press(&mut editor, KeyCode::Down, KeyModifiers::NONE);
+        press(&mut editor, KeyCode::Char('e'), KeyModifiers::CONTROL);
+        assert_eq!(editor.cursor_position(), (2, 2));
+        press(&mut editor, KeyCode::Down, KeyModifiers::NONE);
+        assert!(!press(&mut editor, KeyCode::Down, KeyModifiers::NONE));
+    }
+
+    #[test]
+    fn custom_fields_cross_columns_and_rows() {
+        let mut fields = KeyValueEditor::default();
+        fields.set_fields(&HashMap::from([("region".to_string(), "eu".to_string())]));
+        assert_eq!(fields.rows.len(), 2);
+
+        let key = |code| KeyEvent::new(code, KeyModifiers::NONE);
+        fields.handle_key(key(KeyCode::End));
+        fields.handle_key(key(KeyCode::Right));
+        assert_eq!(fields.column, FieldColumn::Value);
+        fields.handle_key(key(KeyCode::Left));
+        assert_eq!(fields.column, FieldColumn::Key);
+
+        fields.handle_key(key(KeyCode::Down));
+        fields.current_mut().insert_str("zone");
+        fields.handle_key(key(KeyCode::Enter));
+        fields.current_mut().insert_str("b");
+        assert_eq!(fields.fields().unwrap()["zone"], "b");
+
+        fields.current_mut().clear();
+        fields.handle_key(key(KeyCode::Left));
+        fields.current_mut().set_text("region");
+        assert!(fields.fields().unwrap_err().contains("more than once"));
+
+        fields.handle_key(KeyEvent::new(KeyCode::Char('d'), KeyModifiers::CONTROL));
+        assert_eq!(fields.fields().unwrap(), HashMap::from([("region".to_string(), "eu".to_string())]));
+    }
+}
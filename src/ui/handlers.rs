@@ -1,281 +1,668 @@
-use crate::ui::app::{App, View, ActiveField, InputMode};
+use crate::config::SETTING_KEYS;
+use crate::ui::app::{App, View, ActiveField, BulkOperation, CopyField, InputMode, MainFocus, PendingAction};
+use crate::ui::keymap::{Action, Context, KeyChord};
 use crate::models::EntryType;
-use crossterm::event::{self, KeyCode, KeyEvent};
-use clipboard::{ClipboardProvider, ClipboardContext};
-use std::thread;
-use std::time::Duration;
+use crossterm::event::{KeyCode, KeyEvent, MouseButton, MouseEvent, MouseEventKind};
+
+/// Runs actions that mean the same on every screen. Returns `false` for anything else.
+fn handle_global_action(app: &mut App, action: Action) -> bool {
+    match action {
+        Action::Quit => app.should_quit = true,
+        Action::NextTheme => app.next_theme(),
+        Action::Help => app.show_help = true,
+        _ => return false,
+    }
+    true
+}
+
+/// For screens where the user is typing: text keys belong to the input, and only command keys
+/// (e.g. `C-t`) reach the keymap.
+fn resolve_command_key(app: &mut App, context: Context, key: KeyEvent) -> Option<Action> {
+    if KeyChord::from(key).is_text() {
+        app.pending_keys.clear();
+        return None;
+    }
+    app.resolve_key(context, key)
+}
 
 pub fn handle_lock_screen_input(app: &mut App, key: KeyEvent) -> Result<(), Box<dyn std::error::Error>> {
-    if key.modifiers.contains(event::KeyModifiers::CONTROL) && key.code == KeyCode::Char('r') {
-        app.reset()?;
-        app.error_message = Some("Password vault has been reset. Create a new master password.".to_string());
-    } else if key.modifiers.contains(event::KeyModifiers::CONTROL) && key.code == KeyCode::Char('t') {
-        app.next_theme();
-    } else {
-        match key.code {
-            KeyCode::Enter => {
-                if !app.master_password.is_empty() {
-                    app.unlock_vault()?;
-                } else {
-                    app.error_message = Some("Password cannot be empty".to_string());
-                }
+    match key.code {
+        KeyCode::Enter => {
+            if !app.master_password.is_empty() {
+                app.unlock_vault()?;
+            } else {
+                app.error_message = Some("Password cannot be empty".to_string());
             }
-            KeyCode::Char(c) => {
+        }
+        KeyCode::Tab | KeyCode::BackTab => app.editing_key_file = !app.editing_key_file,
+        KeyCode::Char(c) if KeyChord::from(key).is_text() => {
+            app.pending_keys.clear();
+            if app.editing_key_file {
+                app.key_file.push(c);
+            } else {
                 app.master_password.push(c);
-                app.error_message = None; 
             }
-            KeyCode::Backspace => {
+            app.error_message = None;
+        }
+        KeyCode::Backspace => {
+            if app.editing_key_file {
+                app.key_file.pop();
+            } else {
                 app.master_password.pop();
-                app.error_message = None; 
             }
-            KeyCode::Esc => {
-                app.should_quit = true;
-            }
-            _ => {}
+            app.error_message = None;
         }
+        _ => match resolve_command_key(app, Context::Lock, key) {
+            Some(Action::ResetVault) => {
+                app.error_message = None;
+                app.request_confirmation(PendingAction::ResetVault);
+            }
+            Some(action) => {
+                handle_global_action(app, action);
+            }
+            None => {}
+        },
     }
     Ok(())
 }
 
 pub fn handle_main_screen_input(app: &mut App, key: KeyEvent) -> Result<(), Box<dyn std::error::Error>> {
-    if key.modifiers.contains(event::KeyModifiers::CONTROL) && key.code == KeyCode::Char('t') {
-        app.next_theme();
-        return Ok(());
+    // Any key dismisses the message popup from the previous action.
+    app.info_message = None;
+    app.error_message = None;
+    if app.input_mode == InputMode::Editing {
+        return handle_search_input(app, key);
     }
-    match app.input_mode {
-        InputMode::Normal => match key.code {
-            KeyCode::Char('q') => {
-                app.should_quit = true;
-            }
-            KeyCode::Char('e') => {
-                app.export_vault()?;
-            }
-            KeyCode::Char('i') => {
-                app.import_vault()?;
-            }
-            KeyCode::Char('a') => {
-                app.current_view = View::AddCredential;
-            }
-            KeyCode::Char('/') => {
-                app.input_mode = InputMode::Editing;
-            }
-            KeyCode::Down => {
-                if !app.credentials.is_empty() {
-                    let i = match app.selected_credential {
-                        Some(i) => {
-                            if i >= app.credentials.len() - 1 {
-                                0
-                            } else {
-                                i + 1
-                            }
-                        }
-                        None => 0,
-                    };
-                    app.selected_credential = Some(i);
-                }
-            }
-            KeyCode::Up => {
-                if !app.credentials.is_empty() {
-                    let i = match app.selected_credential {
-                        Some(i) => {
-                            if i == 0 {
-                                app.credentials.len() - 1
-                            } else {
-                                i - 1
-                            }
-                        }
-                        None => 0,
-                    };
-                    app.selected_credential = Some(i);
-                }
-            }
-            KeyCode::Enter => {
-                if let Some(selected_index) = app.selected_credential {
-                    if let Some(credential) = app.credentials.get(selected_index) {
-                        app.selected_id = Some(credential.id.clone());
-                        app.current_view = View::ViewCredential;
-                    }
+    if app.main_focus == MainFocus::Tags {
+        return handle_tag_sidebar_input(app, key);
+    }
+    let action = match app.resolve_key(Context::Main, key) {
+        Some(action) => action,
+        None => {
+            // Number keys open the quick-access entries unless they have been bound to something else.
+            if let KeyCode::Char(c @ '1'..='9') = key.code {
+                if app.pending_keys.is_empty() {
+                    app.open_quick_access(c as usize - '0' as usize);
                 }
             }
-            _ => {}
-        },
-        InputMode::Editing => match key.code {
-            KeyCode::Char(c) => {
-                app.search_query.push(c);
-                app.filter_credentials();
-            }
-            KeyCode::Backspace => {
-                app.search_query.pop();
-                app.filter_credentials();
+            return Ok(());
+        }
+    };
+    match action {
+        Action::Export => {
+            app.request_confirmation(PendingAction::Export);
+        }
+        Action::Import => {
+            app.request_confirmation(PendingAction::Import);
+        }
+        Action::Settings => {
+            app.open_settings();
+        }
+        Action::Add if app.check_writable() => {
+            // Drop any id left over from viewing a credential so the form adds rather than edits.
+            app.clear_form();
+            app.current_view = View::AddCredential;
+        }
+        Action::Search => {
+            app.input_mode = InputMode::Editing;
+        }
+        Action::CycleSort => {
+            app.next_sort_mode();
+        }
+        Action::ToggleFolders => {
+            app.toggle_folder_view();
+        }
+        Action::TogglePreview => {
+            app.toggle_preview();
+        }
+        Action::ToggleMark => {
+            app.toggle_mark();
+        }
+        Action::MarkAll => {
+            app.toggle_mark_all();
+        }
+        Action::BulkActions => {
+            app.open_bulk_menu();
+        }
+        Action::ToggleTrash => {
+            app.toggle_trash()?;
+        }
+        Action::ReviewConflicts => {
+            app.open_conflicts();
+        }
+        Action::AuditLog => {
+            app.open_audit_log();
+        }
+        Action::FocusTags if !app.tags.is_empty() => {
+            app.main_focus = MainFocus::Tags;
+        }
+        Action::MoveDown => {
+            app.move_selection(1);
+        }
+        Action::MoveUp => {
+            app.move_selection(-1);
+        }
+        Action::MoveFirst => {
+            app.select_edge(false);
+        }
+        Action::MoveLast => {
+            app.select_edge(true);
+        }
+        Action::ToggleFavorite => {
+            app.toggle_selected_favorite()?;
+        }
+        Action::CollapseFolder => {
+            app.set_selected_folder_collapsed(Some(true));
+        }
+        Action::ExpandFolder => {
+            app.set_selected_folder_collapsed(Some(false));
+        }
+        Action::Open => {
+            app.open_selected();
+        }
+        action => {
+            handle_global_action(app, action);
+        }
+    }
+    Ok(())
+}
+
+fn handle_search_input(app: &mut App, key: KeyEvent) -> Result<(), Box<dyn std::error::Error>> {
+    match key.code {
+        KeyCode::Char(c) if KeyChord::from(key).is_text() => {
+            app.search_query.push(c);
+            app.filter_credentials();
+        }
+        KeyCode::Backspace => {
+            app.search_query.pop();
+            app.filter_credentials();
+        }
+        KeyCode::Esc => {
+            app.input_mode = InputMode::Normal;
+            app.search_query.clear();
+            app.filter_credentials();
+        }
+        KeyCode::Enter => {
+            app.input_mode = InputMode::Normal;
+        }
+        _ => {
+            if let Some(action) = resolve_command_key(app, Context::Global, key) {
+                handle_global_action(app, action);
             }
-            KeyCode::Esc => {
-                app.input_mode = InputMode::Normal;
-                app.search_query.clear();
+        }
+    }
+    Ok(())
+}
+
+fn handle_tag_sidebar_input(app: &mut App, key: KeyEvent) -> Result<(), Box<dyn std::error::Error>> {
+    let action = match app.resolve_key(Context::Tags, key) {
+        Some(action) => action,
+        None => return Ok(()),
+    };
+    match action {
+        Action::Back => {
+            app.main_focus = MainFocus::List;
+        }
+        Action::ClearFilter => {
+            app.main_focus = MainFocus::List;
+            if app.tag_filter.take().is_some() {
                 app.filter_credentials();
             }
-            KeyCode::Enter => {
-                app.input_mode = InputMode::Normal;
-            }
-            _ => {}
-        },
+        }
+        Action::MoveDown if !app.tags.is_empty() => {
+            app.tag_cursor = (app.tag_cursor + 1) % app.tags.len();
+        }
+        Action::MoveUp if !app.tags.is_empty() => {
+            app.tag_cursor = app.tag_cursor.checked_sub(1).unwrap_or(app.tags.len() - 1);
+        }
+        Action::MoveFirst => {
+            app.tag_cursor = 0;
+        }
+        Action::MoveLast => {
+            app.tag_cursor = app.tags.len().saturating_sub(1);
+        }
+        Action::Open => {
+            app.toggle_tag_filter();
+        }
+        action => {
+            handle_global_action(app, action);
+        }
     }
     Ok(())
 }
 
 pub fn handle_add_credential_input(app: &mut App, key: KeyEvent) -> Result<(), Box<dyn std::error::Error>> {
-    if key.modifiers.contains(event::KeyModifiers::CONTROL) && key.code == KeyCode::Char('t') {
-        app.next_theme();
+    // Any key dismisses the error from a failed save.
+    if app.error_message.take().is_some() {
         return Ok(());
     }
     match app.input_mode {
-        InputMode::Normal => match key.code {
-            KeyCode::Char('i') => {
-                app.input_mode = InputMode::Editing;
-            }
-            KeyCode::Char('q') | KeyCode::Esc => {
-                app.clear_form();
-                app.current_view = View::Main;
-                app.input_mode = InputMode::Normal;
-            }
-            KeyCode::Char('t') => {
-                if app.selected_id.is_none() {
+        InputMode::Normal => {
+            let action = match app.resolve_key(Context::Form, key) {
+                Some(action) => action,
+                None => return Ok(()),
+            };
+            match action {
+                Action::Insert => {
+                    app.input_mode = InputMode::Editing;
+                }
+                Action::Back => {
+                    app.clear_form();
+                    app.current_view = View::Main;
+                    app.input_mode = InputMode::Normal;
+                }
+                Action::ToggleType if app.selected_id.is_none() => {
                     app.entry_type = match app.entry_type {
                         EntryType::Password => EntryType::ApiKey,
                         EntryType::ApiKey => EntryType::Password,
                     };
                     app.active_field = Some(ActiveField::Service);
                 }
+                Action::NextField => {
+                    app.next_field();
+                }
+                Action::PreviousField => {
+                    app.previous_field();
+                }
+                Action::Save => {
+                    // First save to a temporary variable to avoid state issues
+                    let is_update = app.selected_id.is_some();
+
+                    // Reset states before performing operation
+                    app.input_mode = InputMode::Normal;
+                    app.current_view = View::Main;
+
+                    if let Err(e) = if is_update {
+                        app.update_selected_credential()
+                    } else {
+                        app.add_credential()
+                    } {
+                        app.error_message = Some(format!("Error: {}", e));
+                        app.current_view = View::AddCredential;
+                    }
+                }
+                action => {
+                    handle_global_action(app, action);
+                }
             }
-            KeyCode::Tab => {
-                app.next_field();
+        }
+        InputMode::Editing => {
+            let consumed = match app.active_field {
+                Some(ActiveField::CustomFields) => app.custom_fields_input.handle_key(key),
+                Some(ActiveField::IsActive) => match key.code {
+                    KeyCode::Char(' ') | KeyCode::Backspace => {
+                        app.is_active_input = !app.is_active_input;
+                        true
+                    }
+                    _ => false,
+                },
+                _ => app.active_editor_mut().is_some_and(|editor| editor.handle_key(key)),
+            };
+            if consumed {
+                app.pending_keys.clear();
+                if app.active_field == Some(ActiveField::Secret) {
+                    app.update_password_strength();
+                }
+                return Ok(());
             }
-            KeyCode::Enter => {
-                // First save to a temporary variable to avoid state issues
-                let is_update = app.selected_id.is_some();
-                
-                // Reset states before performing operation
-                app.input_mode = InputMode::Normal;
-                app.current_view = View::Main;
+            match key.code {
+                KeyCode::Esc => {
+                    app.input_mode = InputMode::Normal;
+                }
+                // Enter in a single-line field, and Up/Down past the first or last line, move between fields.
+                KeyCode::Tab | KeyCode::Enter | KeyCode::Down => {
+                    app.next_field();
+                }
+                KeyCode::BackTab | KeyCode::Up => {
+                    app.previous_field();
+                }
+                _ => {
+                    if let Some(action) = resolve_command_key(app, Context::Global, key) {
+                        handle_global_action(app, action);
+                    }
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Inserts text from a bracketed paste into whatever input has focus.
+pub fn handle_paste(app: &mut App, text: &str) {
+    // Line breaks only make sense in the notes field; elsewhere a trailing newline is dropped and
+    // the editors turn the rest into spaces.
+    let single_line = text.trim_end_matches(['\r', '\n']);
+    if let Some(confirmation) = app.confirmation.as_mut() {
+        confirmation.input.push_str(single_line);
+        return;
+    }
+    if let Some(menu) = app.bulk_menu.as_mut() {
+        if let Some((_, editor)) = menu.prompt.as_mut() {
+            editor.insert_str(single_line);
+        }
+        return;
+    }
+    match app.current_view {
+        View::LockScreen if app.editing_key_file => {
+            app.key_file.push_str(single_line);
+            app.error_message = None;
+        }
+        View::LockScreen => {
+            app.master_password.push_str(single_line);
+            app.error_message = None;
+        }
+        View::Main if app.input_mode == InputMode::Editing => {
+            app.search_query.push_str(&single_line.replace(['\r', '\n'], " "));
+            app.filter_credentials();
+        }
+        View::Settings => {
+            if let Some(input) = app.settings_input.as_mut() {
+                input.push_str(single_line);
+            }
+        }
+        View::AddCredential => {
+            app.input_mode = InputMode::Editing;
+            let text = if app.active_field == Some(ActiveField::Notes) { text } else { single_line };
+            if let Some(editor) = app.active_editor_mut() {
+                editor.insert_str(text);
+            }
+            if app.active_field == Some(ActiveField::Secret) {
+                app.update_password_strength();
+            }
+        }
+        _ => {}
+    }
+}
 
-                if let Err(e) = if is_update {
-                    app.update_selected_credential()
-                } else {
-                    app.add_credential()
-                } {
-                    app.error_message = Some(format!("Error: {}", e));
-                    app.current_view = View::AddCredential;
+pub fn handle_view_credential_input(app: &mut App, key: KeyEvent) -> Result<(), Box<dyn std::error::Error>> {
+    // Any key dismisses the message popup from the previous action.
+    app.info_message = None;
+    app.error_message = None;
+    let action = match app.resolve_key(Context::View, key) {
+        Some(action) => action,
+        None => {
+            match key.code {
+                // Number keys copy custom fields, and Enter continues a username-then-secret sequence,
+                // unless those keys have been bound to something else.
+                KeyCode::Char(c @ '1'..='9') if app.pending_keys.is_empty() => {
+                    app.copy_custom_field(c as usize - '0' as usize)?;
+                }
+                KeyCode::Enter if app.copy_sequence.is_some() => {
+                    app.advance_copy_sequence()?;
                 }
+                _ => {}
             }
-            _ => {}
-        },
-        InputMode::Editing => match key.code {
+            return Ok(());
+        }
+    };
+    match action {
+        Action::Back => {
+            app.current_view = View::Main;
+            app.show_secret = false; // Reset when leaving view
+            app.copy_sequence = None;
+        }
+        Action::ToggleSecret if app.check_can_reveal() => {
+            if app.show_secret {
+                app.show_secret = false;
+            } else {
+                app.reveal_selected_secret()?;
+            }
+        }
+        Action::ToggleFavorite => {
+            app.toggle_selected_favorite()?;
+        }
+        Action::CopySecret => {
+            app.copy_field(CopyField::Secret)?;
+        }
+        Action::CopyUsername => {
+            app.copy_field(CopyField::Username)?;
+        }
+        Action::CopyNotes => {
+            app.copy_field(CopyField::Notes)?;
+        }
+        Action::CopyOtp => {
+            app.copy_field(CopyField::Otp)?;
+        }
+        Action::CopySequence if app.check_can_reveal() => {
+            app.advance_copy_sequence()?;
+        }
+        Action::ScrollDown => {
+            app.notes_scroll.scroll_by(1);
+        }
+        Action::ScrollUp => {
+            app.notes_scroll.scroll_by(-1);
+        }
+        Action::PageDown => {
+            app.notes_scroll.scroll_pages(1);
+        }
+        Action::PageUp => {
+            app.notes_scroll.scroll_pages(-1);
+        }
+        // If a credential is selected, load it for editing
+        Action::Edit if app.selected().is_some() && app.check_can_edit() => {
+            app.copy_sequence = None;
+            app.load_selected_credential_for_edit()?;
+            app.current_view = View::AddCredential;
+        }
+        Action::Delete => {
+            app.request_confirmation(PendingAction::DeleteCredential);
+        }
+        action => {
+            handle_global_action(app, action);
+        }
+    }
+    Ok(())
+}
+
+pub fn handle_settings_input(app: &mut App, key: KeyEvent) -> Result<(), Box<dyn std::error::Error>> {
+    app.info_message = None;
+    if let Some(input) = app.settings_input.as_mut() {
+        match key.code {
+            KeyCode::Enter => app.commit_setting_input(),
             KeyCode::Esc => {
-                app.input_mode = InputMode::Normal;
+                app.settings_input = None;
+                app.error_message = None;
             }
             KeyCode::Backspace => {
-                if let Some(active_field) = app.active_field {
-                    match active_field {
-                        ActiveField::Service => {
-                            app.service_input.pop();
-                        }
-                        ActiveField::Username => {
-                            app.username_input.pop();
-                        }
-                        ActiveField::Secret => {
-                            app.secret_input.pop();
-                            app.update_password_strength();
-                        }
-                        ActiveField::Notes => {
-                            app.notes_input.pop();
-                        }
-                        ActiveField::Tags => {
-                            app.tags_input.pop();
-                        }
-                        ActiveField::CustomFields => {
-                            app.custom_fields_input.pop();
-                        }
-                        ActiveField::IsActive => {
-                            app.is_active_input = !app.is_active_input;
-                        }
-                    }
+                input.pop();
+            }
+            KeyCode::Char(c) if KeyChord::from(key).is_text() => input.push(c),
+            _ => {
+                if let Some(action) = resolve_command_key(app, Context::Global, key) {
+                    handle_global_action(app, action);
                 }
             }
-            KeyCode::Char(c) => {
-                if let Some(active_field) = app.active_field {
-                    match active_field {
-                        ActiveField::Service => {
-                            app.service_input.push(c);
-                        }
-                        ActiveField::Username => {
-                            app.username_input.push(c);
-                        }
-                        ActiveField::Secret => {
-                            app.secret_input.push(c);
-                            app.update_password_strength();
-                        }
-                        ActiveField::Notes => {
-                            app.notes_input.push(c);
-                        }
-                        ActiveField::Tags => {
-                            app.tags_input.push(c);
-                        }
-                        ActiveField::CustomFields => {
-                            app.custom_fields_input.push(c);
-                        }
-                        ActiveField::IsActive => {
-                            // IsActive is toggled with backspace or space
-                            if c == ' ' {
-                                app.is_active_input = !app.is_active_input;
-                            }
-                        }
-                    }
+        }
+        return Ok(());
+    }
+    app.error_message = None;
+    let action = match app.resolve_key(Context::Settings, key) {
+        Some(action) => action,
+        None => return Ok(()),
+    };
+    match action {
+        Action::Back => app.discard_settings(),
+        Action::MoveDown => app.settings_cursor = (app.settings_cursor + 1) % SETTING_KEYS.len(),
+        Action::MoveUp => {
+            app.settings_cursor = app.settings_cursor.checked_sub(1).unwrap_or(SETTING_KEYS.len() - 1);
+        }
+        // Themes and keymaps are picked from the installed ones rather than typed.
+        Action::Open | Action::NextValue if app.selected_setting_is_choice() => app.cycle_draft_choice(1),
+        Action::PreviousValue if app.selected_setting_is_choice() => app.cycle_draft_choice(-1),
+        Action::Open => app.edit_selected_setting(),
+        Action::Save => app.save_settings(),
+        action => {
+            handle_global_action(app, action);
+        }
+    }
+    Ok(())
+}
+
+/// Keys for the confirmation dialog, which takes every key while it is open.
+pub fn handle_confirmation_input(app: &mut App, key: KeyEvent) -> Result<(), Box<dyn std::error::Error>> {
+    let needs_phrase = match &app.confirmation {
+        Some(confirmation) => confirmation.phrase.is_some(),
+        None => return Ok(()),
+    };
+    if needs_phrase {
+        match key.code {
+            KeyCode::Esc => app.confirmation = None,
+            KeyCode::Enter if app.confirmation_ready() => app.confirm_pending()?,
+            KeyCode::Backspace => {
+                if let Some(confirmation) = app.confirmation.as_mut() {
+                    confirmation.input.pop();
+                }
+            }
+            KeyCode::Char(c) if KeyChord::from(key).is_text() => {
+                if let Some(confirmation) = app.confirmation.as_mut() {
+                    confirmation.input.push(c);
                 }
             }
             _ => {}
-        },
+        }
+        return Ok(());
+    }
+    match key.code {
+        KeyCode::Char('y') | KeyCode::Char('Y') | KeyCode::Enter => app.confirm_pending()?,
+        KeyCode::Char('n') | KeyCode::Char('N') | KeyCode::Char('q') | KeyCode::Esc => app.confirmation = None,
+        _ => {}
     }
     Ok(())
 }
 
-pub fn handle_view_credential_input(app: &mut App, key: KeyEvent) -> Result<(), Box<dyn std::error::Error>> {
-    if key.modifiers.contains(event::KeyModifiers::CONTROL) && key.code == KeyCode::Char('t') {
-        app.next_theme();
+/// The bulk menu picks an operation by its key; operations that need a tag or folder then ask for it.
+pub fn handle_bulk_menu_input(app: &mut App, key: KeyEvent) -> Result<(), Box<dyn std::error::Error>> {
+    let menu = match app.bulk_menu.as_mut() {
+        Some(menu) => menu,
+        None => return Ok(()),
+    };
+    if let Some((operation, editor)) = menu.prompt.as_mut() {
+        match key.code {
+            KeyCode::Esc => menu.prompt = None,
+            KeyCode::Enter => {
+                let (operation, value) = (*operation, editor.text().to_string());
+                app.run_bulk_operation(operation, &value)?;
+            }
+            _ => {
+                editor.handle_key(key);
+            }
+        }
         return Ok(());
     }
-    match key.code {
-        KeyCode::Char('q') | KeyCode::Esc => {
-            app.current_view = View::Main;
-            app.show_secret = false; // Reset when leaving view
+    let action = match app.resolve_key(Context::Bulk, key) {
+        Some(action) => action,
+        None => return Ok(()),
+    };
+    if action == Action::Back {
+        app.bulk_menu = None;
+        return Ok(());
+    }
+    match BulkOperation::menu(app.show_trash).iter().find(|(bound, ..)| *bound == action) {
+        Some((_, operation, _)) => app.choose_bulk_operation(*operation)?,
+        None => {
+            handle_global_action(app, action);
+        }
+    }
+    Ok(())
+}
+
+/// The conflict review steps through the conflicts; each one is settled by keeping the current
+/// version or switching to the other.
+pub fn handle_conflicts_input(app: &mut App, key: KeyEvent) -> Result<(), Box<dyn std::error::Error>> {
+    let action = match app.resolve_key(Context::Conflicts, key) {
+        Some(action) => action,
+        None => return Ok(()),
+    };
+    let review = match app.conflict_review.as_mut() {
+        Some(review) => review,
+        None => return Ok(()),
+    };
+    match action {
+        Action::Back => app.conflict_review = None,
+        Action::MoveDown => review.cursor = (review.cursor + 1).min(review.conflicts.len().saturating_sub(1)),
+        Action::MoveUp => review.cursor = review.cursor.saturating_sub(1),
+        Action::KeepCurrent => app.resolve_conflict(false)?,
+        Action::UseOther => app.resolve_conflict(true)?,
+        action => {
+            handle_global_action(app, action);
         }
-        KeyCode::Char('s') => {
-            app.show_secret = !app.show_secret;
+    }
+    Ok(())
+}
+
+/// The audit log viewer only scrolls.
+pub fn handle_audit_input(app: &mut App, key: KeyEvent) -> Result<(), Box<dyn std::error::Error>> {
+    let action = match app.resolve_key(Context::Audit, key) {
+        Some(action) => action,
+        None => return Ok(()),
+    };
+    let view = match app.audit_view.as_mut() {
+        Some(view) => view,
+        None => return Ok(()),
+    };
+    let last = view.log.entries.len().saturating_sub(1);
+    match action {
+        Action::Back => app.audit_view = None,
+        Action::ScrollDown => view.scroll = (view.scroll + 1).min(last),
+        Action::ScrollUp => view.scroll = view.scroll.saturating_sub(1),
+        Action::PageDown => view.scroll = (view.scroll + 10).min(last),
+        Action::PageUp => view.scroll = view.scroll.saturating_sub(10),
+        Action::MoveFirst => view.scroll = 0,
+        Action::MoveLast => view.scroll = last,
+        action => {
+            handle_global_action(app, action);
         }
-        KeyCode::Char('c') => {
-            if let Some(index) = app.selected_credential {
-                if let Some(cred) = app.credentials.get(index) {
-                    let mut ctx: ClipboardContext = ClipboardProvider::new().unwrap();
-                    let secret = String::from_utf8_lossy(&cred.secret).to_string();
-                    ctx.set_contents(secret).unwrap();
-                    app.info_message = Some("Copied to clipboard!".to_string());
+    }
+    Ok(())
+}
 
-                    thread::spawn(|| {
-                        thread::sleep(Duration::from_secs(30));
-                        let mut ctx: ClipboardContext = ClipboardProvider::new().unwrap();
-                        ctx.set_contents("".to_string()).unwrap();
-                    });
+/// Mouse clicks select rows in the credential list and tag sidebar; the wheel moves through lists
+/// and scrolls the detail panes.
+pub fn handle_mouse(app: &mut App, mouse: MouseEvent) -> Result<(), Box<dyn std::error::Error>> {
+    if app.show_help || app.confirmation.is_some() || app.bulk_menu.is_some() || app.conflict_review.is_some() || app.audit_view.is_some() {
+        return Ok(());
+    }
+    let (column, row) = (mouse.column, mouse.row);
+    let step = match mouse.kind {
+        MouseEventKind::ScrollDown => 1,
+        MouseEventKind::ScrollUp => -1,
+        _ => 0,
+    };
+    match app.current_view {
+        View::Main if app.input_mode == InputMode::Normal => {
+            if step != 0 {
+                if app.tags_scroll.contains(column, row) && !app.tags.is_empty() {
+                    app.main_focus = MainFocus::Tags;
+                    app.tag_cursor = (app.tag_cursor as isize + step).clamp(0, app.tags.len() as isize - 1) as usize;
+                } else if app.list_scroll.contains(column, row) {
+                    app.main_focus = MainFocus::List;
+                    app.scroll_selection(step);
+                }
+            } else if mouse.kind == MouseEventKind::Down(MouseButton::Left) {
+                if let Some(line) = app.tags_scroll.line_at(column, row).filter(|&l| l < app.tags.len()) {
+                    app.main_focus = MainFocus::Tags;
+                    app.tag_cursor = line;
+                    app.toggle_tag_filter();
+                } else if let Some(line) = app.list_scroll.line_at(column, row) {
+                    app.main_focus = MainFocus::List;
+                    // A click selects a row; clicking the selected row again opens it.
+                    if app.selected_credential == Some(line) {
+                        app.open_selected();
+                    } else {
+                        app.select_row(line);
+                    }
                 }
             }
         }
-        KeyCode::Char('e') => {
-            // If a credential is selected, load it for editing
-            if app.selected_credential.is_some() {
-                app.load_selected_credential_for_edit()?;
-                app.current_view = View::AddCredential;
+        View::ViewCredential if step != 0 => {
+            if app.fields_scroll.contains(column, row) {
+                app.fields_scroll.scroll_by(step);
+            } else {
+                app.notes_scroll.scroll_by(step);
             }
         }
-        KeyCode::Char('d') => {
-            app.remove_selected_credential()?;
-            app.current_view = View::Main;
+        View::Settings if step != 0 && app.settings_input.is_none() => {
+            app.settings_cursor = (app.settings_cursor as isize + step).clamp(0, SETTING_KEYS.len() as isize - 1) as usize;
         }
         _ => {}
     }
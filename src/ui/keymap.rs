@@ -0,0 +1,760 @@
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// Where a key press happens. Bindings in `Global` apply everywhere unless a screen binds the same keys.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Context {
+    Global,
+    Lock,
+    Main,
+    Tags,
+    View,
+    Form,
+    Settings,
+    Bulk,
+    Conflicts,
+    Audit,
+}
+
+impl Context {
+    pub const ALL: [Context; 10] = [
+        Context::Global,
+        Context::Lock,
+        Context::Main,
+        Context::Tags,
+        Context::View,
+        Context::Form,
+        Context::Settings,
+        Context::Bulk,
+        Context::Conflicts,
+        Context::Audit,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Context::Global => "global",
+            Context::Lock => "lock",
+            Context::Main => "main",
+            Context::Tags => "tags",
+            Context::View => "view",
+            Context::Form => "form",
+            Context::Settings => "settings",
+            Context::Bulk => "bulk",
+            Context::Conflicts => "conflicts",
+            Context::Audit => "audit",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|c| c.name() == name)
+    }
+
+    /// The actions that can be bound on this screen, in the order the help overlay lists them.
+    pub fn actions(self) -> &'static [Action] {
+        use Action::*;
+        match self {
+            Context::Global => &[Quit, NextTheme, Help],
+            Context::Lock => &[ResetVault, Quit],
+            Context::Main => &[
+                MoveDown, MoveUp, MoveFirst, MoveLast, Open, CollapseFolder, ExpandFolder, Search, Add,
+                ToggleFavorite, CycleSort, ToggleFolders, TogglePreview, ToggleMark, MarkAll, BulkActions,
+                ToggleTrash, ReviewConflicts, AuditLog, FocusTags, Export, Import, Settings, Quit,
+            ],
+            Context::Tags => &[MoveDown, MoveUp, MoveFirst, MoveLast, Open, Back, ClearFilter, Quit],
+            Context::View => &[
+                CopySecret, CopyUsername, CopyNotes, CopyOtp, CopySequence, ToggleSecret, ToggleFavorite, Edit,
+                Delete, ScrollDown, ScrollUp, PageDown, PageUp, Back,
+            ],
+            Context::Form => &[Insert, NextField, PreviousField, ToggleType, Save, Back],
+            Context::Settings => &[MoveDown, MoveUp, Open, PreviousValue, NextValue, Save, Back],
+            Context::Bulk => &[AddTag, RemoveTag, MoveToFolder, ToggleActive, Delete, Restore, Export, Back],
+            Context::Conflicts => &[KeepCurrent, UseOther, MoveDown, MoveUp, Back],
+            Context::Audit => &[ScrollDown, ScrollUp, PageDown, PageUp, MoveFirst, MoveLast, Back],
+        }
+    }
+}
+
+/// Something a key binding can do.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    Quit,
+    NextTheme,
+    Help,
+    ResetVault,
+    MoveDown,
+    MoveUp,
+    MoveFirst,
+    MoveLast,
+    Open,
+    Back,
+    CollapseFolder,
+    ExpandFolder,
+    Search,
+    Add,
+    ToggleFavorite,
+    CycleSort,
+    ToggleFolders,
+    TogglePreview,
+    ToggleMark,
+    MarkAll,
+    BulkActions,
+    ToggleTrash,
+    ReviewConflicts,
+    AuditLog,
+    FocusTags,
+    Export,
+    Import,
+    Settings,
+    ClearFilter,
+    ToggleSecret,
+    CopySecret,
+    CopyUsername,
+    CopyNotes,
+    CopyOtp,
+    CopySequence,
+    ScrollDown,
+    ScrollUp,
+    PageDown,
+    PageUp,
+    Edit,
+    Delete,
+    Insert,
+    NextField,
+    PreviousField,
+    ToggleType,
+    Save,
+    PreviousValue,
+    NextValue,
+    AddTag,
+    RemoveTag,
+    MoveToFolder,
+    ToggleActive,
+    Restore,
+    KeepCurrent,
+    UseOther,
+}
+
+/// Config name, short label for the status line, and description for the help overlay.
+const ACTION_INFO: &[(Action, &str, &str, &str)] = &[
+    (Action::Quit, "quit", "Quit", "Quit the application"),
+    (Action::NextTheme, "next_theme", "Theme", "Switch to the next theme"),
+    (Action::Help, "help", "Help", "Show this help"),
+    (Action::ResetVault, "reset_vault", "Reset", "Delete the vault and start over"),
+    (Action::MoveDown, "move_down", "Down", "Move down"),
+    (Action::MoveUp, "move_up", "Up", "Move up"),
+    (Action::MoveFirst, "move_first", "First", "Jump to the first entry"),
+    (Action::MoveLast, "move_last", "Last", "Jump to the last entry"),
+    (Action::Open, "open", "Open", "Open the selected entry, fold a folder, or edit a setting"),
+    (Action::Back, "back", "Back", "Go back without saving"),
+    (Action::CollapseFolder, "collapse_folder", "Collapse", "Collapse the selected folder"),
+    (Action::ExpandFolder, "expand_folder", "Expand", "Expand the selected folder"),
+    (Action::Search, "search", "Search", "Search (fuzzy, with tag:/type:/user: filters)"),
+    (Action::Add, "add", "Add", "Add a credential"),
+    (Action::ToggleFavorite, "toggle_favorite", "Fav", "Mark or unmark as favorite"),
+    (Action::CycleSort, "cycle_sort", "Sort", "Cycle the sort order"),
+    (Action::ToggleFolders, "toggle_folders", "Folders", "Toggle the folder tree"),
+    (Action::TogglePreview, "toggle_preview", "Preview", "Show or hide the preview pane"),
+    (Action::ToggleMark, "toggle_mark", "Mark", "Mark or unmark the selected credential"),
+    (Action::MarkAll, "mark_all", "Mark all", "Mark every listed credential, or clear the marks"),
+    (Action::BulkActions, "bulk_actions", "Bulk", "Act on the marked credentials"),
+    (Action::ToggleTrash, "toggle_trash", "Trash", "Switch between the vault and the trash"),
+    (Action::ReviewConflicts, "review_conflicts", "Conflicts", "Review sync conflicts"),
+    (Action::AuditLog, "audit_log", "Audit", "Show the audit log"),
+    (Action::FocusTags, "focus_tags", "Tags", "Move focus to the tag sidebar"),
+    (Action::Export, "export", "Export", "Export as plaintext JSON"),
+    (Action::Import, "import", "Import", "Import credentials from the export file"),
+    (Action::Settings, "settings", "Settings", "Open the settings screen"),
+    (Action::ClearFilter, "clear_filter", "Clear", "Clear the tag filter and return to the list"),
+    (Action::ToggleSecret, "toggle_secret", "Show", "Show or hide the secret"),
+    (Action::CopySecret, "copy_secret", "Copy", "Copy the secret"),
+    (Action::CopyUsername, "copy_username", "User", "Copy the username"),
+    (Action::CopyNotes, "copy_notes", "Notes", "Copy the notes"),
+    (Action::CopyOtp, "copy_otp", "OTP", "Copy the current one-time password"),
+    (Action::CopySequence, "copy_sequence", "User, then secret", "Copy the username, then the secret on the next press"),
+    (Action::ScrollDown, "scroll_down", "Scroll down", "Scroll down"),
+    (Action::ScrollUp, "scroll_up", "Scroll up", "Scroll up"),
+    (Action::PageDown, "page_down", "Page down", "Scroll down a page"),
+    (Action::PageUp, "page_up", "Page up", "Scroll up a page"),
+    (Action::Edit, "edit", "Edit", "Edit the credential"),
+    (Action::Delete, "delete", "Delete", "Move to the trash, or delete for good from the trash"),
+    (Action::Insert, "insert", "Insert", "Start typing in the selected field"),
+    (Action::NextField, "next_field", "Next Field", "Move to the next field"),
+    (Action::PreviousField, "previous_field", "Prev Field", "Move to the previous field"),
+    (Action::ToggleType, "toggle_type", "Type", "Switch between password and API key (new entries only)"),
+    (Action::Save, "save", "Save", "Save"),
+    (Action::PreviousValue, "previous_value", "Prev", "Previous choice for theme or keymap"),
+    (Action::NextValue, "next_value", "Next", "Next choice for theme or keymap"),
+    (Action::AddTag, "add_tag", "Tag", "Add a tag"),
+    (Action::RemoveTag, "remove_tag", "Untag", "Remove a tag"),
+    (Action::MoveToFolder, "move_to_folder", "Move", "Move to a folder"),
+    (Action::ToggleActive, "toggle_active", "Active", "Toggle API keys active/inactive"),
+    (Action::Restore, "restore", "Restore", "Restore from the trash"),
+    (Action::KeepCurrent, "keep_current", "Keep current", "Keep the current version"),
+    (Action::UseOther, "use_other", "Use other version", "Switch to the other version"),
+];
+
+impl Action {
+    fn info(self) -> &'static (Action, &'static str, &'static str, &'static str) {
+        ACTION_INFO.iter().find(|(a, ..)| *a == self).expect("every action has an ACTION_INFO entry")
+    }
+
+    pub fn name(self) -> &'static str {
+        self.info().1
+    }
+
+    pub fn label(self) -> &'static str {
+        self.info().2
+    }
+
+    pub fn description(self) -> &'static str {
+        self.info().3
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        ACTION_INFO.iter().find(|(_, n, ..)| *n == name).map(|(a, ..)| *a)
+    }
+}
+
+/// One key press with its modifiers, e.g. `C-x` or `G`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeyChord {
+    code: KeyCode,
+    modifiers: KeyModifiers,
+}
+
+impl From<KeyEvent> for KeyChord {
+    fn from(key: KeyEvent) -> Self {
+        let mut modifiers = key.modifiers & (KeyModifiers::CONTROL | KeyModifiers::ALT | KeyModifiers::SHIFT);
+        // Shift is already part of characters (`G`, `?`) and of BackTab, so it is not tracked separately.
+        if matches!(key.code, KeyCode::Char(_) | KeyCode::BackTab) {
+            modifiers.remove(KeyModifiers::SHIFT);
+        }
+        Self { code: key.code, modifiers }
+    }
+}
+
+impl KeyChord {
+    /// Whether this key would type text, as opposed to being a command key.
+    pub fn is_text(&self) -> bool {
+        matches!(self.code, KeyCode::Char(_)) && !self.modifiers.intersects(KeyModifiers::CONTROL | KeyModifiers::ALT)
+    }
+
+    /// Parses Emacs-style notation: `a`, `G`, `?`, `Enter`, `S-Tab`, `C-x`, `M-<`, `F1`.
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut modifiers = KeyModifiers::empty();
+        let mut rest = text;
+        while rest.len() > 2 && rest.as_bytes()[1] == b'-' {
+            match &rest[..1] {
+                "C" => modifiers |= KeyModifiers::CONTROL,
+                "M" | "A" => modifiers |= KeyModifiers::ALT,
+                "S" => modifiers |= KeyModifiers::SHIFT,
+                _ => break,
+            }
+            rest = &rest[2..];
+        }
+        let mut chars = rest.chars();
+        let code = match (chars.next(), chars.next()) {
+            (Some(c), None) if modifiers.contains(KeyModifiers::SHIFT) => KeyCode::Char(c.to_ascii_uppercase()),
+            (Some(c), None) => KeyCode::Char(c),
+            _ => match rest.to_lowercase().as_str() {
+                "enter" | "return" | "ret" => KeyCode::Enter,
+                "esc" | "escape" => KeyCode::Esc,
+                "tab" if modifiers.contains(KeyModifiers::SHIFT) => KeyCode::BackTab,
+                "tab" => KeyCode::Tab,
+                "backtab" => KeyCode::BackTab,
+                "backspace" | "bs" => KeyCode::Backspace,
+                "delete" | "del" => KeyCode::Delete,
+                "insert" | "ins" => KeyCode::Insert,
+                "up" => KeyCode::Up,
+                "down" => KeyCode::Down,
+                "left" => KeyCode::Left,
+                "right" => KeyCode::Right,
+                "home" => KeyCode::Home,
+                "end" => KeyCode::End,
+                "pageup" | "pgup" => KeyCode::PageUp,
+                "pagedown" | "pgdn" => KeyCode::PageDown,
+                "space" | "spc" => KeyCode::Char(' '),
+                name => match name.strip_prefix('f').and_then(|n| n.parse::<u8>().ok()) {
+                    Some(n @ 1..=12) => KeyCode::F(n),
+                    _ => return Err(format!("unknown key '{}'", text)),
+                },
+            },
+        };
+        Ok(KeyChord::from(KeyEvent::new(code, modifiers)))
+    }
+}
+
+impl fmt::Display for KeyChord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.modifiers.contains(KeyModifiers::CONTROL) {
+            write!(f, "C-")?;
+        }
+        if self.modifiers.contains(KeyModifiers::ALT) {
+            write!(f, "M-")?;
+        }
+        if self.modifiers.contains(KeyModifiers::SHIFT) {
+            write!(f, "S-")?;
+        }
+        match self.code {
+            KeyCode::Char(' ') => write!(f, "Space"),
+            KeyCode::Char(c) => write!(f, "{}", c),
+            KeyCode::Enter => write!(f, "Enter"),
+            KeyCode::Esc => write!(f, "Esc"),
+            KeyCode::Tab => write!(f, "Tab"),
+            KeyCode::BackTab => write!(f, "S-Tab"),
+            KeyCode::Backspace => write!(f, "Backspace"),
+            KeyCode::Delete => write!(f, "Del"),
+            KeyCode::Insert => write!(f, "Ins"),
+            KeyCode::Up => write!(f, "↑"),
+            KeyCode::Down => write!(f, "↓"),
+            KeyCode::Left => write!(f, "←"),
+            KeyCode::Right => write!(f, "→"),
+            KeyCode::Home => write!(f, "Home"),
+            KeyCode::End => write!(f, "End"),
+            KeyCode::PageUp => write!(f, "PgUp"),
+            KeyCode::PageDown => write!(f, "PgDn"),
+            KeyCode::F(n) => write!(f, "F{}", n),
+            _ => write!(f, "?"),
+        }
+    }
+}
+
+fn parse_sequence(text: &str) -> Result<Vec<KeyChord>, String> {
+    let chords = text.split_whitespace().map(KeyChord::parse).collect::<Result<Vec<_>, _>>()?;
+    if chords.is_empty() {
+        return Err("empty key binding".to_string());
+    }
+    Ok(chords)
+}
+
+fn format_sequence(sequence: &[KeyChord]) -> String {
+    sequence.iter().map(ToString::to_string).collect::<Vec<_>>().join(" ")
+}
+
+/// One or several key sequences, as written in the `[keys]` tables of the settings file.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum KeyList {
+    One(String),
+    Many(Vec<String>),
+}
+
+impl KeyList {
+    fn sequences(&self) -> Vec<&str> {
+        match self {
+            KeyList::One(key) => vec![key.as_str()],
+            KeyList::Many(keys) => keys.iter().map(String::as_str).collect(),
+        }
+    }
+}
+
+/// User overrides: context name → action name → keys.
+pub type KeyOverrides = BTreeMap<String, BTreeMap<String, KeyList>>;
+
+type PresetTable = &'static [(&'static str, &'static str, &'static [&'static str])];
+
+const DEFAULT_KEYS: PresetTable = &[
+    ("global", "quit", &["C-c"]),
+    ("global", "next_theme", &["C-t"]),
+    ("global", "help", &["?"]),
+    ("lock", "reset_vault", &["C-r"]),
+    ("lock", "quit", &["Esc"]),
+    ("main", "move_down", &["Down"]),
+    ("main", "move_up", &["Up"]),
+    ("main", "move_first", &["Home"]),
+    ("main", "move_last", &["End"]),
+    ("main", "open", &["Enter"]),
+    ("main", "collapse_folder", &["Left"]),
+    ("main", "expand_folder", &["Right"]),
+    ("main", "search", &["/"]),
+    ("main", "add", &["a"]),
+    ("main", "toggle_favorite", &["f"]),
+    ("main", "cycle_sort", &["o"]),
+    ("main", "toggle_folders", &["g"]),
+    ("main", "toggle_preview", &["p"]),
+    ("main", "toggle_mark", &["Space"]),
+    ("main", "mark_all", &["*"]),
+    ("main", "bulk_actions", &["b"]),
+    ("main", "toggle_trash", &["T"]),
+    ("main", "review_conflicts", &["C"]),
+    ("main", "audit_log", &["L"]),
+    ("main", "focus_tags", &["Tab"]),
+    ("main", "export", &["E"]),
+    ("main", "import", &["I"]),
+    ("main", "settings", &["s"]),
+    ("main", "quit", &["q"]),
+    ("tags", "move_down", &["Down"]),
+    ("tags", "move_up", &["Up"]),
+    ("tags", "move_first", &["Home"]),
+    ("tags", "move_last", &["End"]),
+    ("tags", "open", &["Enter"]),
+    ("tags", "back", &["Tab"]),
+    ("tags", "clear_filter", &["Esc"]),
+    ("tags", "quit", &["q"]),
+    ("view", "copy_secret", &["c"]),
+    ("view", "copy_username", &["u"]),
+    ("view", "copy_notes", &["n"]),
+    ("view", "copy_otp", &["o"]),
+    ("view", "copy_sequence", &["l"]),
+    ("view", "scroll_down", &["Down"]),
+    ("view", "scroll_up", &["Up"]),
+    ("view", "page_down", &["PageDown"]),
+    ("view", "page_up", &["PageUp"]),
+    ("view", "toggle_secret", &["s"]),
+    ("view", "toggle_favorite", &["f"]),
+    ("view", "edit", &["e"]),
+    ("view", "delete", &["d"]),
+    ("view", "back", &["q", "Esc"]),
+    ("form", "insert", &["i"]),
+    ("form", "next_field", &["Tab"]),
+    ("form", "previous_field", &["S-Tab"]),
+    ("form", "toggle_type", &["t"]),
+    ("form", "save", &["Enter"]),
+    ("form", "back", &["q", "Esc"]),
+    ("settings", "move_down", &["Down"]),
+    ("settings", "move_up", &["Up"]),
+    ("settings", "open", &["Enter", "i"]),
+    ("settings", "previous_value", &["Left"]),
+    ("settings", "next_value", &["Right"]),
+    ("settings", "save", &["s"]),
+    ("settings", "back", &["q", "Esc"]),
+    ("bulk", "add_tag", &["t"]),
+    ("bulk", "remove_tag", &["u"]),
+    ("bulk", "move_to_folder", &["m"]),
+    ("bulk", "toggle_active", &["a"]),
+    ("bulk", "delete", &["d"]),
+    ("bulk", "restore", &["r"]),
+    ("bulk", "export", &["x"]),
+    ("bulk", "back", &["q", "Esc"]),
+    ("conflicts", "keep_current", &["c"]),
+    ("conflicts", "use_other", &["o"]),
+    ("conflicts", "move_down", &["Down", "j"]),
+    ("conflicts", "move_up", &["Up", "k"]),
+    ("conflicts", "back", &["q", "Esc"]),
+    ("audit", "scroll_down", &["Down", "j"]),
+    ("audit", "scroll_up", &["Up", "k"]),
+    ("audit", "page_down", &["PageDown"]),
+    ("audit", "page_up", &["PageUp"]),
+    ("audit", "move_first", &["Home"]),
+    ("audit", "move_last", &["End"]),
+    ("audit", "back", &["q", "Esc"]),
+];
+
+/// Applied on top of the defaults.
+const VIM_KEYS: PresetTable = &[
+    ("main", "move_down", &["j", "Down"]),
+    ("main", "move_up", &["k", "Up"]),
+    ("main", "move_first", &["g g", "Home"]),
+    ("main", "move_last", &["G", "End"]),
+    ("main", "collapse_folder", &["h", "Left"]),
+    ("main", "expand_folder", &["l", "Right"]),
+    ("main", "toggle_folders", &["z i"]),
+    ("tags", "move_down", &["j", "Down"]),
+    ("tags", "move_up", &["k", "Up"]),
+    ("tags", "move_first", &["g g", "Home"]),
+    ("tags", "move_last", &["G", "End"]),
+    ("view", "back", &["q", "Esc", "h"]),
+    ("view", "copy_secret", &["y y", "c"]),
+    ("view", "copy_sequence", &["y u"]),
+    ("view", "copy_username", &["u"]),
+    ("view", "scroll_down", &["j", "C-e", "Down"]),
+    ("view", "scroll_up", &["k", "C-y", "Up"]),
+    ("view", "page_down", &["C-f", "PageDown"]),
+    ("view", "page_up", &["C-b", "PageUp"]),
+    ("settings", "move_down", &["j", "Down"]),
+    ("settings", "move_up", &["k", "Up"]),
+    ("settings", "previous_value", &["h", "Left"]),
+    ("settings", "next_value", &["l", "Right"]),
+    ("audit", "page_down", &["C-f", "PageDown"]),
+    ("audit", "page_up", &["C-b", "PageUp"]),
+    ("audit", "move_first", &["g g", "Home"]),
+    ("audit", "move_last", &["G", "End"]),
+];
+
+/// Applied on top of the defaults.
+const EMACS_KEYS: PresetTable = &[
+    ("global", "quit", &["C-x C-c"]),
+    ("global", "help", &["?", "F1"]),
+    ("main", "move_down", &["C-n", "Down"]),
+    ("main", "move_up", &["C-p", "Up"]),
+    ("main", "move_first", &["M-<", "Home"]),
+    ("main", "move_last", &["M->", "End"]),
+    ("main", "collapse_folder", &["C-b", "Left"]),
+    ("main", "expand_folder", &["C-f", "Right"]),
+    ("main", "search", &["C-s", "/"]),
+    ("main", "toggle_mark", &["C-Space", "Space"]),
+    ("tags", "move_down", &["C-n", "Down"]),
+    ("tags", "move_up", &["C-p", "Up"]),
+    ("tags", "clear_filter", &["C-g", "Esc"]),
+    ("view", "copy_secret", &["M-w", "c"]),
+    ("view", "back", &["C-g", "q", "Esc"]),
+    ("view", "scroll_down", &["C-n", "Down"]),
+    ("view", "scroll_up", &["C-p", "Up"]),
+    ("view", "page_down", &["C-v", "PageDown"]),
+    ("view", "page_up", &["M-v", "PageUp"]),
+    ("form", "save", &["C-x C-s", "Enter"]),
+    ("form", "back", &["C-g", "q", "Esc"]),
+    ("settings", "move_down", &["C-n", "Down"]),
+    ("settings", "move_up", &["C-p", "Up"]),
+    ("settings", "previous_value", &["C-b", "Left"]),
+    ("settings", "next_value", &["C-f", "Right"]),
+    ("settings", "save", &["C-x C-s", "s"]),
+    ("settings", "back", &["C-g", "q", "Esc"]),
+    ("bulk", "back", &["C-g", "q", "Esc"]),
+    ("conflicts", "move_down", &["C-n", "Down"]),
+    ("conflicts", "move_up", &["C-p", "Up"]),
+    ("conflicts", "back", &["C-g", "q", "Esc"]),
+    ("audit", "scroll_down", &["C-n", "Down"]),
+    ("audit", "scroll_up", &["C-p", "Up"]),
+    ("audit", "page_down", &["C-v", "PageDown"]),
+    ("audit", "page_up", &["M-v", "PageUp"]),
+    ("audit", "move_first", &["M-<", "Home"]),
+    ("audit", "move_last", &["M->", "End"]),
+    ("audit", "back", &["C-g", "q", "Esc"]),
+];
+
+struct Binding {
+    context: Context,
+    action: Action,
+    keys: Vec<Vec<KeyChord>>,
+}
+
+/// The outcome of feeding keys to the keymap.
+pub enum Resolution {
+    Action(Action),
+    /// The keys so far start a longer binding, e.g. the first `g` of `g g`.
+    Pending,
+    Unbound,
+}
+
+/// Maps key sequences to actions for each screen.
+pub struct Keymap {
+    pub name: String,
+    bindings: Vec<Binding>,
+}
+
+impl Keymap {
+    pub const PRESETS: [&'static str; 3] = ["default", "vim", "emacs"];
+
+    /// Builds a preset and applies the user's overrides. Each override replaces the keys of its action.
+    pub fn build(preset: &str, overrides: &KeyOverrides) -> Result<Self, String> {
+        let mut keymap = Keymap { name: preset.to_string(), bindings: Vec::new() };
+        let extra = match preset {
+            "default" => &[][..],
+            "vim" => VIM_KEYS,
+            "emacs" => EMACS_KEYS,
+            _ => return Err(format!("unknown keymap '{}' (available: {})", preset, Self::PRESETS.join(", "))),
+        };
+        for (context, action, keys) in DEFAULT_KEYS.iter().chain(extra) {
+            keymap.bind(context, action, keys)?;
+        }
+        for (context, actions) in overrides {
+            for (action, keys) in actions {
+                keymap
+                    .bind(context, action, &keys.sequences())
+                    .map_err(|e| format!("[keys.{}] {}: {}", context, action, e))?;
+            }
+        }
+        keymap.check_conflicts()?;
+        Ok(keymap)
+    }
+
+    fn bind(&mut self, context: &str, action: &str, keys: &[&str]) -> Result<(), String> {
+        let context = Context::from_name(context).ok_or_else(|| format!("unknown screen '{}'", context))?;
+        let action = Action::from_name(action)
+            .filter(|a| context.actions().contains(a))
+            .ok_or_else(|| format!("'{}' is not an action on the {} screen", action, context.name()))?;
+        let keys = keys.iter().map(|k| parse_sequence(k)).collect::<Result<Vec<_>, _>>()?;
+        // The new keys take over from whatever else used them on this screen.
+        for binding in self.bindings.iter_mut().filter(|b| b.context == context) {
+            binding.keys.retain(|existing| !keys.contains(existing));
+        }
+        self.bindings.retain(|b| !(b.context == context && b.action == action));
+        self.bindings.push(Binding { context, action, keys });
+        Ok(())
+    }
+
+    /// Rejects bindings that could never fire because a shorter one on the same screen is a prefix of them.
+    fn check_conflicts(&self) -> Result<(), String> {
+        for context in Context::ALL.into_iter().filter(|c| *c != Context::Global) {
+            let visible: Vec<(&Binding, &Vec<KeyChord>)> = self
+                .bindings
+                .iter()
+                .filter(|b| b.context == context || b.context == Context::Global)
+                .flat_map(|b| b.keys.iter().map(move |k| (b, k)))
+                .collect();
+            for (a, a_keys) in &visible {
+                for (b, b_keys) in &visible {
+                    if a_keys.len() < b_keys.len() && b_keys.starts_with(a_keys) {
+                        return Err(format!(
+                            "'{}' ({}.{}) hides '{}' ({}.{})",
+                            format_sequence(a_keys),
+                            a.context.name(),
+                            a.action.name(),
+                            format_sequence(b_keys),
+                            b.context.name(),
+                            b.action.name()
+                        ));
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Looks up the keys typed so far. Screen bindings win over global ones.
+    pub fn resolve(&self, context: Context, typed: &[KeyChord]) -> Resolution {
+        let mut pending = false;
+        for scope in [context, Context::Global] {
+            for binding in self.bindings.iter().filter(|b| b.context == scope) {
+                for keys in &binding.keys {
+                    if keys.as_slice() == typed {
+                        return Resolution::Action(binding.action);
+                    }
+                    if keys.starts_with(typed) {
+                        pending = true;
+                    }
+                }
+            }
+        }
+        if pending {
+            Resolution::Pending
+        } else {
+            Resolution::Unbound
+        }
+    }
+
+    /// The keys bound to `action` on a screen, formatted for display.
+    pub fn keys_for(&self, context: Context, action: Action) -> Vec<String> {
+        self.bindings
+            .iter()
+            .filter(|b| b.context == context && b.action == action)
+            .flat_map(|b| b.keys.iter().map(|k| format_sequence(k)))
+            .collect()
+    }
+
+    /// A status-line hint such as `j: Down | a: Add`, using the first key of each action.
+    pub fn hint(&self, context: Context, actions: &[Action]) -> String {
+        actions
+            .iter()
+            .filter_map(|&action| {
+                self.keys_for(context, action)
+                    .into_iter()
+                    .next()
+                    .map(|key| format!("{}: {}", key, action.label()))
+            })
+            .collect::<Vec<_>>()
+            .join(" | ")
+    }
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        Self::build("default", &KeyOverrides::new()).expect("the default keymap is valid")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chord(code: KeyCode, modifiers: KeyModifiers) -> KeyChord {
+        KeyChord::from(KeyEvent::new(code, modifiers))
+    }
+
+    fn keys(text: &str) -> Vec<KeyChord> {
+        parse_sequence(text).unwrap()
+    }
+
+    fn overrides(context: &str, action: &str, keys: &str) -> KeyOverrides {
+        let mut actions = BTreeMap::new();
+        actions.insert(action.to_string(), KeyList::One(keys.to_string()));
+        let mut overrides = KeyOverrides::new();
+        overrides.insert(context.to_string(), actions);
+        overrides
+    }
+
+    fn build_error(preset: &str, overrides: &KeyOverrides) -> String {
+        Keymap::build(preset, overrides).err().expect("the keymap should be rejected")
+    }
+
+    #[test]
+    fn parses_keys_with_modifiers() {
+        assert_eq!(KeyChord::parse("a").unwrap(), chord(KeyCode::Char('a'), KeyModifiers::NONE));
+        assert_eq!(KeyChord::parse("C-x").unwrap(), chord(KeyCode::Char('x'), KeyModifiers::CONTROL));
+        assert_eq!(KeyChord::parse("M-<").unwrap(), chord(KeyCode::Char('<'), KeyModifiers::ALT));
+        assert_eq!(KeyChord::parse("C-M-Enter").unwrap(), chord(KeyCode::Enter, KeyModifiers::CONTROL | KeyModifiers::ALT));
+        // Shift is part of the character, and of the key Shift-Tab sends.
+        assert_eq!(KeyChord::parse("S-g").unwrap(), KeyChord::parse("G").unwrap());
+        assert_eq!(KeyChord::parse("S-Tab").unwrap(), chord(KeyCode::BackTab, KeyModifiers::NONE));
+        assert_eq!(KeyChord::parse("pgdn").unwrap(), chord(KeyCode::PageDown, KeyModifiers::NONE));
+        assert_eq!(KeyChord::parse("F12").unwrap(), chord(KeyCode::F(12), KeyModifiers::NONE));
+        // A lone dash is a key, not a modifier.
+        assert_eq!(KeyChord::parse("-").unwrap(), chord(KeyCode::Char('-'), KeyModifiers::NONE));
+        assert_eq!(KeyChord::parse("C-x").unwrap().to_string(), "C-x");
+    }
+
+    #[test]
+    fn rejects_unknown_keys() {
+        for text in ["", "Foo", "F13", "C-", "X-a"] {
+            assert!(KeyChord::parse(text).is_err(), "{:?} parsed", text);
+        }
+        assert!(parse_sequence("  ").is_err());
+    }
+
+    #[test]
+    fn resolves_key_sequences() {
+        let keymap = Keymap::build("vim", &KeyOverrides::new()).unwrap();
+        assert!(matches!(keymap.resolve(Context::Main, &keys("g")), Resolution::Pending));
+        assert!(matches!(keymap.resolve(Context::Main, &keys("g g")), Resolution::Action(Action::MoveFirst)));
+        assert!(matches!(keymap.resolve(Context::Main, &keys("g x")), Resolution::Unbound));
+        assert!(matches!(keymap.resolve(Context::View, &keys("y u")), Resolution::Action(Action::CopySequence)));
+
+        // Screen bindings are tried before global ones, which still apply everywhere else.
+        let emacs = Keymap::build("emacs", &KeyOverrides::new()).unwrap();
+        assert!(matches!(emacs.resolve(Context::Form, &keys("C-x")), Resolution::Pending));
+        assert!(matches!(emacs.resolve(Context::Form, &keys("C-x C-s")), Resolution::Action(Action::Save)));
+        assert!(matches!(emacs.resolve(Context::Form, &keys("C-x C-c")), Resolution::Action(Action::Quit)));
+        assert!(matches!(emacs.resolve(Context::Audit, &keys("C-x C-c")), Resolution::Action(Action::Quit)));
+    }
+
+    #[test]
+    fn presets_add_to_the_defaults() {
+        let default = Keymap::default();
+        assert_eq!(default.keys_for(Context::View, Action::CopySequence), ["l"]);
+        assert!(matches!(default.resolve(Context::Main, &keys("j")), Resolution::Unbound));
+
+        let vim = Keymap::build("vim", &KeyOverrides::new()).unwrap();
+        assert_eq!(vim.keys_for(Context::View, Action::CopySequence), ["y u"]);
+        assert_eq!(vim.keys_for(Context::Main, Action::MoveDown), ["j", "↓"]);
+        // Untouched by the preset.
+        assert_eq!(vim.keys_for(Context::Main, Action::Add), ["a"]);
+
+        assert!(build_error("nano", &KeyOverrides::new()).contains("unknown keymap"));
+    }
+
+    #[test]
+    fn overrides_take_keys_from_other_actions() {
+        let keymap = Keymap::build("default", &overrides("main", "export", "a")).unwrap();
+        assert_eq!(keymap.keys_for(Context::Main, Action::Export), ["a"]);
+        assert!(keymap.keys_for(Context::Main, Action::Add).is_empty());
+        // Other screens keep their own `a`.
+        assert_eq!(keymap.keys_for(Context::Bulk, Action::ToggleActive), ["a"]);
+    }
+
+    #[test]
+    fn reports_bad_and_conflicting_bindings() {
+        let error = build_error("vim", &overrides("main", "add", "g"));
+        assert!(error.contains("'g' (main.add) hides 'g g' (main.move_first)"), "{}", error);
+        // A global binding hides the sequences it starts on every screen.
+        assert!(Keymap::build("default", &overrides("global", "help", "y")).is_ok());
+        let error = build_error("vim", &overrides("global", "help", "y"));
+        assert!(error.contains("'y' (global.help) hides 'y "), "{}", error);
+
+        assert!(build_error("default", &overrides("nowhere", "add", "a")).contains("unknown screen"));
+        assert!(build_error("default", &overrides("view", "add", "a")).contains("not an action on the view screen"));
+        assert!(build_error("default", &overrides("main", "add", "C-")).contains("unknown key"));
+    }
+}
@@ -0,0 +1,272 @@
+use crate::models::{Credential, EntryType};
+use std::collections::{BTreeMap, HashSet};
+
+/// How the main list is ordered when no search query is active.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum SortMode {
+    Name,
+    Updated,
+    Created,
+    Type,
+}
+
+impl SortMode {
+    pub fn next(self) -> Self {
+        match self {
+            SortMode::Name => SortMode::Updated,
+            SortMode::Updated => SortMode::Created,
+            SortMode::Created => SortMode::Type,
+            SortMode::Type => SortMode::Name,
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            SortMode::Name => "name",
+            SortMode::Updated => "updated",
+            SortMode::Created => "created",
+            SortMode::Type => "type",
+        }
+    }
+
+    /// Sorts credential indices in place. Ties fall back to the service name so the order is stable between unlocks.
+    pub fn sort(self, credentials: &[Credential], indices: &mut [usize]) {
+        let by_name = |a: &Credential, b: &Credential| {
+            a.service
+                .to_lowercase()
+                .cmp(&b.service.to_lowercase())
+                .then_with(|| a.username.to_lowercase().cmp(&b.username.to_lowercase()))
+                .then_with(|| a.id.cmp(&b.id))
+        };
+        indices.sort_by(|&a, &b| {
+            let (a, b) = (&credentials[a], &credentials[b]);
+            match self {
+                SortMode::Name => by_name(a, b),
+                SortMode::Updated => b.updated_at.cmp(&a.updated_at).then_with(|| by_name(a, b)),
+                SortMode::Created => b.created_at.cmp(&a.created_at).then_with(|| by_name(a, b)),
+                SortMode::Type => type_rank(&a.entry_type).cmp(&type_rank(&b.entry_type)).then_with(|| by_name(a, b)),
+            }
+        });
+    }
+}
+
+fn type_rank(entry_type: &EntryType) -> u8 {
+    match entry_type {
+        EntryType::Password => 0,
+        EntryType::ApiKey => 1,
+    }
+}
+
+/// How many recently used credentials the "Recent" section shows.
+pub const RECENT_LIMIT: usize = 5;
+
+/// One line of the main credential list.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ListRow {
+    Section {
+        title: String,
+    },
+    Folder {
+        path: String,
+        name: String,
+        depth: usize,
+        count: usize,
+        collapsed: bool,
+    },
+    Credential {
+        index: usize,
+        depth: usize,
+        /// The number key that opens this entry, for quick-access rows.
+        shortcut: Option<usize>,
+    },
+}
+
+#[derive(Default)]
+struct FolderNode {
+    children: BTreeMap<String, FolderNode>,
+    entries: Vec<usize>,
+    count: usize,
+}
+
+/// Lays out the visible credentials, either flat or as a folder tree.
+/// `visible` must already be in display order; that order is kept within each folder.
+pub fn build_rows(
+    credentials: &[Credential],
+    visible: &[usize],
+    group_by_folder: bool,
+    collapsed: &HashSet<String>,
+) -> Vec<ListRow> {
+    if !group_by_folder {
+        return visible.iter().map(|&index| ListRow::Credential { index, depth: 0, shortcut: None }).collect();
+    }
+
+    let mut root = FolderNode::default();
+    for &index in visible {
+        let mut node = &mut root;
+        for part in credentials[index].folder.split('/').filter(|p| !p.is_empty()) {
+            node = node.children.entry(part.to_string()).or_default();
+            node.count += 1;
+        }
+        node.entries.push(index);
+    }
+
+    let mut rows = Vec::new();
+    push_folder(&root, "", 0, collapsed, &mut rows);
+    rows
+}
+
+fn push_folder(node: &FolderNode, prefix: &str, depth: usize, collapsed: &HashSet<String>, rows: &mut Vec<ListRow>) {
+    for (name, child) in &node.children {
+        let path = if prefix.is_empty() { name.clone() } else { format!("{}/{}", prefix, name) };
+        let is_collapsed = collapsed.contains(&path);
+        rows.push(ListRow::Folder {
+            path: path.clone(),
+            name: name.clone(),
+            depth,
+            count: child.count,
+            collapsed: is_collapsed,
+        });
+        if !is_collapsed {
+            push_folder(child, &path, depth + 1, collapsed, rows);
+        }
+    }
+    rows.extend(node.entries.iter().map(|&index| ListRow::Credential { index, depth, shortcut: None }));
+}
+
+/// Builds the "Favorites" and "Recent" sections shown above the list while browsing.
+/// Returns the rows together with the credentials in shortcut order (`1` to `9`).
+pub fn quick_access_rows(credentials: &[Credential], visible: &[usize]) -> (Vec<ListRow>, Vec<usize>) {
+    let mut favorites: Vec<usize> = visible.iter().copied().filter(|&i| credentials[i].favorite).collect();
+    favorites.sort_by_key(|&i| credentials[i].service.to_lowercase());
+
+    let mut recent: Vec<usize> = visible
+        .iter()
+        .copied()
+        .filter(|&i| !credentials[i].favorite && credentials[i].last_used_at.is_some())
+        .collect();
+    recent.sort_by(|&a, &b| credentials[b].last_used_at.cmp(&credentials[a].last_used_at));
+    recent.truncate(RECENT_LIMIT);
+
+    let mut rows = Vec::new();
+    let mut order = Vec::new();
+    for (title, entries) in [("★ Favorites", &favorites), ("🕘 Recent", &recent)] {
+        if entries.is_empty() {
+            continue;
+        }
+        rows.push(ListRow::Section { title: title.to_string() });
+        for &index in entries {
+            order.push(index);
+            let shortcut = if order.len() <= 9 { Some(order.len()) } else { None };
+            rows.push(ListRow::Credential { index, depth: 0, shortcut });
+        }
+    }
+    if !rows.is_empty() {
+        rows.push(ListRow::Section { title: "All".to_string() });
+    }
+    order.truncate(9);
+    (rows, order)
+}
+
+/// Counts how many credentials carry each tag, sorted by tag name.
+pub fn tag_counts(credentials: &[Credential]) -> Vec<(String, usize)> {
+    let mut counts: BTreeMap<String, usize> = BTreeMap::new();
+    for credential in credentials {
+        for tag in credential.tags.iter().filter(|t| !t.is_empty()) {
+            *counts.entry(tag.clone()).or_default() += 1;
+        }
+    }
+    counts.into_iter().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{Duration, Utc};
+
+    fn credential(service: &str, entry_type: EntryType, folder: &str, age_days: i64) -> Credential {
+        let mut credential = match entry_type {
+            EntryType::Password => Credential::new_password(service.into(), "user".into(), b"x".to_vec(), String::new(), vec![]),
+            EntryType::ApiKey => Credential::new_api_key(service.into(), "user".into(), b"x".to_vec(), String::new(), true, vec![]),
+        };
+        credential.folder = folder.to_string();
+        credential.created_at = Utc::now() - Duration::days(age_days);
+        credential.updated_at = Utc::now() - Duration::days(10 - age_days);
+        credential
+    }
+
+    fn sorted(mode: SortMode, credentials: &[Credential]) -> Vec<&str> {
+        let mut indices: Vec<usize> = (0..credentials.len()).collect();
+        mode.sort(credentials, &mut indices);
+        indices.iter().map(|&i| credentials[i].service.as_str()).collect()
+    }
+
+    #[test]
+    fn sort_modes() {
+        let credentials = vec![
+            credential("gitlab", EntryType::ApiKey, "", 1),
+            credential("AWS", EntryType::Password, "", 3),
+            credential("bank", EntryType::Password, "", 2),
+        ];
+        assert_eq!(sorted(SortMode::Name, &credentials), ["AWS", "bank", "gitlab"]);
+        // Newest first.
+        assert_eq!(sorted(SortMode::Created, &credentials), ["gitlab", "bank", "AWS"]);
+        assert_eq!(sorted(SortMode::Updated, &credentials), ["AWS", "bank", "gitlab"]);
+        // Passwords before API keys, then by name.
+        assert_eq!(sorted(SortMode::Type, &credentials), ["AWS", "bank", "gitlab"]);
+        assert_eq!(SortMode::Type.next(), SortMode::Name);
+    }
+
+    #[test]
+    fn folder_tree_counts_nested_entries_and_keeps_order() {
+        let credentials = vec![
+            credential("top", EntryType::Password, "", 1),
+            credential("ci", EntryType::Password, "Work/Infra", 1),
+            credential("mail", EntryType::Password, "Work", 1),
+            credential("bank", EntryType::Password, "Home", 1),
+        ];
+        let rows = build_rows(&credentials, &[0, 1, 2, 3], true, &HashSet::new());
+        let folder = |path: &str, name: &str, depth, count| ListRow::Folder {
+            path: path.into(),
+            name: name.into(),
+            depth,
+            count,
+            collapsed: false,
+        };
+        let entry = |index, depth| ListRow::Credential { index, depth, shortcut: None };
+        assert_eq!(
+            rows,
+            vec![
+                folder("Home", "Home", 0, 1),
+                entry(3, 1),
+                folder("Work", "Work", 0, 2),
+                folder("Work/Infra", "Infra", 1, 1),
+                entry(1, 2),
+                entry(2, 1),
+                entry(0, 0),
+            ]
+        );
+    }
+
+    #[test]
+    fn collapsed_folders_hide_their_contents() {
+        let credentials = vec![
+            credential("ci", EntryType::Password, "Work/Infra", 1),
+            credential("mail", EntryType::Password, "Work", 1),
+        ];
+        let collapsed: HashSet<String> = ["Work".to_string()].into();
+        let rows = build_rows(&credentials, &[0, 1], true, &collapsed);
+        assert_eq!(
+            rows,
+            vec![ListRow::Folder { path: "Work".into(), name: "Work".into(), depth: 0, count: 2, collapsed: true }]
+        );
+
+        let flat = build_rows(&credentials, &[1, 0], false, &collapsed);
+        assert_eq!(
+            flat,
+            vec![
+                ListRow::Credential { index: 1, depth: 0, shortcut: None },
+                ListRow::Credential { index: 0, depth: 0, shortcut: None },
+            ]
+        );
+    }
+}
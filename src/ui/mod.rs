@@ -1,6 +1,10 @@
 pub mod app;
 pub mod components;
+pub mod editor;
 pub mod handlers;
+pub mod keymap;
+pub mod list;
+pub mod scroll;
 pub mod theme;
 
 use crate::ui::app::App;
@@ -16,22 +20,73 @@ pub fn run_app<B: Backend>(
     loop {
         app.check_inactivity();
         terminal.draw(|f| {
+            components::draw_background(f, f.size(), app);
             match app.current_view {
                 app::View::LockScreen => components::draw_lock_screen(f, app),
                 app::View::Main => components::draw_main_screen(f, app),
                 app::View::AddCredential => components::draw_add_credential_screen(f, app),
                 app::View::ViewCredential => components::draw_view_credential_screen(f, app),
+                app::View::Settings => components::draw_settings_screen(f, app),
+            }
+            if let Some(menu) = &app.bulk_menu {
+                components::draw_bulk_menu(f, app, menu);
+            }
+            if let Some(review) = &app.conflict_review {
+                components::draw_conflict_review(f, app, review);
+            }
+            if let Some(view) = &app.audit_view {
+                components::draw_audit_log(f, app, view);
+            }
+            if let Some(confirmation) = &app.confirmation {
+                components::draw_confirm_dialog(f, app, confirmation);
+            }
+            if app.show_help {
+                components::draw_help_overlay(f, app);
             }
         })?;
 
         if event::poll(std::time::Duration::from_millis(250))? {
-            if let event::Event::Key(key) = event::read()? {
+            let event = event::read()?;
+            match &event {
+                event::Event::Paste(text) => {
+                    app.reset_activity_timer();
+                    handlers::handle_paste(app, text);
+                }
+                event::Event::Mouse(mouse) => {
+                    app.reset_activity_timer();
+                    handlers::handle_mouse(app, *mouse)?;
+                }
+                // The next frame is laid out for the new size.
+                _ => {}
+            }
+            if let event::Event::Key(key) = event {
                 app.reset_activity_timer();
+                if app.show_help {
+                    app.show_help = false;
+                    continue;
+                }
+                if app.confirmation.is_some() {
+                    handlers::handle_confirmation_input(app, key)?;
+                    continue;
+                }
+                if app.bulk_menu.is_some() {
+                    handlers::handle_bulk_menu_input(app, key)?;
+                    continue;
+                }
+                if app.conflict_review.is_some() {
+                    handlers::handle_conflicts_input(app, key)?;
+                    continue;
+                }
+                if app.audit_view.is_some() {
+                    handlers::handle_audit_input(app, key)?;
+                    continue;
+                }
                 match app.current_view {
                     app::View::LockScreen => handlers::handle_lock_screen_input(app, key)?,
                     app::View::Main => handlers::handle_main_screen_input(app, key)?,
                     app::View::AddCredential => handlers::handle_add_credential_input(app, key)?,
                     app::View::ViewCredential => handlers::handle_view_credential_input(app, key)?,
+                    app::View::Settings => handlers::handle_settings_input(app, key)?,
                 }
             }
         }
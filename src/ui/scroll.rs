@@ -0,0 +1,97 @@
+use std::cell::Cell;
+use tui::{buffer::Buffer, layout::Rect, style::Style, widgets::Widget};
+
+/// The scroll position of a pane and where it was last drawn.
+///
+/// Drawing only gets `&App`, so the position is kept in cells: each frame clamps it to the
+/// content and records the pane's area, which the mouse handler uses to hit-test clicks.
+#[derive(Debug, Default)]
+pub struct ScrollState {
+    offset: Cell<usize>,
+    area: Cell<Rect>,
+}
+
+impl ScrollState {
+    pub fn reset(&self) {
+        self.offset.set(0);
+    }
+
+    /// Forgets the pane's area for frames in which it is not drawn, so clicks no longer land on it.
+    pub fn hide(&self) {
+        self.area.set(Rect::default());
+    }
+
+    /// Scrolls by `delta` lines. The next frame clamps the result to the content.
+    pub fn scroll_by(&self, delta: isize) {
+        self.offset.set(self.offset.get().saturating_add_signed(delta));
+    }
+
+    /// Scrolls by `pages` screenfuls, keeping one line of the previous page in view.
+    pub fn scroll_pages(&self, pages: isize) {
+        let page = (self.area.get().height as isize - 1).max(1);
+        self.scroll_by(pages * page);
+    }
+
+    /// Records the content area of a free-scrolling pane and returns the first visible line.
+    pub fn update(&self, area: Rect, total: usize) -> usize {
+        self.area.set(area);
+        let offset = self.offset.get().min(total.saturating_sub(area.height as usize));
+        self.offset.set(offset);
+        offset
+    }
+
+    /// Like [`ScrollState::update`], but for a list: the offset moves just enough to keep `selected` visible.
+    pub fn follow(&self, area: Rect, total: usize, selected: Option<usize>) -> usize {
+        let height = (area.height as usize).max(1);
+        if let Some(selected) = selected {
+            let offset = self.offset.get();
+            if selected < offset {
+                self.offset.set(selected);
+            } else if selected >= offset + height {
+                self.offset.set(selected + 1 - height);
+            }
+        }
+        self.update(area, total)
+    }
+
+    pub fn contains(&self, column: u16, row: u16) -> bool {
+        let area = self.area.get();
+        column >= area.x && column < area.right() && row >= area.y && row < area.bottom()
+    }
+
+    /// The content line under a mouse position, if it is inside the pane.
+    pub fn line_at(&self, column: u16, row: u16) -> Option<usize> {
+        if self.contains(column, row) {
+            Some(self.offset.get() + (row - self.area.get().y) as usize)
+        } else {
+            None
+        }
+    }
+}
+
+/// A vertical scrollbar drawn over the right border of a pane. Draws nothing when the content fits.
+pub struct Scrollbar {
+    pub offset: usize,
+    pub total: usize,
+    /// How many lines fit in the pane.
+    pub visible: usize,
+    pub style: Style,
+}
+
+impl Widget for Scrollbar {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        // `area` is the pane including its border; the track runs between the corners.
+        if self.total <= self.visible || area.height < 3 || area.width == 0 {
+            return;
+        }
+        let track = (area.height - 2) as usize;
+        let thumb = (track * self.visible / self.total).clamp(1, track);
+        let max_offset = self.total - self.visible;
+        let start = (track - thumb) * self.offset.min(max_offset) / max_offset;
+        let x = area.right() - 1;
+        for i in 0..track {
+            let symbol = if (start..start + thumb).contains(&i) { "█" } else { "│" };
+            buf.get_mut(x, area.y + 1 + i as u16).set_symbol(symbol).set_style(self.style);
+        }
+    }
+}
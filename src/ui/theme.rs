@@ -1,4 +1,5 @@
-
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
 use tui::style::Color;
 
 #[derive(Clone)]
@@ -38,16 +39,68 @@ impl Theme {
             background: Color::White,
             foreground: Color::Black,
             primary: Color::Blue,
-            secondary: Color::Green,
-            accent: Color::Red,
-            error: Color::Red,
-            success: Color::Green,
-            warning: Color::Yellow,
+            secondary: Color::Rgb(0, 110, 40),
+            accent: Color::Rgb(150, 0, 110),
+            error: Color::Rgb(180, 0, 0),
+            success: Color::Rgb(0, 110, 40),
+            warning: Color::Rgb(150, 90, 0),
             border: Color::DarkGray,
-            highlight_bg: Color::Yellow,
+            highlight_bg: Color::Rgb(200, 215, 255),
             highlight_fg: Color::Black,
         }
     }
+
+    /// Bright colors on black, with nothing dimmer than light gray.
+    pub fn high_contrast() -> Self {
+        Self {
+            background: Color::Black,
+            foreground: Color::White,
+            primary: Color::LightYellow,
+            secondary: Color::LightCyan,
+            accent: Color::White,
+            error: Color::LightRed,
+            success: Color::LightGreen,
+            warning: Color::LightYellow,
+            border: Color::White,
+            highlight_bg: Color::LightYellow,
+            highlight_fg: Color::Black,
+        }
+    }
+
+    /// Black on white for light terminals, with saturated dark colors.
+    pub fn high_contrast_light() -> Self {
+        Self {
+            background: Color::White,
+            foreground: Color::Black,
+            primary: Color::Rgb(0, 0, 160),
+            secondary: Color::Rgb(0, 90, 0),
+            accent: Color::Black,
+            error: Color::Rgb(160, 0, 0),
+            success: Color::Rgb(0, 90, 0),
+            warning: Color::Rgb(120, 60, 0),
+            border: Color::Black,
+            highlight_bg: Color::Black,
+            highlight_fg: Color::White,
+        }
+    }
+
+    /// The Okabe-Ito palette, which stays distinguishable with the common forms of color blindness.
+    /// Errors and successes differ in brightness as well as hue.
+    pub fn colorblind() -> Self {
+        Self {
+            background: Color::Black,
+            foreground: Color::White,
+            primary: Color::Rgb(86, 180, 233),
+            secondary: Color::Rgb(230, 159, 0),
+            accent: Color::Rgb(204, 121, 167),
+            error: Color::Rgb(213, 94, 0),
+            success: Color::Rgb(0, 158, 115),
+            warning: Color::Rgb(240, 228, 66),
+            border: Color::Gray,
+            highlight_bg: Color::Rgb(0, 114, 178),
+            highlight_fg: Color::White,
+        }
+    }
 }
 
 impl Default for Theme {
@@ -55,3 +108,175 @@ impl Default for Theme {
         Self::dark()
     }
 }
+
+/// The themes compiled into the binary, in the order they are cycled through.
+fn builtin_themes() -> Vec<(String, Theme)> {
+    vec![
+        ("dark".to_string(), Theme::dark()),
+        ("light".to_string(), Theme::light()),
+        ("high-contrast".to_string(), Theme::high_contrast()),
+        ("high-contrast-light".to_string(), Theme::high_contrast_light()),
+        ("colorblind".to_string(), Theme::colorblind()),
+    ]
+}
+
+/// A color as written in a theme file: a name, `#rrggbb`, or a 256-color palette index.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ColorValue {
+    Index(u8),
+    Text(String),
+}
+
+impl ColorValue {
+    fn parse(&self) -> Result<Color, String> {
+        let text = match self {
+            ColorValue::Index(index) => return Ok(Color::Indexed(*index)),
+            ColorValue::Text(text) => text.trim().to_lowercase(),
+        };
+        if let Some(hex) = text.strip_prefix('#') {
+            if hex.len() == 6 && hex.chars().all(|c| c.is_ascii_hexdigit()) {
+                let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).unwrap_or(0);
+                return Ok(Color::Rgb(channel(0), channel(2), channel(4)));
+            }
+            return Err(format!("'{}' is not a #rrggbb color", text));
+        }
+        if let Ok(index) = text.parse::<u8>() {
+            return Ok(Color::Indexed(index));
+        }
+        let color = match text.replace(['-', '_', ' '], "").as_str() {
+            "reset" | "default" => Color::Reset,
+            "black" => Color::Black,
+            "red" => Color::Red,
+            "green" => Color::Green,
+            "yellow" => Color::Yellow,
+            "blue" => Color::Blue,
+            "magenta" => Color::Magenta,
+            "cyan" => Color::Cyan,
+            "gray" | "grey" => Color::Gray,
+            "darkgray" | "darkgrey" => Color::DarkGray,
+            "lightred" => Color::LightRed,
+            "lightgreen" => Color::LightGreen,
+            "lightyellow" => Color::LightYellow,
+            "lightblue" => Color::LightBlue,
+            "lightmagenta" => Color::LightMagenta,
+            "lightcyan" => Color::LightCyan,
+            "white" => Color::White,
+            _ => return Err(format!("unknown color '{}'", text)),
+        };
+        Ok(color)
+    }
+}
+
+/// The contents of a theme file. Colors that are left out come from `base`.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ThemeFile {
+    name: Option<String>,
+    base: Option<String>,
+    background: Option<ColorValue>,
+    foreground: Option<ColorValue>,
+    primary: Option<ColorValue>,
+    secondary: Option<ColorValue>,
+    accent: Option<ColorValue>,
+    error: Option<ColorValue>,
+    success: Option<ColorValue>,
+    warning: Option<ColorValue>,
+    border: Option<ColorValue>,
+    highlight_bg: Option<ColorValue>,
+    highlight_fg: Option<ColorValue>,
+}
+
+/// Every theme that can be selected: the built-in ones followed by those found in the themes directory.
+pub struct ThemeRegistry {
+    themes: Vec<(String, Theme)>,
+    /// Theme files that could not be loaded, with the reason.
+    pub errors: Vec<String>,
+}
+
+impl ThemeRegistry {
+    /// Where user themes live: a `themes` directory next to the settings file.
+    pub fn dir_for(config_path: &Path) -> PathBuf {
+        config_path.parent().unwrap_or_else(|| Path::new(".")).join("themes")
+    }
+
+    /// Loads the built-in themes plus every `*.toml` file in `dir`.
+    /// A file whose name matches a built-in theme replaces it.
+    pub fn load(dir: &Path) -> Self {
+        let mut registry = Self { themes: builtin_themes(), errors: Vec::new() };
+        let mut paths: Vec<PathBuf> = match std::fs::read_dir(dir) {
+            Ok(entries) => entries
+                .filter_map(|entry| entry.ok().map(|e| e.path()))
+                .filter(|path| path.extension().is_some_and(|ext| ext == "toml"))
+                .collect(),
+            Err(_) => Vec::new(),
+        };
+        paths.sort();
+        for path in paths {
+            match registry.load_file(&path) {
+                Ok((name, theme)) => match registry.themes.iter_mut().find(|(n, _)| *n == name) {
+                    Some(existing) => existing.1 = theme,
+                    None => registry.themes.push((name, theme)),
+                },
+                Err(e) => registry.errors.push(format!("{}: {}", path.display(), e)),
+            }
+        }
+        registry
+    }
+
+    fn load_file(&self, path: &Path) -> Result<(String, Theme), String> {
+        let text = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+        let file: ThemeFile = toml::from_str(&text).map_err(|e| e.message().to_string())?;
+        let name = match file.name {
+            Some(name) => name.trim().to_string(),
+            None => path.file_stem().and_then(|s| s.to_str()).unwrap_or_default().to_string(),
+        };
+        if name.is_empty() {
+            return Err("theme has no name".to_string());
+        }
+        let mut theme = match &file.base {
+            Some(base) => self.get(base).ok_or_else(|| format!("unknown base theme '{}'", base))?,
+            None => Theme::default(),
+        };
+        let fields: [(&Option<ColorValue>, &mut Color); 11] = [
+            (&file.background, &mut theme.background),
+            (&file.foreground, &mut theme.foreground),
+            (&file.primary, &mut theme.primary),
+            (&file.secondary, &mut theme.secondary),
+            (&file.accent, &mut theme.accent),
+            (&file.error, &mut theme.error),
+            (&file.success, &mut theme.success),
+            (&file.warning, &mut theme.warning),
+            (&file.border, &mut theme.border),
+            (&file.highlight_bg, &mut theme.highlight_bg),
+            (&file.highlight_fg, &mut theme.highlight_fg),
+        ];
+        for (value, slot) in fields {
+            if let Some(value) = value {
+                *slot = value.parse()?;
+            }
+        }
+        Ok((name, theme))
+    }
+
+    pub fn get(&self, name: &str) -> Option<Theme> {
+        self.themes.iter().find(|(n, _)| n == name).map(|(_, theme)| theme.clone())
+    }
+
+    pub fn names(&self) -> Vec<&str> {
+        self.themes.iter().map(|(name, _)| name.as_str()).collect()
+    }
+
+    /// The theme `step` places after `current` in cycling order, wrapping around.
+    pub fn cycle(&self, current: &str, step: isize) -> &str {
+        let count = self.themes.len() as isize;
+        let position = self.themes.iter().position(|(n, _)| n == current).unwrap_or(0) as isize;
+        &self.themes[(position + step).rem_euclid(count) as usize].0
+    }
+}
+
+impl Default for ThemeRegistry {
+    fn default() -> Self {
+        Self { themes: builtin_themes(), errors: Vec::new() }
+    }
+}